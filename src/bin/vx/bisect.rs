@@ -0,0 +1,159 @@
+use crate::exit::{failure, CommandError};
+use crate::tree::describe_commit_id;
+use clap::{Args, Subcommand};
+use std::process::Command;
+use vx::context::Context;
+use vx::core::bisect::{Bisect, BisectStep};
+use vx::core::commit::{Commit, CommitID};
+
+#[derive(Args, Debug)]
+pub(super) struct BisectArgs {
+    #[command(subcommand)]
+    cmd: BisectCommands,
+}
+
+#[derive(Debug, Subcommand)]
+enum BisectCommands {
+    /// Start searching the commits between a good and a bad one for the first bad commit
+    Start {
+        /// The commit known to be good, e.g. a sequence number on the current branch
+        good: String,
+        /// A later commit of the same branch known to be bad
+        bad: String,
+        /// Command testing every commit, see `run`, the commits are marked by hand if omitted
+        #[arg(last = true)]
+        command: Vec<String>,
+    },
+    /// Mark the checked out commit as good
+    Good,
+    /// Mark the checked out commit as bad
+    Bad,
+    /// Test the commits left with a command run in the checkout until the first bad commit is
+    /// found, a commit is good if the command exits with zero and bad otherwise
+    Run {
+        #[arg(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+        command: Vec<String>,
+    },
+    /// Show the commits left to test
+    Status,
+    /// End the bisect and check out the commit it was started at
+    Reset,
+}
+
+pub(super) fn exec(args: &BisectArgs) -> Result<(), CommandError> {
+    let context = Context::init().map_err(|err| format!("Error initializing context: {}", err))?;
+    match &args.cmd {
+        BisectCommands::Start { good, bad, command } => start(&context, good, bad, command),
+        BisectCommands::Good => mark(&context, true),
+        BisectCommands::Bad => mark(&context, false),
+        BisectCommands::Run { command } => run(&context, command),
+        BisectCommands::Status => status(&context),
+        BisectCommands::Reset => reset(&context),
+    }
+}
+
+fn start(context: &Context, good: &str, bad: &str, command: &[String]) -> Result<(), CommandError> {
+    let step = Bisect::start(context, good, bad)
+        .map_err(|e| failure(&e, format!("Failed to start bisect: {:?}", e)))?;
+    let found = print_step(context, &step);
+    if command.is_empty() || found {
+        return Ok(());
+    }
+    run(context, command)
+}
+
+fn mark(context: &Context, good: bool) -> Result<(), CommandError> {
+    let step = Bisect::mark(context, good)
+        .map_err(|e| failure(&e, format!("Failed to mark commit: {:?}", e)))?;
+    print_step(context, &step);
+    Ok(())
+}
+
+fn run(context: &Context, command: &[String]) -> Result<(), CommandError> {
+    if Bisect::get(context)
+        .map_err(|e| failure(&e, format!("Failed to get bisect: {:?}", e)))?
+        .is_none()
+    {
+        return Err("No bisect in progress, start one with `vx bisect start`".into());
+    }
+
+    loop {
+        let status = Command::new(&command[0])
+            .args(&command[1..])
+            .current_dir(&context.checkout_path)
+            .status()
+            .map_err(|e| {
+                failure(
+                    &e,
+                    format!("Failed to run '{}': {:?}", command.join(" "), e),
+                )
+            })?;
+        // a command killed by a signal did not test anything, e.g. it was interrupted
+        if status.code().is_none() {
+            return Err(
+                format!("Command '{}' was terminated: {}", command.join(" "), status).into(),
+            );
+        }
+
+        let step = Bisect::mark(context, status.success())
+            .map_err(|e| failure(&e, format!("Failed to mark commit: {:?}", e)))?;
+        if print_step(context, &step) {
+            return Ok(());
+        }
+    }
+}
+
+fn status(context: &Context) -> Result<(), CommandError> {
+    let bisect = Bisect::get(context)
+        .map_err(|e| failure(&e, format!("Failed to get bisect: {:?}", e)))?
+        .ok_or_else(|| "No bisect in progress".to_string())?;
+    let commit = |seq| CommitID {
+        branch: bisect.branch,
+        seq,
+    };
+    println!("Good: {}", describe_commit_id(context, commit(bisect.good)));
+    println!("Bad: {}", describe_commit_id(context, commit(bisect.bad)));
+    println!(
+        "{} commit(s) left to test, started at {}",
+        bisect.bad - bisect.good - 1,
+        describe_commit_id(context, bisect.origin)
+    );
+    Ok(())
+}
+
+fn reset(context: &Context) -> Result<(), CommandError> {
+    let commit = Bisect::reset(context)
+        .map_err(|e| failure(&e, format!("Failed to reset bisect: {:?}", e)))?;
+    println!(
+        "Bisect reset, checked out commit {}",
+        describe(context, &commit)
+    );
+    Ok(())
+}
+
+/// Prints where the bisect stands, returns true once the first bad commit is found.
+fn print_step(context: &Context, step: &BisectStep) -> bool {
+    match step {
+        BisectStep::Testing(commit, steps) => {
+            println!(
+                "Testing commit {}, {} step(s) left at most",
+                describe(context, commit),
+                steps
+            );
+            false
+        }
+        BisectStep::Found(commit) => {
+            println!("First bad commit: {}", describe(context, commit));
+            println!("Run `vx bisect reset` to end the bisect");
+            true
+        }
+    }
+}
+
+fn describe(context: &Context, commit: &Commit) -> String {
+    format!(
+        "{} {}",
+        describe_commit_id(context, commit.id),
+        commit.subject()
+    )
+}
@@ -0,0 +1,180 @@
+use crate::exit::{failure, CommandError};
+use crate::output;
+use clap::{Args, Subcommand};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use vx::context::Context;
+use vx::core::blob::Blob;
+use vx::core::digest::{Digest, DigestExt};
+use vx::core::repo::Repo;
+
+#[derive(Args, Debug)]
+pub(super) struct BlobArgs {
+    #[command(subcommand)]
+    cmd: BlobCommands,
+}
+
+#[derive(Debug, Subcommand)]
+enum BlobCommands {
+    /// Verify the content of every blob in the blob store
+    VerifyAll,
+    /// Print the content of a blob, or a part of it
+    Cat {
+        /// Hash of the blob in hexadecimal
+        hash: String,
+        /// Number of bytes to skip at the start of the blob
+        #[arg(long, default_value_t = 0)]
+        offset: u64,
+        /// Maximum number of bytes to print, everything up to the end of the blob if not provided
+        #[arg(long)]
+        len: Option<u64>,
+        /// File to write the content to, `-` for standard output
+        #[arg(long, short, value_name = "PATH", default_value = output::STDOUT)]
+        output: PathBuf,
+    },
+    /// Store the content of a file in the blob store and print its hash
+    Import {
+        /// Path of the file to store
+        path: PathBuf,
+    },
+    /// Check the blob store
+    Fsck {
+        /// Report trees and blobs unreachable from any branch or tag, which a garbage collection
+        /// would remove, without deleting anything
+        #[arg(long)]
+        gc_dry_run: bool,
+    },
+}
+
+pub(super) fn exec(args: &BlobArgs) -> Result<(), CommandError> {
+    let context = Context::init().map_err(|err| format!("Error initializing context: {}", err))?;
+    match &args.cmd {
+        BlobCommands::VerifyAll => verify_all(&context),
+        BlobCommands::Cat {
+            hash,
+            offset,
+            len,
+            output,
+        } => cat(&context, hash, *offset, *len, output),
+        BlobCommands::Import { path } => import(&context, path),
+        BlobCommands::Fsck { gc_dry_run } => {
+            verify_all(&context)?;
+            if *gc_dry_run {
+                gc_dry_run_report(&context)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+pub(super) fn exec_gc(dry_run: bool) -> Result<(), CommandError> {
+    let context = Context::init().map_err(|err| format!("Error initializing context: {}", err))?;
+    if dry_run {
+        gc_dry_run_report(&context)
+    } else {
+        gc(&context)
+    }
+}
+
+fn verify_all(context: &Context) -> Result<(), CommandError> {
+    let report = Blob::verify_all(context)
+        .map_err(|e| failure(&e, format!("Failed to verify blobs: {:?}", e)))?;
+
+    for hash in &report.mismatched {
+        println!("  mismatched blob {}", hash.to_hex_string());
+    }
+    for hash in &report.missing {
+        println!("  missing blob {}", hash.to_hex_string());
+    }
+    for path in &report.orphans {
+        println!("  orphan file {}", path.display());
+    }
+
+    if report.is_ok() {
+        println!("Verified {} blobs, no problems found", report.checked);
+        Ok(())
+    } else {
+        Err(format!(
+            "Verified {} blobs: {} mismatched, {} missing, {} orphan files",
+            report.checked,
+            report.mismatched.len(),
+            report.missing.len(),
+            report.orphans.len()
+        )
+        .into())
+    }
+}
+
+fn cat(
+    context: &Context,
+    hash: &str,
+    offset: u64,
+    len: Option<u64>,
+    path: &Path,
+) -> Result<(), CommandError> {
+    let contenthash =
+        Digest::from_hex_string(hash).ok_or_else(|| format!("Invalid blob hash: {}", hash))?;
+    let content = Blob::read_range(context, contenthash, offset, len.unwrap_or(u64::MAX))
+        .map_err(|e| failure(&e, format!("Failed to read blob: {:?}", e)))?;
+
+    // the content is written as is, it may well be binary
+    let mut out = output::open(path)?;
+    out.write_all(&content)
+        .map_err(|e| failure(&e, format!("Failed to write blob content: {:?}", e)))?;
+    Ok(output::finish(out, path)?)
+}
+
+fn import(context: &Context, path: &Path) -> Result<(), CommandError> {
+    let blob = Blob::import(context, path).map_err(|e| {
+        failure(
+            &e,
+            format!("Failed to import '{}': {:?}", path.display(), e),
+        )
+    })?;
+    println!("{}", blob.contenthash.to_hex_string());
+    Ok(())
+}
+
+fn gc(context: &Context) -> Result<(), CommandError> {
+    let report = Repo::gc(context)
+        .map_err(|e| failure(&e, format!("Failed to collect garbage: {:?}", e)))?;
+    println!(
+        "Marked {} trees and {} blobs reachable from {} commits",
+        report.reachable_trees, report.reachable_blobs, report.commit_count
+    );
+    println!(
+        "Removed {} trees and {} blobs, freeing {} bytes",
+        report.unreachable_trees.len(),
+        report.unreachable_blobs.len(),
+        report.unreachable_size()
+    );
+    Ok(())
+}
+
+fn gc_dry_run_report(context: &Context) -> Result<(), CommandError> {
+    let report = Repo::find_unreachable(context)
+        .map_err(|e| failure(&e, format!("Failed to find unreachable objects: {:?}", e)))?;
+
+    for hash in &report.unreachable_trees {
+        println!("  unreachable tree {}", hash.to_hex_string());
+    }
+    for blob in &report.unreachable_blobs {
+        println!(
+            "  unreachable blob {} {}",
+            blob.contenthash.to_hex_string(),
+            blob.size
+        );
+    }
+
+    println!(
+        "Marked {} trees and {} blobs reachable from {} commits",
+        report.reachable_trees, report.reachable_blobs, report.commit_count
+    );
+    println!(
+        "Garbage collection would remove {} trees and {} blobs of {} bytes",
+        report.unreachable_trees.len(),
+        report.unreachable_blobs.len(),
+        report.unreachable_size()
+    );
+    Ok(())
+}
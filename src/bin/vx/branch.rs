@@ -1,6 +1,13 @@
+use crate::exit::{failure, CommandError};
+use crate::json;
+use crate::template::SHORT_HASH_LEN;
 use clap::{Args, Subcommand};
+use std::collections::HashMap;
 use vx::context::Context;
-use vx::core::branch::Branch;
+use vx::core::branch::{Branch, BranchMerge};
+use vx::core::commit::Commit;
+use vx::core::digest::DigestExt;
+use vx::core::merge::ConflictKind;
 
 #[derive(Args, Debug)]
 pub(super) struct BranchArgs {
@@ -13,62 +20,177 @@ enum BranchCommands {
     New {
         name: String,
     },
-    List,
+    /// List branches, as JSON with `--json` including commit counts relative to the parent
+    /// branch
+    List {
+        /// Also show the number of commits and the size of the head tree of every branch
+        #[arg(long)]
+        with_size: bool,
+    },
     Show {
         // Optional branch name, if not provided show current branch
         #[arg(default_value = None)]
         name: Option<String>,
+        /// Also list the latest N commits of the branch
+        #[arg(long, value_name = "N")]
+        commits: Option<usize>,
+    },
+    /// Refuse direct commits to the branch unless forced
+    Protect {
+        name: String,
+    },
+    /// Allow direct commits to the branch again
+    Unprotect {
+        name: String,
+    },
+    /// Rename a branch, its commits, tags and stashes follow it
+    Rename {
+        old: String,
+        new: String,
+    },
+    /// Merge the changes of a branch into the current one and commit them, conflicting files
+    /// are left in the checkout to resolve and commit by hand
+    Merge {
+        name: String,
+        /// Commit the merge even if the current branch is protected
+        #[arg(long)]
+        force: bool,
     },
 }
-pub(super) fn exec(args: &BranchArgs) -> Result<(), String> {
+/// Whether the command prints JSON with `--json`.
+pub(super) fn prints_json(args: &BranchArgs) -> bool {
+    matches!(args.cmd, BranchCommands::List { .. })
+}
+
+pub(super) fn exec(args: &BranchArgs, json: bool) -> Result<(), CommandError> {
     let context = Context::init().map_err(|err| format!("Error initializing context: {}", err))?;
     match &args.cmd {
         BranchCommands::New { name } => new(&context, name),
-        BranchCommands::List => list(&context),
-        BranchCommands::Show { name } => show(&context, name.clone()),
+        BranchCommands::List { with_size } => {
+            if json {
+                list_json(&context, *with_size)
+            } else {
+                list(&context, *with_size)
+            }
+        }
+        BranchCommands::Show { name, commits } => show(&context, name.clone(), *commits),
+        BranchCommands::Protect { name } => protect(&context, name, true),
+        BranchCommands::Unprotect { name } => protect(&context, name, false),
+        BranchCommands::Rename { old, new } => rename(&context, old, new),
+        BranchCommands::Merge { name, force } => merge(&context, name, *force),
     }
 }
 
-fn new(context: &Context, name: &str) -> Result<(), String> {
+fn new(context: &Context, name: &str) -> Result<(), CommandError> {
     match Branch::new(context, name.to_string()) {
         Ok(branch) => {
             println!("Created new branch: {:?}", branch.name);
             Ok(())
         }
-        Err(e) => Err(format!("Failed to create new branch: {:?}", e)),
+        Err(e) => Err(failure(&e, format!("Failed to create new branch: {:?}", e))),
     }
 }
 
-fn list(context: &Context) -> Result<(), String> {
+fn list(context: &Context, with_size: bool) -> Result<(), CommandError> {
     match Branch::list(context) {
         Ok(branches) => {
             for branch in branches {
-                println!(
+                let mut line = format!(
                     "Branch ID: {}, Name: {}, Version: {}, Head Sequence: {}",
                     branch.id, branch.name, branch.ver, branch.headseq
                 );
+                if with_size {
+                    let size = head_size(context, &branch)?;
+                    line.push_str(&format!(", Commits: {}, Size: {}", branch.ahead(), size));
+                }
+                println!("{}", line);
             }
             Ok(())
         }
-        Err(e) => Err(format!("Failed to list branches: {:?}", e)),
+        Err(e) => Err(failure(&e, format!("Failed to list branches: {:?}", e))),
+    }
+}
+
+fn list_json(context: &Context, with_size: bool) -> Result<(), CommandError> {
+    let branches = Branch::list(context)
+        .map_err(|e| failure(&e, format!("Failed to list branches: {:?}", e)))?;
+    let by_id: HashMap<u64, &Branch> = branches.iter().map(|b| (b.id, b)).collect();
+
+    let mut values = Vec::new();
+    for branch in &branches {
+        // Counts are not defined for the foundational branch, as it has no parent.
+        // Ids are rendered as strings, they do not fit into the double precision of JSON numbers.
+        let (ahead, behind) = match by_id.get(&branch.parent) {
+            Some(parent) if !branch.is_foundational() => (
+                branch.ahead().to_string(),
+                branch.behind(parent).to_string(),
+            ),
+            _ => ("null".to_string(), "null".to_string()),
+        };
+        let mut fields = vec![
+            ("id", json::string(&branch.id.to_string())),
+            ("name", json::string(&branch.name)),
+            ("ver", branch.ver.to_string()),
+            ("headseq", branch.headseq.to_string()),
+            ("parent", json::string(&branch.parent.to_string())),
+            ("parentseq", branch.parentseq.to_string()),
+            ("ahead", ahead),
+            ("behind", behind),
+        ];
+        if with_size {
+            fields.push(("commits", branch.ahead().to_string()));
+            fields.push(("size", head_size(context, branch)?.to_string()));
+        }
+        values.push(json::object(&fields));
     }
+
+    println!("{}", json::array(&values));
+    Ok(())
+}
+
+/// Gets the size of the head tree of a branch for listing.
+fn head_size(context: &Context, branch: &Branch) -> Result<u64, CommandError> {
+    branch.head_size(context).map_err(|e| {
+        failure(
+            &e,
+            format!("Failed to get size of branch '{}': {:?}", branch.name, e),
+        )
+    })
 }
 
-fn show(context: &Context, name: Option<String>) -> Result<(), String> {
+fn show(
+    context: &Context,
+    name: Option<String>,
+    commits: Option<usize>,
+) -> Result<(), CommandError> {
     let branch = match name {
         Some(branch_name) => {
             // Show specific branch
             match Branch::get_by_name(context, &branch_name) {
                 Ok(branch) => branch,
-                Err(e) => return Err(format!("Failed to get branch '{}': {:?}", branch_name, e)),
+                Err(e) => {
+                    return Err(failure(
+                        &e,
+                        format!("Failed to get branch '{}': {:?}", branch_name, e),
+                    ))
+                }
             }
         }
         None => match Branch::get_current(context) {
             Ok(branch) => branch,
-            Err(e) => return Err(format!("Failed to get current branch: {:?}", e)),
+            Err(e) => {
+                return Err(failure(
+                    &e,
+                    format!("Failed to get current branch: {:?}", e),
+                ))
+            }
         },
     };
 
+    let protected = branch
+        .is_protected(context)
+        .map_err(|e| failure(&e, format!("Failed to get branch protection: {:?}", e)))?;
+
     println!("Branch Details:");
     println!("  ID:            {}", branch.id);
     println!("  Name:          {}", branch.name);
@@ -76,6 +198,77 @@ fn show(context: &Context, name: Option<String>) -> Result<(), String> {
     println!("  Head Sequence: {}", branch.headseq);
     println!("  Parent:        {}", branch.parent);
     println!("  Parent Seq:    {}", branch.parentseq);
+    println!("  Protected:     {}", protected);
+
+    if let Some(limit) = commits {
+        let commits = Commit::list_recent(context, &branch, limit)
+            .map_err(|e| failure(&e, format!("Failed to list commits: {:?}", e)))?;
+        println!("Recent Commits:");
+        for commit in commits {
+            println!(
+                "  {}\t{}\t{}",
+                commit.id.seq,
+                &commit.hash.to_hex_string()[..SHORT_HASH_LEN],
+                commit.subject()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+fn rename(context: &Context, old: &str, new: &str) -> Result<(), CommandError> {
+    let branch = Branch::rename(context, old, new)
+        .map_err(|e| failure(&e, format!("Failed to rename branch '{}': {:?}", old, e)))?;
+    println!("Renamed branch {} to {}", old, branch.name);
+    Ok(())
+}
+
+fn merge(context: &Context, name: &str, force: bool) -> Result<(), CommandError> {
+    let merge = Branch::merge(context, name, force)
+        .map_err(|e| failure(&e, format!("Failed to merge branch '{}': {:?}", name, e)))?;
+    match merge {
+        BranchMerge::UpToDate => println!("Already up to date with branch {}", name),
+        BranchMerge::Committed(commit) => println!(
+            "Merged branch {} in commit {} - {}",
+            name,
+            commit.id.seq,
+            commit.subject()
+        ),
+        // conflicts stop the command like a paused rebuild, so scripts do not take it as merged
+        BranchMerge::Conflicts(conflicts) => {
+            let mut message = "Conflicts:".to_string();
+            for conflict in conflicts {
+                let kind_str = match conflict.kind {
+                    ConflictKind::Content => "both modified",
+                    ConflictKind::ModifiedDeleted => "deleted in branch, kept local",
+                    ConflictKind::DeletedModified => "deleted locally, restored from branch",
+                    ConflictKind::Binary => "binary, kept local",
+                };
+                message.push_str(&format!("\n  {} {}", kind_str, conflict.path.display()));
+            }
+            message
+                .push_str("\nResolve the conflicts and run `vx commit new` to complete the merge");
+            return Err(message.into());
+        }
+    }
+    Ok(())
+}
 
+fn protect(context: &Context, name: &str, protected: bool) -> Result<(), CommandError> {
+    let branch = Branch::get_by_name(context, name)
+        .map_err(|e| failure(&e, format!("Failed to get branch '{}': {:?}", name, e)))?;
+    branch
+        .set_protected(context, protected)
+        .map_err(|e| failure(&e, format!("Failed to update branch protection: {:?}", e)))?;
+
+    if protected {
+        println!(
+            "Protected branch {}, direct commits require --force",
+            branch.name
+        );
+    } else {
+        println!("Unprotected branch {}", branch.name);
+    }
     Ok(())
 }
@@ -0,0 +1,60 @@
+// Coloring of command output printed to a terminal.
+
+use std::io::IsTerminal;
+use vx::core::tree::ChangeAction;
+
+/// Environment variable disabling colors in automatic mode when set, see https://no-color.org.
+const NO_COLOR_ENV: &str = "NO_COLOR";
+
+const GREEN: &str = "\x1b[32m";
+const RED: &str = "\x1b[31m";
+const YELLOW: &str = "\x1b[33m";
+const RESET: &str = "\x1b[0m";
+
+/// When output is colored.
+#[derive(Debug, Clone, Copy)]
+pub(super) enum ColorMode {
+    /// Only when standard output is a terminal and `NO_COLOR_ENV` is not set.
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorMode {
+    /// Decides whether output is colored.
+    pub(super) fn enabled(self) -> bool {
+        match self {
+            ColorMode::Auto => {
+                std::io::stdout().is_terminal() && std::env::var_os(NO_COLOR_ENV).is_none()
+            }
+            ColorMode::Always => true,
+            ColorMode::Never => false,
+        }
+    }
+}
+
+pub(super) fn parse_mode(mode: &str) -> Result<ColorMode, String> {
+    match mode {
+        "auto" => Ok(ColorMode::Auto),
+        "always" => Ok(ColorMode::Always),
+        "never" => Ok(ColorMode::Never),
+        _ => Err(format!("Expected auto, always or never, got '{}'", mode)),
+    }
+}
+
+/// Colors text describing a change by its action: green for added, red for deleted and yellow
+/// for modified, leaving its indentation as it is, or leaves the whole text as it is if coloring
+/// is disabled.
+pub(super) fn paint_action(action: &ChangeAction, text: &str, enabled: bool) -> String {
+    if !enabled {
+        return text.to_string();
+    }
+    let color = match action {
+        ChangeAction::Added => GREEN,
+        ChangeAction::Deleted => RED,
+        ChangeAction::Modified => YELLOW,
+    };
+    let body = text.trim_start();
+    let indent = &text[..text.len() - body.len()];
+    format!("{}{}{}{}", indent, color, body, RESET)
+}
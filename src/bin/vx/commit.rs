@@ -1,6 +1,20 @@
+use crate::editor;
+use crate::exit::{failure, CommandError, ExitCode};
+use crate::json;
+use crate::resolve;
+use crate::template::{Extra, Template, SHORT_HASH_LEN};
+use crate::time;
+use crate::tree;
 use clap::{Args, Subcommand};
+use std::collections::HashMap;
+use std::path::PathBuf;
 use vx::context::Context;
+use vx::core::branch::Branch;
 use vx::core::commit::Commit;
+use vx::core::digest::DigestExt;
+use vx::core::signing::{self, Verification};
+use vx::core::tree::Tree;
+use vx::storage::commit::CommitError;
 
 #[derive(Args, Debug)]
 pub(super) struct CommitArgs {
@@ -8,95 +22,978 @@ pub(super) struct CommitArgs {
     cmd: CommitCommands,
 }
 
+#[derive(Args, Debug)]
+pub(super) struct LogArgs {
+    // Optional branch name to list commits from
+    #[arg(default_value = None)]
+    branch: Option<String>,
+    /// Render the history across the parent branches as a graph
+    #[arg(long)]
+    graph: bool,
+    /// Continue into the parent branches at the commits they were forked at, down to the
+    /// foundational branch, showing branch names and short hashes
+    #[arg(long, conflicts_with = "graph")]
+    follow: bool,
+    /// Show how long ago each commit was created along with its time
+    #[arg(long)]
+    relative: bool,
+    /// Show the author of every commit before its subject
+    #[arg(long, conflicts_with_all = ["graph", "format"])]
+    author: bool,
+    /// Only list commits created at or after this time, in seconds since the Unix epoch or as an
+    /// ISO 8601 date, e.g. "2024-03-01"
+    #[arg(long, value_name = "DATE", value_parser = time::parse, conflicts_with = "graph")]
+    since: Option<u64>,
+    /// Only list commits created at or before this time, in the same formats as --since
+    #[arg(long, value_name = "DATE", value_parser = time::parse, conflicts_with = "graph")]
+    until: Option<u64>,
+    /// Render every commit with a template, e.g. "%h %b:%s %m", see `commit show --help`
+    #[arg(long, value_parser = Template::parse, conflicts_with = "graph")]
+    format: Option<Template>,
+    /// Only list commits changing these paths, relative to the current directory
+    #[arg(last = true, conflicts_with = "graph")]
+    paths: Vec<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+pub(super) struct ResetArgs {
+    /// The commit of the current branch to move its head to, e.g. "main:3", "3" or a tag name
+    spec: String,
+    /// Leave the checkout as it is, the default, as vx has no staging area to reset apart from it
+    #[arg(long, conflicts_with = "hard")]
+    soft: bool,
+    /// Also check out the commit, discarding the changes of dropped commits and any local ones
+    #[arg(long)]
+    hard: bool,
+    /// Reset even if the branch is protected
+    #[arg(long)]
+    force: bool,
+}
+
+#[derive(Args, Debug)]
+pub(super) struct RevertArgs {
+    /// The commit whose changes to undo, e.g. "main:3", "3" or a tag name
+    spec: String,
+    /// Revert even if the branch is protected
+    #[arg(long)]
+    force: bool,
+}
+
 #[derive(Debug, Subcommand)]
 enum CommitCommands {
     New {
-        message: String,
-    },
-    List {
-        // Optional branch name to list commits from
-        #[arg(default_value = None)]
-        branch: Option<String>,
+        #[arg(required_unless_present_any = ["reuse_message", "reedit_message"])]
+        message: Option<String>,
+        /// Reuse the message of an existing commit, e.g. "main:3", "3" or a tag name
+        #[arg(long, value_name = "SPEC", conflicts_with_all = ["message", "reedit_message"])]
+        reuse_message: Option<String>,
+        /// Like --reuse-message, but open the message in an editor first
+        #[arg(long, value_name = "SPEC", conflicts_with = "message")]
+        reedit_message: Option<String>,
+        /// Date of the commit, in seconds since the Unix epoch or as an ISO 8601 date, e.g.
+        /// "2024-03-01T12:30:00Z"; defaults to $SOURCE_DATE_EPOCH if set, otherwise to now
+        #[arg(long, value_parser = time::parse)]
+        date: Option<u64>,
+        /// Commit even if the branch is protected
+        #[arg(long)]
+        force: bool,
+        /// Ask for every file a rebuild conflicts on whether to take mine, take theirs, edit or
+        /// skip it, continuing the rebuild once all are resolved
+        #[arg(long, conflicts_with = "dry_run")]
+        interactive: bool,
+        /// Only report what would be committed, without writing anything
+        #[arg(long)]
+        dry_run: bool,
+        /// Sign the commit with the key registered by `vx repo set-key`
+        #[arg(long, conflicts_with = "dry_run")]
+        sign: bool,
     },
+    List(LogArgs),
     Show {
         // Commit specification in format "branch_name:seq" or just "seq" or "branch_name"
         #[arg(default_value = None)]
         spec: Option<String>,
+        /// Print the stored records of the commit as JSON, including all its versions
+        #[arg(long)]
+        raw: bool,
+        /// Show how long ago the commit was created along with its time
+        #[arg(long)]
+        relative: bool,
+        /// Render the commit with a template: %h short hash, %H hash, %T tree hash, %b branch,
+        /// %s sequence, %v version, %m subject, %an author name, %ae author email, %at time in
+        /// seconds since the Unix epoch, %ad date, %n line break, %% percent sign
+        #[arg(long, value_parser = Template::parse, conflicts_with = "raw")]
+        format: Option<Template>,
+    },
+    /// Check the signature of a commit, failing unless it is signed and the signature matches
+    Verify {
+        /// The commit to check, e.g. "main:3", "3" or a tag name
+        spec: String,
+    },
+    /// Show changes between two commits
+    Diff {
+        /// The commit to compare from
+        from: String,
+        /// The commit to compare to
+        to: String,
+        /// Only show changes within these paths, relative to the current directory
+        paths: Vec<PathBuf>,
     },
     Amend {
         message: Option<String>,
+        /// New author of the commit, typically in "Name <email>" format
+        #[arg(long)]
+        author: Option<String>,
+        /// Amend even if the branch is protected
+        #[arg(long)]
+        force: bool,
+        /// Ask for every file a rebuild conflicts on whether to take mine, take theirs, edit or
+        /// skip it, continuing the rebuild once all are resolved
+        #[arg(long)]
+        interactive: bool,
+    },
+    /// Move a commit of the current branch to another position, rebuilding the commits in between
+    Move {
+        /// The commit to move
+        spec: String,
+        /// Sequence number of the position to move the commit to
+        target: u64,
+        /// Move even if the branch is protected
+        #[arg(long)]
+        force: bool,
     },
+    /// Drop the last commit of the current branch, keeping its changes in the checkout
+    Undo {
+        /// Also check out the previous commit, discarding the changes and any local ones
+        #[arg(long)]
+        hard: bool,
+        /// Undo even if the branch is protected
+        #[arg(long)]
+        force: bool,
+    },
+    /// Continue or abort a rebuild paused on a conflict
+    Rebuild {
+        /// Continue the rebuild once the conflicts are resolved
+        #[arg(long = "continue", required_unless_present = "abort")]
+        resume: bool,
+        /// Abort the rebuild, keeping the changes of the new or amended commit as local changes
+        #[arg(long, conflicts_with = "resume")]
+        abort: bool,
+        /// Ask for every file with conflict markers whether to take mine, take theirs, edit or
+        /// skip it before continuing
+        #[arg(long, conflicts_with = "abort")]
+        interactive: bool,
+    },
+}
+
+/// Whether the command prints JSON with `--json`.
+pub(super) fn prints_json(args: &CommitArgs) -> bool {
+    matches!(
+        args.cmd,
+        CommitCommands::List(_) | CommitCommands::Show { .. } | CommitCommands::Diff { .. }
+    )
 }
 
-pub(super) fn exec(args: &CommitArgs) -> Result<(), String> {
+pub(super) fn exec(args: &CommitArgs, json: bool) -> Result<(), CommandError> {
     let context = Context::init().map_err(|err| format!("Error initializing context: {}", err))?;
     match &args.cmd {
-        CommitCommands::New { message } => new(&context, message.clone()),
-        CommitCommands::List { branch } => list(&context, branch.clone()),
-        CommitCommands::Show { spec } => show(&context, spec.clone()),
-        CommitCommands::Amend { message } => amend(&context, message.clone()),
+        CommitCommands::New {
+            message,
+            reuse_message,
+            reedit_message,
+            date,
+            force,
+            interactive,
+            dry_run,
+            sign,
+        } => {
+            let message = resolve_message(
+                &context,
+                message.as_deref(),
+                reuse_message.as_deref(),
+                reedit_message.as_deref(),
+            )?;
+            if *dry_run {
+                plan_new(&context, &message, *force)
+            } else {
+                new(&context, message, *date, *force, *interactive, *sign)
+            }
+        }
+        CommitCommands::List(args) => log(&context, args, json),
+        CommitCommands::Show {
+            format: Some(_), ..
+        } if json => Err(CommandError::new(
+            ExitCode::Invalid,
+            "A formatted commit can not be printed as JSON".to_string(),
+        )),
+        CommitCommands::Show {
+            spec,
+            format: Some(template),
+            relative,
+            ..
+        } => show_formatted(&context, spec.as_deref(), template, *relative),
+        CommitCommands::Show {
+            spec,
+            raw,
+            relative,
+            ..
+        } => show(&context, spec.clone(), *raw, *relative, json),
+        CommitCommands::Verify { spec } => verify(&context, spec),
+        CommitCommands::Diff { from, to, paths } => diff(&context, from, to, paths, json),
+        CommitCommands::Amend {
+            message,
+            author,
+            force,
+            interactive,
+        } => amend(
+            &context,
+            message.clone(),
+            author.clone(),
+            *force,
+            *interactive,
+        ),
+        CommitCommands::Move {
+            spec,
+            target,
+            force,
+        } => move_commit(&context, spec, *target, *force),
+        CommitCommands::Undo { hard, force } => undo(&context, *hard, *force),
+        CommitCommands::Rebuild { abort: true, .. } => abort_rebuild(&context),
+        CommitCommands::Rebuild { interactive, .. } => continue_rebuild(&context, *interactive),
     }
 }
 
-fn new(context: &Context, message: String) -> Result<(), String> {
-    match Commit::new(context, message) {
-        Ok(commit) => {
-            println!("Created new commit: {} - {}", commit.id.seq, commit.message);
+pub(super) fn exec_log(args: &LogArgs, json: bool) -> Result<(), CommandError> {
+    let context = Context::init().map_err(|err| format!("Error initializing context: {}", err))?;
+    log(&context, args, json)
+}
+
+fn log(context: &Context, args: &LogArgs, json: bool) -> Result<(), CommandError> {
+    if json && (args.graph || args.format.is_some()) {
+        Err(CommandError::new(
+            ExitCode::Invalid,
+            "A graph or a formatted list of commits can not be printed as JSON".to_string(),
+        ))
+    } else if json {
+        list_json(context, args)
+    } else if args.graph {
+        graph_list(context, args.branch.as_deref(), args.relative)
+    } else if let Some(template) = &args.format {
+        list_formatted(context, args, template)
+    } else {
+        list(context, args)
+    }
+}
+
+/// Gets the message of a new commit, either given as it is or taken from an existing commit.
+fn resolve_message(
+    context: &Context,
+    message: Option<&str>,
+    reuse: Option<&str>,
+    reedit: Option<&str>,
+) -> Result<String, CommandError> {
+    let Some(spec) = reuse.or(reedit) else {
+        return Ok(message.unwrap_or_default().to_string());
+    };
+    let message = Commit::get_by_spec(context, spec)
+        .and_then(|commit| commit.get_message(context))
+        .map_err(|e| {
+            failure(
+                &e,
+                format!("Failed to get message of commit '{}': {:?}", spec, e),
+            )
+        })?;
+    match reedit {
+        Some(_) => Ok(editor::edit_message(context, &message)?),
+        None => Ok(message),
+    }
+}
+
+fn new(
+    context: &Context,
+    message: String,
+    date: Option<u64>,
+    force: bool,
+    interactive: bool,
+    sign: bool,
+) -> Result<(), CommandError> {
+    match Commit::new(context, message, date, force, sign) {
+        Ok(outcome) => {
+            let commit = outcome.commit;
+            println!(
+                "Created new commit: {} - {}",
+                commit.id.seq,
+                commit.subject()
+            );
+            print_rebuilt(outcome.rebuilt);
             Ok(())
         }
-        Err(e) => Err(format!("Failed to create new commit: {:?}", e)),
+        Err(CommitError::BranchProtected(name)) => Err(protected_error(&name)),
+        Err(CommitError::Conflict(seq, paths)) => paused(context, seq, &paths, interactive),
+        Err(CommitError::RebuildInProgress(seq)) => Err(rebuild_in_progress_error(seq)),
+        Err(CommitError::OutOfSpace(e)) => {
+            Err(out_of_space_error("create new commit", &e, "commit again"))
+        }
+        Err(CommitError::PermissionDenied(e)) => {
+            Err(permission_error("create new commit", &e, "commit again"))
+        }
+        Err(e) => Err(failure(&e, format!("Failed to create new commit: {:?}", e))),
     }
 }
 
-fn list(context: &Context, branch: Option<String>) -> Result<(), String> {
-    let commits = match branch {
-        Some(branch_name) => Commit::list_by_branch(context, &branch_name).map_err(|e| {
-            format!(
-                "Failed to list commits for branch '{}': {:?}",
-                branch_name, e
+fn plan_new(context: &Context, message: &str, force: bool) -> Result<(), CommandError> {
+    match Commit::plan_new(context, force) {
+        Ok(plan) => {
+            let subject = message.lines().next().unwrap_or_default().trim_end();
+            println!("Would create new commit: {} - {}", plan.seq, subject);
+            for change in &plan.changes {
+                tree::print_change(&change.action, &change.change_type, &change.path);
+            }
+            if plan.rebuilt > 0 {
+                println!(
+                    "Would rebuild {} commit{} above it",
+                    plan.rebuilt,
+                    if plan.rebuilt == 1 { "" } else { "s" }
+                );
+            }
+            Ok(())
+        }
+        Err(CommitError::BranchProtected(name)) => Err(protected_error(&name)),
+        Err(CommitError::RebuildInProgress(seq)) => Err(rebuild_in_progress_error(seq)),
+        Err(e) => Err(failure(&e, format!("Failed to plan new commit: {:?}", e))),
+    }
+}
+
+/// Lists the commits selected by the arguments, newest first: the commits of a branch, or with
+/// `--follow` its whole lineage, kept if they match the time and path filters.
+fn select_commits(context: &Context, args: &LogArgs) -> Result<Vec<Commit>, CommandError> {
+    let commits = match &args.branch {
+        _ if args.follow => lineage(context, args.branch.as_deref())?,
+        Some(branch_name) => Commit::list_by_branch(context, branch_name).map_err(|e| {
+            failure(
+                &e,
+                format!(
+                    "Failed to list commits for branch '{}': {:?}",
+                    branch_name, e
+                ),
             )
         })?,
-        None => Commit::list(context).map_err(|e| format!("Failed to list commits: {:?}", e))?,
+        None => Commit::list(context)
+            .map_err(|e| failure(&e, format!("Failed to list commits: {:?}", e)))?,
     };
+    let commits = filter_by_time(commits, args.since, args.until);
+    filter_by_paths(context, commits, &args.paths)
+}
 
+/// Lists the commits of a branch followed by the commits of its parent branches from the ones
+/// they were forked at. Centinel commits of forked branches are copies of the commits they were
+/// forked at, so they are left out and every commit is followed by its predecessor.
+fn lineage(context: &Context, branch: Option<&str>) -> Result<Vec<Commit>, CommandError> {
+    let segments = Commit::list_history(context, branch)
+        .map_err(|e| failure(&e, format!("Failed to list commits: {:?}", e)))?;
+    let mut commits = Vec::new();
+    for segment in segments {
+        let foundational = segment.branch.is_foundational();
+        commits.extend(
+            segment
+                .commits
+                .into_iter()
+                .filter(|commit| foundational || commit.id.seq != 0),
+        );
+    }
+    Ok(commits)
+}
+
+fn list(context: &Context, args: &LogArgs) -> Result<(), CommandError> {
+    let commits = select_commits(context, args)?;
+
+    let mut names = HashMap::new();
     for commit in commits {
+        let author = if args.author {
+            format!("{}\t", commit.author)
+        } else {
+            String::new()
+        };
+        // the history of several branches is told apart by names and hashes
+        let id = if args.follow {
+            format!(
+                "{}:{}\t{}",
+                branch_name(context, &mut names, commit.id.branch)?,
+                commit.id.seq,
+                &commit.hash.to_hex_string()[..SHORT_HASH_LEN]
+            )
+        } else {
+            format!("{}:{}", commit.id.branch, commit.id.seq)
+        };
         println!(
-            "{}:{}\tv{}\t{}",
-            commit.id.branch, commit.id.seq, commit.ver, commit.message
+            "{}\tv{}\t{}\t{}{}",
+            id,
+            commit.ver,
+            time::format(commit.created_at, args.relative),
+            author,
+            commit.subject()
         );
     }
     Ok(())
 }
 
-fn show(context: &Context, spec: Option<String>) -> Result<(), String> {
+/// Prints the listed commits as a JSON array of their stored records, like `commit show --raw`.
+fn list_json(context: &Context, args: &LogArgs) -> Result<(), CommandError> {
+    let commits = select_commits(context, args)?;
+
+    let items: Vec<String> = commits
+        .iter()
+        .map(|commit| json::object(&raw_fields(commit)))
+        .collect();
+    println!("{}", json::array(&items));
+    Ok(())
+}
+
+fn list_formatted(
+    context: &Context,
+    args: &LogArgs,
+    template: &Template,
+) -> Result<(), CommandError> {
+    let commits = select_commits(context, args)?;
+
+    let mut names = HashMap::new();
+    for commit in commits {
+        let branch = branch_name(context, &mut names, commit.id.branch)?;
+        let extra = Extra {
+            branch: &branch,
+            relative: args.relative,
+        };
+        println!("{}", template.render(&commit, &extra));
+    }
+    Ok(())
+}
+
+/// Keeps the commits changing any of the paths typed by the user, or all of them without paths.
+/// Keeps the commits created within the given period. Every commit is checked, as clocks may be
+/// skewed and a commit may be dated before the one below it. Commits of unknown time are dated at
+/// the epoch.
+fn filter_by_time(commits: Vec<Commit>, since: Option<u64>, until: Option<u64>) -> Vec<Commit> {
+    commits
+        .into_iter()
+        .filter(|commit| since.is_none_or(|since| commit.created_at >= since))
+        .filter(|commit| until.is_none_or(|until| commit.created_at <= until))
+        .collect()
+}
+
+fn filter_by_paths(
+    context: &Context,
+    commits: Vec<Commit>,
+    paths: &[PathBuf],
+) -> Result<Vec<Commit>, CommandError> {
+    if paths.is_empty() {
+        return Ok(commits);
+    }
+    let paths = paths
+        .iter()
+        .map(|path| context.resolve_path(path))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| failure(&e, format!("Failed to resolve path: {}", e)))?;
+    Commit::filter_by_paths(context, commits, &paths)
+        .map_err(|e| failure(&e, format!("Failed to filter commits by path: {:?}", e)))
+}
+
+/// Looks up the name of a branch by its id, remembering names that were already looked up.
+fn branch_name(
+    context: &Context,
+    names: &mut HashMap<u64, String>,
+    id: u64,
+) -> Result<String, CommandError> {
+    if let Some(name) = names.get(&id) {
+        return Ok(name.clone());
+    }
+    let branch = Branch::get(context, id)
+        .map_err(|e| failure(&e, format!("Failed to get branch: {:?}", e)))?;
+    names.insert(id, branch.name.clone());
+    Ok(branch.name)
+}
+
+fn graph_list(context: &Context, branch: Option<&str>, relative: bool) -> Result<(), CommandError> {
+    let segments = Commit::list_history(context, branch)
+        .map_err(|e| failure(&e, format!("Failed to list commits: {:?}", e)))?;
+
+    // Segments go from the requested branch down to the foundational one, so the requested branch is
+    // drawn with the largest indent and every fork moves one column to the left.
+    let depth = segments.len() - 1;
+    for (i, segment) in segments.iter().enumerate() {
+        let indent = "  ".repeat(depth - i);
+        for commit in &segment.commits {
+            // Centinel commits of forked branches are copies of the parent's commit they were forked at
+            let marker = if commit.id.seq == 0 && !segment.branch.is_foundational() {
+                "o"
+            } else {
+                "*"
+            };
+            println!(
+                "{}{} {}:{}\tv{}\t{}\t{}",
+                indent,
+                marker,
+                segment.branch.name,
+                commit.id.seq,
+                commit.ver,
+                time::format(commit.created_at, relative),
+                commit.subject()
+            );
+        }
+        if !segment.branch.is_foundational() {
+            println!(
+                "{} /\t{} forked from {}:{}",
+                "  ".repeat(depth - i - 1),
+                segment.branch.name,
+                segments[i + 1].branch.name,
+                segment.branch.parentseq
+            );
+        }
+    }
+    Ok(())
+}
+
+fn show(
+    context: &Context,
+    spec: Option<String>,
+    raw: bool,
+    relative: bool,
+    json: bool,
+) -> Result<(), CommandError> {
     let result = match spec {
         Some(commit_spec) => Commit::get_by_spec(context, &commit_spec),
         None => Commit::get_current(context),
     };
 
     match result {
+        Ok(commit) if raw => show_raw(context, &commit),
+        Ok(commit) if json => {
+            println!("{}", json::object(&raw_fields(&commit)));
+            Ok(())
+        }
         Ok(commit) => {
+            let body = commit
+                .get_body(context)
+                .map_err(|e| failure(&e, format!("Failed to show commit: {:?}", e)))?;
             println!(
-                "Branch: {}\nSequence: {}\nHash: {}\nTree Hash: {}\nVersion: {}\nMessage: {}\n",
+                "Branch: {}\nSequence: {}\nHash: {}\nTree Hash: {}\nVersion: {}\nAuthor: {}\nDate: {}\nMessage: {}",
                 commit.id.branch,
                 commit.id.seq,
                 commit.hash,
                 commit.treehash,
                 commit.ver,
-                commit.message,
+                commit.author,
+                time::format(commit.created_at, relative),
+                commit.subject(),
+            );
+            // The body is indented to keep it apart from the fields above
+            if !body.is_empty() {
+                println!();
+                for line in body.lines() {
+                    println!("    {}", line);
+                }
+            }
+            println!();
+            Ok(())
+        }
+        Err(e) => Err(failure(&e, format!("Failed to show commit: {:?}", e))),
+    }
+}
+
+fn verify(context: &Context, spec: &str) -> Result<(), CommandError> {
+    let commit = Commit::get_by_spec(context, spec)
+        .map_err(|e| failure(&e, format!("Failed to get commit '{}': {:?}", spec, e)))?;
+    let verification = commit
+        .verify(context)
+        .map_err(|e| failure(&e, format!("Failed to verify commit: {:?}", e)))?;
+    let id = tree::describe_commit_id(context, commit.id);
+    match verification {
+        Verification::Valid(public_key) => {
+            println!(
+                "Good signature of commit {} by key {}",
+                id,
+                signing::to_hex(&public_key)
+            );
+            Ok(())
+        }
+        Verification::Unsigned => Err(format!("Commit {} is not signed", id).into()),
+        Verification::BadSignature => Err(format!("Bad signature of commit {}", id).into()),
+    }
+}
+
+fn show_formatted(
+    context: &Context,
+    spec: Option<&str>,
+    template: &Template,
+    relative: bool,
+) -> Result<(), CommandError> {
+    let commit = match spec {
+        Some(commit_spec) => Commit::get_by_spec(context, commit_spec),
+        None => Commit::get_current(context),
+    }
+    .map_err(|e| failure(&e, format!("Failed to show commit: {:?}", e)))?;
+
+    let branch = branch_name(context, &mut HashMap::new(), commit.id.branch)?;
+    let extra = Extra {
+        branch: &branch,
+        relative,
+    };
+    println!("{}", template.render(&commit, &extra));
+    Ok(())
+}
+
+/// Prints all stored versions of the commit, marking the one visible in the branch.
+fn show_raw(context: &Context, commit: &Commit) -> Result<(), CommandError> {
+    let versions = Commit::get_versions(context, commit.id)
+        .map_err(|e| failure(&e, format!("Failed to get commit versions: {:?}", e)))?;
+
+    let versions: Vec<String> = versions
+        .iter()
+        .map(|version| {
+            let mut fields = raw_fields(version);
+            fields.push(("visible", (version.ver == commit.ver).to_string()));
+            json::object(&fields)
+        })
+        .collect();
+
+    let raw = json::object(&[
+        ("commit", json::object(&raw_fields(commit))),
+        ("versions", json::array(&versions)),
+    ]);
+    println!("{}", json::pretty(&raw));
+    Ok(())
+}
+
+/// Renders fields of a stored commit record.
+/// Ids and hashes are rendered as strings, they do not fit into the double precision of JSON numbers.
+fn raw_fields(commit: &Commit) -> Vec<(&'static str, String)> {
+    vec![
+        (
+            "id",
+            json::object(&[
+                ("branch", json::string(&commit.id.branch.to_string())),
+                ("seq", commit.id.seq.to_string()),
+            ]),
+        ),
+        ("ver", commit.ver.to_string()),
+        ("hash", json::string(&commit.hash.to_hex_string())),
+        ("treehash", json::string(&commit.treehash.to_hex_string())),
+        ("message", json::string(&commit.message)),
+        (
+            "message_hash",
+            json::string(&commit.message_hash.to_hex_string()),
+        ),
+        ("author", json::string(&commit.author)),
+        ("created_at", commit.created_at.to_string()),
+        (
+            "signature",
+            match &commit.signature {
+                Some(signature) => json::object(&[
+                    (
+                        "public_key",
+                        json::string(&signing::to_hex(&signature.public_key)),
+                    ),
+                    (
+                        "signature",
+                        json::string(&signing::to_hex(&signature.signature)),
+                    ),
+                ]),
+                None => "null".to_string(),
+            },
+        ),
+    ]
+}
+
+fn diff(
+    context: &Context,
+    from: &str,
+    to: &str,
+    paths: &[PathBuf],
+    json: bool,
+) -> Result<(), CommandError> {
+    let from_commit = Commit::get_by_spec(context, from)
+        .map_err(|e| failure(&e, format!("Failed to get commit '{}': {:?}", from, e)))?;
+    let to_commit = Commit::get_by_spec(context, to)
+        .map_err(|e| failure(&e, format!("Failed to get commit '{}': {:?}", to, e)))?;
+
+    let paths = paths
+        .iter()
+        .map(|path| context.resolve_path(path))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| failure(&e, format!("Failed to resolve path: {}", e)))?;
+
+    let changes = Tree::get_diff(context, from_commit.treehash, to_commit.treehash, &paths)
+        .map_err(|e| failure(&e, format!("Failed to diff: {:?}", e)))?;
+
+    if json {
+        return json::print(&changes);
+    }
+    if changes.is_empty() {
+        println!("No differences");
+    } else {
+        println!("Differences:");
+        for change in changes {
+            tree::print_change(&change.action, &change.change_type, &change.path);
+        }
+    }
+    Ok(())
+}
+
+fn amend(
+    context: &Context,
+    message: Option<String>,
+    author: Option<String>,
+    force: bool,
+    interactive: bool,
+) -> Result<(), CommandError> {
+    match Commit::amend(context, message, author, force) {
+        Ok(outcome) => {
+            let commit = outcome.commit;
+            println!("Amended commit: {} - {}", commit.id.seq, commit.subject());
+            print_rebuilt(outcome.rebuilt);
+            Ok(())
+        }
+        Err(CommitError::BranchProtected(name)) => Err(protected_error(&name)),
+        Err(CommitError::Conflict(seq, paths)) => paused(context, seq, &paths, interactive),
+        Err(CommitError::RebuildInProgress(seq)) => Err(rebuild_in_progress_error(seq)),
+        Err(CommitError::OutOfSpace(e)) => {
+            Err(out_of_space_error("amend commit", &e, "amend again"))
+        }
+        Err(CommitError::PermissionDenied(e)) => {
+            Err(permission_error("amend commit", &e, "amend again"))
+        }
+        Err(e) => Err(failure(&e, format!("Failed to amend commit: {:?}", e))),
+    }
+}
+
+fn move_commit(
+    context: &Context,
+    spec: &str,
+    target: u64,
+    force: bool,
+) -> Result<(), CommandError> {
+    match Commit::move_to(context, spec, target, force) {
+        Ok(outcome) => {
+            let commit = outcome.commit;
+            println!("Moved commit to: {} - {}", commit.id.seq, commit.subject());
+            println!(
+                "Rebuilt {} other commit{}",
+                outcome.rebuilt,
+                if outcome.rebuilt == 1 { "" } else { "s" }
             );
             Ok(())
         }
-        Err(e) => Err(format!("Failed to show commit: {:?}", e)),
+        Err(CommitError::BranchProtected(name)) => Err(protected_error(&name)),
+        Err(CommitError::RebuildInProgress(seq)) => Err(rebuild_in_progress_error(seq)),
+        Err(CommitError::MoveConflict(seq, paths)) => {
+            let mut message = format!(
+                "Changes of commit {} can not be reapplied in the new order, the branch was left unchanged:",
+                seq
+            );
+            for path in paths {
+                message.push_str(&format!("\n  {}", path.display()));
+            }
+            Err(message.into())
+        }
+        Err(e) => Err(failure(&e, format!("Failed to move commit: {:?}", e))),
     }
 }
 
-fn amend(context: &Context, message: Option<String>) -> Result<(), String> {
-    match Commit::amend(context, message) {
+fn undo(context: &Context, hard: bool, force: bool) -> Result<(), CommandError> {
+    match Commit::undo(context, hard, force) {
         Ok(commit) => {
-            println!("Amended commit: {} - {}", commit.id.seq, commit.message);
+            println!("Undid commit: {} - {}", commit.id.seq, commit.subject());
+            if hard {
+                println!("Checked out commit: {}", commit.id.seq - 1);
+            } else {
+                println!("Its changes are kept in the checkout as local changes");
+            }
+            Ok(())
+        }
+        Err(CommitError::BranchProtected(name)) => Err(protected_error(&name)),
+        Err(CommitError::RebuildInProgress(seq)) => Err(rebuild_in_progress_error(seq)),
+        Err(e) => Err(failure(&e, format!("Failed to undo commit: {:?}", e))),
+    }
+}
+
+pub(super) fn exec_reset(args: &ResetArgs) -> Result<(), CommandError> {
+    let context = Context::init().map_err(|err| format!("Error initializing context: {}", err))?;
+    match Commit::reset(&context, &args.spec, args.hard, args.force) {
+        Ok(outcome) => {
+            let commit = outcome.commit;
+            println!(
+                "Moved branch head to commit: {} - {}",
+                commit.id.seq,
+                commit.subject()
+            );
+            if outcome.dropped > 0 {
+                println!(
+                    "Dropped {} commit{} above it",
+                    outcome.dropped,
+                    if outcome.dropped == 1 { "" } else { "s" }
+                );
+            }
+            if outcome.restored > 0 {
+                println!(
+                    "Restored {} dropped commit{} up to it",
+                    outcome.restored,
+                    if outcome.restored == 1 { "" } else { "s" }
+                );
+            }
+            if args.hard {
+                println!("Checked out commit: {}", commit.id.seq);
+            } else {
+                println!("The checkout is left as it is, its differences are local changes");
+            }
             Ok(())
         }
-        Err(e) => Err(format!("Failed to amend commit: {:?}", e)),
+        Err(CommitError::BranchProtected(name)) => Err(protected_error(&name)),
+        Err(CommitError::RebuildInProgress(seq)) => Err(rebuild_in_progress_error(seq)),
+        Err(e) => Err(failure(&e, format!("Failed to reset branch: {:?}", e))),
+    }
+}
+
+pub(super) fn exec_revert(args: &RevertArgs) -> Result<(), CommandError> {
+    let context = Context::init().map_err(|err| format!("Error initializing context: {}", err))?;
+    match Commit::revert(&context, &args.spec, args.force) {
+        Ok(outcome) => {
+            let commit = outcome.commit;
+            println!(
+                "Created new commit: {} - {}",
+                commit.id.seq,
+                commit.subject()
+            );
+            print_rebuilt(outcome.rebuilt);
+            Ok(())
+        }
+        Err(CommitError::BranchProtected(name)) => Err(protected_error(&name)),
+        Err(CommitError::RebuildInProgress(seq)) => Err(rebuild_in_progress_error(seq)),
+        Err(CommitError::RevertConflict(seq, paths)) => {
+            let mut message = format!(
+                "Changes of commit {} were changed again since, nothing was reverted:",
+                seq
+            );
+            for path in paths {
+                message.push_str(&format!("\n  {}", path.display()));
+            }
+            Err(message.into())
+        }
+        Err(e) => Err(failure(&e, format!("Failed to revert commit: {:?}", e))),
+    }
+}
+
+fn continue_rebuild(context: &Context, interactive: bool) -> Result<(), CommandError> {
+    match Commit::continue_rebuild(context) {
+        Ok(outcome) => {
+            let commit = outcome.commit;
+            println!(
+                "Rebuild completed, checked out commit: {} - {}",
+                commit.id.seq,
+                commit.subject()
+            );
+            print_rebuilt(outcome.rebuilt);
+            Ok(())
+        }
+        Err(CommitError::Conflict(seq, paths)) => paused(context, seq, &paths, interactive),
+        Err(CommitError::Unresolved(paths)) if interactive => {
+            let left = resolve::resolve(context, "Resolving conflicts:", &paths)?;
+            if resolve::any_conflicted(context, &left)? {
+                return Err(unresolved_error(&left));
+            }
+            continue_rebuild(context, interactive)
+        }
+        Err(CommitError::Unresolved(paths)) => Err(unresolved_error(&paths)),
+        Err(e) => Err(failure(&e, format!("Failed to continue rebuild: {:?}", e))),
+    }
+}
+
+/// Describes conflict markers left in files of a paused rebuild.
+fn unresolved_error(paths: &[PathBuf]) -> CommandError {
+    let mut message = "Conflict markers are still present in:".to_string();
+    for path in paths {
+        message.push_str(&format!("\n  {}", path.display()));
+    }
+    message.into()
+}
+
+fn abort_rebuild(context: &Context) -> Result<(), CommandError> {
+    let commit = Commit::abort_rebuild(context)
+        .map_err(|e| failure(&e, format!("Failed to abort rebuild: {:?}", e)))?;
+    println!(
+        "Rebuild aborted, changes of commit {} - {} are kept as local changes",
+        commit.id.seq,
+        commit.subject()
+    );
+    Ok(())
+}
+
+/// Handles a rebuild paused on a conflict, prompting for the conflicting files when interactive
+/// and continuing the rebuild once all of them are resolved.
+fn paused(
+    context: &Context,
+    seq: u64,
+    paths: &[PathBuf],
+    interactive: bool,
+) -> Result<(), CommandError> {
+    if !interactive {
+        return Err(conflict_error(seq, paths));
+    }
+    let title = format!("Rebuild paused on conflicts in commit {}:", seq);
+    let left = resolve::resolve(context, &title, paths)?;
+    if resolve::any_conflicted(context, &left)? {
+        return Err(conflict_error(seq, &left));
+    }
+    continue_rebuild(context, interactive)
+}
+
+/// Describes a rebuild paused on a conflict.
+fn conflict_error(seq: u64, paths: &[PathBuf]) -> CommandError {
+    let mut message = format!("Rebuild paused on conflicts in commit {}:", seq);
+    for path in paths {
+        message.push_str(&format!("\n  {}", path.display()));
+    }
+    message
+        .push_str("\nResolve them and run `vx commit rebuild --continue`, or `--abort` to give up");
+    message.into()
+}
+
+fn rebuild_in_progress_error(seq: u64) -> CommandError {
+    format!(
+        "Rebuild of commit {} is in progress, finish it with `vx commit rebuild --continue` or `--abort` first",
+        seq
+    )
+    .into()
+}
+
+fn protected_error(branch: &str) -> CommandError {
+    format!(
+        "Branch '{}' is protected from direct commits, pass --force to commit anyway",
+        branch
+    )
+    .into()
+}
+
+pub(super) fn out_of_space_error(action: &str, e: &std::io::Error, retry: &str) -> CommandError {
+    format!(
+        "Failed to {}, the disk is full: {}\nFree some space and {}",
+        action, e, retry
+    )
+    .into()
+}
+
+pub(super) fn permission_error(action: &str, e: &std::io::Error, retry: &str) -> CommandError {
+    format!(
+        "Failed to {}, permission denied: {}\nMake the checkout and its .vx workspace writable and {}",
+        action, e, retry
+    )
+    .into()
+}
+
+fn print_rebuilt(rebuilt: u64) {
+    if rebuilt > 0 {
+        println!(
+            "Rebuilt {} commit{} above it",
+            rebuilt,
+            if rebuilt == 1 { "" } else { "s" }
+        );
     }
 }
@@ -0,0 +1,59 @@
+// Editing of commit messages and conflicting files in the editor of the user.
+
+use std::path::Path;
+use std::process::Command;
+use vx::context::Context;
+
+/// Name of the file in the workspace holding the message being edited.
+const EDIT_FILE_NAME: &str = "COMMIT_EDITMSG";
+
+/// Editor used if none is configured in the environment.
+const DEFAULT_EDITOR: &str = "vi";
+
+/// Opens a message in the editor named by `VX_EDITOR`, `VISUAL` or `EDITOR`, falling back to vi,
+/// and returns the edited message. Lines starting with '#' are dropped, an empty message is
+/// refused so that quitting the editor without saving anything aborts the command.
+pub(super) fn edit_message(context: &Context, message: &str) -> Result<String, String> {
+    let path = context.workspace_path.join(EDIT_FILE_NAME);
+    let content = format!(
+        "{}\n\n# Edit the message, lines starting with '#' are ignored, an empty message aborts.\n",
+        message.trim_end()
+    );
+    std::fs::write(&path, content).map_err(|e| format!("Failed to write message: {:?}", e))?;
+    edit_file(&path)?;
+
+    let edited =
+        std::fs::read_to_string(&path).map_err(|e| format!("Failed to read message: {:?}", e))?;
+    let _ = std::fs::remove_file(&path);
+    let lines: Vec<&str> = edited
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .map(str::trim_end)
+        .collect();
+    let message = lines.join("\n").trim().to_string();
+    if message.is_empty() {
+        return Err("Aborting commit due to an empty message".to_string());
+    }
+    Ok(message)
+}
+
+/// Opens a file in the editor of the user and waits for it to exit.
+pub(super) fn edit_file(path: &Path) -> Result<(), String> {
+    let editor = ["VX_EDITOR", "VISUAL", "EDITOR"]
+        .iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .find(|editor| !editor.trim().is_empty())
+        .unwrap_or_else(|| DEFAULT_EDITOR.to_string());
+    // the editor may come with arguments, e.g. "code --wait"
+    let mut words = editor.split_whitespace();
+    let program = words.next().unwrap_or(DEFAULT_EDITOR);
+    let status = Command::new(program)
+        .args(words)
+        .arg(path)
+        .status()
+        .map_err(|e| format!("Failed to start editor '{}': {:?}", editor, e))?;
+    if !status.success() {
+        return Err(format!("Editor '{}' exited with {}", editor, status));
+    }
+    Ok(())
+}
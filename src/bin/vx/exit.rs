@@ -0,0 +1,168 @@
+// Exit codes of the process telling scripts wrapping vx what kind of error stopped a command,
+// e.g. to tell a commit with nothing to commit from a real failure.
+
+use crate::json;
+
+use vx::core::regex::RegexError;
+use vx::core::workspace::WorkspaceError;
+use vx::storage::blob::BlobError;
+use vx::storage::branch::BranchError;
+use vx::storage::commit::CommitError;
+use vx::storage::repo::RepoError;
+use vx::storage::stash::StashError;
+use vx::storage::tag::TagError;
+use vx::storage::tree::TreeError;
+
+/// Exit code of a failed command by the category of its error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum ExitCode {
+    /// Any error not falling in the categories below.
+    Failure = 1,
+    /// What the command refers to does not exist, e.g. a commit, a branch or a tag.
+    NotFound = 2,
+    /// There is nothing to do, e.g. no changes to commit or to stash.
+    NothingToDo = 3,
+    /// The arguments are invalid, e.g. a name a branch can not have or an unknown option.
+    Invalid = 4,
+    /// The checkout has uncommitted changes, told from a failure by `tree status --summary`.
+    Changes = 5,
+}
+
+/// Error a command stops with: the message printed and the exit code of the process.
+#[derive(Debug)]
+pub(super) struct CommandError {
+    pub(super) code: ExitCode,
+    pub(super) message: String,
+}
+
+impl CommandError {
+    pub(super) fn new(code: ExitCode, message: String) -> Self {
+        CommandError { code, message }
+    }
+
+    /// Renders the error as a JSON object with its exit code and message.
+    pub(super) fn to_json(&self) -> String {
+        json::object(&[
+            ("code", (self.code as i32).to_string()),
+            ("message", json::string(&self.message)),
+        ])
+    }
+}
+
+/// Errors without a category are failures.
+impl From<String> for CommandError {
+    fn from(message: String) -> Self {
+        CommandError::new(ExitCode::Failure, message)
+    }
+}
+
+impl From<&str> for CommandError {
+    fn from(message: &str) -> Self {
+        CommandError::new(ExitCode::Failure, message.to_string())
+    }
+}
+
+/// Makes the error of a command stopped by an error, whose category decides the exit code.
+pub(super) fn failure(err: &impl Category, message: String) -> CommandError {
+    CommandError::new(err.exit_code(), message)
+}
+
+/// Category of an error by its exit code.
+pub(super) trait Category {
+    fn exit_code(&self) -> ExitCode {
+        ExitCode::Failure
+    }
+}
+
+impl Category for std::io::Error {}
+
+/// A pattern is given as an argument, so an invalid one is a usage error.
+impl Category for RegexError {
+    fn exit_code(&self) -> ExitCode {
+        ExitCode::Invalid
+    }
+}
+
+impl Category for CommitError {
+    fn exit_code(&self) -> ExitCode {
+        match self {
+            CommitError::NotFound | CommitError::UnknownSpec(_) => ExitCode::NotFound,
+            CommitError::NoChanges => ExitCode::NothingToDo,
+            CommitError::Branch(err) => err.exit_code(),
+            _ => ExitCode::Failure,
+        }
+    }
+}
+
+impl Category for BranchError {
+    fn exit_code(&self) -> ExitCode {
+        match self {
+            BranchError::NotFound => ExitCode::NotFound,
+            BranchError::InvalidName(_) => ExitCode::Invalid,
+            BranchError::Commit(err) => err.exit_code(),
+            _ => ExitCode::Failure,
+        }
+    }
+}
+
+impl Category for TagError {
+    fn exit_code(&self) -> ExitCode {
+        match self {
+            TagError::NotFound => ExitCode::NotFound,
+            TagError::InvalidName(_) => ExitCode::Invalid,
+            _ => ExitCode::Failure,
+        }
+    }
+}
+
+impl Category for StashError {
+    fn exit_code(&self) -> ExitCode {
+        match self {
+            StashError::NotFound => ExitCode::NotFound,
+            StashError::NoChanges => ExitCode::NothingToDo,
+            _ => ExitCode::Failure,
+        }
+    }
+}
+
+impl Category for RepoError {
+    fn exit_code(&self) -> ExitCode {
+        match self {
+            RepoError::NotFound => ExitCode::NotFound,
+            RepoError::InvalidName(_) => ExitCode::Invalid,
+            _ => ExitCode::Failure,
+        }
+    }
+}
+
+impl Category for TreeError {
+    fn exit_code(&self) -> ExitCode {
+        match self {
+            TreeError::TreeNotFound | TreeError::PathNotFound(_) => ExitCode::NotFound,
+            TreeError::Resolve(err) => err.exit_code(),
+            _ => ExitCode::Failure,
+        }
+    }
+}
+
+impl Category for BlobError {
+    fn exit_code(&self) -> ExitCode {
+        match self {
+            BlobError::BlobNotFound(_) | BlobError::FileNotFound(_) => ExitCode::NotFound,
+            _ => ExitCode::Failure,
+        }
+    }
+}
+
+/// Errors of a workspace take the category of the error they wrap.
+impl Category for WorkspaceError {
+    fn exit_code(&self) -> ExitCode {
+        match self {
+            WorkspaceError::Repo(err) => err.exit_code(),
+            WorkspaceError::Branch(err) => err.exit_code(),
+            WorkspaceError::Commit(err) => err.exit_code(),
+            WorkspaceError::Tree(err) => err.exit_code(),
+            _ => ExitCode::Failure,
+        }
+    }
+}
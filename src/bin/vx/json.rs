@@ -0,0 +1,481 @@
+// Minimal JSON rendering helpers for machine readable output.
+
+use crate::exit::CommandError;
+use serde::ser::{self, Serialize};
+use std::fmt;
+
+/// Renders a string as a quoted JSON string.
+pub(super) fn string(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Renders an object from already rendered values, keeping the order of fields.
+pub(super) fn object(fields: &[(&str, String)]) -> String {
+    let fields: Vec<String> = fields
+        .iter()
+        .map(|(name, value)| format!("{}:{}", string(name), value))
+        .collect();
+    format!("{{{}}}", fields.join(","))
+}
+
+/// Renders an array from already rendered values.
+pub(super) fn array(values: &[String]) -> String {
+    format!("[{}]", values.join(","))
+}
+
+/// Indents rendered JSON with two spaces per level for reading by humans.
+pub(super) fn pretty(json: &str) -> String {
+    let mut out = String::with_capacity(json.len() * 2);
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut chars = json.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if in_string {
+            out.push(c);
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => (),
+            }
+            continue;
+        }
+        match c {
+            '"' => {
+                in_string = true;
+                out.push(c);
+            }
+            '{' | '[' => {
+                out.push(c);
+                // empty containers stay on a single line
+                if matches!(chars.peek(), Some('}') | Some(']')) {
+                    out.push(chars.next().unwrap_or_default());
+                } else {
+                    depth += 1;
+                    push_newline(&mut out, depth);
+                }
+            }
+            '}' | ']' => {
+                depth -= 1;
+                push_newline(&mut out, depth);
+                out.push(c);
+            }
+            ',' => {
+                out.push(c);
+                push_newline(&mut out, depth);
+            }
+            ':' => out.push_str(": "),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn push_newline(out: &mut String, depth: usize) {
+    out.push('\n');
+    for _ in 0..depth {
+        out.push_str("  ");
+    }
+}
+
+/// Renders a value as compact JSON through its `Serialize` implementation, with the same escaping
+/// as `string`. Enum variants are tagged externally like in serde's JSON format, and keys of maps
+/// have to render as strings or numbers.
+pub(super) fn to_string<T: Serialize + ?Sized>(value: &T) -> Result<String, Error> {
+    let mut serializer = Serializer { out: String::new() };
+    value.serialize(&mut serializer)?;
+    Ok(serializer.out)
+}
+
+/// Prints a value as a single line of JSON, see `to_string`.
+pub(super) fn print<T: Serialize + ?Sized>(value: &T) -> Result<(), CommandError> {
+    let rendered = to_string(value).map_err(|e| format!("Failed to render JSON: {}", e))?;
+    println!("{}", rendered);
+    Ok(())
+}
+
+/// Error of rendering a value that JSON can not express.
+#[derive(Debug)]
+pub(super) struct Error(String);
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl ser::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error(msg.to_string())
+    }
+}
+
+struct Serializer {
+    out: String,
+}
+
+/// Elements of a sequence, map or struct being rendered, closed by `end` once all are written.
+struct Compound<'a> {
+    ser: &'a mut Serializer,
+    first: bool,
+    end: &'static str,
+}
+
+impl Compound<'_> {
+    fn separate(&mut self) {
+        if !self.first {
+            self.ser.out.push(',');
+        }
+        self.first = false;
+    }
+
+    fn field<T: Serialize + ?Sized>(&mut self, key: &str, value: &T) -> Result<(), Error> {
+        self.separate();
+        self.ser.out.push_str(&string(key));
+        self.ser.out.push(':');
+        value.serialize(&mut *self.ser)
+    }
+
+    fn element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.separate();
+        value.serialize(&mut *self.ser)
+    }
+
+    fn close(self) -> Result<(), Error> {
+        self.ser.out.push_str(self.end);
+        Ok(())
+    }
+}
+
+impl Serializer {
+    fn open(&mut self, start: &str, end: &'static str) -> Compound<'_> {
+        self.out.push_str(start);
+        Compound {
+            ser: self,
+            first: true,
+            end,
+        }
+    }
+
+    /// Opens the object holding an enum variant by its name, see `to_string`.
+    fn open_variant(&mut self, variant: &str, start: char, end: &'static str) -> Compound<'_> {
+        let start = format!("{{{}:{}", string(variant), start);
+        self.open(&start, end)
+    }
+
+    fn number<T: fmt::Display>(&mut self, value: T) -> Result<(), Error> {
+        self.out.push_str(&value.to_string());
+        Ok(())
+    }
+}
+
+impl<'a> ser::Serializer for &'a mut Serializer {
+    type Ok = ();
+    type Error = Error;
+    type SerializeSeq = Compound<'a>;
+    type SerializeTuple = Compound<'a>;
+    type SerializeTupleStruct = Compound<'a>;
+    type SerializeTupleVariant = Compound<'a>;
+    type SerializeMap = Compound<'a>;
+    type SerializeStruct = Compound<'a>;
+    type SerializeStructVariant = Compound<'a>;
+
+    fn serialize_bool(self, value: bool) -> Result<(), Error> {
+        self.number(value)
+    }
+
+    fn serialize_i8(self, value: i8) -> Result<(), Error> {
+        self.number(value)
+    }
+
+    fn serialize_i16(self, value: i16) -> Result<(), Error> {
+        self.number(value)
+    }
+
+    fn serialize_i32(self, value: i32) -> Result<(), Error> {
+        self.number(value)
+    }
+
+    fn serialize_i64(self, value: i64) -> Result<(), Error> {
+        self.number(value)
+    }
+
+    fn serialize_i128(self, value: i128) -> Result<(), Error> {
+        self.number(value)
+    }
+
+    fn serialize_u8(self, value: u8) -> Result<(), Error> {
+        self.number(value)
+    }
+
+    fn serialize_u16(self, value: u16) -> Result<(), Error> {
+        self.number(value)
+    }
+
+    fn serialize_u32(self, value: u32) -> Result<(), Error> {
+        self.number(value)
+    }
+
+    fn serialize_u64(self, value: u64) -> Result<(), Error> {
+        self.number(value)
+    }
+
+    fn serialize_u128(self, value: u128) -> Result<(), Error> {
+        self.number(value)
+    }
+
+    fn serialize_f32(self, value: f32) -> Result<(), Error> {
+        self.serialize_f64(value.into())
+    }
+
+    fn serialize_f64(self, value: f64) -> Result<(), Error> {
+        // JSON has no representation of infinities and NaN
+        if value.is_finite() {
+            self.number(value)
+        } else {
+            self.serialize_unit()
+        }
+    }
+
+    fn serialize_char(self, value: char) -> Result<(), Error> {
+        self.serialize_str(value.encode_utf8(&mut [0; 4]))
+    }
+
+    fn serialize_str(self, value: &str) -> Result<(), Error> {
+        self.out.push_str(&string(value));
+        Ok(())
+    }
+
+    fn serialize_bytes(self, value: &[u8]) -> Result<(), Error> {
+        let mut seq = self.open("[", "]");
+        for byte in value {
+            seq.element(byte)?;
+        }
+        seq.close()
+    }
+
+    fn serialize_none(self) -> Result<(), Error> {
+        self.serialize_unit()
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<(), Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<(), Error> {
+        self.out.push_str("null");
+        Ok(())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<(), Error> {
+        self.serialize_unit()
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+    ) -> Result<(), Error> {
+        self.serialize_str(variant)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        let mut object = self.open("{", "}");
+        object.field(variant, value)?;
+        object.close()
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Compound<'a>, Error> {
+        Ok(self.open("[", "]"))
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Compound<'a>, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<Compound<'a>, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Compound<'a>, Error> {
+        Ok(self.open_variant(variant, '[', "]}"))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Compound<'a>, Error> {
+        Ok(self.open("{", "}"))
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<Compound<'a>, Error> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Compound<'a>, Error> {
+        Ok(self.open_variant(variant, '{', "}}"))
+    }
+}
+
+impl ser::SerializeSeq for Compound<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        self.close()
+    }
+}
+
+impl ser::SerializeTuple for Compound<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        self.close()
+    }
+}
+
+impl ser::SerializeTupleStruct for Compound<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        self.close()
+    }
+}
+
+impl ser::SerializeTupleVariant for Compound<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        self.close()
+    }
+}
+
+impl ser::SerializeMap for Compound<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Error> {
+        self.separate();
+        // keys are strings in JSON, numbers are quoted and anything else is refused
+        let key = to_string(key)?;
+        match key.chars().next() {
+            Some('"') => self.ser.out.push_str(&key),
+            Some('-' | '0'..='9') => self.ser.out.push_str(&string(&key)),
+            _ => {
+                return Err(Error(format!(
+                    "JSON object keys have to be strings, not {}",
+                    key
+                )))
+            }
+        }
+        self.ser.out.push(':');
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        value.serialize(&mut *self.ser)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        self.close()
+    }
+}
+
+impl ser::SerializeStruct for Compound<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.field(key, value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        self.close()
+    }
+}
+
+impl ser::SerializeStructVariant for Compound<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.field(key, value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        self.close()
+    }
+}
@@ -1,8 +1,21 @@
 use clap::{Parser, Subcommand};
+use exit::{CommandError, ExitCode};
 
+mod bisect;
+mod blob;
 mod branch;
+mod color;
 mod commit;
+mod editor;
+mod exit;
+mod json;
+mod output;
 mod repo;
+mod resolve;
+mod stash;
+mod tag;
+mod template;
+mod time;
 mod tree;
 
 #[derive(Parser, Debug)]
@@ -12,36 +25,133 @@ mod tree;
     about = "Simple and powerful version control system"
 )]
 struct Cli {
+    /// Print the output of listing commands and errors as JSON, for tools driving vx; refused
+    /// by commands that print no JSON
+    #[arg(long, global = true)]
+    json: bool,
     #[command(subcommand)]
     cmd: Commands,
 }
 
 #[derive(Debug, Subcommand)]
 enum Commands {
+    Bisect(bisect::BisectArgs),
+    Blob(blob::BlobArgs),
     Branch(branch::BranchArgs),
     Commit(commit::CommitArgs),
+    /// Alias for `tree diff`
+    Diff(tree::DiffArgs),
+    /// Check the repository and its environment for common problems
+    Doctor,
+    /// Check that commits, trees and blobs referenced by the repository are stored and intact
+    Fsck,
+    /// Remove trees and blobs unreachable from any branch or tag
+    Gc {
+        /// Report what would be removed and how many bytes it would free, without deleting
+        /// anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Alias for `commit list`
+    Log(commit::LogArgs),
     Repo(repo::RepoArgs),
+    /// Move the head of the current branch to one of its commits
+    Reset(commit::ResetArgs),
+    /// Create a commit undoing the changes of another one
+    Revert(commit::RevertArgs),
+    /// Alias for `tree status`
+    Status(tree::StatusArgs),
+    Stash(stash::StashArgs),
+    Tag(tag::TagArgs),
     Tree(tree::TreeArgs),
 }
 
 fn main() {
-    let cli = Cli::parse();
+    let cli = Cli::try_parse().unwrap_or_else(|err| {
+        // help and version are printed as errors too, invalid arguments are usage errors
+        if !err.use_stderr() {
+            let _ = err.print();
+            std::process::exit(0);
+        }
+        if json_requested() {
+            let rendered = err.to_string();
+            let message = rendered
+                .strip_prefix("error: ")
+                .unwrap_or(&rendered)
+                .trim_end();
+            eprintln!(
+                "{}",
+                CommandError::new(ExitCode::Invalid, message.to_string()).to_json()
+            );
+        } else {
+            let _ = err.print();
+        }
+        std::process::exit(ExitCode::Invalid as i32);
+    });
 
     // It is possible to pass args' ownership to the command's executor and potentially reuse
     // data without cloning, but this will put some constraints on further evolution of the CLI code,
     // so use cloning for now which should not be a big deal and may be also optimized away.
 
     let result = match &cli.cmd {
-        Commands::Branch(args) => branch::exec(args),
-        Commands::Commit(args) => commit::exec(args),
-        Commands::Repo(args) => repo::exec(args),
-        Commands::Tree(args) => tree::exec(args),
+        _ if cli.json && !prints_json(&cli.cmd) => Err(CommandError::new(
+            ExitCode::Invalid,
+            "The command does not print JSON, run it without --json".to_string(),
+        )),
+        Commands::Bisect(args) => bisect::exec(args),
+        Commands::Blob(args) => blob::exec(args),
+        Commands::Branch(args) => branch::exec(args, cli.json),
+        Commands::Commit(args) => commit::exec(args, cli.json),
+        Commands::Diff(args) => tree::exec_diff(args, cli.json),
+        Commands::Doctor => repo::exec_doctor(),
+        Commands::Fsck => repo::exec_fsck(),
+        Commands::Gc { dry_run } => blob::exec_gc(*dry_run),
+        Commands::Log(args) => commit::exec_log(args, cli.json),
+        Commands::Repo(args) => repo::exec(args, cli.json),
+        Commands::Reset(args) => commit::exec_reset(args),
+        Commands::Revert(args) => commit::exec_revert(args),
+        Commands::Status(args) => tree::exec_status(args, cli.json),
+        Commands::Stash(args) => stash::exec(args, cli.json),
+        Commands::Tag(args) => tag::exec(args, cli.json),
+        Commands::Tree(args) => tree::exec(args, cli.json),
     };
 
     if let Err(err) = result {
-        eprintln!("{}", err);
+        if cli.json {
+            eprintln!("{}", err.to_json());
+        } else {
+            eprintln!("{}", err.message);
+        }
+        std::process::exit(err.code as i32);
+    }
+}
+
+/// Whether `--json` is among the arguments, for usage errors reported before they are parsed.
+/// Arguments after `--` are values, not flags.
+fn json_requested() -> bool {
+    std::env::args_os()
+        .skip(1)
+        .take_while(|arg| arg != "--")
+        .any(|arg| arg == "--json")
+}
 
-        // TODO: binary protocol, i.e. exit code based on error type.
-        std::process::exit(1);
+/// Whether a command prints JSON with `--json`, the others refuse the flag rather than print text
+/// a tool would fail to parse.
+fn prints_json(cmd: &Commands) -> bool {
+    match cmd {
+        Commands::Branch(args) => branch::prints_json(args),
+        Commands::Commit(args) => commit::prints_json(args),
+        Commands::Repo(args) => repo::prints_json(args),
+        Commands::Stash(args) => stash::prints_json(args),
+        Commands::Tag(args) => tag::prints_json(args),
+        Commands::Tree(args) => tree::prints_json(args),
+        Commands::Diff(_) | Commands::Log(_) | Commands::Status(_) => true,
+        Commands::Bisect(_)
+        | Commands::Blob(_)
+        | Commands::Doctor
+        | Commands::Fsck
+        | Commands::Gc { .. }
+        | Commands::Reset(_)
+        | Commands::Revert(_) => false,
     }
 }
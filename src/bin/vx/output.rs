@@ -0,0 +1,30 @@
+// Destinations of the commands writing content, a file or standard output.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// Path standing for standard output.
+pub(super) const STDOUT: &str = "-";
+
+/// Whether the path stands for standard output.
+pub(super) fn is_stdout(path: &Path) -> bool {
+    path.as_os_str() == STDOUT
+}
+
+/// Opens the destination of a command, standard output for `-`, otherwise a file created or
+/// truncated at the path.
+pub(super) fn open(path: &Path) -> Result<Box<dyn Write>, String> {
+    if is_stdout(path) {
+        return Ok(Box::new(BufWriter::new(std::io::stdout().lock())));
+    }
+    let file = File::create(path)
+        .map_err(|e| format!("Failed to create '{}': {:?}", path.display(), e))?;
+    Ok(Box::new(BufWriter::new(file)))
+}
+
+/// Flushes a destination opened with `open`, reporting errors the drop would swallow.
+pub(super) fn finish(mut out: Box<dyn Write>, path: &Path) -> Result<(), String> {
+    out.flush()
+        .map_err(|e| format!("Failed to write '{}': {:?}", path.display(), e))
+}
@@ -1,6 +1,16 @@
+use crate::exit::{failure, CommandError};
+use crate::json;
+use crate::output;
 use clap::{Args, Subcommand};
 use std::collections::HashMap;
-use vx::core::repo::Repo;
+use std::io::{BufRead, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use vx::context::Context;
+use vx::core::digest::DigestExt;
+use vx::core::repo::{Repo, RepoStatus};
+use vx::core::signing;
+use vx::core::tree::TRUST_INDEX_SIZE_KEY;
+use vx::storage::repo::RepoError;
 
 #[derive(Args, Debug)]
 pub(super) struct RepoArgs {
@@ -10,21 +20,459 @@ pub(super) struct RepoArgs {
 
 #[derive(Debug, Subcommand)]
 enum RepoCommands {
-    New { name: String },
+    New {
+        name: String,
+        // Metadata entries in the form key=value, e.g. ignore.defaults=
+        #[arg(long = "meta", value_parser = parse_meta)]
+        meta: Vec<(String, String)>,
+        /// Create the repository even inside the checkout of another repository
+        #[arg(long)]
+        allow_nested: bool,
+    },
+    Size,
+    Status,
+    /// Describe the repository: its name, storage layout, schema versions and settings, as JSON
+    /// with `--json` for tools adapting to the repository
+    Info,
+    /// Delete the repository the command is run in
+    Destroy {
+        /// Also remove the checkout with all files in it, not only the .vx workspace
+        #[arg(long)]
+        checkout: bool,
+        /// Do not ask for confirmation, required when not run interactively
+        #[arg(long)]
+        yes: bool,
+        /// Destroy the repository even if the checkout has uncommitted changes
+        #[arg(long)]
+        force: bool,
+    },
+    /// Check that the hashes of all commits visible in branches match their content
+    VerifyChain,
+    /// Register the ed25519 key `commit new --sign` signs commits with, read from a file holding
+    /// its 32-byte secret, raw or as 64 hexadecimal digits; only the path to the file is stored
+    SetKey {
+        path: PathBuf,
+    },
+    /// Compact the databases of the workspace, reporting their sizes before and after
+    Pack,
+    /// Write the history of a branch as a git fast-import stream
+    ExportGit {
+        /// Branch to export, the current one if not provided
+        #[arg(long)]
+        branch: Option<String>,
+        /// File to write the stream to, `-` for standard output
+        #[arg(long, short, value_name = "PATH", default_value = output::STDOUT)]
+        output: PathBuf,
+    },
 }
 
-pub(super) fn exec(args: &RepoArgs) -> Result<(), String> {
+/// Whether the command prints JSON with `--json`.
+pub(super) fn prints_json(args: &RepoArgs) -> bool {
+    matches!(args.cmd, RepoCommands::Info)
+}
+
+pub(super) fn exec(args: &RepoArgs, json: bool) -> Result<(), CommandError> {
     match &args.cmd {
-        RepoCommands::New { name } => new(name),
+        RepoCommands::New {
+            name,
+            meta,
+            allow_nested,
+        } => new(name, meta, *allow_nested),
+        RepoCommands::Size => size(),
+        RepoCommands::Status => status(),
+        RepoCommands::Info => info(json),
+        RepoCommands::Destroy {
+            checkout,
+            yes,
+            force,
+        } => destroy(*checkout, *yes, *force),
+        RepoCommands::VerifyChain => verify_chain(),
+        RepoCommands::SetKey { path } => set_key(path),
+        RepoCommands::Pack => pack(),
+        RepoCommands::ExportGit { branch, output } => export_git(branch.as_deref(), output),
+    }
+}
+
+pub(super) fn exec_doctor() -> Result<(), CommandError> {
+    // interrupted workflows are reported rather than recovered
+    let context = Context::discover().ok_or_else(|| {
+        "Repository: not found in the current directory or any parent directory".to_string()
+    })?;
+    println!("Repository: {}", context.checkout_path.display());
+    println!("Workspace: {}", context.workspace_path.display());
+
+    let report = Repo::doctor(&context);
+    for (name, error) in &report.unavailable_dbs {
+        println!("Database {} can not be opened: {}", name, error);
+    }
+    for path in &report.read_only {
+        println!("Folder {} is not writable", path.display());
+    }
+    if let Some(status) = &report.status {
+        println!("{}", describe_position(status));
+        if status.is_rebuild() {
+            println!(
+                "Rebuild of commit {} is in progress with {} conflict(s)",
+                status.rebuild_seq,
+                status.conflicts.len()
+            );
+        }
+    }
+    if let Some(id) = report.pending_commit {
+        println!(
+            "Commit {}:{} was interrupted, it is completed by the next vx command",
+            id.branch, id.seq
+        );
+    }
+    if let Some(id) = report.pending_checkout {
+        println!(
+            "Checkout of commit {}:{} was interrupted, run `vx tree checkout --continue`",
+            id.branch, id.seq
+        );
+    }
+    if let Some(chain) = &report.chain {
+        println!(
+            "Commits: {} checked, {} mismatched",
+            chain.checked,
+            chain.mismatched.len()
+        );
+    }
+    for error in &report.errors {
+        println!("{}", error);
+    }
+
+    if report.is_ok() {
+        println!("No problems found");
+        Ok(())
+    } else {
+        Err("Problems found".into())
     }
 }
 
-fn new(name: &str) -> Result<(), String> {
-    match Repo::new(name.to_string(), HashMap::new()) {
+pub(super) fn exec_fsck() -> Result<(), CommandError> {
+    let context = Context::init().map_err(|err| format!("Error initializing context: {}", err))?;
+    let report = Repo::fsck(&context)
+        .map_err(|e| failure(&e, format!("Failed to check repository: {:?}", e)))?;
+
+    for (branch, id) in &report.missing_commits {
+        println!("  missing commit {}:{}", branch, id.seq);
+    }
+    if let Some((id, headseq)) = report.current_ahead {
+        println!(
+            "  current commit {}:{} is above the branch head {}",
+            id.branch, id.seq, headseq
+        );
+    }
+    for (tag, id) in &report.missing_tag_commits {
+        println!("  missing commit {}:{} of tag {}", id.branch, id.seq, tag);
+    }
+    for tag in &report.missing_tag_trees {
+        println!("  missing tree of tag {}", tag);
+    }
+    for hash in &report.missing_trees {
+        println!("  missing tree {}", hash.to_hex_string());
+    }
+    for hash in &report.missing_blobs {
+        println!("  missing blob {}", hash.to_hex_string());
+    }
+    for hash in &report.blobs.mismatched {
+        println!("  mismatched blob {}", hash.to_hex_string());
+    }
+    for hash in &report.blobs.missing {
+        println!("  missing blob file {}", hash.to_hex_string());
+    }
+    for path in &report.blobs.orphans {
+        println!("  orphan file {}", path.display());
+    }
+
+    let checked = format!(
+        "Checked {} commits, {} tags, {} trees and {} blobs",
+        report.checked_commits, report.checked_tags, report.checked_trees, report.blobs.checked
+    );
+    if report.is_ok() {
+        println!("{}, no problems found", checked);
+        Ok(())
+    } else {
+        let problems = report.missing_commits.len()
+            + usize::from(report.current_ahead.is_some())
+            + report.missing_tag_commits.len()
+            + report.missing_tag_trees.len()
+            + report.missing_trees.len()
+            + report.missing_blobs.len()
+            + report.blobs.mismatched.len()
+            + report.blobs.missing.len()
+            + report.blobs.orphans.len();
+        Err(format!("{}, found {} problem(s)", checked, problems).into())
+    }
+}
+
+fn parse_meta(entry: &str) -> Result<(String, String), String> {
+    match entry.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("Expected key=value, got '{}'", entry)),
+    }
+}
+
+fn new(name: &str, meta: &[(String, String)], allow_nested: bool) -> Result<(), CommandError> {
+    let metadata: HashMap<String, String> = meta.iter().cloned().collect();
+    let result = match Context::discover() {
+        Some(outer) if allow_nested => {
+            eprintln!(
+                "Warning: creating a repository inside the checkout of {}, it will be tracked there unless ignored",
+                outer.checkout_path.display()
+            );
+            Repo::new_nested(name.to_string(), metadata)
+        }
+        _ => Repo::new(name.to_string(), metadata),
+    };
+    match result {
         Ok((repo, _)) => {
             println!("Created new repository: {}", repo.name);
+            if let Some(size) = repo.metadata.get(TRUST_INDEX_SIZE_KEY) {
+                eprintln!(
+                    "Warning: files from {} bytes are taken as unchanged while their mtime and size are, a change keeping both goes unnoticed",
+                    size
+                );
+            }
             Ok(())
         }
-        Err(e) => Err(format!("Failed to create new repository: {:?}", e)),
+        Err(RepoError::NestedRepo(outer)) => Err(format!(
+            "Refusing to create a repository inside the checkout of {}, pass --allow-nested to create it anyway",
+            outer.display()
+        ).into()),
+        Err(e) => Err(failure(&e, format!("Failed to create new repository: {:?}", e))),
+    }
+}
+
+fn size() -> Result<(), CommandError> {
+    let context = Context::init().map_err(|err| format!("Error initializing context: {}", err))?;
+    match Repo::size(&context) {
+        Ok(size) => {
+            println!("Repository Size:");
+            println!("  Logical Size:  {}", size.logical_size);
+            println!("  Stored Size:   {}", size.stored_size);
+            println!("  Disk Size:     {}", size.disk_size);
+            println!("  Dedup Ratio:   {:.2}", size.dedup_ratio());
+            println!("  Blobs:         {}", size.blob_count);
+            println!("  Trees:         {}", size.tree_count);
+            println!("  Commits:       {}", size.commit_count);
+            println!("  Branches:      {}", size.branch_count);
+            Ok(())
+        }
+        Err(e) => Err(failure(
+            &e,
+            format!("Failed to get repository size: {:?}", e),
+        )),
+    }
+}
+
+fn pack() -> Result<(), CommandError> {
+    let context = Context::init().map_err(|err| format!("Error initializing context: {}", err))?;
+    let packed = Repo::pack(&context)
+        .map_err(|e| failure(&e, format!("Failed to pack repository: {:?}", e)))?;
+
+    println!("Packed databases:");
+    for db in &packed {
+        println!("  {}: {} -> {}", db.name, db.size_before, db.size_after);
+    }
+    let before: u64 = packed.iter().map(|db| db.size_before).sum();
+    let after: u64 = packed.iter().map(|db| db.size_after).sum();
+    println!("Total: {} -> {}", before, after);
+    Ok(())
+}
+
+fn export_git(branch: Option<&str>, path: &Path) -> Result<(), CommandError> {
+    let context = Context::init().map_err(|err| format!("Error initializing context: {}", err))?;
+    let mut out = output::open(path)?;
+    let export = Repo::export_git(&context, branch, &mut out)
+        .map_err(|e| failure(&e, format!("Failed to export repository: {:?}", e)))?;
+    output::finish(out, path)?;
+
+    // stdout may carry the stream, so the summary goes to stderr
+    eprintln!(
+        "Exported {} commit(s) and {} blob(s) to branch {}",
+        export.commits, export.blobs, export.branch
+    );
+    Ok(())
+}
+
+fn status() -> Result<(), CommandError> {
+    let context = Context::init().map_err(|err| format!("Error initializing context: {}", err))?;
+    let status = Repo::status(&context)
+        .map_err(|e| failure(&e, format!("Failed to get repository status: {:?}", e)))?;
+    println!("{}", describe_position(&status));
+    if status.is_rebuild() {
+        println!(
+            "Rebuild of commit {} is in progress with {} conflict(s), resolve them and run `vx commit rebuild --continue`",
+            status.rebuild_seq,
+            status.conflicts.len()
+        );
+        for path in &status.conflicts {
+            println!("  {}", path.display());
+        }
+    }
+    Ok(())
+}
+
+fn info(as_json: bool) -> Result<(), CommandError> {
+    let context = Context::init().map_err(|err| format!("Error initializing context: {}", err))?;
+    let info = Repo::info(&context)
+        .map_err(|e| failure(&e, format!("Failed to describe repository: {:?}", e)))?;
+
+    if as_json {
+        let versions: Vec<(&str, String)> = info
+            .schema_versions
+            .iter()
+            .map(|(record, version)| (*record, version.to_string()))
+            .collect();
+        let settings: Vec<(&str, String)> = info
+            .settings
+            .iter()
+            .map(|(key, value)| match value {
+                Some(value) => (*key, json::string(value)),
+                None => (*key, "null".to_string()),
+            })
+            .collect();
+        let metadata: Vec<(&str, String)> = info
+            .metadata
+            .iter()
+            .map(|(key, value)| (key.as_str(), json::string(value)))
+            .collect();
+        let rendered = json::object(&[
+            ("name", json::string(&info.name)),
+            ("defaultBranch", json::string(&info.default_branch)),
+            ("hashAlgorithm", json::string(info.hash_algorithm)),
+            ("compression", json::string(info.compression)),
+            ("blobShardDigits", info.blob_shard_digits.to_string()),
+            ("schemaVersions", json::object(&versions)),
+            ("settings", json::object(&settings)),
+            ("metadata", json::object(&metadata)),
+        ]);
+        println!("{}", json::pretty(&rendered));
+        return Ok(());
+    }
+
+    let versions: Vec<String> = info
+        .schema_versions
+        .iter()
+        .map(|(record, version)| format!("{} {}", record, version))
+        .collect();
+    println!("Name:            {}", info.name);
+    println!("Default branch:  {}", info.default_branch);
+    println!("Hash algorithm:  {}", info.hash_algorithm);
+    println!("Compression:     {}", info.compression);
+    println!("Blob sharding:   {} hex digit(s)", info.blob_shard_digits);
+    println!("Schema versions: {}", versions.join(", "));
+    println!("Settings:");
+    for (key, value) in &info.settings {
+        println!("  {} = {}", key, value.as_deref().unwrap_or("(default)"));
+    }
+    if !info.metadata.is_empty() {
+        println!("Metadata:");
+        for (key, value) in &info.metadata {
+            println!("  {} = {}", key, value);
+        }
+    }
+    Ok(())
+}
+
+fn destroy(with_checkout: bool, yes: bool, force: bool) -> Result<(), CommandError> {
+    let context = Context::init().map_err(|err| format!("Error initializing context: {}", err))?;
+    let target = if with_checkout {
+        &context.checkout_path
+    } else {
+        &context.workspace_path
+    };
+
+    if !yes
+        && !confirm(&format!(
+            "Remove {}? This can not be undone",
+            target.display()
+        ))?
+    {
+        return Err("Repository was not destroyed".into());
+    }
+
+    Repo::destroy(&context, with_checkout, force).map_err(|e| match e {
+        RepoError::UncommittedChanges(count) => format!(
+            "Checkout has {} uncommitted change(s), commit them or pass --force to lose them",
+            count
+        ),
+        e => format!("Failed to destroy repository: {:?}", e),
+    })?;
+    println!("Destroyed repository, removed {}", target.display());
+    Ok(())
+}
+
+fn verify_chain() -> Result<(), CommandError> {
+    let context = Context::init().map_err(|err| format!("Error initializing context: {}", err))?;
+    let report = Repo::verify_chain(&context)
+        .map_err(|e| failure(&e, format!("Failed to verify commits: {:?}", e)))?;
+
+    for mismatch in &report.mismatched {
+        println!(
+            "  mismatched commit {}:{} v{}, stored hash {}, computed {}",
+            mismatch.branch,
+            mismatch.commit.id.seq,
+            mismatch.commit.ver,
+            mismatch.commit.hash.to_hex_string(),
+            mismatch.computed.to_hex_string()
+        );
+    }
+
+    if report.is_ok() {
+        println!("Verified {} commits, no problems found", report.checked);
+        Ok(())
+    } else {
+        Err(format!(
+            "Verified {} commits: {} mismatched",
+            report.checked,
+            report.mismatched.len()
+        )
+        .into())
+    }
+}
+
+fn set_key(path: &Path) -> Result<(), CommandError> {
+    let context = Context::init().map_err(|err| format!("Error initializing context: {}", err))?;
+    let public_key = Repo::set_signing_key(&context, path)
+        .map_err(|e| failure(&e, format!("Failed to set signing key: {:?}", e)))?;
+    println!(
+        "Signing key set, public key {}",
+        signing::to_hex(&public_key)
+    );
+    Ok(())
+}
+
+/// Asks the user to confirm an action on the terminal.
+fn confirm(question: &str) -> Result<bool, String> {
+    if !std::io::stdin().is_terminal() {
+        return Err("Confirmation required, pass --yes when not running interactively".to_string());
+    }
+    print!("{} [y/N] ", question);
+    std::io::stdout()
+        .flush()
+        .map_err(|e| format!("Failed to write prompt: {:?}", e))?;
+
+    let mut answer = String::new();
+    std::io::stdin()
+        .lock()
+        .read_line(&mut answer)
+        .map_err(|e| format!("Failed to read answer: {:?}", e))?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+/// Describes the position of the current commit in its branch.
+pub(super) fn describe_position(status: &RepoStatus) -> String {
+    if status.is_detached() {
+        format!(
+            "Detached at {}:{} (head is {}), new commits are inserted here and the commits above rebuilt",
+            status.branch.name, status.commit_id.seq, status.branch.headseq
+        )
+    } else {
+        format!(
+            "On branch {} at head {}",
+            status.branch.name, status.commit_id.seq
+        )
     }
 }
@@ -0,0 +1,113 @@
+// Interactive resolution of the conflicts left by merges and rebuilds.
+
+use crate::editor;
+use std::io::{BufRead, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use vx::context::Context;
+use vx::core::merge::{self, Side, CONFLICT_START};
+
+/// Asks, for every file still holding conflict markers, whether to take the local side, take the
+/// incoming side, edit the file or skip it, and returns the given files that were not resolved.
+/// Prompts are only shown when standard input is a terminal, otherwise every file keeps its
+/// markers to be resolved by hand, as without `--interactive`.
+pub(super) fn resolve(
+    context: &Context,
+    title: &str,
+    paths: &[PathBuf],
+) -> Result<Vec<PathBuf>, String> {
+    if !any_conflicted(context, paths)? {
+        return Ok(paths.to_vec());
+    }
+    if !std::io::stdin().is_terminal() {
+        println!("Standard input is not a terminal, conflict markers are left in the files");
+        return Ok(paths.to_vec());
+    }
+
+    println!("{}", title);
+    let mut left = Vec::new();
+    let mut input = std::io::stdin().lock();
+    let mut closed = false;
+    for path in paths {
+        // once the input is closed, the remaining files keep their markers
+        if closed || read_conflicted(context, path)?.is_none() {
+            left.push(path.clone());
+            continue;
+        }
+        match prompt(context, &mut input, path)? {
+            Some(true) => {}
+            Some(false) => left.push(path.clone()),
+            None => {
+                closed = true;
+                left.push(path.clone());
+            }
+        }
+    }
+    Ok(left)
+}
+
+/// Whether any of the files still holds conflict markers.
+pub(super) fn any_conflicted(context: &Context, paths: &[PathBuf]) -> Result<bool, String> {
+    for path in paths {
+        if read_conflicted(context, path)?.is_some() {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Prompts for a single file until its conflicts are resolved or it is skipped.
+/// Returns whether the file was resolved, or nothing once the input is closed.
+fn prompt(
+    context: &Context,
+    input: &mut impl BufRead,
+    path: &Path,
+) -> Result<Option<bool>, String> {
+    let abs_path = context.checkout_path.join(path);
+    while let Some(content) = read_conflicted(context, path)? {
+        print!(
+            "Conflict in {}: take [m]ine, take [t]heirs, [e]dit or [s]kip? ",
+            path.display()
+        );
+        std::io::stdout()
+            .flush()
+            .map_err(|e| format!("Failed to write prompt: {:?}", e))?;
+
+        let mut answer = String::new();
+        let read = input
+            .read_line(&mut answer)
+            .map_err(|e| format!("Failed to read answer: {:?}", e))?;
+        if read == 0 {
+            println!();
+            return Ok(None);
+        }
+        let side = match answer.trim() {
+            "m" | "mine" => Side::Local,
+            "t" | "theirs" => Side::Incoming,
+            "e" | "edit" => {
+                editor::edit_file(&abs_path)?;
+                continue;
+            }
+            "s" | "skip" => return Ok(Some(false)),
+            _ => {
+                println!("Please answer m, t, e or s");
+                continue;
+            }
+        };
+        std::fs::write(&abs_path, merge::resolve_conflicts(&content, side))
+            .map_err(|e| format!("Failed to write {}: {:?}", path.display(), e))?;
+    }
+    println!("Resolved {}", path.display());
+    Ok(Some(true))
+}
+
+/// Reads a file of the checkout if it still holds conflict markers, a missing or binary file has
+/// none.
+fn read_conflicted(context: &Context, path: &Path) -> Result<Option<String>, String> {
+    match std::fs::read(context.checkout_path.join(path)) {
+        Ok(content) => Ok(String::from_utf8(content)
+            .ok()
+            .filter(|content| content.lines().any(|line| line.starts_with(CONFLICT_START)))),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(format!("Failed to read {}: {:?}", path.display(), e)),
+    }
+}
@@ -0,0 +1,143 @@
+use crate::exit::{failure, CommandError, ExitCode};
+use crate::json;
+use clap::{Args, Subcommand};
+use vx::context::Context;
+use vx::core::digest::DigestExt;
+use vx::core::merge::ConflictKind;
+use vx::core::stash::Stash;
+use vx::storage::stash::StashError;
+
+#[derive(Args, Debug)]
+pub(super) struct StashArgs {
+    #[command(subcommand)]
+    cmd: StashCommands,
+}
+
+#[derive(Debug, Subcommand)]
+enum StashCommands {
+    Push {
+        // Optional description, if not provided the subject of the current commit is used
+        #[arg(default_value = None)]
+        message: Option<String>,
+    },
+    Pop {
+        // Optional stash id, if not provided the latest stash is restored
+        #[arg(default_value = None)]
+        id: Option<u64>,
+    },
+    /// List stashes, as JSON with `--json`
+    List,
+    Drop {
+        id: u64,
+    },
+}
+
+/// Whether the command prints JSON with `--json`.
+pub(super) fn prints_json(args: &StashArgs) -> bool {
+    matches!(args.cmd, StashCommands::List)
+}
+
+pub(super) fn exec(args: &StashArgs, json: bool) -> Result<(), CommandError> {
+    let context = Context::init().map_err(|err| format!("Error initializing context: {}", err))?;
+    match &args.cmd {
+        StashCommands::Push { message } => push(&context, message.clone()),
+        StashCommands::Pop { id } => pop(&context, *id),
+        StashCommands::List => list(&context, json),
+        StashCommands::Drop { id } => drop_stash(&context, *id),
+    }
+}
+
+fn push(context: &Context, message: Option<String>) -> Result<(), CommandError> {
+    match Stash::push(context, message) {
+        Ok(stash) => {
+            println!(
+                "Saved local changes as stash {}: {}",
+                stash.id, stash.message
+            );
+            Ok(())
+        }
+        Err(StashError::NoChanges) => Err(CommandError::new(
+            ExitCode::NothingToDo,
+            "No local changes to stash".to_string(),
+        )),
+        Err(e) => Err(failure(&e, format!("Failed to stash changes: {:?}", e))),
+    }
+}
+
+fn pop(context: &Context, id: Option<u64>) -> Result<(), CommandError> {
+    let result = Stash::pop(context, id)
+        .map_err(|e| failure(&e, format!("Failed to restore stash: {:?}", e)))?;
+
+    println!(
+        "Restored stash {}: {}",
+        result.stash.id, result.stash.message
+    );
+    // conflicts stop the command like a paused rebuild, so scripts do not take it as restored
+    if !result.conflicts.is_empty() {
+        let mut message = "Conflicts:".to_string();
+        for conflict in result.conflicts {
+            let kind_str = match conflict.kind {
+                ConflictKind::Content => "both modified",
+                ConflictKind::ModifiedDeleted => "deleted in stash, kept local",
+                ConflictKind::DeletedModified => "deleted locally, restored from stash",
+                ConflictKind::Binary => "binary, kept local",
+            };
+            message.push_str(&format!("\n  {} {}", kind_str, conflict.path.display()));
+        }
+        message.push_str(&format!(
+            "\nThe stash is kept, drop it with 'vx stash drop {}' once the conflicts are resolved",
+            result.stash.id
+        ));
+        return Err(message.into());
+    }
+    Ok(())
+}
+
+fn list(context: &Context, json: bool) -> Result<(), CommandError> {
+    match Stash::list(context) {
+        Ok(stashes) if json => {
+            // ids and hashes are rendered as strings, they do not fit into the double precision of
+            // JSON numbers
+            let values: Vec<String> = stashes
+                .iter()
+                .map(|stash| {
+                    json::object(&[
+                        ("id", stash.id.to_string()),
+                        ("message", json::string(&stash.message)),
+                        (
+                            "base",
+                            json::object(&[
+                                ("branch", json::string(&stash.base.branch.to_string())),
+                                ("seq", stash.base.seq.to_string()),
+                            ]),
+                        ),
+                        ("basetree", json::string(&stash.basetree.to_hex_string())),
+                        ("treehash", json::string(&stash.treehash.to_hex_string())),
+                    ])
+                })
+                .collect();
+            println!("{}", json::array(&values));
+            Ok(())
+        }
+        Ok(stashes) => {
+            for stash in stashes {
+                println!(
+                    "{}\t{}:{}\t{}",
+                    stash.id, stash.base.branch, stash.base.seq, stash.message
+                );
+            }
+            Ok(())
+        }
+        Err(e) => Err(failure(&e, format!("Failed to list stashes: {:?}", e))),
+    }
+}
+
+fn drop_stash(context: &Context, id: u64) -> Result<(), CommandError> {
+    match Stash::delete(context, id) {
+        Ok(stash) => {
+            println!("Dropped stash {}: {}", stash.id, stash.message);
+            Ok(())
+        }
+        Err(e) => Err(failure(&e, format!("Failed to drop stash {}: {:?}", id, e))),
+    }
+}
@@ -0,0 +1,101 @@
+use crate::exit::{failure, CommandError};
+use crate::json;
+use clap::{Args, Subcommand};
+use vx::context::Context;
+use vx::core::tag::Tag;
+
+#[derive(Args, Debug)]
+pub(super) struct TagArgs {
+    #[command(subcommand)]
+    cmd: TagCommands,
+}
+
+#[derive(Debug, Subcommand)]
+enum TagCommands {
+    Add {
+        name: String,
+        // Optional commit specification, if not provided tag the current commit
+        #[arg(default_value = None)]
+        spec: Option<String>,
+    },
+    /// List tags, as JSON with `--json`
+    List,
+    Delete {
+        name: String,
+    },
+}
+
+/// Whether the command prints JSON with `--json`.
+pub(super) fn prints_json(args: &TagArgs) -> bool {
+    matches!(args.cmd, TagCommands::List)
+}
+
+pub(super) fn exec(args: &TagArgs, json: bool) -> Result<(), CommandError> {
+    let context = Context::init().map_err(|err| format!("Error initializing context: {}", err))?;
+    match &args.cmd {
+        TagCommands::Add { name, spec } => add(&context, name, spec.as_deref()),
+        TagCommands::List => list(&context, json),
+        TagCommands::Delete { name } => delete(&context, name),
+    }
+}
+
+fn add(context: &Context, name: &str, spec: Option<&str>) -> Result<(), CommandError> {
+    match Tag::new(context, name.to_string(), spec) {
+        Ok(tag) => {
+            println!(
+                "Created new tag: {} -> {}:{}",
+                tag.name, tag.commit_id.branch, tag.commit_id.seq
+            );
+            Ok(())
+        }
+        Err(e) => Err(failure(&e, format!("Failed to create new tag: {:?}", e))),
+    }
+}
+
+fn list(context: &Context, json: bool) -> Result<(), CommandError> {
+    match Tag::list(context) {
+        Ok(tags) if json => {
+            // ids are rendered as strings, they do not fit into the double precision of JSON numbers
+            let values: Vec<String> = tags
+                .iter()
+                .map(|tag| {
+                    json::object(&[
+                        ("name", json::string(&tag.name)),
+                        (
+                            "commit",
+                            json::object(&[
+                                ("branch", json::string(&tag.commit_id.branch.to_string())),
+                                ("seq", tag.commit_id.seq.to_string()),
+                            ]),
+                        ),
+                    ])
+                })
+                .collect();
+            println!("{}", json::array(&values));
+            Ok(())
+        }
+        Ok(tags) => {
+            for tag in tags {
+                println!(
+                    "{}\t{}:{}",
+                    tag.name, tag.commit_id.branch, tag.commit_id.seq
+                );
+            }
+            Ok(())
+        }
+        Err(e) => Err(failure(&e, format!("Failed to list tags: {:?}", e))),
+    }
+}
+
+fn delete(context: &Context, name: &str) -> Result<(), CommandError> {
+    match Tag::delete(context, name) {
+        Ok(tag) => {
+            println!("Deleted tag: {}", tag.name);
+            Ok(())
+        }
+        Err(e) => Err(failure(
+            &e,
+            format!("Failed to delete tag '{}': {:?}", name, e),
+        )),
+    }
+}
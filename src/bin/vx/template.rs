@@ -0,0 +1,141 @@
+// Format templates rendering commits for scripts, e.g. `--format "%h %s %m"`.
+
+use crate::time;
+use vx::core::commit::Commit;
+use vx::core::digest::DigestExt;
+
+/// Number of hexadecimal digits of a short hash.
+pub(super) const SHORT_HASH_LEN: usize = 7;
+
+/// A parsed format template, made of literal text and placeholders of commit fields.
+#[derive(Debug, Clone)]
+pub(super) struct Template {
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone)]
+enum Part {
+    Literal(String),
+    Field(Field),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Field {
+    ShortHash,
+    Hash,
+    TreeHash,
+    Branch,
+    Seq,
+    Version,
+    Subject,
+    AuthorName,
+    AuthorEmail,
+    AuthorTime,
+    AuthorDate,
+}
+
+/// Placeholders sorted so that longer ones are matched first.
+const PLACEHOLDERS: &[(&str, Field)] = &[
+    ("an", Field::AuthorName),
+    ("ae", Field::AuthorEmail),
+    ("at", Field::AuthorTime),
+    ("ad", Field::AuthorDate),
+    ("h", Field::ShortHash),
+    ("H", Field::Hash),
+    ("T", Field::TreeHash),
+    ("b", Field::Branch),
+    ("s", Field::Seq),
+    ("v", Field::Version),
+    ("m", Field::Subject),
+];
+
+/// Values of a commit that are not stored in the commit itself.
+pub(super) struct Extra<'a> {
+    /// Name of the branch of the commit.
+    pub(super) branch: &'a str,
+    /// Whether times are shown along with how long ago they were.
+    pub(super) relative: bool,
+}
+
+impl Template {
+    /// Parses a template, to be used as a clap value parser.
+    /// Besides the placeholders of commit fields, `%n` stands for a line break and `%%` for a
+    /// percent sign.
+    pub(super) fn parse(template: &str) -> Result<Self, String> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut rest = template;
+
+        while let Some(pos) = rest.find('%') {
+            literal.push_str(&rest[..pos]);
+            rest = &rest[pos + 1..];
+
+            if let Some(after) = rest.strip_prefix('%') {
+                literal.push('%');
+                rest = after;
+                continue;
+            }
+            if let Some(after) = rest.strip_prefix('n') {
+                literal.push('\n');
+                rest = after;
+                continue;
+            }
+
+            let Some(&(name, field)) = PLACEHOLDERS.iter().find(|(name, _)| rest.starts_with(name))
+            else {
+                return Err(format!(
+                    "Unknown placeholder '%{}', expected one of %h, %H, %T, %b, %s, %v, %m, %an, %ae, %at, %ad, %n, %%",
+                    rest.chars().next().map(String::from).unwrap_or_default()
+                ));
+            };
+            if !literal.is_empty() {
+                parts.push(Part::Literal(std::mem::take(&mut literal)));
+            }
+            parts.push(Part::Field(field));
+            rest = &rest[name.len()..];
+        }
+
+        literal.push_str(rest);
+        if !literal.is_empty() {
+            parts.push(Part::Literal(literal));
+        }
+        Ok(Template { parts })
+    }
+
+    /// Renders the template for a commit.
+    pub(super) fn render(&self, commit: &Commit, extra: &Extra) -> String {
+        let mut out = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Literal(text) => out.push_str(text),
+                Part::Field(field) => out.push_str(&render_field(*field, commit, extra)),
+            }
+        }
+        out
+    }
+}
+
+fn render_field(field: Field, commit: &Commit, extra: &Extra) -> String {
+    match field {
+        Field::ShortHash => commit.hash.to_hex_string()[..SHORT_HASH_LEN].to_string(),
+        Field::Hash => commit.hash.to_hex_string(),
+        Field::TreeHash => commit.treehash.to_hex_string(),
+        Field::Branch => extra.branch.to_string(),
+        Field::Seq => commit.id.seq.to_string(),
+        Field::Version => commit.ver.to_string(),
+        Field::Subject => commit.subject().to_string(),
+        Field::AuthorName => split_author(&commit.author).0.to_string(),
+        Field::AuthorEmail => split_author(&commit.author).1.to_string(),
+        Field::AuthorTime => commit.created_at.to_string(),
+        Field::AuthorDate => time::format(commit.created_at, extra.relative),
+    }
+}
+
+/// Splits an author in "Name <email>" format into the name and the email, the email is empty if
+/// the author does not have one.
+fn split_author(author: &str) -> (&str, &str) {
+    match author.split_once('<') {
+        Some((name, email)) => (name.trim(), email.trim_end().trim_end_matches('>')),
+        None => (author.trim(), ""),
+    }
+}
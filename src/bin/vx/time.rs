@@ -0,0 +1,196 @@
+// Formatting and parsing of commit timestamps for humans.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+use vx::core::commit::Commit;
+
+const MINUTE: u64 = 60;
+const HOUR: u64 = 60 * MINUTE;
+const DAY: u64 = 24 * HOUR;
+const MONTH: u64 = 30 * DAY;
+const YEAR: u64 = 365 * DAY;
+
+/// Formats a commit time as an ISO 8601 UTC timestamp, optionally followed by the time elapsed since.
+pub(super) fn format(created_at: u64, relative: bool) -> String {
+    if created_at == Commit::UNKNOWN_TIME {
+        return "-".to_string();
+    }
+    if relative {
+        format!(
+            "{} ({})",
+            format_iso(created_at),
+            format_relative(created_at)
+        )
+    } else {
+        format_iso(created_at)
+    }
+}
+
+/// Formats seconds since the Unix epoch as an ISO 8601 UTC timestamp, e.g. 2024-03-01T12:30:00Z.
+fn format_iso(secs: u64) -> String {
+    let days = secs / DAY;
+    let time = secs % DAY;
+    let (year, month, day) = civil_from_days(days as i64);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        time / HOUR,
+        time % HOUR / MINUTE,
+        time % MINUTE
+    )
+}
+
+/// Parses a commit time given in seconds since the Unix epoch or as an ISO 8601 date, to be used
+/// as a clap value parser. Dates are either a day, e.g. `2024-03-01`, or a day and a time, e.g.
+/// `2024-03-01T12:30:00+02:00`, taken as UTC unless an offset is given.
+pub(super) fn parse(value: &str) -> Result<u64, String> {
+    if !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit()) {
+        return value
+            .parse()
+            .map_err(|_| format!("Time '{}' is out of range", value));
+    }
+    parse_iso(value).ok_or_else(|| {
+        format!(
+            "Invalid date '{}', expected seconds since the Unix epoch or an ISO 8601 date like 2024-03-01T12:30:00Z",
+            value
+        )
+    })
+}
+
+fn parse_iso(value: &str) -> Option<u64> {
+    let (date, time) = match value.split_once(['T', ' ']) {
+        Some((date, time)) => (date, Some(time)),
+        None => (value, None),
+    };
+
+    let mut fields = date.splitn(3, '-');
+    let year: i64 = number(fields.next()?, 4)?;
+    let month: u64 = number(fields.next()?, 2)?;
+    let day: u64 = number(fields.next()?, 2)?;
+    if !(1..=12).contains(&month) || day < 1 || day > days_in_month(year, month) {
+        return None;
+    }
+
+    let (secs, offset) = match time {
+        Some(time) => parse_time(time)?,
+        None => (0, 0),
+    };
+    let secs = days_from_civil(year, month, day) * DAY as i64 + secs as i64 - offset;
+    u64::try_from(secs).ok()
+}
+
+/// Parses the time of a day with an optional offset from UTC, e.g. `12:30:00Z` or `12:30+02:00`,
+/// into seconds since midnight and the offset in seconds.
+fn parse_time(time: &str) -> Option<(u64, i64)> {
+    let (time, offset) = if let Some(time) = time.strip_suffix('Z') {
+        (time, 0)
+    } else if let Some(pos) = time.rfind(['+', '-']) {
+        let sign = if time[pos..].starts_with('-') { -1 } else { 1 };
+        let zone = time[pos + 1..].replace(':', "");
+        if zone.len() != 4 {
+            return None;
+        }
+        let hours: i64 = number(&zone[..2], 2)?;
+        let minutes: i64 = number(&zone[2..], 2)?;
+        if hours > 23 || minutes > 59 {
+            return None;
+        }
+        (
+            &time[..pos],
+            sign * (hours * HOUR as i64 + minutes * MINUTE as i64),
+        )
+    } else {
+        (time, 0)
+    };
+
+    // fractions of a second are dropped, commit times are stored in whole seconds
+    let time = match time.split_once('.') {
+        Some((time, fraction)) => {
+            if fraction.is_empty() || !fraction.bytes().all(|b| b.is_ascii_digit()) {
+                return None;
+            }
+            time
+        }
+        None => time,
+    };
+    let mut fields = time.split(':');
+    let hours: u64 = number(fields.next()?, 2)?;
+    let minutes: u64 = number(fields.next()?, 2)?;
+    let seconds: u64 = match fields.next() {
+        Some(seconds) => number(seconds, 2)?,
+        None => 0,
+    };
+    if fields.next().is_some() || hours > 23 || minutes > 59 || seconds > 60 {
+        return None;
+    }
+    Some((hours * HOUR + minutes * MINUTE + seconds, offset))
+}
+
+/// Parses a field of a date made of exactly the given number of digits.
+fn number<T: std::str::FromStr>(field: &str, digits: usize) -> Option<T> {
+    if field.len() != digits || !field.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    field.parse().ok()
+}
+
+fn days_in_month(year: i64, month: u64) -> u64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Formats the time elapsed since the given moment, e.g. "3 hours ago".
+fn format_relative(secs: u64) -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default();
+    // clocks of different machines are not in sync
+    if secs > now {
+        return "in the future".to_string();
+    }
+
+    let elapsed = now - secs;
+    let (count, unit) = match elapsed {
+        e if e < MINUTE => return "just now".to_string(),
+        e if e < HOUR => (e / MINUTE, "minute"),
+        e if e < DAY => (e / HOUR, "hour"),
+        e if e < MONTH => (e / DAY, "day"),
+        e if e < YEAR => (e / MONTH, "month"),
+        e => (e / YEAR, "year"),
+    };
+    let plural = if count == 1 { "" } else { "s" };
+    format!("{} {}{} ago", count, unit, plural)
+}
+
+/// Converts a proleptic Gregorian date into days since the Unix epoch, the inverse of
+/// `civil_from_days`.
+fn days_from_civil(year: i64, month: u64, day: u64) -> i64 {
+    let year = year - i64::from(month <= 2);
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400) as u64;
+    let mp = if month > 2 { month - 3 } else { month + 9 };
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe as i64 - 719_468
+}
+
+/// Converts days since the Unix epoch into a proleptic Gregorian date.
+// Howard Hinnant's algorithm, eras are 400 year cycles starting on March 1st.
+fn civil_from_days(days: i64) -> (i64, u64, u64) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097) as u64;
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe as i64 + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
@@ -1,6 +1,22 @@
+use crate::color::{self, ColorMode};
+use crate::commit::{out_of_space_error, permission_error};
+use crate::exit::{failure, CommandError, ExitCode};
+use crate::json;
+use crate::output;
+use crate::resolve;
 use clap::{Args, Subcommand};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
 use vx::context::Context;
-use vx::core::tree::{ChangeAction, ChangeType, Tree};
+use vx::core::branch::Branch;
+use vx::core::commit::{Commit, CommitID};
+use vx::core::digest::Digest;
+use vx::core::merge::ConflictKind;
+use vx::core::regex::Regex;
+use vx::core::rename::{DEFAULT_RENAME_THRESHOLD, MAX_RENAME_CANDIDATES};
+use vx::core::repo::Repo;
+use vx::core::tree::{Change, ChangeAction, ChangeType, CheckoutReport, Tree, Untracked};
+use vx::storage::tree::TreeError;
 
 #[derive(Args, Debug)]
 pub(super) struct TreeArgs {
@@ -8,55 +24,803 @@ pub(super) struct TreeArgs {
     cmd: TreeCommands,
 }
 
+/// Show changes of the checkout against the current commit
+#[derive(Args, Debug)]
+pub(super) struct StatusArgs {
+    /// Do not apply ignore rules, marking files and folders they would hide
+    #[arg(long)]
+    include_ignored: bool,
+    /// How to report added files and folders: "no" hides them, "normal" does not descend into
+    /// added folders, "all" lists everything inside them
+    #[arg(long, value_name = "MODE", default_value = "normal", value_parser = parse_untracked)]
+    untracked: Untracked,
+    /// Do not report text files whose only changes are whitespace at the end of lines
+    #[arg(long, conflicts_with = "include_ignored")]
+    ignore_whitespace: bool,
+    /// Only print the number of changes by action and type, exiting with 5 if there are any
+    #[arg(long, conflicts_with = "include_ignored")]
+    summary: bool,
+    /// Color changes by action: "auto" colors them only when printing to a terminal, which
+    /// `--color` alone overrides like "always", "never" disables colors
+    #[arg(
+        long,
+        value_name = "WHEN",
+        default_value = "auto",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "always",
+        value_parser = color::parse_mode
+    )]
+    color: ColorMode,
+}
+
+/// Show changes between the stored trees of two commits, without reading the checkout
+#[derive(Args, Debug)]
+pub(super) struct DiffArgs {
+    /// The commit to compare from
+    from: String,
+    /// The commit to compare to, if not provided compare to the current commit
+    #[arg(default_value = None)]
+    to: Option<String>,
+    /// List only the paths of changed files
+    #[arg(long, conflicts_with = "stat")]
+    name_only: bool,
+    /// Show sizes of changed files and a summary
+    #[arg(long)]
+    stat: bool,
+    /// Pair deleted and added files sharing at least PERCENT of their content as renames,
+    /// 50 unless given, 100 only pairs files of the same content
+    #[arg(
+        long,
+        value_name = "PERCENT",
+        num_args = 0..=1,
+        require_equals = true,
+        value_parser = parse_threshold,
+        conflicts_with_all = ["name_only", "stat"]
+    )]
+    find_renames: Option<Option<u8>>,
+}
+
 #[derive(Debug, Subcommand)]
 enum TreeCommands {
-    Status,
+    Status(StatusArgs),
     Checkout {
         /// The commit ID to checkout
-        commit_id: String,
+        #[arg(required_unless_present_any = ["resume", "orphan"])]
+        commit_id: Option<String>,
+        /// Keep local changes, merging them with the changes of the checked out commit
+        #[arg(long)]
+        merge: bool,
+        /// Ask for every conflicting file whether to take mine, take theirs, edit or skip it;
+        /// conflict markers are left when standard input is not a terminal
+        #[arg(long, requires = "merge")]
+        interactive: bool,
+        /// Finish a checkout interrupted by a failure
+        #[arg(long = "continue", conflicts_with_all = ["commit_id", "merge"])]
+        resume: bool,
+        /// Start a new branch with no history, keeping the checkout as is for its first commit
+        #[arg(long, value_name = "NEWBRANCH", conflicts_with_all = ["commit_id", "merge", "resume"])]
+        orphan: Option<String>,
+        /// Remove everything in the checkout, ignored files included, and write the commit afresh
+        #[arg(long, conflicts_with_all = ["merge", "resume", "orphan"])]
+        force_clean: bool,
+        /// Write every file that can be written, reporting the ones that failed at the end
+        #[arg(long, conflicts_with_all = ["merge", "resume", "orphan"])]
+        keep_going: bool,
+        /// Print nothing but errors; with `--json` the numbers of files the checkout wrote and
+        /// removed are printed as JSON instead of messages
+        #[arg(long, conflicts_with_all = ["merge", "orphan"])]
+        quiet: bool,
+    },
+    Diff(DiffArgs),
+    /// Write a file or folder of a commit into a directory outside of the checkout
+    Extract {
+        /// The commit to extract from
+        spec: String,
+        /// Path of the file or folder in the commit, relative to the current directory
+        path: PathBuf,
+        /// Directory to write to, must not exist or be empty, or `-` to write the content of a file
+        /// to standard output
+        dest: PathBuf,
+    },
+    /// Write a file or folder of a commit back into the checkout, leaving everything else as it is
+    Restore {
+        /// The commit to restore from
+        spec: String,
+        /// Path of the file or folder in the commit, relative to the current directory
+        path: PathBuf,
+    },
+    Grep {
+        /// Regular expression to search for
+        pattern: String,
+        /// The commit to search in, if not provided search the current commit
+        #[arg(default_value = None)]
+        spec: Option<String>,
     },
 }
 
-pub(super) fn exec(args: &TreeArgs) -> Result<(), String> {
+/// Whether the command prints JSON with `--json`.
+pub(super) fn prints_json(args: &TreeArgs) -> bool {
+    matches!(
+        args.cmd,
+        TreeCommands::Status(_) | TreeCommands::Checkout { .. } | TreeCommands::Diff(_)
+    )
+}
+
+pub(super) fn exec(args: &TreeArgs, json: bool) -> Result<(), CommandError> {
     let context = Context::init().map_err(|err| format!("Error initializing context: {}", err))?;
     match &args.cmd {
-        TreeCommands::Status => status(&context),
-        TreeCommands::Checkout { commit_id } => checkout(&context, commit_id),
+        TreeCommands::Status(args) => status(&context, args, json),
+        TreeCommands::Checkout {
+            commit_id,
+            merge,
+            interactive,
+            resume,
+            orphan,
+            force_clean,
+            keep_going,
+            quiet,
+        } => {
+            if json && (*merge || *resume || orphan.is_some() || *quiet) {
+                return Err(CommandError::new(
+                    ExitCode::Invalid,
+                    "A checkout with --merge, --continue, --orphan or --quiet does not print JSON"
+                        .to_string(),
+                ));
+            }
+            let output = match (quiet, json) {
+                (_, true) => Output::Json,
+                (true, _) => Output::Quiet,
+                _ => Output::Normal,
+            };
+            match (commit_id, orphan) {
+                _ if *resume => continue_checkout(&context, output),
+                (_, Some(name)) => checkout_orphan(&context, name),
+                (Some(commit_id), None) if *merge => {
+                    checkout_merge(&context, commit_id, *interactive)
+                }
+                (Some(commit_id), None) if *keep_going => {
+                    checkout_keep_going(&context, commit_id, *force_clean, output)
+                }
+                (Some(commit_id), None) if *force_clean => {
+                    checkout_clean(&context, commit_id, output)
+                }
+                (Some(commit_id), None) => checkout(&context, commit_id, output),
+                (None, None) => Err(CommandError::new(
+                    ExitCode::Invalid,
+                    "Commit to checkout is required".to_string(),
+                )),
+            }
+        }
+        TreeCommands::Diff(args) => diff_with(&context, args, json),
+        TreeCommands::Grep { pattern, spec } => grep(&context, pattern, spec.as_deref()),
+        TreeCommands::Extract { spec, path, dest } => extract(&context, spec, path, dest),
+        TreeCommands::Restore { spec, path } => restore(&context, spec, path),
+    }
+}
+
+pub(super) fn exec_diff(args: &DiffArgs, json: bool) -> Result<(), CommandError> {
+    let context = Context::init().map_err(|err| format!("Error initializing context: {}", err))?;
+    diff_with(&context, args, json)
+}
+
+fn diff_with(context: &Context, args: &DiffArgs, json: bool) -> Result<(), CommandError> {
+    let (from, to) = (&args.from, args.to.as_deref());
+    if json && (args.find_renames.is_some() || args.name_only || args.stat) {
+        Err(CommandError::new(
+            ExitCode::Invalid,
+            "A diff with --find-renames, --name-only or --stat can not be printed as JSON"
+                .to_string(),
+        ))
+    } else if let Some(threshold) = args.find_renames {
+        let threshold = threshold.unwrap_or(DEFAULT_RENAME_THRESHOLD);
+        diff_renames(context, from, to, threshold)
+    } else if args.name_only {
+        diff_name_only(context, from, to)
+    } else if args.stat {
+        diff_stat(context, from, to)
+    } else {
+        diff(context, from, to, json)
+    }
+}
+
+pub(super) fn exec_status(args: &StatusArgs, json: bool) -> Result<(), CommandError> {
+    let context = Context::init().map_err(|err| format!("Error initializing context: {}", err))?;
+    status(&context, args, json)
+}
+
+fn parse_untracked(mode: &str) -> Result<Untracked, String> {
+    match mode {
+        "no" => Ok(Untracked::No),
+        "normal" => Ok(Untracked::Normal),
+        "all" => Ok(Untracked::All),
+        _ => Err(format!("Expected no, normal or all, got '{}'", mode)),
+    }
+}
+
+fn parse_threshold(value: &str) -> Result<u8, String> {
+    match value.parse() {
+        Ok(percent) if (1..=100).contains(&percent) => Ok(percent),
+        _ => Err(format!(
+            "Expected a percentage from 1 to 100, got '{}'",
+            value
+        )),
+    }
+}
+
+fn status(context: &Context, args: &StatusArgs, json: bool) -> Result<(), CommandError> {
+    if args.summary && json {
+        return Err(CommandError::new(
+            ExitCode::Invalid,
+            "A summary of changes can not be printed as JSON".to_string(),
+        ));
+    }
+    if args.include_ignored {
+        return status_including_ignored(context, args.untracked, args.color.enabled(), json);
     }
+    status_with(
+        context,
+        args.untracked,
+        args.ignore_whitespace,
+        args.summary,
+        args.color.enabled(),
+        json,
+    )
 }
 
-fn status(context: &Context) -> Result<(), String> {
-    match Tree::get_changed_files(context) {
+fn status_with(
+    context: &Context,
+    untracked: Untracked,
+    ignore_whitespace: bool,
+    summary: bool,
+    color: bool,
+    json: bool,
+) -> Result<(), CommandError> {
+    check_no_pending_checkout(context)?;
+    let changes = Tree::get_changed_files_with(context, untracked).and_then(|changes| {
+        if ignore_whitespace {
+            Tree::filter_whitespace_changes(context, changes)
+        } else {
+            Ok(changes)
+        }
+    });
+    match changes {
+        Ok(changes) if summary => print_summary(&changes),
+        Ok(changes) if json => json::print(&changes),
         Ok(changes) => {
             if changes.is_empty() {
                 println!("No files changed since current commit");
             } else {
                 println!("Files changed since current commit:");
                 for change in changes {
-                    let type_str = match change.change_type {
-                        ChangeType::File => "file",
-                        ChangeType::Folder => "folder",
-                    };
-                    let action_str = match change.action {
-                        ChangeAction::Added => "added",
-                        ChangeAction::Deleted => "deleted",
-                        ChangeAction::Modified => "modified",
-                    };
-                    println!("  {} {} {}", action_str, type_str, change.path.display());
+                    let line = describe_change(&change.action, &change.change_type, &change.path);
+                    println!("{}", color::paint_action(&change.action, &line, color));
                 }
             }
             Ok(())
         }
-        Err(e) => Err(format!("Failed to list changed files: {:?}", e)),
+        Err(e) => Err(failure(
+            &e,
+            format!("Failed to list changed files: {:?}", e),
+        )),
+    }
+}
+
+fn status_including_ignored(
+    context: &Context,
+    untracked: Untracked,
+    color: bool,
+    json: bool,
+) -> Result<(), CommandError> {
+    check_no_pending_checkout(context)?;
+    let changes = Tree::get_changed_files_including_ignored(context, untracked)
+        .map_err(|e| failure(&e, format!("Failed to list changed files: {:?}", e)))?;
+    if json {
+        let items: Vec<IgnoredChange> = changes
+            .iter()
+            .map(|(change, ignored)| IgnoredChange {
+                change,
+                ignored: *ignored,
+            })
+            .collect();
+        return json::print(&items);
+    }
+    if changes.is_empty() {
+        println!("No files changed since current commit, including ignored ones");
+        return Ok(());
+    }
+
+    println!("Files changed since current commit, including ignored ones:");
+    for (change, ignored) in changes {
+        let line = describe_change(&change.action, &change.change_type, &change.path);
+        let line = color::paint_action(&change.action, &line, color);
+        if ignored {
+            println!("{} (ignored)", line);
+        } else {
+            println!("{}", line);
+        }
+    }
+    Ok(())
+}
+
+/// Prints the number of changes by action, in total and by type, e.g. for a shell prompt.
+/// The checkout having changes is reported as an error, so scripts can tell it by the exit code.
+fn print_summary(changes: &[Change]) -> Result<(), CommandError> {
+    if changes.is_empty() {
+        println!("No files changed since current commit");
+        return Ok(());
+    }
+
+    // counts of added, modified and deleted files, then folders
+    let mut counts = [[0usize; 3]; 2];
+    for change in changes {
+        let row = match change.change_type {
+            ChangeType::File => 0,
+            ChangeType::Folder => 1,
+        };
+        let column = match change.action {
+            ChangeAction::Added => 0,
+            ChangeAction::Modified => 1,
+            ChangeAction::Deleted => 2,
+        };
+        counts[row][column] += 1;
+    }
+    let describe = |counts: [usize; 3]| {
+        format!(
+            "{} added, {} modified, {} deleted",
+            counts[0], counts[1], counts[2]
+        )
+    };
+
+    let total = [0, 1, 2].map(|column| counts[0][column] + counts[1][column]);
+    println!("{}", describe(total));
+    println!("  files: {}", describe(counts[0]));
+    println!("  folders: {}", describe(counts[1]));
+    Err(CommandError::new(
+        ExitCode::Changes,
+        format!("Checkout has {} uncommitted change(s)", changes.len()),
+    ))
+}
+
+pub(super) fn print_change(action: &ChangeAction, change_type: &ChangeType, path: &Path) {
+    println!("{}", describe_change(action, change_type, path));
+}
+
+fn describe_change(action: &ChangeAction, change_type: &ChangeType, path: &Path) -> String {
+    format!(
+        "  {} {} {}",
+        action_name(action),
+        type_name(change_type),
+        path.display()
+    )
+}
+
+/// A change printed as JSON along with whether the ignore rules would hide it.
+#[derive(Serialize)]
+struct IgnoredChange<'a> {
+    #[serde(flatten)]
+    change: &'a Change,
+    ignored: bool,
+}
+
+fn action_name(action: &ChangeAction) -> &'static str {
+    match action {
+        ChangeAction::Added => "added",
+        ChangeAction::Deleted => "deleted",
+        ChangeAction::Modified => "modified",
+    }
+}
+
+fn type_name(change_type: &ChangeType) -> &'static str {
+    match change_type {
+        ChangeType::File => "file",
+        ChangeType::Folder => "folder",
+    }
+}
+
+/// Resolves hashes of the trees of the compared commits.
+fn resolve_trees(
+    context: &Context,
+    from: &str,
+    to: Option<&str>,
+) -> Result<(Digest, Digest), CommandError> {
+    let from_commit = Commit::get_by_spec(context, from)
+        .map_err(|e| failure(&e, format!("Failed to get commit '{}': {:?}", from, e)))?;
+    let to_commit = match to {
+        Some(to) => Commit::get_by_spec(context, to)
+            .map_err(|e| failure(&e, format!("Failed to get commit '{}': {:?}", to, e)))?,
+        None => Commit::get_current(context)
+            .map_err(|e| failure(&e, format!("Failed to get current commit: {:?}", e)))?,
+    };
+    Ok((from_commit.treehash, to_commit.treehash))
+}
+
+fn diff(context: &Context, from: &str, to: Option<&str>, json: bool) -> Result<(), CommandError> {
+    let (left, right) = resolve_trees(context, from, to)?;
+    let changes = Tree::get_diff(context, left, right, &[])
+        .map_err(|e| failure(&e, format!("Failed to diff: {:?}", e)))?;
+
+    if json {
+        return json::print(&changes);
+    }
+    if changes.is_empty() {
+        println!("No differences");
+    } else {
+        println!("Differences:");
+        for change in changes {
+            print_change(&change.action, &change.change_type, &change.path);
+        }
+    }
+    Ok(())
+}
+
+fn diff_renames(
+    context: &Context,
+    from: &str,
+    to: Option<&str>,
+    threshold: u8,
+) -> Result<(), CommandError> {
+    let (left, right) = resolve_trees(context, from, to)?;
+    let diff = Tree::get_diff_renames(context, left, right, threshold)
+        .map_err(|e| failure(&e, format!("Failed to diff: {:?}", e)))?;
+
+    if diff.renames.is_empty() && diff.changes.is_empty() {
+        println!("No differences");
+        return Ok(());
+    }
+    println!("Differences:");
+    for rename in &diff.renames {
+        println!(
+            "  renamed file {} -> {} ({}%)",
+            rename.from.display(),
+            rename.to.display(),
+            rename.similarity
+        );
+    }
+    for change in &diff.changes {
+        print_change(&change.action, &change.change_type, &change.path);
+    }
+    if diff.limited {
+        println!(
+            "More than {} files were added or deleted, only files of the same content were paired",
+            MAX_RENAME_CANDIDATES
+        );
+    }
+    Ok(())
+}
+
+fn diff_name_only(context: &Context, from: &str, to: Option<&str>) -> Result<(), CommandError> {
+    let (left, right) = resolve_trees(context, from, to)?;
+    let stats = Tree::get_diff_stat(context, left, right)
+        .map_err(|e| failure(&e, format!("Failed to diff: {:?}", e)))?;
+
+    for stat in stats {
+        println!("{}", stat.path.display());
+    }
+    Ok(())
+}
+
+fn diff_stat(context: &Context, from: &str, to: Option<&str>) -> Result<(), CommandError> {
+    let (left, right) = resolve_trees(context, from, to)?;
+    let stats = Tree::get_diff_stat(context, left, right)
+        .map_err(|e| failure(&e, format!("Failed to diff: {:?}", e)))?;
+
+    let width = stats
+        .iter()
+        .map(|stat| stat.path.display().to_string().len())
+        .max()
+        .unwrap_or(0);
+
+    // Without a content diff, bytes are counted as the difference of file sizes
+    let (mut added, mut removed) = (0, 0);
+    for stat in &stats {
+        let (plus, minus) = if stat.size_right >= stat.size_left {
+            (stat.size_right - stat.size_left, 0)
+        } else {
+            (0, stat.size_left - stat.size_right)
+        };
+        added += plus;
+        removed += minus;
+        println!(
+            " {:<width$} | +{} -{}{}",
+            stat.path.display().to_string(),
+            plus,
+            minus,
+            if stat.binary { " (binary)" } else { "" },
+            width = width
+        );
+    }
+    println!(
+        " {} file{} changed, {} bytes added(+), {} bytes removed(-)",
+        stats.len(),
+        if stats.len() == 1 { "" } else { "s" },
+        added,
+        removed
+    );
+    Ok(())
+}
+
+fn grep(context: &Context, pattern: &str, spec: Option<&str>) -> Result<(), CommandError> {
+    let regex = Regex::new(pattern).map_err(|e| failure(&e, e.to_string()))?;
+    let commit = match spec {
+        Some(spec) => Commit::get_by_spec(context, spec)
+            .map_err(|e| failure(&e, format!("Failed to get commit '{}': {:?}", spec, e)))?,
+        None => Commit::get_current(context)
+            .map_err(|e| failure(&e, format!("Failed to get current commit: {:?}", e)))?,
+    };
+
+    let matches = Tree::grep(context, commit.treehash, &regex)
+        .map_err(|e| failure(&e, format!("Failed to search: {:?}", e)))?;
+    for m in matches {
+        println!("{}:{}:{}", m.path.display(), m.line_number, m.line);
+    }
+    Ok(())
+}
+
+fn extract(context: &Context, spec: &str, path: &Path, dest: &Path) -> Result<(), CommandError> {
+    let commit = Commit::get_by_spec(context, spec)
+        .map_err(|e| failure(&e, format!("Failed to get commit '{}': {:?}", spec, e)))?;
+    let path = context
+        .resolve_path(path)
+        .map_err(|e| failure(&e, format!("Failed to resolve path: {}", e)))?;
+
+    if output::is_stdout(dest) {
+        let mut out = output::open(dest)?;
+        Tree::extract_file(context, commit.treehash, &path, &mut out)
+            .map_err(|e| failure(&e, format!("Failed to extract: {:?}", e)))?;
+        return Ok(output::finish(out, dest)?);
     }
+
+    Tree::extract(context, commit.treehash, &path, dest)
+        .map_err(|e| failure(&e, format!("Failed to extract: {:?}", e)))?;
+    println!(
+        "Extracted '{}' of commit {} into {}",
+        path.display(),
+        spec,
+        dest.display()
+    );
+    Ok(())
+}
+
+fn restore(context: &Context, spec: &str, path: &Path) -> Result<(), CommandError> {
+    let commit = Commit::get_by_spec(context, spec)
+        .map_err(|e| failure(&e, format!("Failed to get commit '{}': {:?}", spec, e)))?;
+    let path = context
+        .resolve_path(path)
+        .map_err(|e| failure(&e, format!("Failed to resolve path: {}", e)))?;
+    Tree::restore(context, commit.treehash, &path)
+        .map_err(|e| failure(&e, format!("Failed to restore: {:?}", e)))?;
+    println!("Restored '{}' from commit {}", path.display(), spec);
+    Ok(())
+}
+
+/// How a checkout reports its outcome.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Output {
+    Normal,
+    /// Nothing but errors.
+    Quiet,
+    /// The report as a JSON object, for scripts checking out many commits.
+    Json,
 }
 
-fn checkout(context: &Context, commit_id: &str) -> Result<(), String> {
+fn checkout(context: &Context, commit_id: &str, output: Output) -> Result<(), CommandError> {
     match Tree::checkout(context, commit_id) {
-        Ok(()) => {
-            println!("Successfully checked out commit: {}", commit_id);
+        Ok(report) => {
+            let message = format!("Successfully checked out commit: {}", commit_id);
+            print_outcome(context, commit_id, &message, &report, output);
+            Ok(())
+        }
+        Err(TreeError::OutOfSpace(e)) => Err(out_of_space_error(
+            "checkout commit",
+            &e,
+            checkout_retry(context),
+        )),
+        Err(TreeError::PermissionDenied(e)) => Err(permission_error(
+            "checkout commit",
+            &e,
+            checkout_retry(context),
+        )),
+        Err(e) => Err(failure(
+            &e,
+            format!(
+                "Failed to checkout commit: {:?}\nFix the problem and {}",
+                e,
+                checkout_retry(context)
+            ),
+        )),
+    }
+}
+
+/// Tells how to retry a failed checkout: `--continue` only applies once the checkout has recorded
+/// its intent, failures before that, e.g. an unknown commit, left the files untouched.
+fn checkout_retry(context: &Context) -> &'static str {
+    match Tree::get_pending_checkout(context) {
+        Ok(Some(_)) => "run `vx tree checkout --continue` to finish the checkout",
+        _ => "run the checkout again",
+    }
+}
+
+fn checkout_clean(context: &Context, commit_id: &str, output: Output) -> Result<(), CommandError> {
+    match Tree::checkout_clean(context, commit_id) {
+        Ok(report) => {
+            let message =
+                format!("Successfully checked out commit {} into a clean checkout", commit_id);
+            print_outcome(context, commit_id, &message, &report, output);
             Ok(())
         }
-        Err(e) => Err(format!("Failed to checkout commit: {:?}", e)),
+        Err(e) => Err(failure(&e, format!(
+            "Failed to checkout commit: {:?}\nFix the problem and run `vx tree checkout {} --force-clean` again",
+            e, commit_id
+        ))),
+    }
+}
+
+fn checkout_keep_going(
+    context: &Context,
+    commit_id: &str,
+    clean: bool,
+    output: Output,
+) -> Result<(), CommandError> {
+    let report = Tree::checkout_keep_going(context, commit_id, clean)
+        .map_err(|e| failure(&e, format!("Failed to checkout commit: {:?}", e)))?;
+    if report.failed.is_empty() {
+        let message = format!("Successfully checked out commit: {}", commit_id);
+        print_outcome(context, commit_id, &message, &report, output);
+        return Ok(());
+    }
+
+    match output {
+        Output::Json => println!("{}", report_json(context, commit_id, &report)),
+        Output::Normal => print_report(&report),
+        Output::Quiet => (),
+    }
+    // the failures are printed in every mode, they are what needs fixing
+    eprintln!("Failed to write {} path(s):", report.failed.len());
+    for failure in &report.failed {
+        eprintln!("  {}: {}", failure.path.display(), failure.error);
+    }
+    Err(format!(
+        "Checkout of commit {} is incomplete, the current commit is unchanged
+Fix the problems and run `vx tree checkout --continue` to finish the checkout",
+        commit_id
+    )
+    .into())
+}
+
+fn checkout_orphan(context: &Context, name: &str) -> Result<(), CommandError> {
+    let branch = Tree::checkout_orphan(context, name)
+        .map_err(|e| failure(&e, format!("Failed to checkout orphan branch: {}", e)))?;
+    println!(
+        "Switched to new branch '{}' with no history, the next commit will capture the current files",
+        branch.name
+    );
+    Ok(())
+}
+
+fn continue_checkout(context: &Context, output: Output) -> Result<(), CommandError> {
+    let commit_id = Tree::continue_checkout(context)
+        .map_err(|e| failure(&e, format!("Failed to continue checkout: {:?}", e)))?;
+    if output == Output::Quiet {
+        return Ok(());
+    }
+    println!(
+        "Successfully checked out commit: {}",
+        describe_commit_id(context, commit_id)
+    );
+    print_detached(context);
+    Ok(())
+}
+
+/// Fails if a checkout was interrupted, as the files are then a mix of two commits.
+fn check_no_pending_checkout(context: &Context) -> Result<(), CommandError> {
+    let pending = Tree::get_pending_checkout(context)
+        .map_err(|e| failure(&e, format!("Failed to get pending checkout: {:?}", e)))?;
+    match pending {
+        Some(commit_id) => Err(format!(
+            "Checkout of {} was interrupted, run `vx tree checkout --continue` to finish it",
+            describe_commit_id(context, commit_id)
+        )
+        .into()),
+        None => Ok(()),
+    }
+}
+
+/// Renders a commit id with the branch name, falling back to the branch id.
+pub(super) fn describe_commit_id(context: &Context, commit_id: CommitID) -> String {
+    match Branch::get(context, commit_id.branch) {
+        Ok(branch) => format!("{}:{}", branch.name, commit_id.seq),
+        Err(_) => format!("{}:{}", commit_id.branch, commit_id.seq),
+    }
+}
+
+/// Prints the outcome of a checkout in the requested form.
+fn print_outcome(
+    context: &Context,
+    commit_id: &str,
+    message: &str,
+    report: &CheckoutReport,
+    output: Output,
+) {
+    match output {
+        Output::Normal => {
+            println!("{}", message);
+            print_report(report);
+            print_detached(context);
+        }
+        Output::Quiet => (),
+        Output::Json => println!("{}", report_json(context, commit_id, report)),
+    }
+}
+
+/// Renders the counts of a checkout report as a JSON object, along with whether the checkout is
+/// detached from the branch head.
+fn report_json(context: &Context, commit_id: &str, report: &CheckoutReport) -> String {
+    let detached = Repo::status(context)
+        .map(|status| status.is_detached())
+        .unwrap_or(false);
+    json::object(&[
+        ("commit", json::string(commit_id)),
+        ("created", report.created.len().to_string()),
+        ("overwritten", report.overwritten.len().to_string()),
+        ("deleted", report.deleted.len().to_string()),
+        ("unchanged", report.unchanged.to_string()),
+        ("failed", report.failed.len().to_string()),
+        ("detached", detached.to_string()),
+    ])
+}
+
+/// Prints how many files and folders a checkout wrote, removed and left as they were.
+fn print_report(report: &CheckoutReport) {
+    println!(
+        "Updated {} path(s): {} created, {} overwritten, {} deleted, {} unchanged",
+        report.created.len() + report.overwritten.len() + report.deleted.len(),
+        report.created.len(),
+        report.overwritten.len(),
+        report.deleted.len(),
+        report.unchanged
+    );
+}
+
+/// Warns if the checked out commit is not the branch head.
+fn print_detached(context: &Context) {
+    if let Ok(status) = Repo::status(context) {
+        if status.is_detached() {
+            println!("{}", crate::repo::describe_position(&status));
+        }
+    }
+}
+
+fn checkout_merge(
+    context: &Context,
+    commit_id: &str,
+    interactive: bool,
+) -> Result<(), CommandError> {
+    check_no_pending_checkout(context)?;
+    let conflicts = Tree::checkout_merge(context, commit_id)
+        .map_err(|e| failure(&e, format!("Failed to checkout commit: {:?}", e)))?;
+
+    println!("Successfully checked out commit: {}", commit_id);
+    print_detached(context);
+    if !conflicts.is_empty() {
+        println!("Conflicts:");
+        for conflict in &conflicts {
+            let kind_str = match conflict.kind {
+                ConflictKind::Content => "both modified",
+                ConflictKind::ModifiedDeleted => "deleted in target, kept local",
+                ConflictKind::DeletedModified => "deleted locally, restored from target",
+                ConflictKind::Binary => "binary, kept local",
+            };
+            println!("  {} {}", kind_str, conflict.path.display());
+        }
+    }
+    if interactive {
+        let paths: Vec<PathBuf> = conflicts
+            .into_iter()
+            .filter(|conflict| conflict.kind == ConflictKind::Content)
+            .map(|conflict| conflict.path)
+            .collect();
+        resolve::resolve(context, "Resolving conflicts:", &paths)?;
     }
+    Ok(())
 }
@@ -1,5 +1,21 @@
+use crate::core::blob;
+use crate::core::commit;
 use crate::global::DATA_FOLDER;
-use std::path::PathBuf;
+use crate::storage::repo::{self as repostore, RepoError};
+use crate::storage::REPO_FILE_NAME;
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread::ThreadId;
+
+/// Key of the repository metadata entry setting the capacity of the page cache of each database
+/// in bytes.
+pub const DB_CACHE_CAPACITY_KEY: &str = "db.cacheCapacity";
+
+/// Key of the repository metadata entry setting the interval of background flushes of the
+/// databases in milliseconds, `0` turns them off.
+pub const DB_FLUSH_EVERY_MS_KEY: &str = "db.flushEveryMs";
 
 /// Represents the context of the version control system.
 #[derive(Debug, Clone)]
@@ -8,6 +24,147 @@ pub struct Context {
     pub workspace_path: PathBuf,
     /// Path to the currently checked out branch.
     pub checkout_path: PathBuf,
+    /// Set for throwaway repositories, shared by all clones of the context.
+    temporary: Option<Arc<Temporary>>,
+    /// Flushes deferred by batches, shared by all clones of the context.
+    batch: Arc<Mutex<BatchState>>,
+    /// Tuning of the databases, read from repository metadata when the first one is opened.
+    tuning: Arc<OnceLock<DbTuning>>,
+    /// Compression level of new blobs, read from repository metadata when the first one is stored.
+    compression_level: Arc<OnceLock<u32>>,
+    /// Branch locks held by threads of the process, shared by all clones of the context.
+    branch_locks: Arc<Mutex<HeldLocks>>,
+}
+
+/// Settings of sled applied to the databases of a repository, sled's defaults are kept for the
+/// ones that are not set.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DbTuning {
+    /// Capacity of the page cache of each database in bytes.
+    pub cache_capacity: Option<u64>,
+    /// Interval of background flushes in milliseconds, `0` if they are turned off.
+    pub flush_every_ms: Option<u64>,
+}
+
+impl DbTuning {
+    /// Reads the tuning from repository metadata.
+    pub fn from_metadata(metadata: &HashMap<String, String>) -> Result<Self, RepoError> {
+        let parse = |key: &str, expected: &str| match metadata.get(key) {
+            Some(value) => value.parse().map(Some).map_err(|_| {
+                RepoError::Other(format!(
+                    "Invalid value '{}' of {}, expected {}",
+                    value, key, expected
+                ))
+            }),
+            None => Ok(None),
+        };
+        Ok(DbTuning {
+            cache_capacity: parse(DB_CACHE_CAPACITY_KEY, "a size in bytes")?,
+            flush_every_ms: parse(DB_FLUSH_EVERY_MS_KEY, "a number of milliseconds")?,
+        })
+    }
+
+    /// Applies the tuning to a sled configuration.
+    fn apply(self, mut config: sled::Config) -> sled::Config {
+        if let Some(capacity) = self.cache_capacity {
+            config = config.cache_capacity(capacity);
+        }
+        if let Some(ms) = self.flush_every_ms {
+            config = config.flush_every_ms((ms > 0).then_some(ms));
+        }
+        config
+    }
+}
+
+/// Databases written during a batch, kept open so that they are flushed once the batch ends.
+#[derive(Debug, Default)]
+struct BatchState {
+    /// Number of batches started and not yet finished, they may be nested.
+    depth: usize,
+    /// Open databases by file name.
+    dbs: HashMap<&'static str, sled::Db>,
+}
+
+/// Guard of a batch started with `Context::batch`. Flushes are deferred until the outermost batch
+/// is finished or dropped, then every database opened during the batch is flushed once.
+#[must_use = "flushes are deferred only while the batch is alive"]
+pub struct Batch<'a> {
+    context: &'a Context,
+    finished: bool,
+}
+
+impl Batch<'_> {
+    /// Finishes the batch, flushing the databases written during it if it is the outermost one.
+    pub fn finish(mut self) -> Result<(), sled::Error> {
+        self.finished = true;
+        self.context.end_batch()
+    }
+}
+
+impl Drop for Batch<'_> {
+    fn drop(&mut self) {
+        // A batch left early, e.g. on an error, still flushes what was written
+        if !self.finished {
+            let _ = self.context.end_batch();
+        }
+    }
+}
+
+/// Name of the folder of the workspace holding the lock files of branches.
+const LOCKS_FOLDER: &str = "locks";
+
+/// Lock file of a branch held by a thread, taken again by the same thread without blocking.
+#[derive(Debug)]
+struct HeldLock {
+    /// The locked file, unlocked once it is closed.
+    _file: std::fs::File,
+    /// Number of guards of the thread holding the lock.
+    depth: usize,
+}
+
+/// Branch locks held by threads of the process, by branch id and thread.
+type HeldLocks = HashMap<(u64, ThreadId), HeldLock>;
+
+/// Guard of a branch lock taken with `Context::lock_branch`, released once the outermost guard of
+/// the thread is dropped.
+#[must_use = "the branch is unlocked once the guard is dropped"]
+pub(crate) struct BranchLock<'a> {
+    context: &'a Context,
+    key: (u64, ThreadId),
+}
+
+impl Drop for BranchLock<'_> {
+    fn drop(&mut self) {
+        if let Ok(mut locks) = self.context.branch_locks.lock() {
+            if let Some(held) = locks.get_mut(&self.key) {
+                held.depth -= 1;
+                if held.depth == 0 {
+                    locks.remove(&self.key);
+                }
+            }
+        }
+    }
+}
+
+/// State of a throwaway repository. Its databases live in sled's temporary mode, so they are kept
+/// open for the lifetime of the context, and the whole repository directory is removed once the
+/// last clone of the context is dropped.
+#[derive(Debug)]
+struct Temporary {
+    /// Root directory of the repository, containing both the checkout and the workspace.
+    root: PathBuf,
+    /// Open databases by file name.
+    dbs: Mutex<HashMap<&'static str, sled::Db>>,
+}
+
+impl Drop for Temporary {
+    fn drop(&mut self) {
+        // Databases have to be closed before their files are removed
+        if let Ok(mut dbs) = self.dbs.lock() {
+            dbs.clear();
+        }
+        let _ = std::fs::remove_dir_all(&self.root);
+    }
 }
 
 impl Context {
@@ -16,13 +173,300 @@ impl Context {
         Context {
             workspace_path,
             checkout_path,
+            temporary: None,
+            batch: Arc::default(),
+            tuning: Arc::default(),
+            compression_level: Arc::default(),
+            branch_locks: Arc::default(),
         }
     }
+
+    /// Creates a new Context for a throwaway repository, which is removed along with the
+    /// checkout path once the context and all its clones are dropped.
+    pub fn new_temporary(workspace_path: PathBuf, checkout_path: PathBuf) -> Self {
+        let temporary = Temporary {
+            root: checkout_path.clone(),
+            dbs: Mutex::new(HashMap::new()),
+        };
+        Context {
+            workspace_path,
+            checkout_path,
+            temporary: Some(Arc::new(temporary)),
+            batch: Arc::default(),
+            tuning: Arc::default(),
+            compression_level: Arc::default(),
+            branch_locks: Arc::default(),
+        }
+    }
+
+    /// Returns true if the context belongs to a throwaway repository.
+    pub fn is_temporary(&self) -> bool {
+        self.temporary.is_some()
+    }
+
+    /// Opens a database of the workspace.
+    /// Databases of throwaway repositories are opened once in sled's temporary mode and shared.
+    /// During a batch, databases are opened once as well, to be flushed when the batch ends.
+    pub(crate) fn open_db(&self, file_name: &'static str) -> Result<sled::Db, sled::Error> {
+        // the tuning may open the repository database, so it is read before taking any lock
+        let config = self.db_config(file_name)?;
+        match &self.temporary {
+            None => {
+                let mut batch = self.lock_batch()?;
+                if batch.depth == 0 {
+                    return config.open();
+                }
+                if let Some(db) = batch.dbs.get(file_name) {
+                    return Ok(db.clone());
+                }
+                let db = config.open()?;
+                batch.dbs.insert(file_name, db.clone());
+                Ok(db)
+            }
+            Some(temporary) => {
+                let mut dbs = temporary.dbs.lock().map_err(|_| {
+                    sled::Error::Unsupported("Database cache is poisoned".to_string())
+                })?;
+                if let Some(db) = dbs.get(file_name) {
+                    return Ok(db.clone());
+                }
+                let db = config.temporary(true).open()?;
+                dbs.insert(file_name, db.clone());
+                Ok(db)
+            }
+        }
+    }
+
+    /// Builds the sled configuration of a database of the workspace, tuned as the repository
+    /// metadata says. The repository database holds the tuning and is flushed after every write,
+    /// so it keeps sled's default cache and is opened without background flushes.
+    fn db_config(&self, file_name: &'static str) -> Result<sled::Config, sled::Error> {
+        let config = sled::Config::new().path(self.workspace_path.join(file_name));
+        if file_name == REPO_FILE_NAME {
+            return Ok(config.flush_every_ms(None));
+        }
+        Ok(self.db_tuning()?.apply(config))
+    }
+
+    /// Sets the tuning of the databases unless one was already read, e.g. for a new repository
+    /// whose metadata is known without reading it back.
+    pub(crate) fn set_db_tuning(&self, tuning: DbTuning) {
+        let _ = self.tuning.set(tuning);
+    }
+
+    /// Gets the tuning of the databases, reading it from repository metadata the first time.
+    fn db_tuning(&self) -> Result<DbTuning, sled::Error> {
+        if let Some(tuning) = self.tuning.get() {
+            return Ok(*tuning);
+        }
+        let tuning = repostore::get_metadata(self)
+            .and_then(|metadata| DbTuning::from_metadata(&metadata))
+            .map_err(|e| {
+                sled::Error::Unsupported(format!("Failed to read database tuning: {}", e))
+            })?;
+        Ok(*self.tuning.get_or_init(|| tuning))
+    }
+
+    /// Gets the compression level of new blobs, reading it from repository metadata the first time.
+    pub(crate) fn compression_level(&self) -> Result<u32, RepoError> {
+        if let Some(level) = self.compression_level.get() {
+            return Ok(*level);
+        }
+        let level = blob::compression_level(&repostore::get_metadata(self)?)?;
+        Ok(*self.compression_level.get_or_init(|| level))
+    }
+
+    /// Rewrites a database of the workspace into a fresh copy, leaving behind the stale pages that
+    /// sled accumulates with updates and deletions. Returns the size of the database on disk before
+    /// and after, or None if the database was never created.
+    /// Databases of throwaway repositories live in memory, so they are only measured. The database
+    /// must not be open elsewhere in the process.
+    pub(crate) fn compact_db(
+        &self,
+        file_name: &'static str,
+    ) -> Result<Option<(u64, u64)>, sled::Error> {
+        let path = self.workspace_path.join(file_name);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let before = dir_size(&path)?;
+        if self.temporary.is_some() {
+            return Ok(Some((before, before)));
+        }
+
+        let packed_path = self.workspace_path.join(format!("{}.pack", file_name));
+        let old_path = self.workspace_path.join(format!("{}.old", file_name));
+        for leftover in [&packed_path, &old_path] {
+            if leftover.exists() {
+                std::fs::remove_dir_all(leftover)?;
+            }
+        }
+
+        {
+            let db = sled::open(&path)?;
+            let packed = sled::open(&packed_path)?;
+            packed.import(db.export());
+            packed.flush()?;
+        }
+
+        // the old copy is only removed once the packed one is in place
+        std::fs::rename(&path, &old_path)?;
+        std::fs::rename(&packed_path, &path)?;
+        std::fs::remove_dir_all(&old_path)?;
+
+        Ok(Some((before, dir_size(&path)?)))
+    }
+
+    /// Starts a batch of writes, deferring flushes until the returned guard is finished or dropped.
+    /// Bulk workflows, e.g. saving many commits or persisting a large tree, use it to flush every
+    /// database once instead of after every record. The batch should wrap the whole workflow, as
+    /// databases opened before it started are not flushed when it ends.
+    pub fn batch(&self) -> Batch<'_> {
+        if let Ok(mut batch) = self.lock_batch() {
+            batch.depth += 1;
+        }
+        Batch {
+            context: self,
+            finished: false,
+        }
+    }
+
+    /// Flushes a database tree to disk, unless a batch is in progress and the flush is deferred.
+    pub(crate) fn flush(&self, tree: &sled::Tree) -> Result<(), sled::Error> {
+        if self.lock_batch()?.depth > 0 {
+            return Ok(());
+        }
+        tree.flush()?;
+        Ok(())
+    }
+
+    fn lock_batch(&self) -> Result<std::sync::MutexGuard<'_, BatchState>, sled::Error> {
+        self.batch
+            .lock()
+            .map_err(|_| sled::Error::Unsupported("Batch state is poisoned".to_string()))
+    }
+
+    /// Ends a batch, flushing and closing its databases once the outermost batch ends.
+    fn end_batch(&self) -> Result<(), sled::Error> {
+        let dbs = {
+            let mut batch = self.lock_batch()?;
+            batch.depth = batch.depth.saturating_sub(1);
+            if batch.depth > 0 {
+                return Ok(());
+            }
+            std::mem::take(&mut batch.dbs)
+        };
+        // every database is flushed even if one of them fails
+        let mut result = Ok(());
+        for db in dbs.values() {
+            if let Err(err) = db.flush() {
+                result = result.and(Err(err));
+            }
+        }
+        result
+    }
+
+    /// Takes the advisory lock of a branch, waiting for other processes and threads holding it.
+    /// Commits to different branches do not wait for each other, only moving the head of a
+    /// branch needs to be serialized, as the blob and tree stores are content addressed. The lock
+    /// is a file named by the branch id in the workspace, locked with the locking of the operating
+    /// system, so it is released even if the process holding it dies. A thread already holding the
+    /// lock takes it again right away, so workflows locking a branch may call each other.
+    pub(crate) fn lock_branch(&self, branch_id: u64) -> Result<BranchLock<'_>, std::io::Error> {
+        let key = (branch_id, std::thread::current().id());
+        let guard = BranchLock { context: self, key };
+        if let Some(held) = self.lock_branch_locks()?.get_mut(&key) {
+            held.depth += 1;
+            return Ok(guard);
+        }
+
+        // the lock is waited for without holding the locks of other branches
+        let folder = self.workspace_path.join(LOCKS_FOLDER);
+        std::fs::create_dir_all(&folder)?;
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(folder.join(format!("branch-{}.lock", branch_id)))?;
+        file.lock()?;
+        self.lock_branch_locks()?.insert(
+            key,
+            HeldLock {
+                _file: file,
+                depth: 1,
+            },
+        );
+        Ok(guard)
+    }
+
+    fn lock_branch_locks(&self) -> Result<std::sync::MutexGuard<'_, HeldLocks>, std::io::Error> {
+        self.branch_locks
+            .lock()
+            .map_err(|_| std::io::Error::other("Branch locks are poisoned"))
+    }
+
+    /// Resolves a path typed by the user relative to the current working directory into a path
+    /// relative to the checkout root, so that commands behave the same from any nested directory.
+    /// The path does not have to exist, e.g. to refer to a deleted file.
+    /// Returns an error if the path points outside of the checkout or into the workspace.
+    pub fn resolve_path(&self, path: &Path) -> Result<PathBuf, std::io::Error> {
+        let absolute = resolve_lenient(&std::env::current_dir()?.join(path))?;
+        let root = self.checkout_path.canonicalize()?;
+
+        let relative = absolute.strip_prefix(&root).map_err(|_| {
+            std::io::Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "Path '{}' is outside of the repository at '{}'",
+                    path.display(),
+                    root.display()
+                ),
+            )
+        })?;
+        if relative.starts_with(DATA_FOLDER) {
+            return Err(std::io::Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "Path '{}' is inside the {} directory",
+                    path.display(),
+                    DATA_FOLDER
+                ),
+            ));
+        }
+        Ok(relative.to_path_buf())
+    }
+
     /// Searches the current working directory and upwards for a folder named `.vx`.
     /// If found, returns a Context object initialized with the path to this folder.
     /// Otherwise, returns an error.
+    /// Workflows interrupted by a failure in a previous run are completed before returning.
     pub fn init() -> Result<Self, std::io::Error> {
-        let mut current_dir = std::env::current_dir()?;
+        Self::open(&std::env::current_dir()?)
+    }
+
+    /// Same as `init`, but searches from the given directory instead of the current one.
+    pub fn open(path: &Path) -> Result<Self, std::io::Error> {
+        let context = Self::find(path)?;
+        commit::recover(&context).map_err(|e| {
+            std::io::Error::other(format!("Failed to recover pending commit: {:?}", e))
+        })?;
+        commit::recover_current(&context).map_err(|e| {
+            std::io::Error::other(format!("Failed to recover current commit: {:?}", e))
+        })?;
+        Ok(context)
+    }
+
+    /// Searches the current working directory and upwards for a repository like `init`, returning
+    /// None if there is none, or if the directories can not be searched, so that callers can tell
+    /// whether they are in a repository without handling an error.
+    /// Interrupted workflows are left as they are, see `find`.
+    pub fn discover() -> Option<Self> {
+        Self::find(Path::new(".")).ok()
+    }
+
+    /// Same as `open`, but interrupted workflows are left as they are, e.g. to inspect them.
+    pub fn find(path: &Path) -> Result<Self, std::io::Error> {
+        let mut current_dir = resolve_lenient(&std::env::current_dir()?.join(path))?;
 
         loop {
             let vx_path = current_dir.join(DATA_FOLDER);
@@ -44,3 +488,45 @@ impl Context {
         ))
     }
 }
+
+/// Canonicalizes the existing part of an absolute path, resolving symlinks, and normalizes the
+/// rest lexically.
+pub(crate) fn resolve_lenient(path: &Path) -> Result<PathBuf, std::io::Error> {
+    let mut resolved = PathBuf::new();
+    let mut exists = true;
+    for component in path.components() {
+        match component {
+            Component::Prefix(_) | Component::RootDir => resolved.push(component),
+            Component::CurDir => (),
+            Component::ParentDir => {
+                resolved.pop();
+            }
+            Component::Normal(name) => {
+                resolved.push(name);
+                if exists {
+                    match resolved.canonicalize() {
+                        Ok(canonical) => resolved = canonical,
+                        Err(err) if err.kind() == ErrorKind::NotFound => exists = false,
+                        Err(err) => return Err(err),
+                    }
+                }
+            }
+        }
+    }
+    Ok(resolved)
+}
+
+/// Sums up the sizes of all files in a directory, recursively.
+fn dir_size(path: &Path) -> Result<u64, std::io::Error> {
+    let mut size = 0;
+    for entry in std::fs::read_dir(path)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        if metadata.is_dir() {
+            size += dir_size(&entry.path())?;
+        } else {
+            size += metadata.len();
+        }
+    }
+    Ok(size)
+}
@@ -0,0 +1,183 @@
+use crate::context::Context;
+use crate::core::blob::{ContentKind, Conversion, LineEndings};
+use crate::core::ignore::matches_pattern;
+use crate::storage::tree::TreeError;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// Name of the files setting attributes of the paths in their folder and below, similar to
+/// `.gitattributes`.
+/// Every line holds a pattern followed by attributes separated by whitespace, e.g.
+/// `*.bat eol=crlf` or `assets/*.svg binary`, and lines starting with `#` are comments.
+pub const ATTRIBUTES_FILE_NAME: &str = ".vxattributes";
+
+/// Reads the content of the attributes file of a folder, if there is one.
+type Reader<'a> = Box<dyn Fn(&Path) -> Result<Option<Vec<u8>>, TreeError> + Send + Sync + 'a>;
+
+/// Parsed rules of folders by path relative to the root.
+type Folders = HashMap<PathBuf, Arc<Vec<Rule>>>;
+
+/// Per-path settings of how files are converted between the checkout and the blob store.
+/// Attributes files are looked up from the root folder down to the folder of a file, so the
+/// deepest matching line wins, and within a file the last one does.
+/// Supported attributes are:
+/// - `binary` and `text`, forcing the kind of the content instead of detecting it,
+/// - `eol=keep|lf|crlf|native`, overriding the line ending policy of the repository.
+///
+/// Unknown attributes and values are skipped, so files written for newer versions still apply.
+pub struct Attributes<'a> {
+    eol: LineEndings,
+    read: Option<Reader<'a>>,
+    /// Rules of every folder looked at so far.
+    folders: Mutex<Folders>,
+}
+
+/// A line of an attributes file.
+#[derive(Debug, Clone)]
+struct Rule {
+    pattern: String,
+    kind: Option<ContentKind>,
+    eol: Option<LineEndings>,
+}
+
+impl<'a> Attributes<'a> {
+    /// Attributes that leave every file to the line ending policy given.
+    pub fn none(eol: LineEndings) -> Self {
+        Attributes {
+            eol,
+            read: None,
+            folders: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Attributes read from the files in the checkout on top of the line ending policy given.
+    pub fn from_checkout(context: &'a Context, eol: LineEndings) -> Self {
+        let read = move |dir: &Path| match std::fs::read(
+            context.checkout_path.join(dir).join(ATTRIBUTES_FILE_NAME),
+        ) {
+            Ok(content) => Ok(Some(content)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        };
+        Self::from_reader(eol, read)
+    }
+
+    /// Attributes read by a function returning the content of the attributes file of a folder,
+    /// e.g. from a vx tree about to be written into the checkout.
+    pub(crate) fn from_reader(
+        eol: LineEndings,
+        read: impl Fn(&Path) -> Result<Option<Vec<u8>>, TreeError> + Send + Sync + 'a,
+    ) -> Self {
+        Attributes {
+            eol,
+            read: Some(Box::new(read)),
+            folders: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Resolves the conversion of a file by its path relative to the root of the checkout.
+    pub fn resolve(&self, path: &Path) -> Result<Conversion, TreeError> {
+        let mut conversion = Conversion {
+            eol: self.eol,
+            kind: None,
+        };
+        if self.read.is_none() {
+            return Ok(conversion);
+        }
+
+        let mut dir = PathBuf::new();
+        let mut components = path.components().peekable();
+        while let Some(component) = components.next() {
+            let rules = self.rules(&dir)?;
+            // a file is matched by its path relative to the folder of the attributes file
+            let relative = path.strip_prefix(&dir).unwrap_or(path);
+            for rule in rules.iter().filter(|rule| rule.matches(relative)) {
+                conversion.kind = rule.kind.or(conversion.kind);
+                conversion.eol = rule.eol.unwrap_or(conversion.eol);
+            }
+
+            if components.peek().is_none() {
+                break;
+            }
+            dir.push(component);
+        }
+        Ok(conversion)
+    }
+
+    /// Gets the rules of a folder, reading its attributes file the first time it is looked at.
+    fn rules(&self, dir: &Path) -> Result<Arc<Vec<Rule>>, TreeError> {
+        if let Some(rules) = self.lock()?.get(dir) {
+            return Ok(rules.clone());
+        }
+
+        // the lock is not held while reading, a folder read twice by two workers is parsed twice
+        let rules = match &self.read {
+            Some(read) => read(dir)?
+                .map(|content| parse(&String::from_utf8_lossy(&content)))
+                .unwrap_or_default(),
+            None => Vec::new(),
+        };
+        let rules = Arc::new(rules);
+        self.lock()?.insert(dir.to_path_buf(), rules.clone());
+        Ok(rules)
+    }
+
+    fn lock(&self) -> Result<MutexGuard<'_, Folders>, TreeError> {
+        self.folders
+            .lock()
+            .map_err(|_| TreeError::Other("Attributes cache is poisoned".to_string()))
+    }
+}
+
+impl Rule {
+    /// Patterns without a slash match file names at any depth, the others match the whole path
+    /// relative to the folder of the attributes file.
+    fn matches(&self, relative: &Path) -> bool {
+        if self.pattern.contains('/') {
+            let path: Vec<&str> = relative.iter().filter_map(|name| name.to_str()).collect();
+            matches_pattern(self.pattern.trim_start_matches('/'), &path.join("/"))
+        } else {
+            match relative.file_name().and_then(|name| name.to_str()) {
+                Some(name) => matches_pattern(&self.pattern, name),
+                None => false,
+            }
+        }
+    }
+}
+
+/// Parses the content of an attributes file, lines without any known attribute are skipped.
+fn parse(content: &str) -> Vec<Rule> {
+    let mut rules = Vec::new();
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut words = line.split_whitespace();
+        let Some(pattern) = words.next() else {
+            continue;
+        };
+        let mut rule = Rule {
+            pattern: pattern.to_string(),
+            kind: None,
+            eol: None,
+        };
+        for attribute in words {
+            match attribute {
+                "binary" => rule.kind = Some(ContentKind::Binary),
+                "text" => rule.kind = Some(ContentKind::Text),
+                _ => {
+                    if let Some(eol) = attribute.strip_prefix("eol=").and_then(LineEndings::parse) {
+                        rule.eol = Some(eol);
+                    }
+                }
+            }
+        }
+        if rule.kind.is_some() || rule.eol.is_some() {
+            rules.push(rule);
+        }
+    }
+    rules
+}
@@ -0,0 +1,147 @@
+// Binary search over the commits of a branch for the first one introducing a problem.
+//
+// Commits of a branch are numbered contiguously, so the commits between a good and a bad one are
+// a plain range of sequence numbers and its midpoint is found without walking any history.
+
+use crate::context::Context;
+use crate::core::commit::{Commit, CommitID, CurrentCommitSpec};
+use crate::core::tree::Tree;
+use crate::storage::commit::{self as commitstore, CommitError};
+use serde::{Deserialize, Serialize};
+
+/// State of a bisect in progress, kept in the workspace between invocations until it is reset.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Bisect {
+    /// Branch whose commits are searched.
+    pub branch: u64,
+    /// Sequence number of the latest commit known to be good.
+    pub good: u64,
+    /// Sequence number of the earliest commit known to be bad.
+    pub bad: u64,
+    /// Commit checked out when the bisect started, checked out again once it is reset.
+    pub origin: CommitID,
+}
+
+/// Where a bisect stands after a step.
+#[derive(Debug, Clone)]
+pub enum BisectStep {
+    /// The commit checked out to be tested next, with the number of steps left at most.
+    Testing(Commit, u32),
+    /// The first bad commit, checked out, nothing is left to test.
+    Found(Commit),
+}
+
+impl Bisect {
+    /// Starts a bisect between a commit known to be good and a later commit of the same branch
+    /// known to be bad, and checks out the commit halfway between them.
+    /// Refused while another bisect is in progress or the checkout has local changes, as those
+    /// would be overwritten by the checkouts of the tested commits.
+    pub fn start(context: &Context, good: &str, bad: &str) -> Result<BisectStep, CommitError> {
+        if commitstore::get_bisect(context)?.is_some() {
+            return Err(CommitError::Other(
+                "A bisect is already in progress, reset it first".to_string(),
+            ));
+        }
+        let changes = Tree::get_changed_files(context)
+            .map_err(|e| CommitError::Other(format!("Failed to get changed files: {:?}", e)))?;
+        if !changes.is_empty() {
+            return Err(CommitError::Other(format!(
+                "Checkout has {} uncommitted change(s), commit or stash them first",
+                changes.len()
+            )));
+        }
+
+        let good = Commit::get_by_spec(context, good)?;
+        let bad = Commit::get_by_spec(context, bad)?;
+        if good.id.branch != bad.id.branch {
+            return Err(CommitError::Other(
+                "The good and the bad commit must be on the same branch".to_string(),
+            ));
+        }
+        if good.id.seq >= bad.id.seq {
+            return Err(CommitError::Other(format!(
+                "The good commit {} must come before the bad commit {}",
+                good.id.seq, bad.id.seq
+            )));
+        }
+
+        let bisect = Bisect {
+            branch: good.id.branch,
+            good: good.id.seq,
+            bad: bad.id.seq,
+            origin: CurrentCommitSpec::get(context)?.commit_id,
+        };
+        commitstore::save_bisect(context, &bisect)?;
+        bisect.next(context)
+    }
+
+    /// Marks the checked out commit as good or bad, narrowing the range accordingly, and checks
+    /// out the next commit to test, or the first bad commit once the range is narrowed to it.
+    pub fn mark(context: &Context, good: bool) -> Result<BisectStep, CommitError> {
+        let mut bisect = Self::get_required(context)?;
+        let current = CurrentCommitSpec::get(context)?.commit_id;
+        if current.branch != bisect.branch
+            || current.seq <= bisect.good
+            || current.seq >= bisect.bad
+        {
+            return Err(CommitError::Other(format!(
+                "Commit {} is not between the good commit {} and the bad commit {}",
+                current.seq, bisect.good, bisect.bad
+            )));
+        }
+
+        if good {
+            bisect.good = current.seq;
+        } else {
+            bisect.bad = current.seq;
+        }
+        commitstore::save_bisect(context, &bisect)?;
+        bisect.next(context)
+    }
+
+    /// Ends the bisect and checks out the commit it was started at, which is returned.
+    pub fn reset(context: &Context) -> Result<Commit, CommitError> {
+        let bisect = Self::get_required(context)?;
+        Tree::checkout_id(context, bisect.origin)
+            .map_err(|e| CommitError::Other(format!("Failed to checkout commit: {:?}", e)))?;
+        commitstore::clear_bisect(context)?;
+        Commit::get(context, bisect.origin)
+    }
+
+    /// Retrieves the bisect in progress, if any.
+    pub fn get(context: &Context) -> Result<Option<Self>, CommitError> {
+        commitstore::get_bisect(context)
+    }
+
+    fn get_required(context: &Context) -> Result<Self, CommitError> {
+        Self::get(context)?.ok_or_else(|| CommitError::Other("No bisect in progress".to_string()))
+    }
+
+    /// Checks out the commit halfway between the good and the bad one, or the bad one if they are
+    /// adjacent.
+    fn next(&self, context: &Context) -> Result<BisectStep, CommitError> {
+        let left = self.bad - self.good;
+        let seq = if left > 1 {
+            self.good + left / 2
+        } else {
+            self.bad
+        };
+        let commit_id = CommitID {
+            branch: self.branch,
+            seq,
+        };
+        let current = CurrentCommitSpec::get(context)?.commit_id;
+        if (current.branch, current.seq) != (commit_id.branch, commit_id.seq) {
+            Tree::checkout_id(context, commit_id)
+                .map_err(|e| CommitError::Other(format!("Failed to checkout commit: {:?}", e)))?;
+        }
+
+        let commit = Commit::get(context, commit_id)?;
+        if left > 1 {
+            // every step halves the commits between the good and the bad one
+            Ok(BisectStep::Testing(commit, (left - 1).ilog2() + 1))
+        } else {
+            Ok(BisectStep::Found(commit))
+        }
+    }
+}
@@ -1,41 +1,431 @@
 use crate::context::Context;
-use crate::core::digest::Digest;
-use crate::storage::blob::{self as blobstore, BlobError};
+use crate::core::digest::{Digest, DigestExt};
+use crate::core::repo::Repo;
+use crate::storage::blob::{self as blobstore, BlobError, BlobReader};
+use crate::storage::repo::RepoError;
 use serde::{Deserialize, Serialize};
 use sled::Db;
-use std::path::Path;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
 
 /// Represents a binary large object (Blob).
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Blob {
     /// Hash of the blob's content, used as a unique identifier
     pub contenthash: Digest,
-    /// Size of the blob in bytes
+    /// Size of the content of the blob in bytes, before it is compressed in the store
     pub size: u64,
+    /// Whether the content is text or binary, detected when the blob is stored.
+    /// It is not a part of the content hash.
+    pub kind: ContentKind,
+}
+
+/// Number of bytes at the start of a blob that are checked to tell binary content from text.
+pub const KIND_CHECK_SIZE: usize = 8000;
+
+/// Kind of the content of a blob.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ContentKind {
+    /// Not detected, for blobs stored before the kind was introduced.
+    Unknown,
+    Text,
+    Binary,
+}
+
+impl ContentKind {
+    /// Detects the kind of content from its first `KIND_CHECK_SIZE` bytes.
+    /// Similar to git, content with a null byte is binary, and so is content which is not UTF-8.
+    pub fn detect(head: &[u8]) -> Self {
+        let head = &head[..head.len().min(KIND_CHECK_SIZE)];
+        if head.contains(&0) {
+            return ContentKind::Binary;
+        }
+        match std::str::from_utf8(head) {
+            Ok(_) => ContentKind::Text,
+            // the checked part may end in the middle of a character
+            Err(err) if err.error_len().is_none() => ContentKind::Text,
+            Err(_) => ContentKind::Binary,
+        }
+    }
+}
+
+/// Repository metadata key of the compression level of new blobs, from 0 storing the content as
+/// it is to `MAX_COMPRESSION_LEVEL` searching the longest for repeated content.
+pub const COMPRESSION_LEVEL_KEY: &str = "blob.compressionLevel";
+
+/// Compression level used unless `COMPRESSION_LEVEL_KEY` is set.
+pub const DEFAULT_COMPRESSION_LEVEL: u32 = 3;
+
+/// Highest compression level.
+pub const MAX_COMPRESSION_LEVEL: u32 = 9;
+
+/// Reads the compression level of new blobs from repository metadata, refusing invalid values.
+/// Blobs stored with another level, or without compression, are read all the same.
+pub fn compression_level(metadata: &HashMap<String, String>) -> Result<u32, RepoError> {
+    match metadata.get(COMPRESSION_LEVEL_KEY) {
+        Some(value) => value
+            .parse()
+            .ok()
+            .filter(|level| *level <= MAX_COMPRESSION_LEVEL)
+            .ok_or_else(|| {
+                RepoError::Other(format!(
+                    "Invalid value '{}' of {}, expected a level from 0 to {}",
+                    value, COMPRESSION_LEVEL_KEY, MAX_COMPRESSION_LEVEL
+                ))
+            }),
+        None => Ok(DEFAULT_COMPRESSION_LEVEL),
+    }
+}
+
+/// Repository metadata key of the line ending policy for text files, see `LineEndings`.
+pub const LINE_ENDINGS_KEY: &str = "text.eol";
+
+/// How line endings of text files are converted between the checkout and the blob store.
+/// Every policy other than `Keep` stores text with LF line endings, so checkouts on different
+/// platforms produce identical blobs. Binary files are never converted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LineEndings {
+    /// Files are stored and written as they are.
+    #[default]
+    Keep,
+    /// Text files are stored and written with LF line endings.
+    Lf,
+    /// Text files are stored with LF line endings and written with CRLF ones.
+    Crlf,
+    /// Text files are stored with LF line endings and written with the ones of the platform,
+    /// i.e. CRLF on Windows and LF elsewhere.
+    Native,
+}
+
+impl LineEndings {
+    /// Loads the line ending policy of the repository, files are kept as they are by default.
+    pub fn load(context: &Context) -> Result<Self, RepoError> {
+        Self::from_metadata(&Repo::get_metadata(context)?)
+    }
+
+    /// Reads the line ending policy from repository metadata, refusing unknown values.
+    pub fn from_metadata(metadata: &HashMap<String, String>) -> Result<Self, RepoError> {
+        match metadata.get(LINE_ENDINGS_KEY) {
+            Some(value) => Self::parse(value).ok_or_else(|| {
+                RepoError::Other(format!(
+                    "Invalid value '{}' of {}, expected keep, lf, crlf or native",
+                    value, LINE_ENDINGS_KEY
+                ))
+            }),
+            None => Ok(LineEndings::Keep),
+        }
+    }
+
+    /// Parses a policy from its name as stored in the repository metadata.
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "keep" => Some(LineEndings::Keep),
+            "lf" => Some(LineEndings::Lf),
+            "crlf" => Some(LineEndings::Crlf),
+            "native" => Some(LineEndings::Native),
+            _ => None,
+        }
+    }
+
+    /// Name of the policy as stored in the repository metadata.
+    pub fn name(self) -> &'static str {
+        match self {
+            LineEndings::Keep => "keep",
+            LineEndings::Lf => "lf",
+            LineEndings::Crlf => "crlf",
+            LineEndings::Native => "native",
+        }
+    }
+
+    /// Returns true if CRLF line endings of text files are replaced with LF ones when stored.
+    pub fn normalizes(self) -> bool {
+        self != LineEndings::Keep
+    }
+
+    /// Returns true if text files are written to the checkout with CRLF line endings.
+    pub fn writes_crlf(self) -> bool {
+        match self {
+            LineEndings::Crlf => true,
+            LineEndings::Native => cfg!(windows),
+            LineEndings::Keep | LineEndings::Lf => false,
+        }
+    }
+}
+
+/// How a file is converted between the checkout and the blob store, resolved for its path from
+/// the repository policy and `.vxattributes` files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Conversion {
+    /// Line ending policy of the file.
+    pub eol: LineEndings,
+    /// Kind of the content forced by attributes, detected from the content if not set.
+    pub kind: Option<ContentKind>,
+}
+
+impl Conversion {
+    /// Returns true if the content is to be treated as text.
+    fn is_text(self, content: &[u8]) -> bool {
+        match self.kind {
+            Some(kind) => kind == ContentKind::Text,
+            None => ContentKind::detect(content) == ContentKind::Text,
+        }
+    }
+
+    /// Converts the content of a file in the checkout to the content to be stored.
+    pub(crate) fn to_stored(self, content: &[u8]) -> Cow<'_, [u8]> {
+        if !self.eol.normalizes() || !self.is_text(content) {
+            return Cow::Borrowed(content);
+        }
+        if !content.contains(&b'\r') {
+            return Cow::Borrowed(content);
+        }
+
+        let mut stored = Vec::with_capacity(content.len());
+        for (i, &byte) in content.iter().enumerate() {
+            if byte == b'\r' && content.get(i + 1) == Some(&b'\n') {
+                continue;
+            }
+            stored.push(byte);
+        }
+        Cow::Owned(stored)
+    }
+
+    /// Converts stored content to the content of a file to be written into the checkout.
+    pub(crate) fn to_checkout(self, content: &[u8]) -> Cow<'_, [u8]> {
+        if !self.eol.writes_crlf() || !self.is_text(content) {
+            return Cow::Borrowed(content);
+        }
+
+        let mut converted = Vec::with_capacity(content.len() + content.len() / 32);
+        for (i, &byte) in content.iter().enumerate() {
+            // text stored before the policy was set may still contain CRLF
+            if byte == b'\n' && (i == 0 || content[i - 1] != b'\r') {
+                converted.push(b'\r');
+            }
+            converted.push(byte);
+        }
+        Cow::Owned(converted)
+    }
+}
+
+/// Aggregated statistics of the blob store.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BlobStats {
+    /// Number of unique blobs.
+    pub count: u64,
+    /// Total size of the content of unique blobs in bytes, before compression.
+    pub size: u64,
+    /// Total size of the files in the blob store on disk in bytes.
+    pub disk_size: u64,
+}
+
+/// Result of a consistency scan of the blob store.
+#[derive(Debug, Clone, Default)]
+pub struct VerifyReport {
+    /// Number of blobs registered in the database that were checked.
+    pub checked: u64,
+    /// Blobs whose stored content does not match their hash.
+    pub mismatched: Vec<Digest>,
+    /// Blobs registered in the database but missing on disk.
+    pub missing: Vec<Digest>,
+    /// Files on disk in the blob store which are not registered in the database.
+    pub orphans: Vec<PathBuf>,
+}
+
+impl VerifyReport {
+    /// Returns true if no inconsistencies were found.
+    pub fn is_ok(&self) -> bool {
+        self.mismatched.is_empty() && self.missing.is_empty() && self.orphans.is_empty()
+    }
 }
 
 impl Blob {
+    /// Aggregates the number and sizes of stored blobs.
+    pub fn stats(context: &Context) -> Result<BlobStats, BlobError> {
+        let db = blobstore::open(context)?;
+        blobstore::stats(context, &db)
+    }
+
+    /// Scans the whole blob store, recomputing content hashes of all registered blobs and looking for
+    /// files that are not registered.
+    pub fn verify_all(context: &Context) -> Result<VerifyReport, BlobError> {
+        let db = blobstore::open(context)?;
+        blobstore::verify_all(context, &db)
+    }
+
+    /// Reads up to `len` bytes of the content of a stored blob starting at `offset`, e.g. to preview
+    /// the head of a large file without reading all of it.
+    pub fn read_range(
+        context: &Context,
+        contenthash: Digest,
+        offset: u64,
+        len: u64,
+    ) -> Result<Vec<u8>, BlobError> {
+        let db = blobstore::open(context)?;
+        blobstore::read_range(context, &db, contenthash, offset, len)
+    }
+
+    /// Stores the content of a file as it is, without any line ending conversion, e.g. to
+    /// precompute the hash of content for scripts and fixtures. Importing the same content again
+    /// returns the stored blob. Until a commit references it, the blob is reported as unreachable
+    /// by the garbage collection.
+    pub fn import(context: &Context, file_path: &Path) -> Result<Self, BlobError> {
+        let db = blobstore::open(context)?;
+        let blob = blobstore::from_file(context, &db, file_path)?;
+        context.flush(&db)?;
+        Ok(blob)
+    }
+
+    /// Lists metadata of all stored blobs.
+    pub(crate) fn list(context: &Context) -> Result<Vec<Self>, BlobError> {
+        let db = blobstore::open(context)?;
+        blobstore::list(&db)
+    }
+
+    /// Deletes blobs from the store along with their files, e.g. unreachable ones found by a
+    /// garbage collection.
+    pub(crate) fn delete(context: &Context, hashes: &[Digest]) -> Result<(), BlobError> {
+        let db = blobstore::open(context)?;
+        blobstore::delete(context, &db, hashes)
+    }
+
     /// Opens the blob database.
     pub(crate) fn open(context: &Context) -> Result<Db, BlobError> {
         blobstore::open(context)
     }
 
     /// Creates a `Blob` from a file, compute digest and size, and store it in the database.
+    /// Line endings of text files are converted, and the kind is overridden, as the conversion
+    /// says.
     pub(crate) fn from_file(
         context: &Context,
         db: &Db,
         file_path: &Path,
+        conversion: Conversion,
+    ) -> Result<Self, BlobError> {
+        let mut blob = match read_text(file_path, conversion)? {
+            Some(content) => blobstore::from_bytes(context, db, &conversion.to_stored(&content))?,
+            None => blobstore::from_file(context, db, file_path)?,
+        };
+        // the stored kind is the detected one, the forced kind is kept with the file in the tree
+        if let Some(kind) = conversion.kind {
+            blob.kind = kind;
+        }
+        Ok(blob)
+    }
+
+    /// Computes the hash a file in the checkout would be stored with, without storing it.
+    pub(crate) fn hash_file(
+        file_path: &Path,
+        conversion: Conversion,
+    ) -> Result<Digest, std::io::Error> {
+        match read_text(file_path, conversion)? {
+            Some(content) => Ok(Digest::compute_hash_bytes(&conversion.to_stored(&content))),
+            None => Ok(Digest::compute_hash(file_path)?.0),
+        }
+    }
+
+    /// Creates a `Blob` from an in-memory content and stores it in the database.
+    pub(crate) fn from_bytes(
+        context: &Context,
+        db: &Db,
+        content: &[u8],
     ) -> Result<Self, BlobError> {
-        blobstore::from_file(context, db, file_path)
+        blobstore::from_bytes(context, db, content)
     }
 
     /// Copies a `Blob` to a file by calling the appropriate function from storage.
+    /// Line endings of text files are converted as the conversion says.
     pub(crate) fn to_file(
         context: &Context,
         db: &Db,
         contenthash: Digest,
         dest_path: &Path,
+        conversion: Conversion,
     ) -> Result<(), BlobError> {
-        blobstore::to_file(context, db, contenthash, dest_path)
+        // known binaries are copied as they are without being read
+        if !conversion.eol.writes_crlf()
+            || conversion.kind == Some(ContentKind::Binary)
+            || (conversion.kind.is_none()
+                && blobstore::get_blob_metadata(db, contenthash)?.kind == ContentKind::Binary)
+        {
+            return blobstore::to_file(context, db, contenthash, dest_path);
+        }
+
+        let content = blobstore::read(context, db, contenthash)?;
+        let content = conversion.to_checkout(&content);
+        if let Err(e) = std::fs::write(dest_path, &content) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                return Err(e.into());
+            }
+            if let Some(parent) = dest_path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(dest_path, &content)?;
+        }
+        Ok(())
+    }
+
+    /// Opens the content of a `Blob` for streaming.
+    pub(crate) fn open_content(
+        context: &Context,
+        db: &Db,
+        contenthash: Digest,
+    ) -> Result<BlobReader, BlobError> {
+        blobstore::open_content(context, db, contenthash)
+    }
+
+    /// Writes the content of a `Blob` to a stream, converting line endings of text files as the
+    /// conversion says, the way `to_file` writes it to a file.
+    pub(crate) fn to_writer(
+        context: &Context,
+        db: &Db,
+        contenthash: Digest,
+        out: &mut impl Write,
+        conversion: Conversion,
+    ) -> Result<(), BlobError> {
+        let content = blobstore::read(context, db, contenthash)?;
+        out.write_all(&conversion.to_checkout(&content))?;
+        Ok(())
+    }
+
+    /// Retrieves metadata of a stored `Blob`.
+    pub(crate) fn get(db: &Db, contenthash: Digest) -> Result<Self, BlobError> {
+        blobstore::get_blob_metadata(db, contenthash)
+    }
+
+    /// Reads the whole content of a `Blob` into memory.
+    pub(crate) fn read(
+        context: &Context,
+        db: &Db,
+        contenthash: Digest,
+    ) -> Result<Vec<u8>, BlobError> {
+        blobstore::read(context, db, contenthash)
+    }
+}
+
+/// Reads the whole content of a text file if its line endings may need to be converted on the way
+/// to the blob store. Binary files are told by their head, so they are not read in full.
+fn read_text(file_path: &Path, conversion: Conversion) -> Result<Option<Vec<u8>>, std::io::Error> {
+    if !conversion.eol.normalizes() {
+        return Ok(None);
+    }
+    match conversion.kind {
+        Some(ContentKind::Binary) => return Ok(None),
+        Some(_) => return Ok(Some(std::fs::read(file_path)?)),
+        None => {}
+    }
+
+    let mut file = std::fs::File::open(file_path)?;
+    let mut content = Vec::new();
+    (&mut file)
+        .take(KIND_CHECK_SIZE as u64)
+        .read_to_end(&mut content)?;
+    if ContentKind::detect(&content) != ContentKind::Text {
+        return Ok(None);
     }
+    file.read_to_end(&mut content)?;
+    Ok(Some(content))
 }
@@ -1,7 +1,15 @@
 use crate::context::Context;
 use crate::core::commit::{Commit, CommitID, CurrentCommitSpec};
+use crate::core::digest::{Digest, DigestExt};
+use crate::core::merge::Conflict;
+use crate::core::repo::{Repo, CREATED_BRANCH_KEY};
+use crate::core::stash::Stash;
+use crate::core::tag::Tag;
+use crate::core::tree::Tree;
 use crate::storage::branch::{self as branchstore, BranchError};
+use crate::storage::commit as commitstore;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
 /// Represents a branch in the version control system.
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -20,8 +28,58 @@ pub struct Branch {
     pub parentseq: u64,
 }
 
+/// Outcome of merging a branch into the current one.
+#[derive(Debug, Clone)]
+pub enum BranchMerge {
+    /// The merged branch has no changes missing from the current one, nothing was done.
+    UpToDate,
+    /// The changes merged cleanly and were committed on top of the current branch.
+    Committed(Commit),
+    /// The changes were written into the checkout, conflicting files are left as the conflicts
+    /// describe to be resolved and committed by hand.
+    Conflicts(Vec<Conflict>),
+}
+
 const FOUNDATIONAL_ID: u64 = 0;
 
+/// Repository metadata key marking a branch as protected from direct commits.
+fn protected_key(name: &str) -> String {
+    format!("branch.{}.protected", name)
+}
+
+/// Prefix of the repository metadata keys recording the last merge between two branches.
+const MERGED_KEY_PREFIX: &str = "branch.merged.";
+
+/// Repository metadata key recording the last merge of branch `source` into branch `target`.
+/// Branch names may contain dots, so the key is made of their ids.
+fn merged_key(target: u64, source: u64) -> String {
+    format!("{}{:016x}.{:016x}", MERGED_KEY_PREFIX, target, source)
+}
+
+/// Record of a merge, the commit of the merge on the target branch and the head of the source
+/// branch it merged.
+struct MergeRecord {
+    seq: u64,
+    hash: Digest,
+    merged: u64,
+}
+
+impl MergeRecord {
+    fn parse(value: &str) -> Option<MergeRecord> {
+        let mut parts = value.split(':');
+        let record = MergeRecord {
+            seq: parts.next()?.parse().ok()?,
+            hash: Digest::from_hex_string(parts.next()?)?,
+            merged: parts.next()?.parse().ok()?,
+        };
+        parts.next().is_none().then_some(record)
+    }
+
+    fn format(&self) -> String {
+        format!("{}:{}:{}", self.seq, self.hash.to_hex_string(), self.merged)
+    }
+}
+
 impl Branch {
     /// Creates a new Branch instance off the current commit.
     pub fn new(context: &Context, name: String) -> Result<Self, BranchError> {
@@ -40,6 +98,11 @@ impl Branch {
             ));
         }
 
+        // the centinel commit copies the full message of the current one
+        let message = commit
+            .get_message(context)
+            .map_err(|e| BranchError::Other(format!("Failed to get commit message: {}", e)))?;
+
         // Create the branch
         let branch = branchstore::new(
             context,
@@ -53,7 +116,7 @@ impl Branch {
         // TODO: potential race condition here, we have a branch but no commit yet. By design every branch
         // must have at least one commit.
         let branch_commit =
-            Commit::create_zero_commit(context, branch.id, commit.treehash, commit.message)
+            Commit::create_zero_commit(context, branch.id, commit.treehash, message, commit.author)
                 .map_err(|e| {
                     BranchError::Other(format!("Failed to create centinel commit: {}", e))
                 })?;
@@ -72,14 +135,46 @@ impl Branch {
         Ok(branch)
     }
 
+    /// Creates a new foundational branch with no history, its centinel commit has an empty tree.
+    /// The new branch becomes current, the checkout is not touched.
+    pub(crate) fn new_orphan(context: &Context, name: String) -> Result<Self, BranchError> {
+        validate_branch_name(&name)?;
+
+        let tree = Tree::create_empty(context)
+            .map_err(|e| BranchError::Other(format!("Failed to create empty tree: {}", e)))?;
+        let branch = Self::create_foundational_branch(context, name)?;
+
+        // TODO: same race condition as in `new`, the branch exists before its centinel commit.
+        let commit = Commit::create_zero_commit(
+            context,
+            branch.id,
+            tree.hash,
+            String::from("Initial commit"),
+            String::new(),
+        )
+        .map_err(|e| BranchError::Other(format!("Failed to create centinel commit: {}", e)))?;
+
+        let current = CurrentCommitSpec {
+            commit_id: commit.id,
+            ver: branch.ver,
+            rebuild_seq: CurrentCommitSpec::NO_REBUILD,
+            rebuild_ver: CurrentCommitSpec::NO_REBUILD,
+        };
+
+        current
+            .save(context)
+            .map_err(|e| BranchError::Other(format!("Failed to set current branch: {}", e)))?;
+
+        Ok(branch)
+    }
+
     /// Retrieves the current branch based on the current commit.
     pub fn get_current(context: &Context) -> Result<Self, BranchError> {
-        // Get the current commit to find out which branch we're on
-        let current_commit = Commit::get_current(context)
+        // The current commit spec already knows the branch, no need to load the commit itself
+        let current = CurrentCommitSpec::get(context)
             .map_err(|e| BranchError::Other(format!("Failed to get current commit: {}", e)))?;
 
-        // Retrieve the branch using the branch ID from the current commit
-        Self::get(context, current_commit.id.branch)
+        Self::get(context, current.commit_id.branch)
     }
 
     /// Checks if this branch is the foundational branch (not based on any other branch).
@@ -87,6 +182,210 @@ impl Branch {
         self.parent == FOUNDATIONAL_ID
     }
 
+    /// Number of commits made in this branch on top of its centinel commit.
+    pub fn ahead(&self) -> u64 {
+        self.headseq - CommitID::SEQ_ZERO
+    }
+
+    /// Number of commits made in the parent branch since this branch was forked off it.
+    pub fn behind(&self, parent: &Branch) -> u64 {
+        parent.headseq.saturating_sub(self.parentseq)
+    }
+
+    /// Total size of the files in the tree of the head commit of the branch, in bytes.
+    /// The size is stored with the tree, so only the head commit and its root tree are read.
+    pub fn head_size(&self, context: &Context) -> Result<u64, BranchError> {
+        let head = Commit::get(
+            context,
+            CommitID {
+                branch: self.id,
+                seq: self.headseq,
+            },
+        )
+        .map_err(|e| BranchError::Other(format!("Failed to get head commit: {:?}", e)))?;
+        Tree::total_size(context, &[head.treehash])
+            .map_err(|e| BranchError::Other(format!("Failed to get head tree: {:?}", e)))
+    }
+
+    /// Checks whether direct commits to the branch are refused.
+    pub fn is_protected(&self, context: &Context) -> Result<bool, BranchError> {
+        let metadata = Repo::get_metadata(context)
+            .map_err(|e| BranchError::Other(format!("Failed to get repository metadata: {}", e)))?;
+        Ok(metadata.contains_key(&protected_key(&self.name)))
+    }
+
+    /// Protects the branch from direct commits, or lifts the protection.
+    /// The flag is kept in the repository metadata, so it applies to every checkout of the branch.
+    pub fn set_protected(&self, context: &Context, protected: bool) -> Result<(), BranchError> {
+        let value = protected.then_some("true");
+        Repo::set_metadata(context, &protected_key(&self.name), value)
+            .map_err(|e| BranchError::Other(format!("Failed to set repository metadata: {}", e)))
+    }
+
+    /// Renames a branch. As the id of a branch is derived from its name, its commits are moved
+    /// under the id of the new name along with everything pointing to them: the branches based on
+    /// it, the current commit, tags, stashes, a bisect in progress, the protection of the branch,
+    /// the records of its merges and its record as the branch the repository was created with. Commits are copied
+    /// before the branch record is moved and the old copies are removed
+    /// last, so the commits of the branch stay reachable if the rename is interrupted.
+    pub fn rename(context: &Context, old: &str, new: &str) -> Result<Branch, BranchError> {
+        validate_branch_name(new)?;
+        let branch = branchstore::get_by_name(context, old)?;
+        let _lock = context
+            .lock_branch(branch.id)
+            .map_err(|e| BranchError::Other(format!("Failed to lock branch: {}", e)))?;
+
+        // pending work refers to the branch by its id, it has to be finished first
+        let pending = Commit::get_pending_commit(context)
+            .map_err(|e| BranchError::Other(format!("Failed to get pending commit: {}", e)))?;
+        let rebuild = CurrentCommitSpec::get(context)
+            .map_err(|e| BranchError::Other(format!("Failed to get current commit: {}", e)))?
+            .is_rebuild();
+        let checkout = Tree::get_pending_checkout(context)
+            .map_err(|e| BranchError::Other(format!("Failed to get pending checkout: {}", e)))?;
+        if pending.is_some() || rebuild || checkout.is_some() {
+            return Err(BranchError::Other(
+                "A commit, rebuild or checkout is in progress, finish it before renaming"
+                    .to_string(),
+            ));
+        }
+        if branchstore::get_by_name(context, new).is_ok() {
+            return Err(BranchError::BranchExists(new.to_string()));
+        }
+        let protected = branch.is_protected(context)?;
+
+        let new_id = branchstore::branch_id(new);
+        commitstore::copy_branch(context, branch.id, new_id)
+            .map_err(|e| BranchError::Other(format!("Failed to copy commits: {}", e)))?;
+        let renamed = match branchstore::rename(context, &branch, new.to_string()) {
+            Ok(renamed) => renamed,
+            Err(err) => {
+                // the copies are not reachable without the branch record
+                commitstore::remove_branch(context, new_id)
+                    .map_err(|e| BranchError::Other(format!("Failed to remove commits: {}", e)))?;
+                return Err(err);
+            }
+        };
+        commitstore::move_branch(context, branch.id, renamed.id)
+            .map_err(|e| BranchError::Other(format!("Failed to move current commit: {}", e)))?;
+        Tag::move_branch(context, branch.id, renamed.id)
+            .map_err(|e| BranchError::Other(format!("Failed to move tags: {}", e)))?;
+        Stash::move_branch(context, branch.id, renamed.id)
+            .map_err(|e| BranchError::Other(format!("Failed to move stashes: {}", e)))?;
+        if protected {
+            renamed.set_protected(context, true)?;
+            branch.set_protected(context, false)?;
+        }
+        let metadata = Repo::get_metadata(context)
+            .map_err(|e| BranchError::Other(format!("Failed to get repository metadata: {}", e)))?;
+        move_merge_records(context, &metadata, branch.id, renamed.id)?;
+        if metadata.get(CREATED_BRANCH_KEY) == Some(&branch.name) {
+            Repo::set_metadata(context, CREATED_BRANCH_KEY, Some(new)).map_err(|e| {
+                BranchError::Other(format!("Failed to set repository metadata: {}", e))
+            })?;
+        }
+        commitstore::remove_branch(context, branch.id)
+            .map_err(|e| BranchError::Other(format!("Failed to remove commits: {}", e)))?;
+
+        Ok(renamed)
+    }
+
+    /// Merges the changes of another branch into the current one, whose head has to be checked
+    /// out without local changes. The common ancestor is the head of the other branch merged last
+    /// if the commit of that merge is still on the current branch, otherwise the commit the branch
+    /// based on the other one was forked at, or of siblings the earlier of the commits of their
+    /// parent they were forked at. The head trees of both branches are merged file by file against
+    /// the tree of the ancestor and written into the checkout, which is committed if there are no
+    /// conflicts. Committed merges are recorded in the repository metadata, a merge with conflicts
+    /// committed by hand is not, so the next merge takes the previous ancestor again.
+    pub fn merge(context: &Context, source: &str, force: bool) -> Result<BranchMerge, BranchError> {
+        let current = Commit::get_current(context)
+            .map_err(|e| BranchError::Other(format!("Failed to get current commit: {}", e)))?;
+        let target = branchstore::get(context, current.id.branch)?;
+        let source = branchstore::get_by_name(context, source)?;
+        if source.id == target.id {
+            return Err(BranchError::Other(format!(
+                "Branch '{}' is the current branch",
+                source.name
+            )));
+        }
+        if current.id.seq != target.headseq {
+            return Err(BranchError::Other(format!(
+                "Check out the head of branch '{}' to merge into it",
+                target.name
+            )));
+        }
+
+        let ancestor = if let Some(merged) = last_merged(context, &target, &source)? {
+            merged
+        } else if source.parent == target.id && !source.is_foundational() {
+            CommitID {
+                branch: target.id,
+                seq: source.parentseq,
+            }
+        } else if target.parent == source.id && !target.is_foundational() {
+            CommitID {
+                branch: source.id,
+                seq: target.parentseq,
+            }
+        } else if source.parent == target.parent && !source.is_foundational() {
+            CommitID {
+                branch: source.parent,
+                seq: source.parentseq.min(target.parentseq),
+            }
+        } else {
+            return Err(BranchError::InvalidParent(format!(
+                "Branches '{}' and '{}' have no common ancestor",
+                source.name, target.name
+            )));
+        };
+        let ancestor = Commit::get(context, ancestor)
+            .map_err(|e| BranchError::Other(format!("Failed to get common ancestor: {}", e)))?;
+        let head = Commit::get(
+            context,
+            CommitID {
+                branch: source.id,
+                seq: source.headseq,
+            },
+        )
+        .map_err(|e| BranchError::Other(format!("Failed to get head commit: {}", e)))?;
+
+        let local = Tree::create(context)
+            .map_err(|e| BranchError::Other(format!("Failed to create tree: {}", e)))?;
+        if local != current.treehash {
+            return Err(BranchError::Other(
+                "There are local changes, commit or stash them before merging".to_string(),
+            ));
+        }
+
+        let merged = Tree::merge(context, ancestor.treehash, current.treehash, head.treehash)
+            .map_err(|e| BranchError::Other(format!("Failed to merge trees: {}", e)))?;
+        if merged.hash == current.treehash && merged.conflicts.is_empty() {
+            return Ok(BranchMerge::UpToDate);
+        }
+        Tree::materialize(context, merged.hash)
+            .map_err(|e| BranchError::Other(format!("Failed to write merged files: {}", e)))?;
+        if !merged.conflicts.is_empty() {
+            return Ok(BranchMerge::Conflicts(merged.conflicts));
+        }
+
+        let message = format!("Merge branch '{}' into '{}'", source.name, target.name);
+        let outcome = Commit::new(context, message, None, force, false)
+            .map_err(|e| BranchError::Commit(Box::new(e)))?;
+        let record = MergeRecord {
+            seq: outcome.commit.id.seq,
+            hash: outcome.commit.hash,
+            merged: source.headseq,
+        };
+        Repo::set_metadata(
+            context,
+            &merged_key(target.id, source.id),
+            Some(&record.format()),
+        )
+        .map_err(|e| BranchError::Other(format!("Failed to set repository metadata: {}", e)))?;
+        Ok(BranchMerge::Committed(outcome.commit))
+    }
+
     /// Retrieves a branch from the database by name.
     pub fn get_by_name(context: &Context, name: &str) -> Result<Branch, BranchError> {
         branchstore::get_by_name(context, name)
@@ -129,6 +428,9 @@ impl Branch {
         new_headseq: u64,
         new_ver: u64,
     ) -> Result<Branch, BranchError> {
+        let _lock = context
+            .lock_branch(branch_id)
+            .map_err(|e| BranchError::Other(format!("Failed to lock branch: {}", e)))?;
         branchstore::update_headseq(context, branch_id, new_headseq, new_ver)
     }
 
@@ -141,14 +443,83 @@ impl Branch {
     // }
 }
 
-/// Validates if a branch name is valid.
+/// Checks if a name is valid for a branch or any other named reference, such as a tag.
 ///
-/// Branch names can only contain lowercase letters, numbers, and the characters: . / -
-fn validate_branch_name(name: &str) -> Result<(), BranchError> {
-    if !name
-        .chars()
-        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '.' || c == '/' || c == '-')
-    {
+/// Names can only contain lowercase letters, numbers, and the characters: . / -
+pub(crate) fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.chars().all(|c| {
+            c.is_ascii_lowercase() || c.is_ascii_digit() || c == '.' || c == '/' || c == '-'
+        })
+}
+
+/// Validates if a branch name is valid.
+/// Gets the head of branch `source` merged into branch `target` last, None if there is no record
+/// of a merge or the commit of the merge is no longer on the target branch, e.g. after an undo.
+fn last_merged(
+    context: &Context,
+    target: &Branch,
+    source: &Branch,
+) -> Result<Option<CommitID>, BranchError> {
+    let metadata = Repo::get_metadata(context)
+        .map_err(|e| BranchError::Other(format!("Failed to get repository metadata: {}", e)))?;
+    let Some(record) = metadata
+        .get(&merged_key(target.id, source.id))
+        .and_then(|value| MergeRecord::parse(value))
+    else {
+        return Ok(None);
+    };
+    if record.seq > target.headseq || record.merged > source.headseq {
+        return Ok(None);
+    }
+    let commit = Commit::get(
+        context,
+        CommitID {
+            branch: target.id,
+            seq: record.seq,
+        },
+    )
+    .map_err(|e| BranchError::Other(format!("Failed to get merge commit: {}", e)))?;
+    Ok((commit.hash == record.hash).then_some(CommitID {
+        branch: source.id,
+        seq: record.merged,
+    }))
+}
+
+/// Moves the records of the merges a renamed branch took part in to its new id.
+fn move_merge_records(
+    context: &Context,
+    metadata: &HashMap<String, String>,
+    old: u64,
+    new: u64,
+) -> Result<(), BranchError> {
+    for (key, value) in metadata {
+        let Some((target, source)) = key
+            .strip_prefix(MERGED_KEY_PREFIX)
+            .and_then(|ids| ids.split_once('.'))
+            .and_then(|(target, source)| {
+                Some((
+                    u64::from_str_radix(target, 16).ok()?,
+                    u64::from_str_radix(source, 16).ok()?,
+                ))
+            })
+        else {
+            continue;
+        };
+        if target != old && source != old {
+            continue;
+        }
+        let replace = |id: u64| if id == old { new } else { id };
+        let moved = merged_key(replace(target), replace(source));
+        Repo::set_metadata(context, &moved, Some(value))
+            .and_then(|_| Repo::set_metadata(context, key, None))
+            .map_err(|e| BranchError::Other(format!("Failed to set repository metadata: {}", e)))?;
+    }
+    Ok(())
+}
+
+pub(crate) fn validate_branch_name(name: &str) -> Result<(), BranchError> {
+    if !is_valid_name(name) {
         return Err(BranchError::InvalidName(
             "Branch names can only contain lowercase letters, numbers, and the following characters: . / -"
                 .to_string(),
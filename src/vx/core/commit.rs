@@ -1,11 +1,38 @@
 use crate::context::Context;
+use crate::core::blob::Blob;
 use crate::core::branch::Branch;
-use crate::core::digest::Digest;
-use crate::core::tree::Tree;
+use crate::core::digest::{Digest, DigestExt};
+use crate::core::merge::CONFLICT_START;
+use crate::core::repo::Repo;
+use crate::core::signing::{self, CommitSignature, Verification};
+use crate::core::tag::Tag;
+use crate::core::tree::{Change, Tree};
+use crate::storage::branch::BranchError;
 use crate::storage::commit::{self as commitstore, CommitError};
+use crate::storage::repo::RepoError;
+use crate::storage::tag::TagError;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
 use xxhash_rust::xxh3::Xxh3;
 
+/// Repository metadata key of the size in bytes above which commit messages are stored as blobs,
+/// which keeps the commit records small. Messages are always stored inline if it is not set.
+pub const MESSAGE_BLOB_SIZE_KEY: &str = "commit.messageBlobSize";
+
+/// Environment variable holding the time in seconds since the Unix epoch that new commits are
+/// dated with instead of the current time, following the reproducible builds convention.
+pub const SOURCE_DATE_EPOCH_ENV: &str = "SOURCE_DATE_EPOCH";
+
+/// Repository metadata key of the author new commits are made by, typically in "Name <email>"
+/// format.
+pub const AUTHOR_KEY: &str = "user.author";
+
+/// Environment variable holding the author new commits are made by unless the metadata sets one,
+/// e.g. from a shell profile for all repositories of a user.
+pub const AUTHOR_ENV: &str = "VX_AUTHOR";
+
 /// Identifier of a commit.
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct CommitID {
@@ -52,7 +79,7 @@ impl CommitID {
 
     /// Resolves a string in format "branch_name:seq" into a CommitID
     ///   - If spec is an integer, it's treated as a sequence number on the current branch
-    ///   - Otherwise, it's treated as a branch name with the head sequence
+    ///   - Otherwise, it's treated as a branch name with the head sequence, or as a tag name
     pub fn resolve(context: &Context, spec: &str) -> Result<Self, CommitError> {
         match spec.find(':') {
             Some(pos) => {
@@ -65,8 +92,8 @@ impl CommitID {
                 })?;
 
                 // Always look up branch by name
-                let branch = Branch::get_by_name(&context, branch_name)
-                    .map_err(|e| CommitError::Other(format!("Branch error: {:?}", e)))?;
+                let branch =
+                    Branch::get_by_name(context, branch_name).map_err(CommitError::Branch)?;
                 Ok(CommitID {
                     branch: branch.id,
                     seq,
@@ -83,13 +110,22 @@ impl CommitID {
                         })
                     }
                     Err(_) => {
-                        // No separator and spec is not an integer - treat as branch name
-                        let branch = Branch::get_by_name(&context, spec)
-                            .map_err(|e| CommitError::Other(format!("Branch error: {:?}", e)))?;
-                        Ok(CommitID {
-                            branch: branch.id,
-                            seq: branch.headseq,
-                        })
+                        // No separator and spec is not an integer - treat as branch name,
+                        // falling back to a tag name if no such branch exists
+                        match Branch::get_by_name(context, spec) {
+                            Ok(branch) => Ok(CommitID {
+                                branch: branch.id,
+                                seq: branch.headseq,
+                            }),
+                            Err(BranchError::NotFound) => match Tag::get(context, spec) {
+                                Ok(tag) => Ok(tag.commit_id),
+                                Err(TagError::NotFound) => {
+                                    Err(CommitError::UnknownSpec(spec.to_string()))
+                                }
+                                Err(e) => Err(CommitError::Other(format!("Tag error: {:?}", e))),
+                            },
+                            Err(e) => Err(CommitError::Branch(e)),
+                        }
                     }
                 }
             }
@@ -108,17 +144,207 @@ pub struct Commit {
     pub hash: Digest,
     /// The hash of the file tree root associated with the commit.
     pub treehash: Digest,
-    /// The commit message.
-    /// TODO: make it a blob?
+    /// The commit message, or only its subject if the message is stored as a blob.
+    /// Use `get_message` to get the full message in either case.
     pub message: String,
-    // TODO: add author and other metadata
+    /// Hash of the blob holding the full message, `Digest::NONE` if the message is stored inline.
+    pub message_hash: Digest,
+    /// The author of the commit, typically in "Name <email>" format, empty if not known.
+    pub author: String,
+    /// Time the commit was created at, in seconds since the Unix epoch, zero if not known.
+    /// Amended and rebuilt commits keep the time of the original commit.
+    pub created_at: u64,
+    /// Signature of the content of the commit, None if the commit is not signed.
+    /// Amended and rebuilt commits are not signed, as their content changes.
+    pub signature: Option<CommitSignature>,
+    // TODO: add other metadata
+}
+
+/// Write-ahead record of a commit that is being saved, describing all the changes to be made.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingCommit {
+    /// The commit to be saved.
+    pub commit: Commit,
+    /// The current commit specification to be set once the commit is saved.
+    pub current: CurrentCommitSpec,
+    /// Head sequence number the branch is advanced to, along with the version of the current commit.
+    /// It is above the new commit if the commits above it were rebuilt.
+    pub headseq: u64,
+}
+
+/// State of a rebuild paused on a conflict, kept until the conflicts are resolved and the rebuild is
+/// continued, or until it is aborted. The commit to resume the rebuild at is tracked by the current
+/// commit specification in the rebuild mode.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingRebuild {
+    /// The new or amended commit the commits above it are rebuilt on top of.
+    pub commit: Commit,
+    /// Head sequence number the branch is advanced to once the rebuild completes.
+    pub headseq: u64,
+    /// Number of positions the rebuilt commits move up, one if a new commit was inserted below them.
+    pub shift: u64,
+    /// Hash of the merged tree of the conflicting commit, which is checked out to resolve conflicts.
+    pub treehash: Digest,
+    /// Paths to the conflicting files, relative to the checkout root.
+    pub conflicts: Vec<PathBuf>,
+}
+
+/// Result of a workflow creating or amending a commit.
+#[derive(Debug, Clone)]
+pub struct CommitOutcome {
+    /// The new or amended commit.
+    pub commit: Commit,
+    /// Number of commits above the new or amended one that were rebuilt on top of it, zero if the
+    /// commit is the branch head.
+    pub rebuilt: u64,
+}
+
+/// Result of moving the head of a branch to one of its commits.
+#[derive(Debug, Clone)]
+pub struct ResetOutcome {
+    /// The commit the head was moved to, now the current commit.
+    pub commit: Commit,
+    /// Number of commits above it that were dropped from the branch.
+    pub dropped: u64,
+    /// Number of commits dropped earlier that were brought back, up to it.
+    pub restored: u64,
+}
+
+/// What creating a new commit would do, without doing it.
+#[derive(Debug, Clone)]
+pub struct CommitPlan {
+    /// Sequence number the new commit would get.
+    pub seq: u64,
+    /// Number of commits above it that would be rebuilt on top of it.
+    pub rebuilt: u64,
+    /// Changes of the checkout that would be committed.
+    pub changes: Vec<Change>,
+}
+
+/// Part of a commit history that belongs to a single branch.
+#[derive(Debug, Clone)]
+pub struct HistorySegment {
+    /// The branch the commits belong to.
+    pub branch: Branch,
+    /// Commits of the branch, sorted by sequence number in descending order.
+    pub commits: Vec<Commit>,
 }
 
 impl Commit {
-    /// Creates a new commit.
-    pub fn new(context: &Context, message: String) -> Result<Self, CommitError> {
-        let treehash = Tree::create(context)
-            .map_err(|e| CommitError::Other(format!("Tree error: {:?}", e)))?;
+    /// Value of `created_at` for commits created before timestamps were introduced.
+    pub const UNKNOWN_TIME: u64 = 0;
+
+    /// First line of the commit message, i.e. its subject.
+    pub fn subject(&self) -> &str {
+        self.message.lines().next().unwrap_or_default().trim_end()
+    }
+
+    /// Recomputes the hash of the commit from its stored content, for it to be checked against `hash`.
+    /// The hash covers the full message, so a message stored as a blob is read.
+    pub fn compute_hash(&self, context: &Context) -> Result<Digest, CommitError> {
+        let message = self.get_message(context)?;
+        Ok(hash_commit(
+            &message,
+            &self.author,
+            self.created_at,
+            self.treehash,
+        ))
+    }
+
+    /// Checks the signature of the commit against its content. The signature covers the full
+    /// message, so a message stored as a blob is read.
+    pub fn verify(&self, context: &Context) -> Result<Verification, CommitError> {
+        let Some(signature) = &self.signature else {
+            return Ok(Verification::Unsigned);
+        };
+        let message = self.get_message(context)?;
+        let payload = signing::payload(&message, &self.author, self.created_at, self.treehash);
+        if !signature.verify(&payload) {
+            return Ok(Verification::BadSignature);
+        }
+        Ok(Verification::Valid(signature.public_key))
+    }
+
+    /// Returns true if the message is stored as a blob rather than inline.
+    pub fn has_message_blob(&self) -> bool {
+        self.message_hash != Digest::NONE
+    }
+
+    /// Gets the full commit message, reading it from the blob store if it is stored as a blob.
+    pub fn get_message(&self, context: &Context) -> Result<String, CommitError> {
+        if !self.has_message_blob() {
+            return Ok(self.message.clone());
+        }
+        let blob_db = Blob::open(context)
+            .map_err(|e| CommitError::Other(format!("Failed to open blob store: {:?}", e)))?;
+        let content = Blob::read(context, &blob_db, self.message_hash)
+            .map_err(|e| CommitError::Other(format!("Failed to read message: {:?}", e)))?;
+        String::from_utf8(content)
+            .map_err(|e| CommitError::Other(format!("Invalid message: {:?}", e)))
+    }
+
+    /// Gets the rest of the commit message after the subject, without the separating blank lines.
+    pub fn get_body(&self, context: &Context) -> Result<String, CommitError> {
+        let message = self.get_message(context)?;
+        let body = match message.split_once('\n') {
+            Some((_, body)) => body.trim_start_matches(['\r', '\n']).trim_end(),
+            None => "",
+        };
+        Ok(body.to_string())
+    }
+
+    /// Creates a new commit on top of the current one.
+    /// If the current commit is not the branch head, the new commit is inserted after it and the
+    /// commits above are rebuilt on top of it. If their changes can not be reapplied cleanly, the
+    /// rebuild is paused with the conflicting commit checked out, see `Commit::continue_rebuild`.
+    /// The commit is dated `created_at` in seconds since the Unix epoch, e.g. to keep the dates of
+    /// imported history, or the current time if not provided, see `SOURCE_DATE_EPOCH_ENV`.
+    /// Commits to a protected branch are refused unless `force` is set.
+    /// The commit is signed with the key registered by `Repo::set_signing_key` if `sign` is set.
+    // The outcome carries the rebuild details along with the commit itself
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(
+        context: &Context,
+        message: String,
+        created_at: Option<u64>,
+        force: bool,
+        sign: bool,
+    ) -> Result<CommitOutcome, CommitError> {
+        Self::create(context, message, created_at, force, sign, None)
+    }
+
+    /// Same as `Commit::new`, with the tree of the commit given instead of captured from the
+    /// checkout if `treehash` is provided. The checkout is left as is.
+    fn create(
+        context: &Context,
+        message: String,
+        created_at: Option<u64>,
+        force: bool,
+        sign: bool,
+        treehash: Option<Digest>,
+    ) -> Result<CommitOutcome, CommitError> {
+        check_no_rebuild(context)?;
+        check_protection(context, force)?;
+        // the key and the author are read upfront, so a missing key or an invalid author is
+        // reported before anything is written
+        let key = if sign {
+            Some(
+                signing::registered_key(context)
+                    .map_err(|e| CommitError::Other(format!("Signing key error: {:?}", e)))?,
+            )
+        } else {
+            None
+        };
+        let metadata = Repo::get_metadata(context).map_err(|e| {
+            CommitError::Other(format!("Failed to get repository metadata: {:?}", e))
+        })?;
+        let author =
+            default_author(&metadata).map_err(|e| CommitError::Other(format!("{:?}", e)))?;
+
+        let treehash = match treehash {
+            Some(treehash) => treehash,
+            None => Tree::create(context).map_err(|e| CommitError::from_tree("Tree error", e))?,
+        };
 
         let commit = Commit::get_current(context)?;
 
@@ -128,8 +354,16 @@ impl Commit {
             return Err(CommitError::NoChanges);
         }
 
-        let branch = Branch::get(context, commit.id.branch)
-            .map_err(|e| CommitError::Other(format!("Branch error: {:?}", e)))?;
+        // the head is read and advanced under the lock, so concurrent commits do not race for it
+        let _lock = context.lock_branch(commit.id.branch)?;
+        let branch = Branch::get(context, commit.id.branch).map_err(CommitError::Branch)?;
+
+        if commit.id.seq > branch.headseq {
+            return Err(CommitError::Other(format!(
+                "Current commit {} is beyond the branch head {}, check out the branch again",
+                commit.id.seq, branch.headseq
+            )));
+        }
 
         let new_ver = branch.ver + 1;
 
@@ -138,42 +372,123 @@ impl Commit {
             seq: commit.id.seq + 1,
         };
 
-        let new_commit = create_commit(new_commit_id, new_ver, treehash, message);
-
-        commitstore::save(context, &new_commit)?;
+        let created_at = match created_at {
+            Some(created_at) => created_at,
+            None => now()?,
+        };
+        // the signature covers the full message, even if only its subject is kept in the commit
+        let signature = key.map(|key| {
+            let payload = signing::payload(&message, &author, created_at, treehash);
+            CommitSignature::sign(&key, &payload)
+        });
+        let mut new_commit = create_commit(
+            context,
+            new_commit_id,
+            new_ver,
+            treehash,
+            message,
+            author,
+            created_at,
+        )?;
+        new_commit.signature = signature;
 
-        if new_commit_id.seq <= branch.headseq {
-            // New commit is in the middle of the branch, so we need to rebuild the branch
-            // TODO: implement branch rebuilding
+        // New commit may be in the middle of the branch, so the commits above are moved one position
+        // up and rebuilt on top of it. They are saved right away but stay invisible until the
+        // branch is advanced.
+        let rebuild = rebuild_above(
+            context,
+            &branch,
+            commit.id.seq,
+            1,
+            commit.treehash,
+            treehash,
+            new_ver,
+        )?;
+        if let Some(conflict) = rebuild.conflict {
+            return pause_rebuild(
+                context,
+                new_commit,
+                branch.headseq + 1,
+                1,
+                &rebuild.commits,
+                conflict,
+            );
         }
+        let rebuilt = rebuild.commits;
+        save_all(context, &rebuilt)?;
 
-        // Finally save the current commit specification to advance the branch head
-        let current = CurrentCommitSpec {
-            commit_id: new_commit_id,
-            ver: new_ver,
-            rebuild_seq: CurrentCommitSpec::NO_REBUILD,
-            rebuild_ver: CurrentCommitSpec::NO_REBUILD,
+        // Record the intent before touching the commit and branch stores, so an interrupted workflow
+        // can be completed on the next run. Trees and blobs are content addressed and already saved.
+        let pending = PendingCommit {
+            commit: new_commit,
+            current: CurrentCommitSpec {
+                commit_id: new_commit_id,
+                ver: new_ver + rebuilt.len() as u64,
+                rebuild_seq: CurrentCommitSpec::NO_REBUILD,
+                rebuild_ver: CurrentCommitSpec::NO_REBUILD,
+            },
+            headseq: new_commit_id.seq + rebuilt.len() as u64,
         };
+        commitstore::save_intent(context, &pending)?;
 
-        current.save(context)?;
+        apply_pending(context, &pending)?;
 
-        // TODO: potential race condition between new commit and branch update
-        // Current commit may be recorded before the branch really updates, so in case of a failure
-        // the current commit's seq will be ahead of the branch's headseq.
+        Ok(CommitOutcome {
+            commit: pending.commit,
+            rebuilt: rebuilt.len() as u64,
+        })
+    }
 
-        Branch::advance_head(context, new_commit.id.branch, new_commit.id.seq, new_ver)
-            .map_err(|e| CommitError::Other(format!("Failed to advance branch head: {}", e)))?;
+    /// Plans a new commit on top of the current one without writing anything: runs the same checks
+    /// as `Commit::new` and lists the changes of the checkout it would capture.
+    pub fn plan_new(context: &Context, force: bool) -> Result<CommitPlan, CommitError> {
+        check_no_rebuild(context)?;
+        check_protection(context, force)?;
 
-        Ok(new_commit)
+        let commit = Commit::get_current(context)?;
+        let branch = Branch::get(context, commit.id.branch).map_err(CommitError::Branch)?;
+
+        if commit.id.seq > branch.headseq {
+            return Err(CommitError::Other(format!(
+                "Current commit {} is beyond the branch head {}, check out the branch again",
+                commit.id.seq, branch.headseq
+            )));
+        }
+
+        // the status walk compares the checkout with the same tree a new commit is based on
+        let changes = Tree::get_changed_files(context)
+            .map_err(|e| CommitError::Other(format!("Tree error: {:?}", e)))?;
+        if changes.is_empty() {
+            return Err(CommitError::NoChanges);
+        }
+
+        Ok(CommitPlan {
+            seq: commit.id.seq + 1,
+            rebuilt: branch.headseq - commit.id.seq,
+            changes,
+        })
     }
 
-    /// Amends the current commit with a new tree and optionally a new message.
-    /// If no message is provided, the existing message is preserved.
-    pub fn amend(context: &Context, message: Option<String>) -> Result<Self, CommitError> {
+    /// Amends the current commit with a new tree and optionally a new message and author.
+    /// If no message or author is provided, the existing ones are preserved.
+    /// The current commit does not have to be the branch head: commits above it are rebuilt on top
+    /// of the amended one, and the rebuild is paused with the conflicting commit checked out if their
+    /// changes can not be reapplied cleanly, see `Commit::continue_rebuild`.
+    /// Amends in a protected branch are refused unless `force` is set.
+    pub fn amend(
+        context: &Context,
+        message: Option<String>,
+        author: Option<String>,
+        force: bool,
+    ) -> Result<CommitOutcome, CommitError> {
+        check_no_rebuild(context)?;
+        check_protection(context, force)?;
+
         // Get the current commit
         let mut current = CurrentCommitSpec::get(context)?;
 
         let current_commit = commitstore::get(context, current.commit_id, current.ver)?;
+        let _lock = context.lock_branch(current.commit_id.branch)?;
 
         // Check if this is a centinel commit (seq is zero)
         if current_commit.id.seq == CommitID::SEQ_ZERO {
@@ -183,91 +498,537 @@ impl Commit {
         }
 
         // Generate a new tree hash from the current working directory
-        let treehash = Tree::create(context)
-            .map_err(|e| CommitError::Other(format!("Tree error: {:?}", e)))?;
+        let treehash =
+            Tree::create(context).map_err(|e| CommitError::from_tree("Tree error", e))?;
 
         let files_changed = current_commit.treehash != treehash;
+        let current_message = current_commit.get_message(context)?;
+        let message_changed = message
+            .as_ref()
+            .is_some_and(|message| *message != current_message);
+        let author_changed = author
+            .as_ref()
+            .is_some_and(|author| *author != current_commit.author);
 
-        // If no changes to the tree and the message remains the same, return NoChanges error
-        if !files_changed
-            && (message.is_none() || message.as_ref() == Some(&current_commit.message))
-        {
+        // If nothing changes, return NoChanges error
+        if !files_changed && !message_changed && !author_changed {
             return Err(CommitError::NoChanges);
         }
 
-        // Use the new message if provided, otherwise keep the existing one
-        let commit_message = message.unwrap_or_else(|| current_commit.message.clone());
+        // Use the new message and author if provided, otherwise keep the existing ones
+        let commit_message = message.unwrap_or(current_message);
+        let commit_author = author.unwrap_or_else(|| current_commit.author.clone());
 
-        let branch = Branch::get(context, current_commit.id.branch)
-            .map_err(|e| CommitError::Other(format!("Branch error: {:?}", e)))?;
+        let branch = Branch::get(context, current_commit.id.branch).map_err(CommitError::Branch)?;
 
-        let mut new_ver = branch.ver + 1;
+        let new_ver = branch.ver + 1;
 
         // Create a new commit with the same ID as the current one, but a different version.
-        let commit = create_commit(current_commit.id, new_ver, treehash, commit_message);
+        let commit = create_commit(
+            context,
+            current_commit.id,
+            new_ver,
+            treehash,
+            commit_message,
+            commit_author,
+            current_commit.created_at,
+        )?;
+
+        // Amended commit may be in the middle of the branch, so the commits above it have to be
+        // rebuilt with new versions. All new versions are above the branch version, so they only
+        // become visible once the branch is advanced, and an interrupted rebuild leaves nothing but
+        // garbage to be overwritten by the next attempt.
+        let rebuild = rebuild_above(
+            context,
+            &branch,
+            commit.id.seq,
+            0,
+            current_commit.treehash,
+            treehash,
+            new_ver,
+        )?;
+        if let Some(conflict) = rebuild.conflict {
+            return pause_rebuild(
+                context,
+                commit,
+                branch.headseq,
+                0,
+                &rebuild.commits,
+                conflict,
+            );
+        }
+        let rebuilt = rebuild.commits;
+        let new_ver = new_ver + rebuilt.len() as u64;
 
         commitstore::save(context, &commit)?;
+        save_all(context, &rebuilt)?;
 
-        if commit.id.seq < branch.headseq {
-            // Amended commit is in the middle of the branch, so we need to rebuild the branch
-            // TODO: implement branch rebuilding
-            if !files_changed {
-                // If files did not change, branch rebuild is trivial as we only have to update upward commits versions
-                // Do not even set the rebuild flag as no checkout will be needed
-                for seq in commit.id.seq..=branch.headseq {
-                    let mut commit = commitstore::get(
-                        context,
-                        CommitID {
-                            branch: commit.id.branch,
-                            seq,
-                        },
-                        branch.ver,
-                    )?;
-                    new_ver += 1;
-                    commit.ver = new_ver;
-                    commitstore::save(context, &commit)?;
-                }
-            } else {
-                // If files changed, we need to rebuild the branch by reapplying all commit's diffs upwards
-
-                // First, set the branch in the rebuild mode
-                // TODO: delay this until the checkout is needed to resolve conflicts.
-                current.rebuild_seq = commit.id.seq;
-                current.rebuild_ver = new_ver;
-                current.save(context)?;
-
-                // Rebuild the branch by diffing and reapplying older versions of commits on top of the new tree
-                for seq in commit.id.seq..=branch.headseq {
-                    let mut commit = commitstore::get(
-                        context,
-                        CommitID {
-                            branch: commit.id.branch,
-                            seq,
-                        },
-                        branch.ver,
-                    )?;
+        // Update the branch to the new version, keeping its head. This makes the new versions visible.
+        Branch::advance_head(context, commit.id.branch, branch.headseq, new_ver)
+            .map_err(|e| CommitError::Other(format!("Failed to advance branch head: {}", e)))?;
 
-                    // TODO: reapply the diffs and resolve conflicts
-                    // This workflow is potentially interruptive and may need user input and file tree
-                    // modifications.
+        // Point the current commit to the new version so the amended commit is picked up.
+        current.ver = new_ver;
+        current.save(context)?;
 
-                    new_ver += 1;
-                    commit.ver = new_ver;
-                    commitstore::save(context, &commit)?;
-                }
+        Ok(CommitOutcome {
+            commit,
+            rebuilt: rebuilt.len() as u64,
+        })
+    }
 
-                // Set the branch out of the rebuild mode
-                current.rebuild_seq = CurrentCommitSpec::NO_REBUILD;
-                current.rebuild_ver = CurrentCommitSpec::NO_REBUILD;
-                current.save(context)?;
+    /// Moves a commit of the current branch to another position, e.g. moving commit 3 to 1 puts it
+    /// right above the centinel and shifts commits 1 and 2 up. The commits between both positions are
+    /// rebuilt by reapplying their changes in the new order, and the commits above them are rebuilt on
+    /// top. If a change can not be reapplied cleanly, the move is refused and the branch is left
+    /// untouched. Superseded versions of the rebuilt commits are kept, see `Commit::get_versions`.
+    /// The checkout must have no local changes, it gets the rebuilt current commit.
+    /// Moves in a protected branch are refused unless `force` is set.
+    pub fn move_to(
+        context: &Context,
+        spec: &str,
+        target: u64,
+        force: bool,
+    ) -> Result<CommitOutcome, CommitError> {
+        check_no_rebuild(context)?;
+        check_protection(context, force)?;
+
+        let current = CurrentCommitSpec::get(context)?;
+        let id = CommitID::resolve(context, spec)?;
+        if id.branch != current.commit_id.branch {
+            return Err(CommitError::Other(
+                "Only commits of the current branch can be moved".to_string(),
+            ));
+        }
+
+        // the branch is read and updated under the lock, so concurrent commits do not race for it
+        let _lock = context.lock_branch(id.branch)?;
+        let branch = Branch::get(context, id.branch).map_err(CommitError::Branch)?;
+
+        if id.seq == CommitID::SEQ_ZERO || target == CommitID::SEQ_ZERO {
+            return Err(CommitError::Other(
+                "Cannot move centinel commit".to_string(),
+            ));
+        }
+        if id.seq.max(target) > branch.headseq {
+            return Err(CommitError::Other(format!(
+                "Position {} is beyond the branch head {}",
+                id.seq.max(target),
+                branch.headseq
+            )));
+        }
+        if id.seq == target {
+            return Err(CommitError::NoChanges);
+        }
+
+        let changes = Tree::get_changed_files(context)
+            .map_err(|e| CommitError::Other(format!("Tree error: {:?}", e)))?;
+        if !changes.is_empty() {
+            return Err(CommitError::Other(format!(
+                "Checkout has {} uncommitted change(s), commit them first",
+                changes.len()
+            )));
+        }
+
+        let get_old = |seq| {
+            commitstore::get(
+                context,
+                CommitID {
+                    branch: branch.id,
+                    seq,
+                },
+                branch.ver,
+            )
+        };
+
+        // old sequence numbers of the affected commits, in their new order
+        let (low, high) = (id.seq.min(target), id.seq.max(target));
+        let mut order: Vec<u64> = (low..=high).filter(|&seq| seq != id.seq).collect();
+        order.insert((target - low) as usize, id.seq);
+
+        let old_high_tree = get_old(high)?.treehash;
+        let mut parent_tree = get_old(low - 1)?.treehash;
+        let mut ver = branch.ver;
+        let mut moved = Vec::with_capacity(order.len());
+
+        // every merge saves a tree, they are flushed once the reordering is done
+        let batch = context.batch();
+        for (seq, old_seq) in (low..).zip(order) {
+            let old_commit = get_old(old_seq)?;
+            let old_parent_tree = get_old(old_seq - 1)?.treehash;
+
+            // Reapply the changes the commit made to its old parent on top of its new parent
+            let merge = Tree::merge(context, old_parent_tree, parent_tree, old_commit.treehash)
+                .map_err(|e| CommitError::Other(format!("Tree error: {:?}", e)))?;
+            if !merge.conflicts.is_empty() {
+                return Err(CommitError::MoveConflict(
+                    old_seq,
+                    merge.conflicts.into_iter().map(|c| c.path).collect(),
+                ));
             }
+
+            ver += 1;
+            parent_tree = merge.hash;
+            moved.push(create_commit(
+                context,
+                CommitID {
+                    branch: branch.id,
+                    seq,
+                },
+                ver,
+                merge.hash,
+                old_commit.get_message(context)?,
+                old_commit.author,
+                old_commit.created_at,
+            )?);
         }
+        batch.finish()?;
 
-        // Update the branch to the new version. This concludes the workflow.
-        Branch::advance_head(context, commit.id.branch, commit.id.seq, new_ver)
+        // commits above the affected range keep their positions, but their parent may have changed
+        let rebuild = rebuild_above(context, &branch, high, 0, old_high_tree, parent_tree, ver)?;
+        if let Some(conflict) = rebuild.conflict {
+            return Err(CommitError::MoveConflict(conflict.seq, conflict.paths));
+        }
+        let rebuilt = rebuild.commits;
+        let new_ver = rebuilt.last().map_or(ver, |commit| commit.ver);
+
+        save_all(context, &moved)?;
+        save_all(context, &rebuilt)?;
+
+        let old_current = Self::get_current(context)?;
+
+        // Update the branch to the new version, keeping its head. This makes the new versions visible.
+        Branch::advance_head(context, branch.id, branch.headseq, new_ver)
             .map_err(|e| CommitError::Other(format!("Failed to advance branch head: {}", e)))?;
 
-        Ok(commit)
+        // the current commit may be one of the reordered ones, its files change then and it is
+        // checked out, recording the intent first so an interrupted checkout can be finished
+        let new_current = Self::get(context, current.commit_id)?;
+        if new_current.treehash != old_current.treehash {
+            Tree::checkout_id(context, current.commit_id)
+                .map_err(|e| CommitError::from_tree("Checkout error", e))?;
+        } else {
+            CurrentCommitSpec {
+                ver: new_ver,
+                ..current
+            }
+            .save(context)?;
+        }
+
+        let commit = moved.swap_remove((target - low) as usize);
+        Ok(CommitOutcome {
+            commit,
+            rebuilt: (moved.len() + rebuilt.len()) as u64,
+        })
+    }
+
+    /// Drops the head commit of the current branch by moving the head one commit back, the dropped
+    /// commit stays retrievable by `Commit::get_versions` until a new commit takes its position.
+    /// The checkout is left as it is, so the changes of the dropped commit become local changes,
+    /// unless `hard` is set: the previous commit is checked out then, discarding local changes.
+    /// Returns the dropped commit. Undoing in a protected branch is refused unless `force` is set.
+    pub fn undo(context: &Context, hard: bool, force: bool) -> Result<Self, CommitError> {
+        check_no_rebuild(context)?;
+        check_protection(context, force)?;
+
+        let mut current = CurrentCommitSpec::get(context)?;
+        // the head is read and moved under the lock, so concurrent commits do not race for it
+        let _lock = context.lock_branch(current.commit_id.branch)?;
+        let branch = Branch::get(context, current.commit_id.branch).map_err(CommitError::Branch)?;
+
+        if current.commit_id.seq == CommitID::SEQ_ZERO {
+            return Err(CommitError::Other(
+                "Cannot undo centinel commit".to_string(),
+            ));
+        }
+        if current.commit_id.seq != branch.headseq {
+            return Err(CommitError::Other(format!(
+                "Current commit {} is not the branch head {}, only the last commit can be undone",
+                current.commit_id.seq, branch.headseq
+            )));
+        }
+
+        let undone = commitstore::get(context, current.commit_id, current.ver)?;
+
+        // The commit record is kept, the new branch version hides it once a new commit is made
+        let new_ver = branch.ver + 1;
+        Branch::advance_head(context, branch.id, branch.headseq - 1, new_ver)
+            .map_err(|e| CommitError::Other(format!("Failed to move branch head: {}", e)))?;
+
+        current.commit_id.seq -= 1;
+        current.ver = new_ver;
+        if hard {
+            // checked out the usual way, so an interrupted checkout is finished with `--continue`
+            Tree::checkout_id(context, current.commit_id)
+                .map_err(|e| CommitError::from_tree("Checkout error", e))?;
+        } else {
+            current.save(context)?;
+        }
+
+        Ok(undone)
+    }
+
+    /// Moves the head of the current branch to the specified commit of the branch, which becomes
+    /// the current commit. Moving it back drops the commits above like `undo` drops the head
+    /// commit, their records stay until new commits take their positions. Moving it forward brings
+    /// dropped commits back, as long as no newer commit took the position of one of them. The
+    /// checkout is left as it is, so the differences from the commit become local changes, unless
+    /// `hard` is set: the commit is checked out then, discarding local changes. Commits of other
+    /// branches are refused, and so is resetting a protected branch unless `force` is set.
+    pub fn reset(
+        context: &Context,
+        spec: &str,
+        hard: bool,
+        force: bool,
+    ) -> Result<ResetOutcome, CommitError> {
+        check_no_rebuild(context)?;
+        check_protection(context, force)?;
+
+        let current = CurrentCommitSpec::get(context)?;
+        // the head is read and moved under the lock, so concurrent commits do not race for it
+        let _lock = context.lock_branch(current.commit_id.branch)?;
+        let branch = Branch::get(context, current.commit_id.branch).map_err(CommitError::Branch)?;
+        let target = CommitID::resolve(context, spec)?;
+        if target.branch != branch.id {
+            return Err(CommitError::Other(format!(
+                "Commit '{}' is not on the current branch {}, check out its branch first",
+                spec, branch.name
+            )));
+        }
+        if target.seq > branch.headseq {
+            check_dropped_chain(context, &branch, target.seq)?;
+        }
+        let commit = Self::get(context, target)?;
+
+        // The commit records are kept, the new branch version hides the dropped ones once a new
+        // commit is made, or shows the restored ones
+        let dropped = branch.headseq.saturating_sub(target.seq);
+        let restored = target.seq.saturating_sub(branch.headseq);
+        let mut ver = branch.ver;
+        if target.seq != branch.headseq {
+            ver += 1;
+            Branch::advance_head(context, branch.id, target.seq, ver)
+                .map_err(|e| CommitError::Other(format!("Failed to move branch head: {}", e)))?;
+        }
+
+        if hard {
+            // checked out the usual way, so an interrupted checkout is finished with `--continue`
+            Tree::checkout_id(context, target)
+                .map_err(|e| CommitError::from_tree("Checkout error", e))?;
+        } else {
+            CurrentCommitSpec {
+                commit_id: target,
+                ver,
+                ..current
+            }
+            .save(context)?;
+        }
+
+        Ok(ResetOutcome {
+            commit,
+            dropped,
+            restored,
+        })
+    }
+
+    /// Creates a new commit undoing the changes the specified commit made to the one below it,
+    /// without rewriting history. The tree below the commit is merged into the current tree
+    /// against the tree of the commit, so only the changes of the commit are undone and the ones
+    /// made since are kept. The checkout has to be free of local changes. If a change of the
+    /// commit was changed again since, nothing is written and the conflicting paths are returned
+    /// as `CommitError::RevertConflict`; if its changes are already undone, `NoChanges`.
+    pub fn revert(
+        context: &Context,
+        spec: &str,
+        force: bool,
+    ) -> Result<CommitOutcome, CommitError> {
+        check_no_rebuild(context)?;
+        check_protection(context, force)?;
+
+        let target = Self::get_by_spec(context, spec)?;
+        if target.id.seq == CommitID::SEQ_ZERO {
+            return Err(CommitError::Other(
+                "Cannot revert centinel commit".to_string(),
+            ));
+        }
+        let below = Self::get(
+            context,
+            CommitID {
+                branch: target.id.branch,
+                seq: target.id.seq - 1,
+            },
+        )?;
+
+        let current = Self::get_current(context)?;
+        let local = Tree::create(context).map_err(|e| CommitError::from_tree("Tree error", e))?;
+        if local != current.treehash {
+            return Err(CommitError::Other(
+                "There are local changes, commit or stash them before reverting".to_string(),
+            ));
+        }
+
+        let merged = Tree::merge(context, target.treehash, current.treehash, below.treehash)
+            .map_err(|e| CommitError::from_tree("Failed to merge trees", e))?;
+        if !merged.conflicts.is_empty() {
+            let paths = merged
+                .conflicts
+                .into_iter()
+                .map(|conflict| conflict.path)
+                .collect();
+            return Err(CommitError::RevertConflict(target.id.seq, paths));
+        }
+        if merged.hash == current.treehash {
+            return Err(CommitError::NoChanges);
+        }
+
+        // the commit is created from the merged tree before any file is written, and the checkout
+        // then records its intent, so an interruption never leaves files of an unknown commit
+        let message = format!("Revert: {}", target.subject());
+        let outcome = Self::create(context, message, None, force, false, Some(merged.hash))?;
+        Tree::checkout_id(context, outcome.commit.id)
+            .map_err(|e| CommitError::from_tree("Checkout error", e))?;
+        Ok(outcome)
+    }
+
+    /// Continues a rebuild paused on a conflict, once the conflicts are resolved in the checkout.
+    /// The checkout becomes the rebuilt conflicting commit, and the commits above it are rebuilt on
+    /// top of it, pausing again on the next conflict. Once all of them are rebuilt, the branch is
+    /// advanced and the new or amended commit the rebuild started from is checked out.
+    pub fn continue_rebuild(context: &Context) -> Result<CommitOutcome, CommitError> {
+        let current = CurrentCommitSpec::get(context)?;
+        let pending = Self::get_pending_rebuild(context)?
+            .ok_or_else(|| CommitError::Other("No rebuild to continue".to_string()))?;
+
+        let mut unresolved = Vec::new();
+        for path in &pending.conflicts {
+            if has_conflict_markers(&context.checkout_path.join(path))? {
+                unresolved.push(path.clone());
+            }
+        }
+        if !unresolved.is_empty() {
+            return Err(CommitError::Unresolved(unresolved));
+        }
+
+        let treehash =
+            Tree::create(context).map_err(|e| CommitError::from_tree("Tree error", e))?;
+
+        let branch = Branch::get(context, pending.commit.id.branch).map_err(CommitError::Branch)?;
+
+        // The branch is not advanced until the rebuild completes, so the commits to rebuild are
+        // still visible at their old positions
+        let old_seq = current.rebuild_seq - pending.shift;
+        let old_commit = commitstore::get(
+            context,
+            CommitID {
+                branch: branch.id,
+                seq: old_seq,
+            },
+            branch.ver,
+        )?;
+        let resolved = create_commit(
+            context,
+            CommitID {
+                branch: branch.id,
+                seq: current.rebuild_seq,
+            },
+            current.rebuild_ver,
+            treehash,
+            old_commit.get_message(context)?,
+            old_commit.author,
+            old_commit.created_at,
+        )?;
+        commitstore::save(context, &resolved)?;
+
+        let rebuild = rebuild_above(
+            context,
+            &branch,
+            old_seq,
+            pending.shift,
+            old_commit.treehash,
+            treehash,
+            current.rebuild_ver,
+        )?;
+        if let Some(conflict) = rebuild.conflict {
+            return pause_rebuild(
+                context,
+                pending.commit,
+                pending.headseq,
+                pending.shift,
+                &rebuild.commits,
+                conflict,
+            );
+        }
+        save_all(context, &rebuild.commits)?;
+
+        let ver = rebuild
+            .commits
+            .last()
+            .map_or(current.rebuild_ver, |commit| commit.ver);
+        let intent = PendingCommit {
+            current: CurrentCommitSpec {
+                commit_id: pending.commit.id,
+                ver,
+                rebuild_seq: CurrentCommitSpec::NO_REBUILD,
+                rebuild_ver: CurrentCommitSpec::NO_REBUILD,
+            },
+            headseq: pending.headseq,
+            commit: pending.commit,
+        };
+        commitstore::save_intent(context, &intent)?;
+        apply_pending(context, &intent)?;
+        commitstore::clear_rebuild(context)?;
+
+        // The checkout holds the last rebuilt commit, move it back to where the workflow started
+        Tree::materialize(context, intent.commit.treehash)
+            .map_err(|e| CommitError::Other(format!("Tree error: {:?}", e)))?;
+
+        Ok(CommitOutcome {
+            rebuilt: intent.headseq - intent.commit.id.seq,
+            commit: intent.commit,
+        })
+    }
+
+    /// Aborts a rebuild paused on a conflict, leaving the branch as it was before the workflow that
+    /// started it. The checkout gets the files of the new or amended commit back, as local changes.
+    /// Returns the abandoned commit.
+    pub fn abort_rebuild(context: &Context) -> Result<Self, CommitError> {
+        let mut current = CurrentCommitSpec::get(context)?;
+        let pending = Self::get_pending_rebuild(context)?
+            .ok_or_else(|| CommitError::Other("No rebuild to abort".to_string()))?;
+
+        Tree::materialize(context, pending.commit.treehash)
+            .map_err(|e| CommitError::Other(format!("Tree error: {:?}", e)))?;
+
+        current.rebuild_seq = CurrentCommitSpec::NO_REBUILD;
+        current.rebuild_ver = CurrentCommitSpec::NO_REBUILD;
+        current.save(context)?;
+        commitstore::clear_rebuild(context)?;
+
+        Ok(pending.commit)
+    }
+
+    /// Retrieves a commit interrupted by a failure before it was fully saved, if any.
+    /// It is completed the next time the repository is opened.
+    pub fn get_pending_commit(context: &Context) -> Result<Option<Commit>, CommitError> {
+        Ok(commitstore::get_intent(context)?.map(|pending| pending.commit))
+    }
+
+    /// Retrieves the state of a rebuild paused on a conflict, if any.
+    pub fn get_pending_rebuild(context: &Context) -> Result<Option<PendingRebuild>, CommitError> {
+        // a state left behind by an interrupted completion does not count once the rebuild mode is off
+        if !CurrentCommitSpec::get(context)?.is_rebuild() {
+            return Ok(None);
+        }
+        commitstore::get_rebuild(context)
+    }
+
+    /// Retrieves the hash of the tree the checkout is based on: the merged tree of the conflicting
+    /// commit while a rebuild is paused, otherwise the tree of the current commit.
+    pub fn get_checkout_tree(context: &Context) -> Result<Digest, CommitError> {
+        match Self::get_pending_rebuild(context)? {
+            Some(pending) => Ok(pending.treehash),
+            None => Ok(Self::get_current(context)?.treehash),
+        }
     }
 
     /// Lists all commits for the current branch.
@@ -275,9 +1036,9 @@ impl Commit {
     /// branches.
     pub fn list(context: &Context) -> Result<Vec<Self>, CommitError> {
         let commit_id = commitstore::get_current(context)?;
-        let branch = Branch::get(context, commit_id.commit_id.branch)
-            .map_err(|e| CommitError::Other(format!("Branch error: {:?}", e)))?;
-        commitstore::list(context, branch.id, branch.ver, branch.headseq)
+        let branch =
+            Branch::get(context, commit_id.commit_id.branch).map_err(CommitError::Branch)?;
+        commitstore::list(context, branch.id, branch.ver, branch.headseq, None)
     }
 
     /// Lists all commits for the specified branch.
@@ -290,21 +1051,127 @@ impl Commit {
     /// A vector of commits in the branch, sorted by sequence number
     pub fn list_by_branch(context: &Context, branch_name: &str) -> Result<Vec<Self>, CommitError> {
         // Resolve branch name to branch object
-        let branch = Branch::get_by_name(context, branch_name)
-            .map_err(|e| CommitError::Other(format!("Branch error: {:?}", e)))?;
+        let branch = Branch::get_by_name(context, branch_name).map_err(CommitError::Branch)?;
 
         // Use the existing list method with the branch's id, version, and head sequence
-        commitstore::list(context, branch.id, branch.ver, branch.headseq)
+        commitstore::list(context, branch.id, branch.ver, branch.headseq, None)
+    }
+
+    /// Lists the commits dropped above the head of the specified branch, e.g. by an undo, that a
+    /// reset can still bring back, sorted by sequence number.
+    pub fn list_restorable(context: &Context, branch_name: &str) -> Result<Vec<Self>, CommitError> {
+        let branch = Branch::get_by_name(context, branch_name).map_err(CommitError::Branch)?;
+        restorable_commits(context, &branch)
+    }
+
+    /// Keeps the commits that changed anything at one of the given paths relative to the checkout
+    /// root, compared to the commit right below them. Commits are expected in descending order of
+    /// sequence numbers, e.g. as listed by `list_by_branch`, the lowest one is compared with the
+    /// commit below it if there is one. Centinel commits change nothing themselves and are never kept.
+    pub fn filter_by_paths(
+        context: &Context,
+        commits: Vec<Self>,
+        paths: &[PathBuf],
+    ) -> Result<Vec<Self>, CommitError> {
+        let path_hashes = |commit: &Commit| {
+            Tree::get_path_hashes(context, commit.treehash, paths)
+                .map_err(|e| CommitError::from_tree("Failed to read tree", e))
+        };
+
+        let mut kept = Vec::new();
+        let mut hashes = match commits.first() {
+            Some(commit) => path_hashes(commit)?,
+            None => return Ok(kept),
+        };
+        let mut commits = commits.into_iter().peekable();
+        while let Some(commit) = commits.next() {
+            if commit.id.seq == CommitID::SEQ_ZERO {
+                continue;
+            }
+            let below_id = CommitID {
+                branch: commit.id.branch,
+                seq: commit.id.seq - 1,
+            };
+            let below_hashes = match commits.peek() {
+                Some(below) => path_hashes(below)?,
+                None => match Self::get(context, below_id) {
+                    Ok(below) => path_hashes(&below)?,
+                    Err(_) => vec![Digest::NONE; paths.len()],
+                },
+            };
+            if below_hashes != hashes {
+                kept.push(commit);
+            }
+            hashes = below_hashes;
+        }
+        Ok(kept)
+    }
+
+    /// Lists the latest commits of a branch, from the head down.
+    pub fn list_recent(
+        context: &Context,
+        branch: &Branch,
+        limit: usize,
+    ) -> Result<Vec<Self>, CommitError> {
+        commitstore::list(context, branch.id, branch.ver, branch.headseq, Some(limit))
+    }
+
+    /// Lists the full lineage of a branch, or of the current branch if no name is provided.
+    /// Starting from the branch head, walks back to its centinel commit and then continues onto the
+    /// parent branch from the commit the branch was forked at, up to the foundational branch.
+    ///
+    /// # Returns
+    /// A vector of history segments, one per branch, starting with the requested branch and ending
+    /// with the foundational one.
+    pub fn list_history(
+        context: &Context,
+        branch_name: Option<&str>,
+    ) -> Result<Vec<HistorySegment>, CommitError> {
+        let mut branch = match branch_name {
+            Some(name) => Branch::get_by_name(context, name),
+            None => Branch::get_current(context),
+        }
+        .map_err(CommitError::Branch)?;
+
+        let mut segments = Vec::new();
+        let mut headseq = branch.headseq;
+
+        loop {
+            let commits = commitstore::list(context, branch.id, branch.ver, headseq, None)?;
+            let parent = branch.parent;
+            let parentseq = branch.parentseq;
+            let foundational = branch.is_foundational();
+            segments.push(HistorySegment { branch, commits });
+
+            if foundational {
+                break;
+            }
+
+            branch = Branch::get(context, parent).map_err(CommitError::Branch)?;
+            headseq = parentseq;
+        }
+
+        Ok(segments)
+    }
+
+    /// Counts all commits in the repository.
+    pub fn count(context: &Context) -> Result<u64, CommitError> {
+        commitstore::count(context)
     }
 
     /// Retrieves a specific commit by id.
     pub fn get(context: &Context, id: CommitID) -> Result<Self, CommitError> {
-        let branch = Branch::get(context, id.branch)
-            .map_err(|e| CommitError::Other(format!("Branch error: {:?}", e)))?;
+        let branch = Branch::get(context, id.branch).map_err(CommitError::Branch)?;
 
         commitstore::get(context, id, branch.ver)
     }
 
+    /// Retrieves all stored versions of a commit, including the ones superseded by amends and
+    /// rebuilds and the ones not yet visible in the branch, sorted by descending version.
+    pub fn get_versions(context: &Context, id: CommitID) -> Result<Vec<Self>, CommitError> {
+        commitstore::get_versions(context, id)
+    }
+
     /// Retrieves a specific commit by id.
     pub fn get_from_current_branch(context: &Context, seq: u64) -> Result<Self, CommitError> {
         let current = CurrentCommitSpec::get(context)?;
@@ -326,6 +1193,7 @@ impl Commit {
     ///   - "branch_name:seq" - Specific sequence on named branch
     ///   - "seq" - Specific sequence on current branch
     ///   - "branch_name" - Head commit on named branch
+    ///   - "tag_name" - Tagged commit
     pub fn get_by_spec(context: &Context, spec: &str) -> Result<Self, CommitError> {
         let commit_id = CommitID::resolve(context, spec)?;
         Self::get(context, commit_id)
@@ -338,8 +1206,10 @@ impl Commit {
         branch_id: u64,
         treehash: Digest,
         message: String,
+        author: String,
     ) -> Result<Self, CommitError> {
         let commit = create_commit(
+            context,
             CommitID {
                 branch: branch_id,
                 seq: CommitID::SEQ_ZERO,
@@ -347,7 +1217,9 @@ impl Commit {
             0,
             treehash,
             message,
-        );
+            author,
+            now()?,
+        )?;
 
         commitstore::save(context, &commit)?;
 
@@ -355,25 +1227,457 @@ impl Commit {
     }
 }
 
+/// Environment variable naming a failpoint to simulate a crash at, used by acceptance tests.
+#[cfg(any(test, feature = "failpoints"))]
+const FAILPOINT_ENV: &str = "VX_FAILPOINT";
+
+/// Returns an error if the failpoint with the given name is enabled.
+#[cfg(any(test, feature = "failpoints"))]
+fn failpoint(name: &str) -> Result<(), CommitError> {
+    if std::env::var(FAILPOINT_ENV).is_ok_and(|value| value == name) {
+        return Err(CommitError::Other(format!("Injected failure at {}", name)));
+    }
+    Ok(())
+}
+
+/// Failpoints are compiled out of builds without the `failpoints` feature.
+#[cfg(not(any(test, feature = "failpoints")))]
+fn failpoint(_name: &str) -> Result<(), CommitError> {
+    Ok(())
+}
+
+/// Applies a pending commit recorded in the write-ahead intent and clears the intent.
+/// Each step is idempotent, so it is safe to apply the same pending commit several times.
+fn apply_pending(context: &Context, pending: &PendingCommit) -> Result<(), CommitError> {
+    commitstore::save(context, &pending.commit)?;
+
+    // Save the current commit specification to advance the branch head
+    pending.current.save(context)?;
+
+    failpoint("commit-before-advance")?;
+
+    Branch::advance_head(
+        context,
+        pending.commit.id.branch,
+        pending.headseq,
+        pending.current.ver,
+    )
+    .map_err(|e| CommitError::Other(format!("Failed to advance branch head: {}", e)))?;
+
+    commitstore::clear_intent(context)
+}
+
+/// Commits rebuilt by `rebuild_above`, up to a conflict if there was one.
+struct Rebuild {
+    /// Commits that were rebuilt cleanly, not saved yet.
+    commits: Vec<Commit>,
+    /// The conflict the rebuild stopped at.
+    conflict: Option<RebuildConflict>,
+}
+
+/// A commit whose changes could not be reapplied cleanly during a rebuild.
+struct RebuildConflict {
+    /// Sequence number of the commit before the rebuild.
+    seq: u64,
+    /// Version the commit gets once the conflicts are resolved.
+    ver: u64,
+    /// Hash of the merged tree, with conflict markers in the conflicting files.
+    treehash: Digest,
+    /// Paths to the conflicting files.
+    paths: Vec<PathBuf>,
+}
+
+/// Rebuilds the commits above `seq` on top of a changed version of the commit at `seq`, moving each
+/// of them `shift` positions up. The changes every commit made to its old parent's tree are reapplied
+/// on top of its rebuilt parent's tree, starting with the change from `old_tree` to `new_tree` at `seq`.
+/// Rebuilt commits get consecutive versions following `ver` and are returned without being saved.
+/// The rebuild stops at the first commit with conflicting changes.
+fn rebuild_above(
+    context: &Context,
+    branch: &Branch,
+    seq: u64,
+    shift: u64,
+    old_tree: Digest,
+    new_tree: Digest,
+    mut ver: u64,
+) -> Result<Rebuild, CommitError> {
+    let files_changed = old_tree != new_tree;
+    let mut rebuilt = Vec::with_capacity(branch.headseq.saturating_sub(seq) as usize);
+
+    // trees of the previous commit before and after the rebuild
+    let mut old_parent_tree = old_tree;
+    let mut new_parent_tree = new_tree;
+    let mut conflict = None;
+
+    // every merge saves a tree, they are flushed once the rebuild is done
+    let batch = context.batch();
+    for old_seq in seq + 1..=branch.headseq {
+        let old_commit = commitstore::get(
+            context,
+            CommitID {
+                branch: branch.id,
+                seq: old_seq,
+            },
+            branch.ver,
+        )?;
+
+        ver += 1;
+
+        let rebuilt_tree = if files_changed {
+            // Reapply the changes the commit made to its old parent on top of the rebuilt parent
+            let merge = Tree::merge(
+                context,
+                old_parent_tree,
+                new_parent_tree,
+                old_commit.treehash,
+            )
+            .map_err(|e| CommitError::Other(format!("Tree error: {:?}", e)))?;
+
+            if !merge.conflicts.is_empty() {
+                conflict = Some(RebuildConflict {
+                    seq: old_seq,
+                    ver,
+                    treehash: merge.hash,
+                    paths: merge.conflicts.into_iter().map(|c| c.path).collect(),
+                });
+                break;
+            }
+
+            merge.hash
+        } else {
+            // If files did not change, branch rebuild is trivial as we only have to update
+            // upward commits versions
+            old_commit.treehash
+        };
+
+        old_parent_tree = old_commit.treehash;
+        new_parent_tree = rebuilt_tree;
+
+        rebuilt.push(create_commit(
+            context,
+            CommitID {
+                branch: branch.id,
+                seq: old_seq + shift,
+            },
+            ver,
+            rebuilt_tree,
+            old_commit.get_message(context)?,
+            old_commit.author,
+            old_commit.created_at,
+        )?);
+    }
+
+    batch.finish()?;
+
+    Ok(Rebuild {
+        commits: rebuilt,
+        conflict,
+    })
+}
+
+/// Saves commits in a batch, flushing the commit store once.
+fn save_all(context: &Context, commits: &[Commit]) -> Result<(), CommitError> {
+    let batch = context.batch();
+    for commit in commits {
+        commitstore::save(context, commit)?;
+    }
+    Ok(batch.finish()?)
+}
+
+/// Pauses a rebuild on a conflict. The new or amended commit and the commits rebuilt so far are
+/// saved, but stay invisible until the branch is advanced once the rebuild completes. The merged tree
+/// of the conflicting commit is checked out for the conflicts to be resolved in place.
+/// Returns the conflict error describing the paused rebuild.
+fn pause_rebuild(
+    context: &Context,
+    commit: Commit,
+    headseq: u64,
+    shift: u64,
+    rebuilt: &[Commit],
+    conflict: RebuildConflict,
+) -> Result<CommitOutcome, CommitError> {
+    commitstore::save(context, &commit)?;
+    save_all(context, rebuilt)?;
+
+    let pending = PendingRebuild {
+        commit,
+        headseq,
+        shift,
+        treehash: conflict.treehash,
+        conflicts: conflict.paths,
+    };
+    commitstore::save_rebuild(context, &pending)?;
+
+    // The current commit stays where the workflow started, so an aborted rebuild leaves no trace
+    let seq = conflict.seq + shift;
+    let mut current = CurrentCommitSpec::get(context)?;
+    current.rebuild_seq = seq;
+    current.rebuild_ver = conflict.ver;
+    current.save(context)?;
+
+    Tree::materialize(context, pending.treehash)
+        .map_err(|e| CommitError::Other(format!("Tree error: {:?}", e)))?;
+
+    Err(CommitError::Conflict(seq, pending.conflicts))
+}
+
+/// Checks whether a file still contains conflict markers, a missing file has none.
+fn has_conflict_markers(path: &Path) -> Result<bool, CommitError> {
+    match std::fs::read(path) {
+        Ok(content) => Ok(content
+            .split(|&byte| byte == b'\n')
+            .any(|line| line.starts_with(CONFLICT_START.as_bytes()))),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(false),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Completes a commit workflow interrupted by a failure, if there is any.
+/// Returns the recovered commit.
+pub(crate) fn recover(context: &Context) -> Result<Option<Commit>, CommitError> {
+    match commitstore::get_intent(context)? {
+        Some(pending) => {
+            apply_pending(context, &pending)?;
+            Ok(Some(pending.commit))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Rolls the current commit back to its value before the latest update if it can not be read or
+/// refers to a commit that does not exist, e.g. after an update torn by a failure, returning the
+/// restored value. A broken current commit without a usable previous value is left as it is, to
+/// be reported by the commands reading it.
+pub(crate) fn recover_current(context: &Context) -> Result<Option<CurrentCommitSpec>, CommitError> {
+    // the check runs on every start, so the store is opened once for all of its reads
+    let batch = context.batch();
+    if !is_broken(context, commitstore::get_current(context)) {
+        return Ok(None);
+    }
+    let restored = match commitstore::get_previous_current(context) {
+        Ok(Some(previous)) if !is_broken(context, Ok(previous)) => {
+            Some(commitstore::restore_previous_current(context)?)
+        }
+        Ok(_) => None,
+        Err(e) if commitstore::is_damaged(&e) => None,
+        Err(e) => return Err(e),
+    };
+    batch.finish()?;
+    Ok(restored)
+}
+
+/// Checks whether a current commit can not be read or refers to a commit that does not exist.
+/// Other failures, e.g. a database held by another process, are not taken as a broken value.
+fn is_broken(context: &Context, current: Result<CurrentCommitSpec, CommitError>) -> bool {
+    match current {
+        Ok(current) => matches!(
+            commitstore::get(context, current.commit_id, current.ver),
+            Err(CommitError::NotFound)
+        ),
+        Err(e) => commitstore::is_damaged(&e),
+    }
+}
+
+/// Refuses to start a workflow while a rebuild is paused on a conflict.
+fn check_no_rebuild(context: &Context) -> Result<(), CommitError> {
+    let current = CurrentCommitSpec::get(context)?;
+    if current.is_rebuild() {
+        return Err(CommitError::RebuildInProgress(current.rebuild_seq));
+    }
+    Ok(())
+}
+
+/// Refuses direct commits to a protected current branch, unless forced.
+fn check_protection(context: &Context, force: bool) -> Result<(), CommitError> {
+    if force {
+        return Ok(());
+    }
+    let branch = Branch::get_current(context).map_err(CommitError::Branch)?;
+    let protected = branch.is_protected(context).map_err(CommitError::Branch)?;
+    if protected {
+        return Err(CommitError::BranchProtected(branch.name));
+    }
+    Ok(())
+}
+
 /// Creates a new commit object with proper hash calculation.
 ///
 /// This function constructs a Commit object with the given parameters and
-/// calculates a hash based on the commit's content. It does not save the commit to the store.
-fn create_commit(id: CommitID, ver: u64, treehash: Digest, message: String) -> Commit {
-    // Calculate hash based on commit contents
+/// calculates a hash based on the commit's content. It does not save the commit to the store,
+/// but a message over the configured size is saved to the blob store.
+fn create_commit(
+    context: &Context,
+    id: CommitID,
+    ver: u64,
+    treehash: Digest,
+    message: String,
+    author: String,
+    created_at: u64,
+) -> Result<Commit, CommitError> {
+    // the hash covers the full message wherever it is stored
+    let hash = hash_commit(&message, &author, created_at, treehash);
+    let (message, message_hash) = store_message(context, message)?;
+
+    Ok(Commit {
+        id,
+        ver,
+        hash,
+        treehash,
+        message,
+        message_hash,
+        author,
+        created_at,
+        signature: None,
+    })
+}
+
+/// Reads the author of new commits from repository metadata, None if it does not set one.
+pub fn configured_author(metadata: &HashMap<String, String>) -> Result<Option<String>, RepoError> {
+    metadata
+        .get(AUTHOR_KEY)
+        .map(|author| check_author(author, AUTHOR_KEY))
+        .transpose()
+}
+
+/// Gets the author of new commits: the one set in repository metadata, otherwise the one set by
+/// `AUTHOR_ENV`, empty if neither sets one.
+pub fn default_author(metadata: &HashMap<String, String>) -> Result<String, RepoError> {
+    if let Some(author) = configured_author(metadata)? {
+        return Ok(author);
+    }
+    match std::env::var(AUTHOR_ENV) {
+        Ok(author) => check_author(&author, AUTHOR_ENV),
+        Err(_) => Ok(String::new()),
+    }
+}
+
+/// Trims an author, refusing one spanning several lines, which could not be exported.
+fn check_author(author: &str, source: &str) -> Result<String, RepoError> {
+    let author = author.trim();
+    if author.contains(['\n', '\r']) {
+        return Err(RepoError::Other(format!(
+            "Invalid author '{}' of {}, expected a single line",
+            author, source
+        )));
+    }
+    Ok(author.to_string())
+}
+
+/// Reads the size above which commit messages are stored as blobs from repository metadata.
+pub fn message_blob_size(metadata: &HashMap<String, String>) -> Result<Option<usize>, RepoError> {
+    match metadata.get(MESSAGE_BLOB_SIZE_KEY) {
+        Some(value) => value.parse().map(Some).map_err(|_| {
+            RepoError::Other(format!(
+                "Invalid value '{}' of {}, expected a size in bytes",
+                value, MESSAGE_BLOB_SIZE_KEY
+            ))
+        }),
+        None => Ok(None),
+    }
+}
+
+/// Saves a message over the configured size to the blob store, returning the part of the message
+/// kept inline, i.e. its subject, along with the hash of the blob.
+/// Messages within the size are kept inline as they are, with `Digest::NONE`.
+fn store_message(context: &Context, message: String) -> Result<(String, Digest), CommitError> {
+    let metadata = Repo::get_metadata(context)
+        .map_err(|e| CommitError::Other(format!("Failed to get repository metadata: {:?}", e)))?;
+    let limit = message_blob_size(&metadata).map_err(|e| CommitError::Other(format!("{:?}", e)))?;
+    if limit.is_none_or(|limit| message.len() <= limit) {
+        return Ok((message, Digest::NONE));
+    }
+
+    let blob_db = Blob::open(context)
+        .map_err(|e| CommitError::Other(format!("Failed to open blob store: {:?}", e)))?;
+    let blob = Blob::from_bytes(context, &blob_db, message.as_bytes())
+        .map_err(|e| CommitError::Other(format!("Failed to store message: {:?}", e)))?;
+    let subject = message.lines().next().unwrap_or_default().trim_end();
+    Ok((subject.to_string(), blob.contenthash))
+}
+
+/// Checks that the commits dropped above the head of a branch, up to `seq`, can still be restored,
+/// see `restorable_commits`.
+fn check_dropped_chain(context: &Context, branch: &Branch, seq: u64) -> Result<(), CommitError> {
+    let restorable = restorable_commits(context, branch)?;
+    let next = CommitID {
+        branch: branch.id,
+        seq: branch.headseq + restorable.len() as u64 + 1,
+    };
+    if seq < next.seq {
+        return Ok(());
+    }
+    Err(match commitstore::get(context, next, branch.ver) {
+        Err(CommitError::NotFound) => CommitError::Other(format!(
+            "Commit {} is above the branch head {} and was never dropped from it",
+            next.seq, branch.headseq
+        )),
+        Err(e) => e,
+        Ok(_) => CommitError::Other(format!(
+            "Commit {} was replaced since it was dropped, commits above it can not be restored",
+            next.seq - 1
+        )),
+    })
+}
+
+/// Lists the commits dropped above the head of a branch that still follow each other on top of
+/// the head, i.e. whose positions no newer commit took, in ascending order. Commits of a chain
+/// have growing versions, so a newer commit taking a position has a version above the ones of the
+/// commits dropped above it.
+fn restorable_commits(context: &Context, branch: &Branch) -> Result<Vec<Commit>, CommitError> {
+    let id = |seq| CommitID {
+        branch: branch.id,
+        seq,
+    };
+    let mut ver = commitstore::get(context, id(branch.headseq), branch.ver)?.ver;
+    let mut commits = Vec::new();
+    for seq in branch.headseq + 1.. {
+        let commit = match commitstore::get(context, id(seq), branch.ver) {
+            Err(CommitError::NotFound) => break,
+            result => result?,
+        };
+        if commit.ver < ver {
+            break;
+        }
+        ver = commit.ver;
+        commits.push(commit);
+    }
+    Ok(commits)
+}
+
+/// Calculates the hash of a commit based on its contents, leaving out its position in the branch.
+fn hash_commit(message: &str, author: &str, created_at: u64, treehash: Digest) -> Digest {
     let mut hasher = Xxh3::new();
 
     hasher.update(message.as_bytes());
+    // Empty author is not hashed to keep hashes of commits created before authors were introduced
+    if !author.is_empty() {
+        hasher.update(author.as_bytes());
+    }
+    // Same for unknown time of commits created before timestamps were introduced
+    if created_at != Commit::UNKNOWN_TIME {
+        hasher.update(&created_at.to_be_bytes());
+    }
     // TODO: add other metadata that defines a commit state, but not a position
 
     hasher.update(&treehash.to_be_bytes());
+    hasher.digest128()
+}
 
-    // Create commit with calculated hash
-    Commit {
-        id,
-        ver,
-        hash: hasher.digest128(),
-        treehash,
-        message,
+/// Current time in seconds since the Unix epoch, unless `SOURCE_DATE_EPOCH_ENV` sets another one.
+fn now() -> Result<u64, CommitError> {
+    if let Some(value) = std::env::var(SOURCE_DATE_EPOCH_ENV)
+        .ok()
+        .filter(|v| !v.is_empty())
+    {
+        return value.parse().map_err(|_| {
+            CommitError::Other(format!(
+                "Invalid value '{}' of {}, expected seconds since the Unix epoch",
+                value, SOURCE_DATE_EPOCH_ENV
+            ))
+        });
     }
+    Ok(SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(Commit::UNKNOWN_TIME))
 }
@@ -1,3 +1,4 @@
+use serde::Serializer;
 use std::fs::File;
 use std::io::{BufReader, Read};
 use std::path::Path;
@@ -5,6 +6,9 @@ use xxhash_rust::xxh3::Xxh3;
 
 pub type Digest = u128;
 
+/// Name of the algorithm computing digests, the 128-bit variant of XXH3.
+pub const HASH_ALGORITHM: &str = "xxh3-128";
+
 /// Trait for converting a digest to a hexadecimal string representation and computing a hash.
 pub trait DigestExt {
     const NONE: Digest = 0;
@@ -12,8 +16,17 @@ pub trait DigestExt {
     /// Converts the digest to a hexadecimal string representation.
     fn to_hex_string(&self) -> String;
 
+    /// Parses a digest from its hexadecimal string representation, as produced by `to_hex_string`.
+    fn from_hex_string(hex: &str) -> Option<Digest>;
+
     /// Computes the hash of a file and returns it as a Digest and the size of the file.
     fn compute_hash(file_path: &Path) -> Result<(Digest, u64), std::io::Error>;
+
+    /// Computes the hash of a stream, consistent with `compute_hash`.
+    fn compute_hash_reader(reader: &mut impl Read) -> Result<(Digest, u64), std::io::Error>;
+
+    /// Computes the hash of an in-memory content, consistent with `compute_hash`.
+    fn compute_hash_bytes(content: &[u8]) -> Digest;
 }
 
 impl DigestExt for Digest {
@@ -22,11 +35,22 @@ impl DigestExt for Digest {
         format!("{:032x}", self)
     }
 
+    fn from_hex_string(hex: &str) -> Option<Digest> {
+        // from_str_radix accepts a leading sign, which is not a part of the representation
+        if hex.is_empty() || hex.len() > 32 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        u128::from_str_radix(hex, 16).ok()
+    }
+
     fn compute_hash(file_path: &Path) -> Result<(Digest, u64), std::io::Error> {
+        let file = File::open(file_path)?;
+        Self::compute_hash_reader(&mut BufReader::new(file))
+    }
+
+    fn compute_hash_reader(reader: &mut impl Read) -> Result<(Digest, u64), std::io::Error> {
         const BUFFER_SIZE: usize = 8192; // 8 KB
 
-        let file = File::open(file_path)?;
-        let mut reader = BufReader::new(file);
         let mut buffer = [0u8; BUFFER_SIZE];
         let mut hasher = Xxh3::new();
         let mut total_size = 0;
@@ -42,4 +66,16 @@ impl DigestExt for Digest {
 
         Ok((hasher.digest128(), total_size)) // Finalize and return the hash and size
     }
+
+    fn compute_hash_bytes(content: &[u8]) -> Digest {
+        let mut hasher = Xxh3::new();
+        hasher.update(content);
+        hasher.digest128()
+    }
+}
+
+/// Serializes a digest as its hexadecimal string representation, for formats a 128-bit number
+/// does not fit into, e.g. JSON.
+pub fn serialize_hex<S: Serializer>(digest: &Digest, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&digest.to_hex_string())
 }
@@ -0,0 +1,290 @@
+use crate::context::Context;
+use crate::core::digest::Digest;
+use crate::core::repo::Repo;
+use crate::storage::repo::RepoError;
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use xxhash_rust::xxh3::Xxh3;
+
+/// Repository metadata key overriding the default ignore patterns.
+/// The value is a comma separated list of patterns replacing the built-in ones, an empty value
+/// disables default ignores altogether.
+pub const DEFAULT_IGNORE_KEY: &str = "ignore.defaults";
+
+/// Files created by operating systems and editors behind the user's back.
+/// The list is kept conservative, so that nothing a user may reasonably want to track is skipped.
+pub const DEFAULT_IGNORE_PATTERNS: &[&str] = &[
+    // macOS Finder metadata
+    ".DS_Store",
+    // Windows Explorer thumbnail caches and folder settings
+    "Thumbs.db",
+    "ehthumbs.db",
+    "desktop.ini",
+    // editor backup files
+    "*~",
+];
+
+/// Name of the files listing the paths not to track in their folder and below, similar to
+/// `.gitignore`.
+/// Every line holds a pattern, and lines starting with `#` are comments. `*` matches any part of a
+/// name and `**` any number of folders, a pattern ending with `/` only matches folders and a
+/// pattern starting with `!` tracks again what an earlier one ignores. Patterns without a slash
+/// match names at any depth, the others match paths relative to the folder of the file.
+pub const IGNORE_FILE_NAME: &str = ".vxignore";
+
+/// Parsed rules of folders by path relative to the root.
+type Folders = HashMap<PathBuf, Arc<Vec<Rule>>>;
+
+/// Rules deciding which files and folders are not tracked: the default patterns matched against
+/// names, then the ignore files looked up from the root folder down to the folder of a path, so
+/// the deepest matching line wins, and within a file the last one does.
+/// Ignore files are read from the checkout the first time their folder is looked at.
+#[derive(Debug)]
+pub struct Ignore {
+    patterns: Vec<String>,
+    /// Root of the checkout the ignore files are read from, None if they are not read.
+    root: Option<PathBuf>,
+    /// Rules of every folder looked at so far.
+    folders: Mutex<Folders>,
+}
+
+/// A line of an ignore file.
+#[derive(Debug, Clone)]
+struct Rule {
+    /// Names of the path matched one by one, `**` standing for any number of them.
+    segments: Vec<String>,
+    negated: bool,
+    dir_only: bool,
+}
+
+impl Ignore {
+    /// Loads ignore rules of the repository, falling back to the default patterns.
+    pub fn load(context: &Context) -> Result<Self, RepoError> {
+        let metadata = Repo::get_metadata(context)?;
+        let patterns = match metadata.get(DEFAULT_IGNORE_KEY) {
+            Some(value) => value
+                .split(',')
+                .map(str::trim)
+                .filter(|pattern| !pattern.is_empty())
+                .map(String::from)
+                .collect(),
+            None => DEFAULT_IGNORE_PATTERNS
+                .iter()
+                .map(|pattern| pattern.to_string())
+                .collect(),
+        };
+        Ok(Ignore {
+            patterns,
+            root: Some(context.checkout_path.clone()),
+            folders: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Rules that do not ignore anything.
+    pub fn none() -> Self {
+        Ignore {
+            patterns: Vec::new(),
+            root: None,
+            folders: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Checks whether a file or folder is ignored by its path relative to the root of the
+    /// checkout, assuming the folders above it are not.
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> io::Result<bool> {
+        let mut ignored = match path.file_name().and_then(OsStr::to_str) {
+            Some(name) => self
+                .patterns
+                .iter()
+                .any(|pattern| matches_pattern(pattern, name)),
+            None => false,
+        };
+        if self.root.is_none() {
+            return Ok(ignored);
+        }
+
+        let mut dir = PathBuf::new();
+        let mut components = path.components().peekable();
+        while let Some(component) = components.next() {
+            let rules = self.rules(&dir)?;
+            // a path is matched relative to the folder of the ignore file
+            let relative: Vec<&str> = path
+                .strip_prefix(&dir)
+                .unwrap_or(path)
+                .iter()
+                .filter_map(OsStr::to_str)
+                .collect();
+            for rule in rules.iter().filter(|rule| rule.matches(&relative, is_dir)) {
+                ignored = !rule.negated;
+            }
+
+            if components.peek().is_none() {
+                break;
+            }
+            dir.push(component);
+        }
+        Ok(ignored)
+    }
+
+    /// Same as `is_ignored` for an absolute path within the checkout, e.g. of an entry of a folder
+    /// being listed.
+    pub(crate) fn is_entry_ignored(&self, path: &Path, is_dir: bool) -> io::Result<bool> {
+        let relative = match &self.root {
+            Some(root) => path.strip_prefix(root).unwrap_or(path),
+            None => path,
+        };
+        self.is_ignored(relative, is_dir)
+    }
+
+    /// Checks whether a file or folder is ignored, either by itself or by any folder above it.
+    pub fn is_path_ignored(&self, path: &Path, is_dir: bool) -> io::Result<bool> {
+        for folder in path.ancestors().skip(1) {
+            if !folder.as_os_str().is_empty() && self.is_ignored(folder, true)? {
+                return Ok(true);
+            }
+        }
+        self.is_ignored(path, is_dir)
+    }
+
+    /// Digest of the rules deciding which entries of a folder are ignored: the default patterns and
+    /// the ignore files of the folder and of every folder above it. An entry ignored earlier may
+    /// only be tracked now if the digest differs.
+    pub(crate) fn digest(&self, dir: &Path) -> io::Result<Digest> {
+        let mut hasher = Xxh3::new();
+        for pattern in &self.patterns {
+            hasher.update(pattern.as_bytes());
+            hasher.update(&[0]);
+        }
+        if self.root.is_some() {
+            for folder in dir.ancestors() {
+                hasher.update(folder.as_os_str().as_encoded_bytes());
+                hasher.update(&[0]);
+                for rule in self.rules(folder)?.iter() {
+                    hasher.update(&(rule.segments.len() as u64).to_be_bytes());
+                    for segment in &rule.segments {
+                        hasher.update(segment.as_bytes());
+                        hasher.update(&[0]);
+                    }
+                    hasher.update(&[rule.negated as u8, rule.dir_only as u8]);
+                }
+            }
+        }
+        Ok(hasher.digest128())
+    }
+
+    /// Gets the rules of a folder, reading its ignore file the first time it is looked at.
+    fn rules(&self, dir: &Path) -> io::Result<Arc<Vec<Rule>>> {
+        if let Some(rules) = self.lock()?.get(dir) {
+            return Ok(rules.clone());
+        }
+
+        // the lock is not held while reading, a folder read twice by two workers is parsed twice
+        let rules = match &self.root {
+            Some(root) => match std::fs::read(root.join(dir).join(IGNORE_FILE_NAME)) {
+                Ok(content) => parse(&String::from_utf8_lossy(&content)),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+                Err(e) => return Err(e),
+            },
+            None => Vec::new(),
+        };
+        let rules = Arc::new(rules);
+        self.lock()?.insert(dir.to_path_buf(), rules.clone());
+        Ok(rules)
+    }
+
+    fn lock(&self) -> io::Result<MutexGuard<'_, Folders>> {
+        self.folders
+            .lock()
+            .map_err(|_| io::Error::other("Ignore rules cache is poisoned"))
+    }
+}
+
+impl Rule {
+    fn matches(&self, relative: &[&str], is_dir: bool) -> bool {
+        (is_dir || !self.dir_only) && matches_segments(&self.segments, relative)
+    }
+}
+
+/// Matches names of a path one by one, `**` matching any number of them, including none.
+fn matches_segments(segments: &[String], names: &[&str]) -> bool {
+    match segments.split_first() {
+        None => names.is_empty(),
+        Some((segment, rest)) if segment == "**" => {
+            (0..=names.len()).any(|skipped| matches_segments(rest, &names[skipped..]))
+        }
+        Some((segment, rest)) => match names.split_first() {
+            Some((name, names)) => matches_pattern(segment, name) && matches_segments(rest, names),
+            None => false,
+        },
+    }
+}
+
+/// Parses the content of an ignore file.
+fn parse(content: &str) -> Vec<Rule> {
+    let mut rules = Vec::new();
+    for line in content.lines() {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (negated, pattern) = match line.strip_prefix('!') {
+            Some(pattern) => (true, pattern),
+            None => (false, line),
+        };
+        // a backslash escapes a leading `#` or `!` that is part of a name
+        let pattern = pattern.strip_prefix('\\').unwrap_or(pattern);
+        let dir_only = pattern.ends_with('/');
+        let pattern = pattern.trim_end_matches('/');
+        // a slash anywhere but at the end anchors the pattern to the folder of the file
+        let anchored = pattern.contains('/');
+        let pattern = pattern.trim_start_matches('/');
+        if pattern.is_empty() {
+            continue;
+        }
+
+        let mut segments = Vec::new();
+        if !anchored {
+            segments.push("**".to_string());
+        }
+        segments.extend(
+            pattern
+                .split('/')
+                .filter(|segment| !segment.is_empty())
+                .map(String::from),
+        );
+        rules.push(Rule {
+            segments,
+            negated,
+            dir_only,
+        });
+    }
+    rules
+}
+
+/// Matches a name against a pattern where `*` stands for any sequence of characters.
+pub(crate) fn matches_pattern(pattern: &str, name: &str) -> bool {
+    let mut parts = pattern.split('*');
+    // split always yields at least one part
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = name.strip_prefix(first) else {
+        return false;
+    };
+
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        // no wildcards, the pattern must match the whole name
+        return rest.is_empty();
+    };
+
+    for part in middle {
+        match rest.find(part) {
+            Some(pos) => rest = &rest[pos + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
@@ -0,0 +1,209 @@
+// Sorted listings of folders with a bounded memory footprint.
+//
+// Names of a folder are sorted in memory in batches. Once a folder has more names than a batch
+// holds, every full batch is sorted and spilled to a run file in the workspace, and the runs are
+// merged while the names are walked, so only a batch plus a name per run are held at a time.
+
+use crate::context::Context;
+use crate::global::TEMP_FOLDER;
+use crate::storage::repo::RepoError;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Repository metadata key setting how many names of a folder are sorted in memory at once.
+/// Folders with more entries are sorted in batches spilled to temporary files, trading some disk
+/// I/O for memory on pathologically large folders.
+pub const DIR_BATCH_SIZE_KEY: &str = "tree.dirBatchSize";
+
+/// Batch size used unless `DIR_BATCH_SIZE_KEY` is set, so only folders with more entries than
+/// any reasonable project has are spilled.
+const DEFAULT_DIR_BATCH_SIZE: usize = 100_000;
+
+/// Separator of names in run files, the only byte besides '/' that file names can not contain.
+const SEPARATOR: u8 = 0;
+
+/// Reads how many names of a folder are sorted in memory at once from repository metadata.
+pub fn dir_batch_size(metadata: &HashMap<String, String>) -> Result<usize, RepoError> {
+    match metadata.get(DIR_BATCH_SIZE_KEY) {
+        Some(value) => match value.parse() {
+            Ok(size) if size > 0 => Ok(size),
+            _ => Err(RepoError::Other(format!(
+                "Invalid value '{}' of {}, expected a positive number of names",
+                value, DIR_BATCH_SIZE_KEY
+            ))),
+        },
+        None => Ok(DEFAULT_DIR_BATCH_SIZE),
+    }
+}
+
+/// Creates sorters of folder listings, spilling their batches into the workspace.
+#[derive(Debug, Clone)]
+pub(crate) struct Lister {
+    spill_dir: PathBuf,
+    batch_size: usize,
+}
+
+impl Lister {
+    pub(crate) fn new(context: &Context, batch_size: usize) -> Self {
+        Lister {
+            spill_dir: context.workspace_path.join(TEMP_FOLDER),
+            batch_size,
+        }
+    }
+
+    pub(crate) fn sorter(&self) -> NameSorter<'_> {
+        NameSorter {
+            lister: self,
+            names: Vec::new(),
+            runs: Runs::default(),
+        }
+    }
+
+    /// Sorts a batch and writes it to a new run file.
+    fn spill(&self, names: &mut Vec<String>) -> io::Result<PathBuf> {
+        // unique per process and run, so concurrent walks do not collide
+        static COUNTER: AtomicU64 = AtomicU64::new(0);
+        std::fs::create_dir_all(&self.spill_dir)?;
+        let path = self.spill_dir.join(format!(
+            "names-{}-{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+
+        names.sort_unstable();
+        let mut out = BufWriter::new(File::create(&path)?);
+        for name in names.drain(..) {
+            out.write_all(name.as_bytes())?;
+            out.write_all(&[SEPARATOR])?;
+        }
+        out.flush()?;
+        Ok(path)
+    }
+}
+
+/// Collects the names of a folder, spilling full batches to run files.
+pub(crate) struct NameSorter<'a> {
+    lister: &'a Lister,
+    names: Vec<String>,
+    runs: Runs,
+}
+
+impl NameSorter<'_> {
+    pub(crate) fn push(&mut self, name: String) -> io::Result<()> {
+        self.names.push(name);
+        if self.names.len() >= self.lister.batch_size {
+            let path = self.lister.spill(&mut self.names)?;
+            self.runs.paths.push(path);
+        }
+        Ok(())
+    }
+
+    /// Finishes the listing, returning the names in sorted order.
+    pub(crate) fn finish(mut self) -> io::Result<SortedNames> {
+        if self.runs.paths.is_empty() {
+            self.names.sort_unstable();
+            let mut names = self.names.into_iter();
+            return Ok(SortedNames {
+                current: names.next(),
+                source: Source::Memory(names),
+            });
+        }
+
+        if !self.names.is_empty() {
+            let path = self.lister.spill(&mut self.names)?;
+            self.runs.paths.push(path);
+        }
+        let mut readers = Vec::with_capacity(self.runs.paths.len());
+        let mut heap = BinaryHeap::with_capacity(self.runs.paths.len());
+        for (run, path) in self.runs.paths.iter().enumerate() {
+            let mut reader = BufReader::new(File::open(path)?);
+            if let Some(name) = read_name(&mut reader)? {
+                heap.push(Reverse((name, run)));
+            }
+            readers.push(reader);
+        }
+        let mut names = SortedNames {
+            current: None,
+            source: Source::Runs {
+                readers,
+                heap,
+                _runs: std::mem::take(&mut self.runs),
+            },
+        };
+        names.advance()?;
+        Ok(names)
+    }
+}
+
+/// Names of a folder in sorted order, walked one at a time.
+#[derive(Debug)]
+pub(crate) struct SortedNames {
+    current: Option<String>,
+    source: Source,
+}
+
+#[derive(Debug)]
+enum Source {
+    Memory(std::vec::IntoIter<String>),
+    /// A k-way merge of run files, the heap holding the next name of every run.
+    Runs {
+        readers: Vec<BufReader<File>>,
+        heap: BinaryHeap<Reverse<(String, usize)>>,
+        _runs: Runs,
+    },
+}
+
+impl SortedNames {
+    /// The name at the current position, None once all names were walked.
+    pub(crate) fn peek(&self) -> Option<&String> {
+        self.current.as_ref()
+    }
+
+    /// Moves to the next name.
+    pub(crate) fn advance(&mut self) -> io::Result<()> {
+        self.current = match &mut self.source {
+            Source::Memory(names) => names.next(),
+            Source::Runs { readers, heap, .. } => match heap.pop() {
+                Some(Reverse((name, run))) => {
+                    if let Some(next) = read_name(&mut readers[run])? {
+                        heap.push(Reverse((next, run)));
+                    }
+                    Some(name)
+                }
+                None => None,
+            },
+        };
+        Ok(())
+    }
+}
+
+/// Run files of a listing, removed once it is dropped.
+#[derive(Debug, Default)]
+struct Runs {
+    paths: Vec<PathBuf>,
+}
+
+impl Drop for Runs {
+    fn drop(&mut self) {
+        for path in &self.paths {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+fn read_name(reader: &mut BufReader<File>) -> io::Result<Option<String>> {
+    let mut bytes = Vec::new();
+    if reader.read_until(SEPARATOR, &mut bytes)? == 0 {
+        return Ok(None);
+    }
+    if bytes.last() == Some(&SEPARATOR) {
+        bytes.pop();
+    }
+    String::from_utf8(bytes)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
@@ -0,0 +1,238 @@
+// Line based three-way merge.
+//
+// Both sides are diffed against the common base using the longest common subsequence of lines.
+// Base lines matched on both sides split the files into stable chunks and unstable chunks in between.
+// An unstable chunk changed only on one side takes that side, a chunk changed identically on both
+// sides is taken once, and a chunk changed differently on both sides is a conflict.
+
+use std::path::PathBuf;
+
+/// Marker starting the local side of a conflict.
+pub const CONFLICT_START: &str = "<<<<<<< local";
+/// Marker separating the local and the incoming sides of a conflict.
+pub const CONFLICT_SEPARATOR: &str = "=======";
+/// Marker ending the incoming side of a conflict.
+pub const CONFLICT_END: &str = ">>>>>>> incoming";
+
+/// Maximum size of the LCS table, larger inputs are treated as a single chunk.
+const MAX_LCS_CELLS: usize = 16 * 1024 * 1024;
+
+/// Kind of a conflict between local and incoming changes of a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictKind {
+    /// Both sides changed the same lines, the file contains conflict markers.
+    Content,
+    /// The file was modified locally but deleted by the incoming change, the local file is kept.
+    ModifiedDeleted,
+    /// The file was deleted locally but modified by the incoming change, the incoming file is restored.
+    DeletedModified,
+    /// Both sides changed a binary file, the local file is kept.
+    Binary,
+}
+
+/// Represents a conflict in a single file.
+#[derive(Debug, Clone)]
+pub struct Conflict {
+    /// Path to the file, relative to the checkout root.
+    pub path: PathBuf,
+    /// Kind of the conflict.
+    pub kind: ConflictKind,
+}
+
+/// Side of a conflict to keep when resolving it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    /// The lines between the start marker and the separator.
+    Local,
+    /// The lines between the separator and the end marker.
+    Incoming,
+}
+
+/// Result of a three-way merge.
+#[derive(Debug, Clone)]
+pub struct MergeResult {
+    /// Merged content, with conflict markers around conflicting chunks.
+    pub content: String,
+    /// Number of conflicting chunks.
+    pub conflicts: usize,
+}
+
+/// Merges local and incoming changes made to a common base, line by line.
+pub fn merge_lines(base: &str, local: &str, incoming: &str) -> MergeResult {
+    let base_lines: Vec<&str> = base.split_inclusive('\n').collect();
+    let local_lines: Vec<&str> = local.split_inclusive('\n').collect();
+    let incoming_lines: Vec<&str> = incoming.split_inclusive('\n').collect();
+
+    let local_matches = match_lines(&base_lines, &local_lines);
+    let incoming_matches = match_lines(&base_lines, &incoming_lines);
+
+    let mut result = MergeResult {
+        content: String::with_capacity(local.len().max(incoming.len())),
+        conflicts: 0,
+    };
+
+    // positions in base, local and incoming lines respectively
+    let (mut b, mut l, mut i) = (0, 0, 0);
+
+    loop {
+        // find the next base line which is kept on both sides
+        let sync = (b..base_lines.len())
+            .find(|&pos| local_matches[pos].is_some() && incoming_matches[pos].is_some());
+
+        let (b_end, l_end, i_end) = match sync {
+            Some(pos) => (
+                pos,
+                local_matches[pos].unwrap(),
+                incoming_matches[pos].unwrap(),
+            ),
+            None => (base_lines.len(), local_lines.len(), incoming_lines.len()),
+        };
+
+        merge_chunk(
+            &mut result,
+            &base_lines[b..b_end],
+            &local_lines[l..l_end],
+            &incoming_lines[i..i_end],
+        );
+
+        match sync {
+            Some(pos) => {
+                result.content.push_str(base_lines[pos]);
+                b = b_end + 1;
+                l = l_end + 1;
+                i = i_end + 1;
+            }
+            None => break,
+        }
+    }
+
+    result
+}
+
+/// Merges a single unstable chunk.
+fn merge_chunk(result: &mut MergeResult, base: &[&str], local: &[&str], incoming: &[&str]) {
+    if local == incoming || incoming == base {
+        push_lines(&mut result.content, local);
+    } else if local == base {
+        push_lines(&mut result.content, incoming);
+    } else {
+        result.conflicts += 1;
+        push_marker(&mut result.content, CONFLICT_START);
+        push_lines(&mut result.content, local);
+        push_marker(&mut result.content, CONFLICT_SEPARATOR);
+        push_lines(&mut result.content, incoming);
+        push_marker(&mut result.content, CONFLICT_END);
+    }
+}
+
+fn push_lines(content: &mut String, lines: &[&str]) {
+    for line in lines {
+        content.push_str(line);
+    }
+}
+
+fn push_marker(content: &mut String, marker: &str) {
+    // the last line of a side may be missing a line break
+    if !content.is_empty() && !content.ends_with('\n') {
+        content.push('\n');
+    }
+    content.push_str(marker);
+    content.push('\n');
+}
+
+/// Resolves every conflict in content merged with conflict markers by keeping one side of it, the
+/// lines outside of conflicts are kept as they are. A conflict missing its end marker, e.g. after
+/// a careless edit, is left untouched.
+pub fn resolve_conflicts(content: &str, side: Side) -> String {
+    let mut resolved = String::with_capacity(content.len());
+    // lines of the conflict being read, with its markers, and the side they belong to
+    let mut conflict: Option<(String, Side)> = None;
+    let mut kept = String::new();
+
+    for line in content.split_inclusive('\n') {
+        let marker = line.trim_end_matches(['\r', '\n']);
+        match conflict.as_mut() {
+            None if marker == CONFLICT_START => conflict = Some((line.to_string(), Side::Local)),
+            None => resolved.push_str(line),
+            Some((raw, current)) => {
+                raw.push_str(line);
+                if marker == CONFLICT_SEPARATOR && *current == Side::Local {
+                    *current = Side::Incoming;
+                } else if marker == CONFLICT_END && *current == Side::Incoming {
+                    resolved.push_str(&kept);
+                    kept.clear();
+                    conflict = None;
+                } else if *current == side {
+                    kept.push_str(line);
+                }
+            }
+        }
+    }
+    if let Some((raw, _)) = conflict {
+        resolved.push_str(&raw);
+    }
+    resolved
+}
+
+/// Matches lines of the base with lines of the other side using their longest common subsequence.
+/// Returns, for every base line, the position of the matching line on the other side, if any.
+fn match_lines(base: &[&str], other: &[&str]) -> Vec<Option<usize>> {
+    let mut matches = vec![None; base.len()];
+
+    // Common prefix and suffix are matched directly, which keeps the LCS table small for typical edits.
+    let prefix = base
+        .iter()
+        .zip(other.iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+    for (pos, m) in matches.iter_mut().enumerate().take(prefix) {
+        *m = Some(pos);
+    }
+
+    let suffix = base[prefix..]
+        .iter()
+        .rev()
+        .zip(other[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    for k in 0..suffix {
+        matches[base.len() - 1 - k] = Some(other.len() - 1 - k);
+    }
+
+    let base_mid = &base[prefix..base.len() - suffix];
+    let other_mid = &other[prefix..other.len() - suffix];
+    let (n, m) = (base_mid.len(), other_mid.len());
+
+    if n == 0 || m == 0 || (n + 1) * (m + 1) > MAX_LCS_CELLS {
+        // nothing to match or too expensive, the whole middle part becomes a single chunk
+        return matches;
+    }
+
+    // lengths[x][y] is the LCS length of base_mid[x..] and other_mid[y..]
+    let width = m + 1;
+    let mut lengths = vec![0u32; (n + 1) * width];
+    for x in (0..n).rev() {
+        for y in (0..m).rev() {
+            lengths[x * width + y] = if base_mid[x] == other_mid[y] {
+                lengths[(x + 1) * width + y + 1] + 1
+            } else {
+                lengths[(x + 1) * width + y].max(lengths[x * width + y + 1])
+            };
+        }
+    }
+
+    let (mut x, mut y) = (0, 0);
+    while x < n && y < m {
+        if base_mid[x] == other_mid[y] {
+            matches[prefix + x] = Some(prefix + y);
+            x += 1;
+            y += 1;
+        } else if lengths[(x + 1) * width + y] >= lengths[x * width + y + 1] {
+            x += 1;
+        } else {
+            y += 1;
+        }
+    }
+
+    matches
+}
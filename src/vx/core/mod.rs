@@ -1,6 +1,18 @@
+pub mod attributes;
+pub mod bisect;
 pub mod blob;
 pub mod branch;
 pub mod commit;
 pub mod digest;
+pub mod ignore;
+pub mod listing;
+pub mod merge;
+pub mod regex;
+pub mod rename;
 pub mod repo;
+pub mod signing;
+pub mod stash;
+pub mod tag;
 pub mod tree;
+pub mod workspace;
+pub mod xattr;
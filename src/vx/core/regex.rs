@@ -0,0 +1,512 @@
+// Minimal regular expressions for searching text content.
+//
+// Patterns are compiled into a program for a Pike VM, which runs all alternatives simultaneously,
+// so matching time is linear in the length of the text regardless of the pattern.
+//
+// Supported syntax:
+//   - literals, `.` for any character, `\` to escape a special character, `\t` and `\n`
+//   - character classes `[abc]`, `[a-z]`, `[^...]` and `\d`, `\w`, `\s` with their negations
+//   - anchors `^` and `$`
+//   - groups `(...)`, alternation `|` and quantifiers `*`, `+`, `?`
+//   - counted quantifiers `{n}`, `{n,}` and `{n,m}`, expanded into copies of what they repeat;
+//     a `{` not starting one is a literal
+
+use thiserror::Error;
+
+/// Largest count of a counted quantifier, as the repeated node is copied that many times.
+const MAX_REPEAT: u32 = 1000;
+/// Largest number of nodes a counted quantifier expands into, nested ones multiply.
+const MAX_REPEAT_SIZE: usize = 100_000;
+
+/// Represents errors that can occur while compiling a pattern.
+#[derive(Error, Debug)]
+pub enum RegexError {
+    #[error("Invalid pattern at position {0}: {1}")]
+    Syntax(usize, String),
+}
+
+/// A compiled regular expression.
+#[derive(Debug, Clone)]
+pub struct Regex {
+    program: Vec<Inst>,
+    classes: Vec<Class>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Inst {
+    Char(char),
+    Any,
+    Class(usize),
+    LineStart,
+    LineEnd,
+    Split(usize, usize),
+    Jump(usize),
+    Match,
+}
+
+#[derive(Debug, Clone)]
+enum ClassItem {
+    Range(char, char),
+    Digit,
+    Word,
+    Space,
+}
+
+#[derive(Debug, Clone)]
+struct Class {
+    items: Vec<ClassItem>,
+    negated: bool,
+}
+
+impl ClassItem {
+    fn matches(&self, c: char) -> bool {
+        match *self {
+            ClassItem::Range(from, to) => from <= c && c <= to,
+            ClassItem::Digit => c.is_ascii_digit(),
+            ClassItem::Word => c.is_alphanumeric() || c == '_',
+            ClassItem::Space => c.is_whitespace(),
+        }
+    }
+}
+
+impl Class {
+    fn matches(&self, c: char) -> bool {
+        self.items.iter().any(|item| item.matches(c)) != self.negated
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Empty,
+    Char(char),
+    Any,
+    Class(Class),
+    LineStart,
+    LineEnd,
+    Concat(Vec<Node>),
+    Alternate(Box<Node>, Box<Node>),
+    Star(Box<Node>),
+    Plus(Box<Node>),
+    Optional(Box<Node>),
+}
+
+impl Node {
+    /// Number of nodes of the tree rooted at the node.
+    fn size(&self) -> usize {
+        1 + match self {
+            Node::Concat(nodes) => nodes.iter().map(Node::size).sum(),
+            Node::Alternate(first, second) => first.size() + second.size(),
+            Node::Star(node) | Node::Plus(node) | Node::Optional(node) => node.size(),
+            _ => 0,
+        }
+    }
+}
+
+impl Regex {
+    /// Compiles a pattern.
+    pub fn new(pattern: &str) -> Result<Self, RegexError> {
+        let mut parser = Parser {
+            chars: pattern.chars().collect(),
+            pos: 0,
+        };
+        let node = parser.parse_alternate()?;
+        if parser.pos < parser.chars.len() {
+            return Err(RegexError::Syntax(parser.pos, "unmatched ')'".to_string()));
+        }
+
+        let mut regex = Regex {
+            program: Vec::new(),
+            classes: Vec::new(),
+        };
+        regex.compile(node);
+        regex.program.push(Inst::Match);
+        Ok(regex)
+    }
+
+    /// Checks whether the pattern matches anywhere in the text.
+    pub fn is_match(&self, text: &str) -> bool {
+        let chars: Vec<char> = text.chars().collect();
+        let mut current = Threads::new(self.program.len());
+        let mut next = Threads::new(self.program.len());
+
+        for pos in 0..=chars.len() {
+            // a match may start at any position
+            self.add_thread(&mut current, 0, pos, chars.len());
+
+            for i in 0..current.list.len() {
+                let pc = current.list[i];
+                match self.program[pc] {
+                    Inst::Match => return true,
+                    Inst::Char(c) if chars.get(pos) == Some(&c) => {
+                        self.add_thread(&mut next, pc + 1, pos + 1, chars.len())
+                    }
+                    Inst::Any if pos < chars.len() => {
+                        self.add_thread(&mut next, pc + 1, pos + 1, chars.len())
+                    }
+                    Inst::Class(class)
+                        if chars
+                            .get(pos)
+                            .is_some_and(|&c| self.classes[class].matches(c)) =>
+                    {
+                        self.add_thread(&mut next, pc + 1, pos + 1, chars.len())
+                    }
+                    _ => (),
+                }
+            }
+
+            std::mem::swap(&mut current, &mut next);
+            next.clear();
+        }
+
+        false
+    }
+
+    /// Adds a thread to the list, following jumps and anchors that do not consume characters.
+    fn add_thread(&self, threads: &mut Threads, pc: usize, pos: usize, len: usize) {
+        if !threads.insert(pc) {
+            return;
+        }
+        match self.program[pc] {
+            Inst::Jump(to) => self.add_thread(threads, to, pos, len),
+            Inst::Split(first, second) => {
+                self.add_thread(threads, first, pos, len);
+                self.add_thread(threads, second, pos, len);
+            }
+            Inst::LineStart if pos == 0 => self.add_thread(threads, pc + 1, pos, len),
+            Inst::LineEnd if pos == len => self.add_thread(threads, pc + 1, pos, len),
+            Inst::LineStart | Inst::LineEnd => (),
+            _ => threads.list.push(pc),
+        }
+    }
+
+    fn compile(&mut self, node: Node) {
+        match node {
+            Node::Empty => (),
+            Node::Char(c) => self.program.push(Inst::Char(c)),
+            Node::Any => self.program.push(Inst::Any),
+            Node::Class(class) => {
+                self.classes.push(class);
+                self.program.push(Inst::Class(self.classes.len() - 1));
+            }
+            Node::LineStart => self.program.push(Inst::LineStart),
+            Node::LineEnd => self.program.push(Inst::LineEnd),
+            Node::Concat(nodes) => {
+                for node in nodes {
+                    self.compile(node);
+                }
+            }
+            Node::Alternate(first, second) => {
+                let split = self.placeholder();
+                self.compile(*first);
+                let jump = self.placeholder();
+                let second_start = self.program.len();
+                self.compile(*second);
+                self.program[split] = Inst::Split(split + 1, second_start);
+                self.program[jump] = Inst::Jump(self.program.len());
+            }
+            Node::Star(node) => {
+                let split = self.placeholder();
+                self.compile(*node);
+                self.program.push(Inst::Jump(split));
+                self.program[split] = Inst::Split(split + 1, self.program.len());
+            }
+            Node::Plus(node) => {
+                let start = self.program.len();
+                self.compile(*node);
+                self.program
+                    .push(Inst::Split(start, self.program.len() + 1));
+            }
+            Node::Optional(node) => {
+                let split = self.placeholder();
+                self.compile(*node);
+                self.program[split] = Inst::Split(split + 1, self.program.len());
+            }
+        }
+    }
+
+    /// Reserves an instruction to be filled in once the jump targets are known.
+    fn placeholder(&mut self) -> usize {
+        self.program.push(Inst::Match);
+        self.program.len() - 1
+    }
+}
+
+/// Set of threads of the Pike VM, keeping the order in which they were added.
+struct Threads {
+    list: Vec<usize>,
+    seen: Vec<bool>,
+}
+
+impl Threads {
+    fn new(size: usize) -> Self {
+        Threads {
+            list: Vec::with_capacity(size),
+            seen: vec![false; size],
+        }
+    }
+
+    /// Marks the instruction as visited, returns false if it already was.
+    fn insert(&mut self, pc: usize) -> bool {
+        !std::mem::replace(&mut self.seen[pc], true)
+    }
+
+    fn clear(&mut self) {
+        self.list.clear();
+        self.seen.fill(false);
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn error(&self, message: &str) -> RegexError {
+        RegexError::Syntax(self.pos, message.to_string())
+    }
+
+    fn parse_alternate(&mut self) -> Result<Node, RegexError> {
+        let mut node = self.parse_concat()?;
+        while self.peek() == Some('|') {
+            self.pos += 1;
+            let second = self.parse_concat()?;
+            node = Node::Alternate(Box::new(node), Box::new(second));
+        }
+        Ok(node)
+    }
+
+    fn parse_concat(&mut self) -> Result<Node, RegexError> {
+        let mut nodes = Vec::new();
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            let atom = self.parse_atom()?;
+            nodes.push(self.parse_quantifiers(atom)?);
+        }
+        Ok(match nodes.len() {
+            0 => Node::Empty,
+            1 => nodes.pop().unwrap(),
+            _ => Node::Concat(nodes),
+        })
+    }
+
+    fn parse_quantifiers(&mut self, mut node: Node) -> Result<Node, RegexError> {
+        while let Some(c) = self.peek() {
+            node = match c {
+                '*' => Node::Star(Box::new(node)),
+                '+' => Node::Plus(Box::new(node)),
+                '?' => Node::Optional(Box::new(node)),
+                '{' => {
+                    let start = self.pos;
+                    let Some((min, max)) = self.parse_bounds()? else {
+                        break;
+                    };
+                    let copies = max.unwrap_or(min).max(1) as usize;
+                    if node.size().saturating_mul(copies) > MAX_REPEAT_SIZE {
+                        return Err(RegexError::Syntax(
+                            start,
+                            "repetition is too large".to_string(),
+                        ));
+                    }
+                    node = repeat(node, min, max);
+                    continue;
+                }
+                _ => break,
+            };
+            self.pos += 1;
+        }
+        Ok(node)
+    }
+
+    /// Parses the bounds of a counted quantifier at a `{`, past its closing `}`.
+    /// Returns None and leaves the position as it is if the brace does not start one.
+    fn parse_bounds(&mut self) -> Result<Option<(u32, Option<u32>)>, RegexError> {
+        let start = self.pos;
+        self.pos += 1;
+        let bounds = match self.parse_count(start)? {
+            Some(min) if self.peek() == Some(',') => {
+                self.pos += 1;
+                Some((min, self.parse_count(start)?))
+            }
+            Some(min) => Some((min, Some(min))),
+            None => None,
+        };
+        match bounds {
+            Some((min, max)) if self.peek() == Some('}') => {
+                self.pos += 1;
+                if max.is_some_and(|max| min > max) {
+                    return Err(RegexError::Syntax(
+                        start,
+                        "repetition minimum is above its maximum".to_string(),
+                    ));
+                }
+                Ok(Some((min, max)))
+            }
+            _ => {
+                self.pos = start;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Parses the digits of a count of a counted quantifier starting at `start`.
+    fn parse_count(&mut self, start: usize) -> Result<Option<u32>, RegexError> {
+        let from = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        if from == self.pos {
+            return Ok(None);
+        }
+        let digits: String = self.chars[from..self.pos].iter().collect();
+        match digits.parse() {
+            Ok(count) if count <= MAX_REPEAT => Ok(Some(count)),
+            _ => Err(RegexError::Syntax(
+                start,
+                format!("repetition count is above {}", MAX_REPEAT),
+            )),
+        }
+    }
+
+    fn parse_atom(&mut self) -> Result<Node, RegexError> {
+        let start = self.pos;
+        if self.peek() == Some('{') && self.parse_bounds()?.is_some() {
+            return Err(RegexError::Syntax(start, "nothing to repeat".to_string()));
+        }
+        let c = self.peek().ok_or_else(|| self.error("unexpected end"))?;
+        self.pos += 1;
+        match c {
+            '(' => {
+                let node = self.parse_alternate()?;
+                if self.peek() != Some(')') {
+                    return Err(self.error("missing ')'"));
+                }
+                self.pos += 1;
+                Ok(node)
+            }
+            '[' => self.parse_class(),
+            '.' => Ok(Node::Any),
+            '^' => Ok(Node::LineStart),
+            '$' => Ok(Node::LineEnd),
+            '*' | '+' | '?' => Err(RegexError::Syntax(
+                self.pos - 1,
+                "nothing to repeat".to_string(),
+            )),
+            '\\' => match self.parse_escape()? {
+                Escape::Char(c) => Ok(Node::Char(c)),
+                Escape::Class(item, negated) => Ok(Node::Class(Class {
+                    items: vec![item],
+                    negated,
+                })),
+            },
+            c => Ok(Node::Char(c)),
+        }
+    }
+
+    fn parse_escape(&mut self) -> Result<Escape, RegexError> {
+        let c = self.peek().ok_or_else(|| self.error("unfinished escape"))?;
+        self.pos += 1;
+        Ok(match c {
+            'd' => Escape::Class(ClassItem::Digit, false),
+            'D' => Escape::Class(ClassItem::Digit, true),
+            'w' => Escape::Class(ClassItem::Word, false),
+            'W' => Escape::Class(ClassItem::Word, true),
+            's' => Escape::Class(ClassItem::Space, false),
+            'S' => Escape::Class(ClassItem::Space, true),
+            't' => Escape::Char('\t'),
+            'n' => Escape::Char('\n'),
+            c => Escape::Char(c),
+        })
+    }
+
+    fn parse_class(&mut self) -> Result<Node, RegexError> {
+        let mut class = Class {
+            items: Vec::new(),
+            negated: false,
+        };
+        if self.peek() == Some('^') {
+            class.negated = true;
+            self.pos += 1;
+        }
+
+        let mut first = true;
+        loop {
+            let c = self.peek().ok_or_else(|| self.error("missing ']'"))?;
+            self.pos += 1;
+            // a closing bracket right after the opening one is a literal
+            if c == ']' && !first {
+                break;
+            }
+            first = false;
+
+            let from = match c {
+                '\\' => match self.parse_escape()? {
+                    Escape::Char(c) => c,
+                    Escape::Class(item, false) => {
+                        class.items.push(item);
+                        continue;
+                    }
+                    Escape::Class(_, true) => {
+                        return Err(self.error("negated classes are not supported in brackets"))
+                    }
+                },
+                c => c,
+            };
+
+            // a dash before the closing bracket is a literal
+            if self.peek() == Some('-') && self.chars.get(self.pos + 1).is_some_and(|&c| c != ']') {
+                self.pos += 1;
+                let to = match self.peek() {
+                    Some('\\') => {
+                        self.pos += 1;
+                        match self.parse_escape()? {
+                            Escape::Char(c) => c,
+                            Escape::Class(..) => return Err(self.error("invalid range")),
+                        }
+                    }
+                    Some(c) => {
+                        self.pos += 1;
+                        c
+                    }
+                    None => return Err(self.error("missing ']'")),
+                };
+                if to < from {
+                    return Err(self.error("invalid range"));
+                }
+                class.items.push(ClassItem::Range(from, to));
+            } else {
+                class.items.push(ClassItem::Range(from, from));
+            }
+        }
+
+        Ok(Node::Class(class))
+    }
+}
+
+enum Escape {
+    Char(char),
+    Class(ClassItem, bool),
+}
+
+/// Expands a counted quantifier into `min` copies of the node, followed by a star if there is no
+/// maximum, or by nested optional copies up to the maximum.
+fn repeat(node: Node, min: u32, max: Option<u32>) -> Node {
+    let mut nodes = vec![node.clone(); min as usize];
+    match max {
+        None => nodes.push(Node::Star(Box::new(node))),
+        Some(max) => {
+            let mut optional = Node::Empty;
+            for _ in min..max {
+                optional = Node::Optional(Box::new(Node::Concat(vec![node.clone(), optional])));
+            }
+            nodes.push(optional);
+        }
+    }
+    Node::Concat(nodes)
+}
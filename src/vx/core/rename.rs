@@ -0,0 +1,213 @@
+// Detection of files that were moved between two vx trees, and possibly edited along the way.
+//
+// Deleted and added files are paired by content: files of the same content are renames right
+// away, the rest are compared by the chunks of content they share. Contents are cut into chunks
+// ending at a newline or after CHUNK_SIZE bytes, so an edit only changes the chunks it touches,
+// and the similarity of two files is the share of bytes in common chunks relative to the larger
+// file.
+
+use crate::core::digest::Digest;
+use crate::core::tree::{Change, ChangeAction};
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use xxhash_rust::xxh3::xxh3_64;
+
+/// Similarity in percent from which files are paired unless a threshold is given.
+pub const DEFAULT_RENAME_THRESHOLD: u8 = 50;
+
+/// Number of deleted or added files above which contents are not compared, as every deleted
+/// file is compared with every added one. Files of the same content are paired regardless.
+pub const MAX_RENAME_CANDIDATES: usize = 1000;
+
+/// Longest chunk of content, for binary files and long lines.
+const CHUNK_SIZE: usize = 64;
+
+/// A file deleted at one path and added at another, with its content mostly kept.
+#[derive(Debug, Clone)]
+pub struct Rename {
+    /// Path of the file in the original tree.
+    pub from: PathBuf,
+    /// Path of the file in the destination tree.
+    pub to: PathBuf,
+    /// How much of the content was kept, in percent, 100 if the content is the same.
+    pub similarity: u8,
+}
+
+/// Changes of files between two vx trees with the renames among them paired.
+#[derive(Debug, Clone)]
+pub struct RenameDiff {
+    /// Files found to be renamed, sorted by their destination path.
+    pub renames: Vec<Rename>,
+    /// Changes of the files that are not part of a rename, sorted by path.
+    pub changes: Vec<Change>,
+    /// Whether there were too many deleted or added files for their contents to be compared,
+    /// see `MAX_RENAME_CANDIDATES`, so only files of the same content were paired.
+    pub limited: bool,
+}
+
+/// A deleted or added file that may be part of a rename.
+struct Candidate {
+    change: Change,
+    size: u64,
+}
+
+/// Chunks of a content, the number of bytes of each distinct chunk by its hash.
+pub(crate) struct Signature {
+    chunks: HashMap<u64, u64>,
+    size: u64,
+}
+
+impl Signature {
+    pub(crate) fn new(content: &[u8]) -> Self {
+        let mut chunks = HashMap::new();
+        let mut start = 0;
+        for end in 1..=content.len() {
+            if content[end - 1] == b'\n' || end - start == CHUNK_SIZE || end == content.len() {
+                *chunks.entry(xxh3_64(&content[start..end])).or_insert(0) += (end - start) as u64;
+                start = end;
+            }
+        }
+        Signature {
+            chunks,
+            size: content.len() as u64,
+        }
+    }
+
+    /// Share of the larger content found in both, in percent.
+    fn similarity(&self, other: &Signature) -> u8 {
+        let larger = self.size.max(other.size);
+        if larger == 0 {
+            return 100;
+        }
+        let shared: u64 = self
+            .chunks
+            .iter()
+            .filter_map(|(hash, bytes)| other.chunks.get(hash).map(|other| (*bytes).min(*other)))
+            .sum();
+        (shared * 100 / larger) as u8
+    }
+}
+
+/// Pairs deleted files with added ones, files of the same content first, then the most similar
+/// pairs at or above the threshold, each file being part of a single rename. Empty files are never
+/// paired, as they share no content. Signatures of contents are loaded on demand, only for pairs
+/// whose sizes allow for the threshold.
+pub(crate) fn find_renames<E>(
+    changes: Vec<Change>,
+    size: impl Fn(Digest) -> Result<u64, E>,
+    mut signature: impl FnMut(Digest) -> Result<Signature, E>,
+    threshold: u8,
+) -> Result<RenameDiff, E> {
+    let mut deleted = Vec::new();
+    let mut added = Vec::new();
+    let mut others = Vec::new();
+    for change in changes {
+        match change.action {
+            ChangeAction::Deleted => {
+                let size = size(change.contenthash_left)?;
+                deleted.push(Candidate { change, size });
+            }
+            ChangeAction::Added => {
+                let size = size(change.contenthash_right)?;
+                added.push(Candidate { change, size });
+            }
+            ChangeAction::Modified => others.push(change),
+        }
+    }
+
+    // (similarity, index of the deleted file, index of the added file)
+    let mut pairs = Vec::new();
+    let mut by_content: HashMap<Digest, Vec<usize>> = HashMap::new();
+    for (index, candidate) in deleted.iter().enumerate() {
+        if candidate.size > 0 {
+            by_content
+                .entry(candidate.change.contenthash_left)
+                .or_default()
+                .push(index);
+        }
+    }
+    let mut paired_deleted = HashSet::new();
+    let mut paired_added = HashSet::new();
+    for (index, candidate) in added.iter().enumerate() {
+        let same = by_content
+            .get_mut(&candidate.change.contenthash_right)
+            .filter(|_| candidate.size > 0)
+            .and_then(|indexes| (!indexes.is_empty()).then(|| indexes.remove(0)));
+        if let Some(from) = same {
+            pairs.push((100, from, index));
+            paired_deleted.insert(from);
+            paired_added.insert(index);
+        }
+    }
+
+    let limited = deleted.len() > MAX_RENAME_CANDIDATES || added.len() > MAX_RENAME_CANDIDATES;
+    if !limited && threshold < 100 {
+        let mut signatures: HashMap<Digest, Signature> = HashMap::new();
+        let mut similar = Vec::new();
+        for (from, left) in deleted.iter().enumerate() {
+            if paired_deleted.contains(&from) || left.size == 0 {
+                continue;
+            }
+            for (to, right) in added.iter().enumerate() {
+                if paired_added.contains(&to) || right.size == 0 {
+                    continue;
+                }
+                // files sharing all of the smaller one are as similar as their sizes allow
+                let bound = left.size.min(right.size) * 100 / left.size.max(right.size);
+                if bound < threshold as u64 {
+                    continue;
+                }
+                for hash in [left.change.contenthash_left, right.change.contenthash_right] {
+                    if let Entry::Vacant(entry) = signatures.entry(hash) {
+                        entry.insert(signature(hash)?);
+                    }
+                }
+                let score = signatures[&left.change.contenthash_left]
+                    .similarity(&signatures[&right.change.contenthash_right]);
+                if score >= threshold {
+                    similar.push((score, from, to));
+                }
+            }
+        }
+
+        // the most similar pairs win, ties are broken by path order
+        similar.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)).then(a.2.cmp(&b.2)));
+        for (score, from, to) in similar {
+            if !paired_deleted.contains(&from) && !paired_added.contains(&to) {
+                pairs.push((score, from, to));
+                paired_deleted.insert(from);
+                paired_added.insert(to);
+            }
+        }
+    }
+
+    let mut renames: Vec<Rename> = pairs
+        .into_iter()
+        .map(|(similarity, from, to)| Rename {
+            from: deleted[from].change.path.clone(),
+            to: added[to].change.path.clone(),
+            similarity,
+        })
+        .collect();
+    renames.sort_by(|a, b| a.to.cmp(&b.to));
+
+    let unpaired = |candidates: Vec<Candidate>, paired: &HashSet<usize>| {
+        candidates
+            .into_iter()
+            .enumerate()
+            .filter(|(index, _)| !paired.contains(index))
+            .map(|(_, candidate)| candidate.change)
+            .collect::<Vec<_>>()
+    };
+    let mut changes = others;
+    changes.extend(unpaired(deleted, &paired_deleted));
+    changes.extend(unpaired(added, &paired_added));
+    changes.sort_by(|a, b| a.path.cmp(&b.path));
+
+    Ok(RenameDiff {
+        renames,
+        changes,
+        limited,
+    })
+}
@@ -1,10 +1,35 @@
-use crate::context::Context;
-use crate::core::branch::Branch;
-use crate::core::commit::{Commit, CurrentCommitSpec};
-use crate::core::tree::Tree;
+use crate::context::{self, Context, DbTuning};
+use crate::core::blob::{self as blobcore, Blob, LineEndings, VerifyReport};
+use crate::core::branch::{validate_branch_name, Branch};
+use crate::core::commit::{self, Commit, CommitID, CurrentCommitSpec};
+use crate::core::digest::{Digest, HASH_ALGORITHM};
+use crate::core::ignore::DEFAULT_IGNORE_KEY;
+use crate::core::listing;
+use crate::core::signing;
+use crate::core::stash::Stash;
+use crate::core::tag::Tag;
+use crate::core::tree::{self, Entry, Tree};
+use crate::core::xattr;
+use crate::storage::blob::SHARD_DIGITS;
+use crate::storage::commit::CommitError;
 use crate::storage::repo::{self as repostore, RepoError};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Repository metadata key naming the foundational branch of a new repository.
+pub const DEFAULT_BRANCH_KEY: &str = "init.defaultBranch";
+
+/// Environment variable naming the foundational branch of a new repository, unless the metadata does.
+pub const DEFAULT_BRANCH_ENV: &str = "VX_DEFAULT_BRANCH";
+
+/// Name of the foundational branch if none is configured.
+const DEFAULT_BRANCH: &str = "main";
+
+/// Repository metadata key recording the name of the foundational branch the repository was
+/// created with, kept up to date by renames of the branch.
+pub const CREATED_BRANCH_KEY: &str = "repo.defaultBranch";
 
 /// Represents a repository in the version control system.
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -15,11 +40,261 @@ pub struct Repo {
     pub metadata: HashMap<String, String>,
 }
 
+/// Aggregated size statistics of a repository.
+#[derive(Debug, Clone, Copy)]
+pub struct RepoSize {
+    /// Total size of the files in all commits in bytes, as if they were stored without deduplication.
+    pub logical_size: u64,
+    /// Total size of unique blobs in bytes.
+    pub stored_size: u64,
+    /// Total size of the blob store on disk in bytes.
+    pub disk_size: u64,
+    /// Number of unique blobs.
+    pub blob_count: u64,
+    /// Number of vx trees.
+    pub tree_count: u64,
+    /// Number of commits.
+    pub commit_count: u64,
+    /// Number of branches.
+    pub branch_count: u64,
+}
+
+/// Size of a database of the workspace on disk before and after it was compacted.
+#[derive(Debug, Clone)]
+pub struct PackedDb {
+    /// File name of the database.
+    pub name: String,
+    /// Size on disk before compaction in bytes.
+    pub size_before: u64,
+    /// Size on disk after compaction in bytes.
+    pub size_after: u64,
+}
+
+/// State of the checkout of a repository.
+#[derive(Debug, Clone)]
+pub struct RepoStatus {
+    /// The branch of the current commit.
+    pub branch: Branch,
+    /// The current commit.
+    pub commit_id: CommitID,
+    /// The commit a paused rebuild stopped at, `CurrentCommitSpec::NO_REBUILD` if there is none.
+    pub rebuild_seq: u64,
+    /// Paths to the conflicting files of a paused rebuild.
+    pub conflicts: Vec<PathBuf>,
+}
+
+impl RepoStatus {
+    /// Returns true if the current commit is not the branch head.
+    /// New commits made in this state are inserted after the current commit and the commits above
+    /// it are rebuilt, rather than being appended to the branch.
+    pub fn is_detached(&self) -> bool {
+        self.commit_id.seq < self.branch.headseq
+    }
+
+    /// Returns true if a rebuild is paused on a conflict.
+    pub fn is_rebuild(&self) -> bool {
+        self.rebuild_seq != CurrentCommitSpec::NO_REBUILD
+    }
+}
+
+/// Objects that are not reachable from any branch, i.e. those a garbage collection would remove.
+#[derive(Debug, Clone)]
+pub struct GcReport {
+    /// Number of commits the reachability was computed from.
+    pub commit_count: u64,
+    /// Number of reachable vx trees.
+    pub reachable_trees: u64,
+    /// Number of reachable blobs.
+    pub reachable_blobs: u64,
+    /// Hashes of unreachable vx trees.
+    pub unreachable_trees: Vec<Digest>,
+    /// Unreachable blobs, with their sizes.
+    pub unreachable_blobs: Vec<Blob>,
+}
+
+/// Result of checking the hashes of the commits visible in all branches against their content.
+#[derive(Debug, Clone)]
+pub struct ChainReport {
+    /// Number of commits checked.
+    pub checked: u64,
+    /// Commits whose stored hash does not match their content.
+    pub mismatched: Vec<HashMismatch>,
+}
+
+/// A commit whose stored hash does not match its content.
+#[derive(Debug, Clone)]
+pub struct HashMismatch {
+    /// Name of the branch the commit belongs to.
+    pub branch: String,
+    /// The commit as stored.
+    pub commit: Commit,
+    /// Hash recomputed from the stored content of the commit.
+    pub computed: Digest,
+}
+
+/// Result of checking that the stores of a repository are consistent, see `Repo::fsck`.
+#[derive(Debug, Clone, Default)]
+pub struct FsckReport {
+    /// Number of commits checked.
+    pub checked_commits: u64,
+    /// Number of tags checked.
+    pub checked_tags: u64,
+    /// Number of vx trees checked.
+    pub checked_trees: u64,
+    /// Commits below the head of a branch that are missing, by branch name.
+    pub missing_commits: Vec<(String, CommitID)>,
+    /// The current commit if it is above the head of its branch, with the head sequence number.
+    pub current_ahead: Option<(CommitID, u64)>,
+    /// Tags whose commit is missing, by tag name.
+    pub missing_tag_commits: Vec<(String, CommitID)>,
+    /// Names of the tags whose commit is stored but not its vx tree.
+    pub missing_tag_trees: Vec<String>,
+    /// Hashes of vx trees referenced by commits or folders but missing.
+    pub missing_trees: Vec<Digest>,
+    /// Hashes of blobs referenced by files or commit messages but missing.
+    pub missing_blobs: Vec<Digest>,
+    /// Result of rehashing the blob files.
+    pub blobs: VerifyReport,
+}
+
+/// Result of the self-check of a repository, see `Repo::doctor`.
+#[derive(Debug, Clone, Default)]
+pub struct DoctorReport {
+    /// Position of the checkout, not set if it could not be read.
+    pub status: Option<RepoStatus>,
+    /// Databases that could not be opened, with the reason.
+    pub unavailable_dbs: Vec<(String, String)>,
+    /// A commit interrupted by a failure, completed the next time the repository is opened.
+    pub pending_commit: Option<CommitID>,
+    /// The commit of a checkout interrupted by a failure, to be continued.
+    pub pending_checkout: Option<CommitID>,
+    /// Folders of the repository that can not be written to.
+    pub read_only: Vec<PathBuf>,
+    /// Result of checking the commit hashes, not set if the commits could not be read.
+    pub chain: Option<ChainReport>,
+    /// Checks that could not be completed, with the reason.
+    pub errors: Vec<String>,
+}
+
+/// Description of how a repository is stored and configured, for tools adapting to it.
+#[derive(Debug, Clone)]
+pub struct RepoInfo {
+    /// Name of the repository.
+    pub name: String,
+    /// Name of the foundational branch the repository was created with.
+    pub default_branch: String,
+    /// Algorithm of the hashes identifying blobs, trees and commits.
+    pub hash_algorithm: &'static str,
+    /// Compression of new blobs on disk.
+    pub compression: &'static str,
+    /// Number of leading hexadecimal digits of a content hash naming the folder of its blob.
+    pub blob_shard_digits: usize,
+    /// Current schema version of every kind of stored record, by record name.
+    pub schema_versions: Vec<(&'static str, u8)>,
+    /// Effective value of every setting by metadata key, None where a built-in default applies.
+    pub settings: Vec<(&'static str, Option<String>)>,
+    /// All metadata entries, sorted by key.
+    pub metadata: Vec<(String, String)>,
+}
+
+/// Summary of a history exported as a git fast-import stream.
+#[derive(Debug, Clone)]
+pub struct GitExport {
+    /// Name of the exported branch, also the name of the git branch.
+    pub branch: String,
+    /// Number of exported commits.
+    pub commits: u64,
+    /// Number of exported blobs, every unique content is exported once.
+    pub blobs: u64,
+}
+
+impl DoctorReport {
+    /// Returns true if no problems were found.
+    /// A paused rebuild or a detached checkout are states of a workflow rather than problems.
+    pub fn is_ok(&self) -> bool {
+        self.status.is_some()
+            && self.unavailable_dbs.is_empty()
+            && self.pending_commit.is_none()
+            && self.pending_checkout.is_none()
+            && self.read_only.is_empty()
+            && self.chain.as_ref().is_some_and(ChainReport::is_ok)
+            && self.errors.is_empty()
+    }
+}
+
+impl ChainReport {
+    /// Returns true if all commits have matching hashes.
+    pub fn is_ok(&self) -> bool {
+        self.mismatched.is_empty()
+    }
+}
+
+impl FsckReport {
+    /// Returns true if no problem was found.
+    pub fn is_ok(&self) -> bool {
+        self.missing_commits.is_empty()
+            && self.current_ahead.is_none()
+            && self.missing_tag_commits.is_empty()
+            && self.missing_tag_trees.is_empty()
+            && self.missing_trees.is_empty()
+            && self.missing_blobs.is_empty()
+            && self.blobs.is_ok()
+    }
+}
+
+impl GcReport {
+    /// Total size of unreachable blobs in bytes.
+    pub fn unreachable_size(&self) -> u64 {
+        self.unreachable_blobs.iter().map(|blob| blob.size).sum()
+    }
+}
+
+impl RepoSize {
+    /// Ratio of the logical size to the size of unique stored content.
+    pub fn dedup_ratio(&self) -> f64 {
+        if self.stored_size == 0 {
+            return 1.0;
+        }
+        self.logical_size as f64 / self.stored_size as f64
+    }
+}
+
 impl Repo {
-    /// Creates a new Repo instance.
+    /// Creates a new Repo instance in a folder named after it in the current directory.
+    /// Refuses to do so inside the checkout of another repository, see `new_nested`.
     pub fn new(
         name: String,
         metadata: HashMap<String, String>,
+    ) -> Result<(Self, Context), RepoError> {
+        if let Ok(outer) = Context::find(Path::new(".")) {
+            return Err(RepoError::NestedRepo(outer.checkout_path));
+        }
+        Self::create(name, metadata, false)
+    }
+
+    /// Same as `new`, but also creates the repository inside the checkout of another one, where
+    /// its files are tracked by the outer repository unless ignored.
+    pub fn new_nested(
+        name: String,
+        metadata: HashMap<String, String>,
+    ) -> Result<(Self, Context), RepoError> {
+        Self::create(name, metadata, false)
+    }
+
+    /// Creates a throwaway repository in the system temporary directory, e.g. for tests or scratch
+    /// work of programs embedding vx. Its databases are not persisted, and the repository with its
+    /// checkout is removed once the returned context and all its clones are dropped.
+    pub fn new_temporary(
+        name: String,
+        metadata: HashMap<String, String>,
+    ) -> Result<(Self, Context), RepoError> {
+        Self::create(name, metadata, true)
+    }
+
+    fn create(
+        name: String,
+        mut metadata: HashMap<String, String>,
+        temporary: bool,
     ) -> Result<(Self, Context), RepoError> {
         // Validate repo name - only allow lowercase alphanumeric and : . / _ characters
         if !name.chars().all(|c| {
@@ -30,18 +305,34 @@ impl Repo {
                     .to_string(),
             ));
         }
-        let (repo, context) = repostore::new(name, metadata)?;
+        let branch_name = default_branch_name(&metadata);
+        validate_branch_name(&branch_name)
+            .map_err(|e| RepoError::InvalidName(format!("Default branch: {}", e)))?;
+
+        LineEndings::from_metadata(&metadata)?;
+        commit::message_blob_size(&metadata)?;
+        commit::configured_author(&metadata)?;
+        tree::trust_index_size(&metadata)?;
+        tree::max_depth(&metadata)?;
+        listing::dir_batch_size(&metadata)?;
+        signing::signing_key_path(&metadata)?;
+        DbTuning::from_metadata(&metadata)?;
+        xattr::capture_names(&metadata)?;
+        metadata.insert(CREATED_BRANCH_KEY.to_string(), branch_name.clone());
+
+        let (repo, context) = repostore::new(name, metadata, temporary)?;
 
         // Create a new empty tree for a centinel commit.
         let tree = Tree::create_empty(&context)
             .map_err(|e| RepoError::Other(format!("Failed to create empty tree: {}", e)))?;
 
-        // Create initial "main" branch using workspace path
-        let branch = Branch::create_foundational_branch(&context, String::from("main"))
-            .map_err(|e| RepoError::Other(format!("Failed to create main branch: {}", e)))?;
+        // Create the initial foundational branch, "main" unless configured otherwise
+        let branch = Branch::create_foundational_branch(&context, branch_name)
+            .map_err(|e| RepoError::Other(format!("Failed to create default branch: {}", e)))?;
 
         // TODO: potential inconsistent state here, we have a branch but no commit yet. By design every branch
-        // must have at least one commit. For now we will solve it by advising the user to trash the repo and start over.
+        // must have at least one commit. For now we will solve it by advising the user to trash the repo with
+        // `vx repo destroy --force` and start over.
 
         // Create a centinel commit with empty tree.
         let commit = Commit::create_zero_commit(
@@ -49,6 +340,7 @@ impl Repo {
             branch.id,
             tree.hash,
             String::from("Initial commit"),
+            String::new(),
         )
         .map_err(|e| RepoError::Other(format!("Failed to create initial commit: {}", e)))?;
 
@@ -66,4 +358,639 @@ impl Repo {
 
         Ok((repo, context))
     }
+
+    /// Deletes the repository by removing its workspace, and the whole checkout if `with_checkout`
+    /// is set. Refuses to do so if the checkout has uncommitted changes, unless `force` is set.
+    pub fn destroy(context: &Context, with_checkout: bool, force: bool) -> Result<(), RepoError> {
+        if !force {
+            let changes = Tree::get_changed_files(context)
+                .map_err(|e| RepoError::Other(format!("Failed to get changed files: {}", e)))?;
+            if !changes.is_empty() {
+                return Err(RepoError::UncommittedChanges(changes.len()));
+            }
+        }
+        repostore::destroy(context, with_checkout)
+    }
+
+    /// Reads the metadata the repository was created with.
+    pub fn get_metadata(context: &Context) -> Result<HashMap<String, String>, RepoError> {
+        repostore::get_metadata(context)
+    }
+
+    /// Sets a metadata entry of the repository, or removes it if `value` is None.
+    pub fn set_metadata(
+        context: &Context,
+        key: &str,
+        value: Option<&str>,
+    ) -> Result<(), RepoError> {
+        repostore::set_metadata(context, key, value)
+    }
+
+    /// Registers the ed25519 key new commits are signed with, see `signing::load_key` for the
+    /// format of its file. The key is loaded to be checked, and only the absolute path to its file
+    /// is stored. Returns the public key.
+    pub fn set_signing_key(context: &Context, path: &Path) -> Result<[u8; 32], RepoError> {
+        let key = signing::load_key(path)?;
+        let path = path.canonicalize().map_err(|e| {
+            RepoError::Other(format!("Failed to resolve '{}': {}", path.display(), e))
+        })?;
+        let path = path.to_str().ok_or_else(|| {
+            RepoError::Other(format!("Path '{}' is not valid UTF-8", path.display()))
+        })?;
+        Self::set_metadata(context, signing::SIGNING_KEY_KEY, Some(path))?;
+        Ok(key.verifying_key().to_bytes())
+    }
+
+    /// Retrieves the state of the checkout.
+    pub fn status(context: &Context) -> Result<RepoStatus, RepoError> {
+        let current = CurrentCommitSpec::get(context)
+            .map_err(|e| RepoError::Other(format!("Failed to get current commit: {}", e)))?;
+        let branch = Branch::get(context, current.commit_id.branch)
+            .map_err(|e| RepoError::Other(format!("Failed to get current branch: {}", e)))?;
+
+        let conflicts = Commit::get_pending_rebuild(context)
+            .map_err(|e| RepoError::Other(format!("Failed to get rebuild state: {}", e)))?
+            .map(|pending| pending.conflicts)
+            .unwrap_or_default();
+
+        Ok(RepoStatus {
+            branch,
+            commit_id: current.commit_id,
+            rebuild_seq: current.rebuild_seq,
+            conflicts,
+        })
+    }
+
+    /// Recomputes the hashes of the commits visible in all branches from their stored content and
+    /// reports the ones that do not match, e.g. due to a corrupted store. No files are read, only
+    /// messages stored as blobs.
+    pub fn verify_chain(context: &Context) -> Result<ChainReport, RepoError> {
+        let branches = Branch::list(context)
+            .map_err(|e| RepoError::Other(format!("Failed to list branches: {}", e)))?;
+
+        let mut report = ChainReport {
+            checked: 0,
+            mismatched: Vec::new(),
+        };
+        for branch in branches {
+            let commits = Commit::list_by_branch(context, &branch.name)
+                .map_err(|e| RepoError::Other(format!("Failed to list commits: {}", e)))?;
+            report.checked += commits.len() as u64;
+            for commit in commits {
+                let computed = commit
+                    .compute_hash(context)
+                    .map_err(|e| RepoError::Other(format!("Failed to hash commit: {}", e)))?;
+                if computed != commit.hash {
+                    report.mismatched.push(HashMismatch {
+                        branch: branch.name.clone(),
+                        commit,
+                        computed,
+                    });
+                }
+            }
+        }
+        Ok(report)
+    }
+
+    /// Finds vx trees and blobs that are not reachable from any branch or tag, without removing
+    /// them. Reachability is marked from the commits visible in all branches, the commits dropped
+    /// above their heads that a reset can still bring back and the tagged commits, down to their
+    /// trees and blobs, including messages stored as blobs. Older versions of amended or rebuilt
+    /// commits are not visible, so the objects only they refer to are reported. Blobs referenced
+    /// by the index of the checkout, the trees of stashes and the commits of tags are always kept.
+    pub fn find_unreachable(context: &Context) -> Result<GcReport, RepoError> {
+        let branches = Branch::list(context)
+            .map_err(|e| RepoError::Other(format!("Failed to list branches: {}", e)))?;
+
+        let mut roots = Vec::new();
+        let mut messages = Vec::new();
+        for branch in &branches {
+            let mut commits = Commit::list_by_branch(context, &branch.name)
+                .map_err(|e| RepoError::Other(format!("Failed to list commits: {}", e)))?;
+            // commits dropped by an undo or a reset stay restorable until replaced
+            commits.extend(
+                Commit::list_restorable(context, &branch.name)
+                    .map_err(|e| RepoError::Other(format!("Failed to list commits: {}", e)))?,
+            );
+            roots.extend(commits.iter().map(|commit| commit.treehash));
+            messages.extend(
+                commits
+                    .iter()
+                    .filter(|commit| commit.has_message_blob())
+                    .map(|commit| commit.message_hash),
+            );
+        }
+        let commit_count = roots.len() as u64;
+
+        // tags keep their commits even once dropped from the branch, e.g. by an undo
+        for commit in tagged_commits(context)?
+            .into_iter()
+            .filter_map(|(_, commit)| commit)
+        {
+            roots.push(commit.treehash);
+            if commit.has_message_blob() {
+                messages.push(commit.message_hash);
+            }
+        }
+
+        // a paused rebuild refers to trees that become visible only once it completes
+        if let Some(pending) = Commit::get_pending_rebuild(context)
+            .map_err(|e| RepoError::Other(format!("Failed to get rebuild state: {}", e)))?
+        {
+            roots.push(pending.commit.treehash);
+            roots.push(pending.treehash);
+        }
+
+        // stashes keep the trees of the changes set aside
+        let stashes = Stash::list(context)
+            .map_err(|e| RepoError::Other(format!("Failed to list stashes: {}", e)))?;
+        roots.extend(stashes.iter().map(|stash| stash.treehash));
+
+        let mut reachable = Tree::mark_reachable(context, &roots)
+            .map_err(|e| RepoError::Other(format!("Failed to walk trees: {}", e)))?;
+        // messages stored as blobs are referenced by the commits rather than trees
+        reachable.blobs.extend(messages);
+
+        let mut unreachable_trees: Vec<Digest> = Tree::list_hashes(context)
+            .map_err(|e| RepoError::Other(format!("Failed to list trees: {}", e)))?
+            .into_iter()
+            .filter(|hash| !reachable.trees.contains(hash))
+            .collect();
+        unreachable_trees.sort();
+
+        let mut unreachable_blobs: Vec<Blob> = Blob::list(context)
+            .map_err(|e| RepoError::Other(format!("Failed to list blobs: {}", e)))?
+            .into_iter()
+            .filter(|blob| !reachable.blobs.contains(&blob.contenthash))
+            .collect();
+        unreachable_blobs.sort_by_key(|blob| blob.contenthash);
+
+        Ok(GcReport {
+            commit_count,
+            reachable_trees: reachable.trees.len() as u64,
+            reachable_blobs: reachable.blobs.len() as u64,
+            unreachable_trees,
+            unreachable_blobs,
+        })
+    }
+
+    /// Checks that the stores of a repository refer to each other consistently: every commit from
+    /// the centinel to the head of every branch and the commit of every tag is stored, the current
+    /// commit is not above the head of its branch, the trees of the commits, of the stashes and of
+    /// their folders are stored, and so are the blobs of their files and of commit messages.
+    /// Finally every blob file is rehashed and compared with its content hash, see
+    /// `Blob::verify_all`. Problems are collected rather than stopping the check, nothing is
+    /// repaired.
+    pub fn fsck(context: &Context) -> Result<FsckReport, RepoError> {
+        let branches = Branch::list(context)
+            .map_err(|e| RepoError::Other(format!("Failed to list branches: {}", e)))?;
+
+        let mut report = FsckReport::default();
+        let mut roots = Vec::new();
+        let mut messages = Vec::new();
+        for branch in &branches {
+            for seq in 0..=branch.headseq {
+                let id = CommitID {
+                    branch: branch.id,
+                    seq,
+                };
+                match Commit::get(context, id) {
+                    Ok(commit) => {
+                        report.checked_commits += 1;
+                        roots.push(commit.treehash);
+                        if commit.has_message_blob() {
+                            messages.push(commit.message_hash);
+                        }
+                    }
+                    Err(CommitError::NotFound) => {
+                        report.missing_commits.push((branch.name.clone(), id))
+                    }
+                    Err(e) => return Err(RepoError::Other(format!("Failed to get commit: {}", e))),
+                }
+            }
+        }
+
+        let current = CurrentCommitSpec::get(context)
+            .map_err(|e| RepoError::Other(format!("Failed to get current commit: {}", e)))?;
+        if let Some(branch) = branches
+            .iter()
+            .find(|branch| branch.id == current.commit_id.branch)
+        {
+            if current.commit_id.seq > branch.headseq {
+                report.current_ahead = Some((current.commit_id, branch.headseq));
+            }
+        }
+
+        let mut tag_trees = Vec::new();
+        for (tag, commit) in tagged_commits(context)? {
+            report.checked_tags += 1;
+            let Some(commit) = commit else {
+                report.missing_tag_commits.push((tag.name, tag.commit_id));
+                continue;
+            };
+            roots.push(commit.treehash);
+            if commit.has_message_blob() {
+                messages.push(commit.message_hash);
+            }
+            tag_trees.push((tag.name, commit.treehash));
+        }
+
+        let stashes = Stash::list(context)
+            .map_err(|e| RepoError::Other(format!("Failed to list stashes: {}", e)))?;
+        roots.extend(stashes.iter().map(|stash| stash.treehash));
+
+        let missing = Tree::find_missing(context, &roots, &messages)
+            .map_err(|e| RepoError::Other(format!("Failed to walk trees: {}", e)))?;
+        report.checked_trees = missing.checked_trees;
+        report.missing_tag_trees = tag_trees
+            .into_iter()
+            .filter(|(_, treehash)| missing.trees.contains(treehash))
+            .map(|(name, _)| name)
+            .collect();
+        report.missing_trees = missing.trees;
+        report.missing_blobs = missing.blobs;
+        report.blobs = Blob::verify_all(context)
+            .map_err(|e| RepoError::Other(format!("Failed to verify blobs: {}", e)))?;
+        Ok(report)
+    }
+
+    /// Removes the vx trees and blobs that are not reachable from any branch or tag, as found by
+    /// `Repo::find_unreachable`, and reports what was removed. The locks of all branches are held
+    /// throughout, so no commit lands between marking and sweeping. Trees are removed before
+    /// blobs, so an interrupted collection never leaves a tree referring to a removed blob.
+    ///
+    /// Objects written by a command still running in another process, e.g. the trees of a
+    /// commit being created, are not referenced yet, so a collection should not run concurrently
+    /// with commands writing to the repository.
+    pub fn gc(context: &Context) -> Result<GcReport, RepoError> {
+        let branches = Branch::list(context)
+            .map_err(|e| RepoError::Other(format!("Failed to list branches: {}", e)))?;
+        let _locks = branches
+            .iter()
+            .map(|branch| context.lock_branch(branch.id))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| RepoError::Other(format!("Failed to lock branches: {}", e)))?;
+
+        let report = Repo::find_unreachable(context)?;
+        Tree::delete(context, &report.unreachable_trees)
+            .map_err(|e| RepoError::Other(format!("Failed to delete trees: {}", e)))?;
+        let blobs: Vec<Digest> = report
+            .unreachable_blobs
+            .iter()
+            .map(|blob| blob.contenthash)
+            .collect();
+        Blob::delete(context, &blobs)
+            .map_err(|e| RepoError::Other(format!("Failed to delete blobs: {}", e)))?;
+        Ok(report)
+    }
+
+    /// Checks the state of a repository for the usual causes of failures, e.g. databases held by
+    /// another process, workflows interrupted by a failure, or folders that can not be written to.
+    /// Every check is run even if others fail, and nothing is changed or recovered, so the context
+    /// is expected to be found with `Context::find`. Files and blobs are not read.
+    pub fn doctor(context: &Context) -> DoctorReport {
+        let mut report = DoctorReport {
+            unavailable_dbs: repostore::find_unavailable(context),
+            ..DoctorReport::default()
+        };
+        if !report.unavailable_dbs.is_empty() {
+            // every other check reads the databases
+            return report;
+        }
+
+        for dir in [&context.workspace_path, &context.checkout_path] {
+            if !is_writable(dir) {
+                report.read_only.push(dir.clone());
+            }
+        }
+
+        match Commit::get_pending_commit(context) {
+            Ok(pending) => report.pending_commit = pending.map(|commit| commit.id),
+            Err(e) => report
+                .errors
+                .push(format!("Failed to get pending commit: {}", e)),
+        }
+        match Tree::get_pending_checkout(context) {
+            Ok(pending) => report.pending_checkout = pending,
+            Err(e) => report
+                .errors
+                .push(format!("Failed to get pending checkout: {}", e)),
+        }
+        match Self::status(context) {
+            Ok(status) => report.status = Some(status),
+            Err(e) => report.errors.push(format!("Failed to get status: {}", e)),
+        }
+        match Self::verify_chain(context) {
+            Ok(chain) => report.chain = Some(chain),
+            Err(e) => report
+                .errors
+                .push(format!("Failed to verify commits: {}", e)),
+        }
+        report
+    }
+
+    /// Writes the history of a branch, or of the current one, as a git fast-import stream, e.g. to
+    /// mirror the repository with `git fast-import`. The history is followed through the parent
+    /// branches down to the foundational one and exported as a linear history of the git branch of
+    /// the same name. Every commit lists all of its files, git does not track folders so empty
+    /// ones are left out, and files are exported as regular non-executable ones or as symbolic
+    /// links.
+    pub fn export_git(
+        context: &Context,
+        branch_name: Option<&str>,
+        out: &mut impl Write,
+    ) -> Result<GitExport, RepoError> {
+        let segments = Commit::list_history(context, branch_name)
+            .map_err(|e| RepoError::Other(format!("Failed to list history: {}", e)))?;
+        let blob_db =
+            Blob::open(context).map_err(|e| RepoError::Other(format!("Blob error: {}", e)))?;
+
+        let mut export = GitExport {
+            branch: segments
+                .first()
+                .map(|segment| segment.branch.name.clone())
+                .unwrap_or_default(),
+            commits: 0,
+            blobs: 0,
+        };
+        // marks of exported blobs by content hash, commits are marked after the blobs
+        let mut marks: HashMap<Digest, u64> = HashMap::new();
+        let mut next_mark = 1;
+        let mut parent = None;
+
+        // segments go from the branch down to the foundational one, commits from the newest
+        for segment in segments.iter().rev() {
+            for commit in segment.commits.iter().rev() {
+                // Centinel commits of forked branches are copies of their parent's commits
+                if commit.id.seq == CommitID::SEQ_ZERO && !segment.branch.is_foundational() {
+                    continue;
+                }
+
+                let mut files = Vec::new();
+                let walk = Tree::walk(context, commit.treehash)
+                    .map_err(|e| RepoError::Other(format!("Failed to read tree: {}", e)))?;
+                for item in walk {
+                    let (path, entry) =
+                        item.map_err(|e| RepoError::Other(format!("Failed to read tree: {}", e)))?;
+                    // git records links as blobs holding their target, like vx does
+                    let (blob, mode) = match entry {
+                        Entry::File(blob) => (blob, "100644"),
+                        Entry::Symlink(blob) => (blob, "120000"),
+                        Entry::Folder(_) => continue,
+                    };
+                    let mark = match marks.get(&blob.contenthash) {
+                        Some(mark) => *mark,
+                        None => {
+                            let mark = next_mark;
+                            next_mark += 1;
+                            writeln!(out, "blob\nmark :{}\ndata {}", mark, blob.size)?;
+                            let mut content =
+                                Blob::open_content(context, &blob_db, blob.contenthash)
+                                    .map_err(|e| RepoError::Other(format!("Blob error: {}", e)))?;
+                            std::io::copy(&mut content, out)?;
+                            writeln!(out)?;
+                            marks.insert(blob.contenthash, mark);
+                            export.blobs += 1;
+                            mark
+                        }
+                    };
+                    files.push((path, mode, mark));
+                }
+
+                let message = commit
+                    .get_message(context)
+                    .map_err(|e| RepoError::Other(format!("Failed to read message: {}", e)))?;
+                let ident = git_ident(&commit.author, commit.created_at);
+                writeln!(
+                    out,
+                    "commit refs/heads/{}\nmark :{}",
+                    export.branch, next_mark
+                )?;
+                writeln!(out, "author {}\ncommitter {}", ident, ident)?;
+                writeln!(out, "data {}\n{}", message.len(), message)?;
+                if let Some(parent) = parent {
+                    writeln!(out, "from :{}", parent)?;
+                }
+                writeln!(out, "deleteall")?;
+                for (path, mode, mark) in files {
+                    writeln!(out, "M {} :{} {}", mode, mark, git_path(&path))?;
+                }
+                writeln!(out)?;
+
+                parent = Some(next_mark);
+                next_mark += 1;
+                export.commits += 1;
+            }
+        }
+        out.flush()?;
+        Ok(export)
+    }
+
+    /// Compacts the databases of the workspace to reclaim the space taken by stale pages.
+    /// Stored records are left as they are, objects no longer referenced are not removed.
+    pub fn pack(context: &Context) -> Result<Vec<PackedDb>, RepoError> {
+        repostore::compact(context)
+    }
+
+    /// Aggregates size statistics of the repository.
+    pub fn size(context: &Context) -> Result<RepoSize, RepoError> {
+        let branches = Branch::list(context)
+            .map_err(|e| RepoError::Other(format!("Failed to list branches: {}", e)))?;
+
+        let mut treehashes = Vec::new();
+        for branch in &branches {
+            let commits = Commit::list_by_branch(context, &branch.name)
+                .map_err(|e| RepoError::Other(format!("Failed to list commits: {}", e)))?;
+            for commit in commits {
+                // Centinel commits of forked branches are copies of their parent's commits
+                if commit.id.seq == CommitID::SEQ_ZERO && !branch.is_foundational() {
+                    continue;
+                }
+                treehashes.push(commit.treehash);
+            }
+        }
+
+        let logical_size = Tree::total_size(context, &treehashes)
+            .map_err(|e| RepoError::Other(format!("Failed to read trees: {}", e)))?;
+        let blobs = Blob::stats(context)
+            .map_err(|e| RepoError::Other(format!("Failed to read blobs: {}", e)))?;
+        let tree_count = Tree::count(context)
+            .map_err(|e| RepoError::Other(format!("Failed to count trees: {}", e)))?;
+        let commit_count = Commit::count(context)
+            .map_err(|e| RepoError::Other(format!("Failed to count commits: {}", e)))?;
+
+        Ok(RepoSize {
+            logical_size,
+            stored_size: blobs.size,
+            disk_size: blobs.disk_size,
+            blob_count: blobs.count,
+            tree_count,
+            commit_count,
+            branch_count: branches.len() as u64,
+        })
+    }
+
+    /// Describes the repository: its name, storage layout and schema, and the settings in effect
+    /// along with the raw metadata they are read from.
+    pub fn info(context: &Context) -> Result<RepoInfo, RepoError> {
+        let metadata = Self::get_metadata(context)?;
+        let default_branch = match metadata.get(CREATED_BRANCH_KEY) {
+            Some(name) => name.clone(),
+            // repositories created before the branch was recorded have it among their foundational
+            // branches, orphan ones are foundational too
+            None => Branch::list(context)
+                .map_err(|e| RepoError::Other(format!("Failed to list branches: {}", e)))?
+                .into_iter()
+                .filter(|branch| branch.is_foundational())
+                .min_by_key(|branch| branch.id)
+                .map(|branch| branch.name)
+                .ok_or_else(|| {
+                    RepoError::Other("Repository has no foundational branch".to_string())
+                })?,
+        };
+
+        let tuning = DbTuning::from_metadata(&metadata)?;
+        let compression_level = blobcore::compression_level(&metadata)?;
+        let xattr_names = xattr::capture_names(&metadata)?;
+        let settings = vec![
+            (
+                blobcore::COMPRESSION_LEVEL_KEY,
+                Some(compression_level.to_string()),
+            ),
+            (
+                blobcore::LINE_ENDINGS_KEY,
+                Some(LineEndings::from_metadata(&metadata)?.name().to_string()),
+            ),
+            (
+                commit::MESSAGE_BLOB_SIZE_KEY,
+                commit::message_blob_size(&metadata)?.map(|size| size.to_string()),
+            ),
+            (commit::AUTHOR_KEY, commit::configured_author(&metadata)?),
+            (
+                tree::TRUST_INDEX_SIZE_KEY,
+                tree::trust_index_size(&metadata)?.map(|size| size.to_string()),
+            ),
+            (
+                tree::MAX_DEPTH_KEY,
+                Some(tree::max_depth(&metadata)?.to_string()),
+            ),
+            (
+                listing::DIR_BATCH_SIZE_KEY,
+                Some(listing::dir_batch_size(&metadata)?.to_string()),
+            ),
+            (
+                context::DB_CACHE_CAPACITY_KEY,
+                tuning.cache_capacity.map(|capacity| capacity.to_string()),
+            ),
+            (
+                context::DB_FLUSH_EVERY_MS_KEY,
+                tuning.flush_every_ms.map(|ms| ms.to_string()),
+            ),
+            (
+                DEFAULT_IGNORE_KEY,
+                metadata.get(DEFAULT_IGNORE_KEY).cloned(),
+            ),
+            (
+                signing::SIGNING_KEY_KEY,
+                signing::signing_key_path(&metadata)?.map(|path| path.display().to_string()),
+            ),
+            (
+                xattr::XATTR_CAPTURE_KEY,
+                (!xattr_names.is_empty()).then(|| xattr_names.join(",")),
+            ),
+        ];
+
+        let mut entries: Vec<(String, String)> = metadata.into_iter().collect();
+        entries.sort();
+        Ok(RepoInfo {
+            name: repostore::name(context)?,
+            default_branch,
+            hash_algorithm: HASH_ALGORITHM,
+            // blobs stored before compression was turned on are read as they are
+            compression: if compression_level > 0 {
+                "lz77"
+            } else {
+                "none"
+            },
+            blob_shard_digits: SHARD_DIGITS,
+            schema_versions: crate::storage::schema_versions(),
+            settings,
+            metadata: entries,
+        })
+    }
+}
+
+/// Gets the commit of every tag, None for a tag whose commit is missing.
+fn tagged_commits(context: &Context) -> Result<Vec<(Tag, Option<Commit>)>, RepoError> {
+    let tags =
+        Tag::list(context).map_err(|e| RepoError::Other(format!("Failed to list tags: {}", e)))?;
+    tags.into_iter()
+        .map(|tag| match Commit::get(context, tag.commit_id) {
+            Ok(commit) => Ok((tag, Some(commit))),
+            Err(CommitError::NotFound) => Ok((tag, None)),
+            Err(e) => Err(RepoError::Other(format!("Failed to get commit: {}", e))),
+        })
+        .collect()
+}
+
+/// Name of the foundational branch of a new repository: taken from the metadata, then from the
+/// environment, falling back to "main".
+fn default_branch_name(metadata: &HashMap<String, String>) -> String {
+    match metadata.get(DEFAULT_BRANCH_KEY) {
+        Some(name) => name.clone(),
+        None => std::env::var(DEFAULT_BRANCH_ENV)
+            .ok()
+            .filter(|name| !name.is_empty())
+            .unwrap_or_else(|| DEFAULT_BRANCH.to_string()),
+    }
+}
+
+/// Formats an author in "Name <email>" format as a git identity with a timestamp. Git requires an
+/// email, so an empty one is used if the author does not have one.
+fn git_ident(author: &str, created_at: u64) -> String {
+    let author = author.trim();
+    let name = match author.split_once('<') {
+        Some(_) => author.to_string(),
+        None if author.is_empty() => "Unknown <>".to_string(),
+        None => format!("{} <>", author),
+    };
+    format!("{} {} +0000", name, created_at)
+}
+
+/// Formats a path of a vx tree as a git path, separated by slashes and quoted if git would not
+/// read it as it is.
+fn git_path(path: &Path) -> String {
+    let path: Vec<String> = path
+        .iter()
+        .map(|name| name.to_string_lossy().into_owned())
+        .collect();
+    let path = path.join("/");
+    if !path.starts_with('"') && !path.contains(['\n', '\\']) {
+        return path;
+    }
+
+    let mut quoted = String::from('"');
+    for c in path.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            _ => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Checks whether files can be created in a folder by creating and removing a probe file.
+fn is_writable(dir: &Path) -> bool {
+    let probe = dir.join(".vx-doctor-probe");
+    match std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&probe)
+    {
+        Ok(_) => std::fs::remove_file(&probe).is_ok(),
+        Err(_) => false,
+    }
 }
@@ -0,0 +1,143 @@
+// Signatures of commits, made with an ed25519 key registered for the repository.
+//
+// A signature covers a SHA-256 digest of the content of a commit, its tree and metadata but not
+// the signature itself, encoded canonically by `payload`. The stored hash of a commit is not
+// cryptographic, so it is not what gets signed. As ed25519 is deterministic, signing a commit
+// again with the same key yields the same signature.
+
+use crate::context::Context;
+use crate::core::digest::Digest;
+use crate::core::repo::Repo;
+use crate::storage::repo::RepoError;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest as _, Sha256};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Repository metadata key holding the path to the file of the key commits are signed with.
+/// Only the path is stored, as metadata is listed by `vx repo info`, the key stays in its file.
+pub const SIGNING_KEY_KEY: &str = "commit.signingKey";
+
+/// Tag starting the payload of a signature, naming its encoding so it can change without an old
+/// signature ever matching a payload of another encoding.
+const PAYLOAD_TAG: &[u8] = b"vx-commit-signature-v1";
+
+/// Signature of a commit along with the public key it is checked with.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommitSignature {
+    /// Public key of the signer.
+    pub public_key: [u8; 32],
+    /// The ed25519 signature of the digest of the commit payload, 64 bytes.
+    pub signature: Vec<u8>,
+}
+
+/// Outcome of checking the signature of a commit.
+#[derive(Debug, Clone)]
+pub enum Verification {
+    /// The commit carries no signature.
+    Unsigned,
+    /// The signature matches the commit, made with the given public key.
+    Valid([u8; 32]),
+    /// The signature was not made for the content of the commit with its public key.
+    BadSignature,
+}
+
+impl CommitSignature {
+    /// Signs the payload of a commit, see `payload`.
+    pub(crate) fn sign(key: &SigningKey, payload: &[u8]) -> Self {
+        CommitSignature {
+            public_key: key.verifying_key().to_bytes(),
+            signature: key.sign(&Sha256::digest(payload)).to_bytes().to_vec(),
+        }
+    }
+
+    /// Checks that the signature was made for the payload with the public key it carries.
+    pub fn verify(&self, payload: &[u8]) -> bool {
+        let Ok(key) = VerifyingKey::from_bytes(&self.public_key) else {
+            return false;
+        };
+        let Ok(signature) = Signature::from_slice(&self.signature) else {
+            return false;
+        };
+        key.verify(&Sha256::digest(payload), &signature).is_ok()
+    }
+}
+
+/// Encodes the content of a commit to be signed: a tag naming the encoding followed by every
+/// field, each prefixed with its length as 8 big-endian bytes, so no bytes can move from one field
+/// to another. Empty fields are encoded like any other, unlike in the stored hash of a commit.
+pub(crate) fn payload(message: &str, author: &str, created_at: u64, treehash: Digest) -> Vec<u8> {
+    let fields: [&[u8]; 5] = [
+        PAYLOAD_TAG,
+        message.as_bytes(),
+        author.as_bytes(),
+        &created_at.to_be_bytes(),
+        &treehash.to_be_bytes(),
+    ];
+    let mut payload = Vec::new();
+    for field in fields {
+        payload.extend_from_slice(&(field.len() as u64).to_be_bytes());
+        payload.extend_from_slice(field);
+    }
+    payload
+}
+
+/// Reads the path to the file of the signing key from repository metadata, None if no key is
+/// registered.
+pub fn signing_key_path(metadata: &HashMap<String, String>) -> Result<Option<PathBuf>, RepoError> {
+    match metadata.get(SIGNING_KEY_KEY) {
+        Some(value) if value.is_empty() => Err(RepoError::Other(format!(
+            "Invalid value of {}, expected a path to a key file",
+            SIGNING_KEY_KEY
+        ))),
+        Some(value) => Ok(Some(PathBuf::from(value))),
+        None => Ok(None),
+    }
+}
+
+/// Loads an ed25519 signing key from a file holding its 32-byte secret, either as raw bytes or
+/// as 64 hexadecimal digits, surrounding whitespace aside.
+pub(crate) fn load_key(path: &Path) -> Result<SigningKey, RepoError> {
+    let content = std::fs::read(path)
+        .map_err(|e| RepoError::Other(format!("Failed to read key '{}': {}", path.display(), e)))?;
+    let secret = match <[u8; 32]>::try_from(content.as_slice()) {
+        Ok(secret) => Some(secret),
+        Err(_) => std::str::from_utf8(&content)
+            .ok()
+            .and_then(|text| parse_hex(text.trim())),
+    };
+    secret
+        .map(|secret| SigningKey::from_bytes(&secret))
+        .ok_or_else(|| {
+            RepoError::Other(format!(
+                "Invalid key '{}', expected a 32-byte ed25519 secret, raw or in hexadecimal",
+                path.display()
+            ))
+        })
+}
+
+/// Loads the signing key registered for the repository.
+pub(crate) fn registered_key(context: &Context) -> Result<SigningKey, RepoError> {
+    let metadata = Repo::get_metadata(context)?;
+    let path = signing_key_path(&metadata)?.ok_or_else(|| {
+        RepoError::Other("No signing key registered, run `vx repo set-key` first".to_string())
+    })?;
+    load_key(&path)
+}
+
+/// Renders bytes of a key or a signature as hexadecimal digits.
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn parse_hex(text: &str) -> Option<[u8; 32]> {
+    if text.len() != 64 || !text.is_ascii() {
+        return None;
+    }
+    let mut bytes = [0; 32];
+    for (index, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&text[index * 2..index * 2 + 2], 16).ok()?;
+    }
+    Some(bytes)
+}
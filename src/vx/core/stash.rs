@@ -0,0 +1,138 @@
+use crate::context::Context;
+use crate::core::branch::Branch;
+use crate::core::commit::{Commit, CommitID, CurrentCommitSpec};
+use crate::core::digest::Digest;
+use crate::core::merge::Conflict;
+use crate::core::tree::Tree;
+use crate::storage::stash::{self as stashstore, StashError};
+use serde::{Deserialize, Serialize};
+
+/// Represents a stash, i.e. local changes set aside to be restored later.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Stash {
+    /// Identifier of the stash, increasing with every new stash.
+    pub id: u64,
+    /// Description of the stashed changes.
+    pub message: String,
+    /// Commit the changes were made on top of.
+    pub base: CommitID,
+    /// Hash of the vx tree of the base commit.
+    pub basetree: Digest,
+    /// Hash of the vx tree of the checkout with the changes.
+    pub treehash: Digest,
+}
+
+/// Result of restoring a stash.
+#[derive(Debug, Clone)]
+pub struct StashPop {
+    /// The restored stash.
+    pub stash: Stash,
+    /// Files that could not be merged cleanly, the stash is kept if there are any.
+    pub conflicts: Vec<Conflict>,
+}
+
+impl Stash {
+    /// Saves the local changes as a new stash and resets the checkout to the current commit.
+    /// The message defaults to the branch, sequence number and subject of the current commit.
+    pub fn push(context: &Context, message: Option<String>) -> Result<Self, StashError> {
+        check_no_rebuild(context)?;
+
+        let current = Commit::get_current(context)
+            .map_err(|e| StashError::Other(format!("Failed to get current commit: {:?}", e)))?;
+        let treehash = Tree::create(context)
+            .map_err(|e| StashError::Other(format!("Failed to create tree: {:?}", e)))?;
+        if treehash == current.treehash {
+            return Err(StashError::NoChanges);
+        }
+
+        let message = match message {
+            Some(message) => message,
+            None => {
+                let branch = Branch::get(context, current.id.branch)
+                    .map_err(|e| StashError::Other(format!("Failed to get branch: {:?}", e)))?;
+                format!(
+                    "WIP on {}:{}: {}",
+                    branch.name,
+                    current.id.seq,
+                    current.subject()
+                )
+            }
+        };
+        let stash = Stash {
+            id: stashstore::next_id(context)?,
+            message,
+            base: current.id,
+            basetree: current.treehash,
+            treehash,
+        };
+        // the stash is saved before the checkout is reset, so the changes can not be lost
+        stashstore::new(context, &stash)?;
+
+        Tree::materialize(context, current.treehash)
+            .map_err(|e| StashError::Other(format!("Failed to reset checkout: {:?}", e)))?;
+
+        Ok(stash)
+    }
+
+    /// Restores the changes of a stash on top of the checkout, or of the latest stash if the id is
+    /// not provided. The changes are merged file by file against the base commit of the stash, the
+    /// stash is deleted unless there are conflicts.
+    pub fn pop(context: &Context, id: Option<u64>) -> Result<StashPop, StashError> {
+        check_no_rebuild(context)?;
+
+        let stash = match id {
+            Some(id) => stashstore::get(context, id)?,
+            None => stashstore::get_latest(context)?,
+        };
+
+        let local = Tree::create(context)
+            .map_err(|e| StashError::Other(format!("Failed to create tree: {:?}", e)))?;
+        let merged = Tree::merge(context, stash.basetree, local, stash.treehash)
+            .map_err(|e| StashError::Other(format!("Failed to merge stash: {:?}", e)))?;
+        Tree::materialize(context, merged.hash)
+            .map_err(|e| StashError::Other(format!("Failed to restore stash: {:?}", e)))?;
+
+        if merged.conflicts.is_empty() {
+            stashstore::delete(context, stash.id)?;
+        }
+
+        Ok(StashPop {
+            stash,
+            conflicts: merged.conflicts,
+        })
+    }
+
+    /// Retrieves a stash from the database by id.
+    pub fn get(context: &Context, id: u64) -> Result<Self, StashError> {
+        stashstore::get(context, id)
+    }
+
+    /// Lists all stashes from the database.
+    pub fn list(context: &Context) -> Result<Vec<Self>, StashError> {
+        stashstore::list(context)
+    }
+
+    /// Deletes a stash from the database by id, its changes are discarded.
+    pub fn delete(context: &Context, id: u64) -> Result<Self, StashError> {
+        stashstore::delete(context, id)
+    }
+
+    /// Points the stashes based on a branch to another branch id, as part of a rename of the
+    /// branch.
+    pub(crate) fn move_branch(context: &Context, from: u64, to: u64) -> Result<(), StashError> {
+        stashstore::move_branch(context, from, to)
+    }
+}
+
+/// Refuses to stash while a rebuild is paused on a conflict, as the checkout holds its resolution.
+fn check_no_rebuild(context: &Context) -> Result<(), StashError> {
+    let current = CurrentCommitSpec::get(context)
+        .map_err(|e| StashError::Other(format!("Failed to get current commit: {:?}", e)))?;
+    if current.is_rebuild() {
+        return Err(StashError::Other(format!(
+            "Rebuild of commit {} is in progress, continue or abort it first",
+            current.rebuild_seq
+        )));
+    }
+    Ok(())
+}
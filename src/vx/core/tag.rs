@@ -0,0 +1,85 @@
+use crate::context::Context;
+use crate::core::branch::{self, Branch};
+use crate::core::commit::{Commit, CommitID};
+use crate::storage::branch::BranchError;
+use crate::storage::tag::{self as tagstore, TagError};
+use serde::{Deserialize, Serialize};
+
+/// Represents a tag, i.e. a human readable immutable label for a commit.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Tag {
+    /// Name of the tag, must be unique in the repository.
+    pub name: String,
+    /// Identifier of the tagged commit. Only the ID is stored, not the version, so the tag keeps
+    /// pointing to the same position in the branch even if the commit gets amended.
+    pub commit_id: CommitID,
+}
+
+impl Tag {
+    /// Creates a new tag for a commit specified by spec, or for the current commit if spec is not provided.
+    /// Names a spec would not resolve to the tag are refused: names of digits only, read as a
+    /// sequence number, and names of existing branches, which take precedence over tags.
+    pub fn new(context: &Context, name: String, spec: Option<&str>) -> Result<Self, TagError> {
+        if !branch::is_valid_name(&name) {
+            return Err(TagError::InvalidName(
+                "Tag names can only contain lowercase letters, numbers, and the following characters: . / -"
+                    .to_string(),
+            ));
+        }
+        if name.chars().all(|c| c.is_ascii_digit()) {
+            return Err(TagError::InvalidName(format!(
+                "'{}' would be read as a sequence number, tag names need a letter or one of . / -",
+                name
+            )));
+        }
+        match Branch::get_by_name(context, &name) {
+            Ok(_) => {
+                return Err(TagError::InvalidName(format!(
+                    "'{}' is the name of a branch, which would hide the tag",
+                    name
+                )))
+            }
+            Err(BranchError::NotFound) => {}
+            Err(e) => {
+                return Err(TagError::Other(format!(
+                    "Failed to look up branches: {:?}",
+                    e
+                )))
+            }
+        }
+
+        let commit = match spec {
+            Some(spec) => Commit::get_by_spec(context, spec),
+            None => Commit::get_current(context),
+        }
+        .map_err(|e| TagError::Other(format!("Failed to resolve commit: {:?}", e)))?;
+
+        let tag = Tag {
+            name,
+            commit_id: commit.id,
+        };
+        tagstore::new(context, &tag)?;
+
+        Ok(tag)
+    }
+
+    /// Retrieves a tag from the database by name.
+    pub fn get(context: &Context, name: &str) -> Result<Self, TagError> {
+        tagstore::get(context, name)
+    }
+
+    /// Lists all tags from the database.
+    pub fn list(context: &Context) -> Result<Vec<Self>, TagError> {
+        tagstore::list(context)
+    }
+
+    /// Deletes a tag from the database by name.
+    pub fn delete(context: &Context, name: &str) -> Result<Self, TagError> {
+        tagstore::delete(context, name)
+    }
+
+    /// Points the tags of a branch to another branch id, as part of a rename of the branch.
+    pub(crate) fn move_branch(context: &Context, from: u64, to: u64) -> Result<(), TagError> {
+        tagstore::move_branch(context, from, to)
+    }
+}
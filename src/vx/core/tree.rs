@@ -1,16 +1,46 @@
 use crate::context::Context;
-use crate::core::blob::Blob;
+use crate::core::attributes::{Attributes, ATTRIBUTES_FILE_NAME};
+use crate::core::blob::{Blob, ContentKind, Conversion, LineEndings};
+use crate::core::branch::Branch;
 use crate::core::commit::{Commit, CommitID, CurrentCommitSpec};
-use crate::core::digest::{Digest, DigestExt};
+use crate::core::digest::{self, Digest, DigestExt};
+use crate::core::ignore::Ignore;
+use crate::core::listing::{self, Lister, SortedNames};
+use crate::core::merge::{self, Conflict, ConflictKind};
+use crate::core::regex::Regex;
+use crate::core::rename::{self, RenameDiff, Signature};
+use crate::core::repo::Repo;
+use crate::core::xattr::{self, Xattr};
 use crate::global::{DATA_FOLDER, TEMP_FOLDER};
+use crate::storage::blob::BlobError;
+use crate::storage::repo::RepoError;
 use crate::storage::tree::{self as treestore, TreeError};
 use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
 use sled::Db;
 use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::io::{BufRead, BufReader, Write};
 use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use xxhash_rust::xxh3::Xxh3;
 
+/// Repository metadata key setting a file size in bytes from which the index is trusted without
+/// reading files: such files are taken as unchanged whenever their mtime and size did not change,
+/// even right after they were modified, while smaller files are only indexed once they settled.
+/// Trades a small risk of missing a change that kept the mtime and size for speed on huge assets.
+pub const TRUST_INDEX_SIZE_KEY: &str = "index.trustSize";
+
+/// Repository metadata key setting how many folders deep trees may be nested, the root folder not
+/// counted. Walks over deeper trees stop with an error naming the offending folder, instead of
+/// exhausting the stack of the recursive ones.
+pub const MAX_DEPTH_KEY: &str = "tree.maxDepth";
+
+/// Nesting limit used unless `MAX_DEPTH_KEY` is set, far beyond any real project and well within
+/// the stack of the worker threads.
+const DEFAULT_MAX_DEPTH: usize = 256;
+
 // Scenarios
 // 1. Read changes between vx tree and filesystem tree (status command)
 //   - Bottom up traversal of both trees
@@ -50,6 +80,12 @@ pub struct File {
     pub name: String,
     /// Blob containing the file's data or folder's content.
     pub blob: Blob,
+    /// Extended attributes of the file sorted by name, only the ones listed by the
+    /// `xattr.capture` repository metadata entry are recorded.
+    pub xattrs: Vec<Xattr>,
+    /// Whether the file is a symbolic link, its blob then holds the target of the link rather than
+    /// the content the link points to.
+    pub symlink: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -62,51 +98,519 @@ pub struct Folder {
     pub hash: Digest,
 }
 
-/// Creates a new empty folder with default values.
-fn default_tree() -> Tree {
-    Tree {
-        hash: Digest::NONE,
-        folders: Vec::new(),
-        files: Vec::new(),
-        size: 0,
-        file_count: 0,
-        folder_count: 0,
-    }
+/// Cached state of a file in the checkout, used to find out that a file has not changed since it
+/// was last hashed without reading it.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct IndexEntry {
+    /// Modification time of the file in nanoseconds since the Unix epoch.
+    pub mtime: u128,
+    /// Size of the file in bytes.
+    pub size: u64,
+    /// Hash of the file's content, the file is guaranteed to be in the blob store.
+    pub contenthash: Digest,
+    /// Kind of the file's content.
+    pub kind: ContentKind,
+    /// Whether line endings were normalized when the content was hashed, the entry only holds
+    /// under a line ending policy that normalizes them the same way.
+    pub normalized: bool,
+}
+
+/// Cached state of a folder in the checkout, used to find out that its entries have not changed
+/// since its vx tree was last built without listing it. A folder's mtime only changes when entries
+/// are added, removed or renamed, so the files it lists are still looked at one by one.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct FolderEntry {
+    /// Modification time of the folder in nanoseconds since the Unix epoch.
+    pub mtime: u128,
+    /// Size of the folder as reported by the filesystem.
+    pub size: u64,
+    /// Hash of the vx tree built from the folder, whose entries are those of the folder.
+    pub treehash: Digest,
+    /// Digest of the ignore rules the folder was listed with, see `Ignore::digest`.
+    pub ignore: Digest,
+}
+
+/// Write-ahead record of a checkout that is being performed.
+/// Files of the checkout are rewritten one by one, so a failure halfway leaves a mix of the current
+/// and the target commit. The record is kept until the checkout completes, and the files are not
+/// compared against the current commit while it exists.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct PendingCheckout {
+    /// The commit being checked out.
+    pub commit_id: CommitID,
 }
 
 impl Tree {
     /// Get the changes between latest vx tree and the current filesystem tree.
     pub fn get_changed_files(context: &Context) -> Result<Vec<Change>, TreeError> {
+        Self::get_changed_files_with(context, Untracked::Normal)
+    }
+
+    /// Same as `get_changed_files`, with the untracked mode deciding how added files and folders
+    /// are reported.
+    pub fn get_changed_files_with(
+        context: &Context,
+        untracked: Untracked,
+    ) -> Result<Vec<Change>, TreeError> {
         // sergeyb: tried to use walkdir, but it's not working as expected
         // too high level, object creation overhead and can't properly traverse bottom up with filtering
 
-        // get the vx tree to compare against, so far current commit
-        let commit = Commit::get_current(context)
+        // get the vx tree to compare against, the current commit unless a rebuild is paused
+        let treehash = Commit::get_checkout_tree(context)
+            .map_err(|e| TreeError::Other(format!("Commit error: {:?}", e)))?;
+
+        let db = treestore::open(context)?;
+        check_no_pending_checkout(&db)?;
+        let ignore = load_ignore(context)?;
+        let attributes = load_attributes(context)?;
+        get_changes_between_vx_tree_and_filesystem_tree(
+            context,
+            &db,
+            &ignore,
+            &attributes,
+            untracked,
+            treehash,
+        )
+    }
+
+    /// Same as `get_changed_files`, but without applying ignore rules, to find out what they hide.
+    /// Every change is paired with a flag telling whether it would otherwise be ignored.
+    pub fn get_changed_files_including_ignored(
+        context: &Context,
+        untracked: Untracked,
+    ) -> Result<Vec<(Change, bool)>, TreeError> {
+        let treehash = Commit::get_checkout_tree(context)
             .map_err(|e| TreeError::Other(format!("Commit error: {:?}", e)))?;
 
         let db = treestore::open(context)?;
-        get_changes_between_vx_tree_and_filesystem_tree(context, &db, commit.treehash)
+        check_no_pending_checkout(&db)?;
+        let ignore = load_ignore(context)?;
+        let changes = get_changes_between_vx_tree_and_filesystem_tree(
+            context,
+            &db,
+            &Ignore::none(),
+            &load_attributes(context)?,
+            untracked,
+            treehash,
+        )?;
+
+        // tracked files are never ignored, so only additions can be hidden by the rules
+        changes
+            .into_iter()
+            .map(|change| {
+                let is_dir = matches!(change.change_type, ChangeType::Folder);
+                let ignored = matches!(change.action, ChangeAction::Added)
+                    && ignore.is_path_ignored(&change.path, is_dir)?;
+                Ok((change, ignored))
+            })
+            .collect()
+    }
+
+    /// Drops modifications of text files that only change whitespace at the end of lines, e.g. CRLF
+    /// line endings replacing LF ones. Both versions of every modified file are read, so it is much
+    /// slower than comparing hashes alone.
+    pub fn filter_whitespace_changes(
+        context: &Context,
+        changes: Vec<Change>,
+    ) -> Result<Vec<Change>, TreeError> {
+        let blob_db = Blob::open(context)
+            .map_err(|e| TreeError::from_blob("Failed to open blob store", e))?;
+
+        let mut filtered = Vec::with_capacity(changes.len());
+        for change in changes {
+            let modified_file = matches!(change.action, ChangeAction::Modified)
+                && matches!(change.change_type, ChangeType::File);
+            if modified_file && is_whitespace_change(context, &blob_db, &change)? {
+                continue;
+            }
+            filtered.push(change);
+        }
+        Ok(filtered)
     }
 
     /// Creates a new vx tree from the current directory recursively.
+    /// Subtrees that did not change since the current commit, or the conflicting commit of a paused
+    /// rebuild, are reused instead of being rebuilt.
+    /// Trees, blobs and index entries are written in a batch, so the stores are flushed once.
     pub fn create(context: &Context) -> Result<Digest, TreeError> {
+        let treehash = Commit::get_checkout_tree(context)
+            .map_err(|e| TreeError::Other(format!("Commit error: {:?}", e)))?;
+
+        let batch = context.batch();
         let db = treestore::open(context)?;
-        let blob_db = Blob::open(context)
-            .map_err(|e| TreeError::Other(format!("Blob store error: {:?}", e)))?;
-        let stats = write_filesystem_tree_to_vx_tree(context, &db, &blob_db, Path::new(""))?;
+        check_no_pending_checkout(&db)?;
+        let index = treestore::open_index(&db)?;
+        let folder_index = treestore::open_folder_index(&db)?;
+        let blob_db =
+            Blob::open(context).map_err(|e| TreeError::from_blob("Blob store error", e))?;
+        let ignore = load_ignore(context)?;
+        let attributes = load_attributes(context)?;
+        let writer = TreeWriter {
+            context,
+            db: &db,
+            blob_db: &blob_db,
+            index: &index,
+            folder_index: &folder_index,
+            ignore: &ignore,
+            attributes: &attributes,
+            started: SystemTime::now(),
+            trust_size: load_trust_size(context)?,
+            xattr_names: load_xattr_names(context)?,
+            max_depth: load_max_depth(context)?,
+        };
+        let stats = write_filesystem_tree_to_vx_tree(&writer, Path::new(""), treehash)?;
+        batch.finish()?;
         Ok(stats.hash)
     }
 
     /// Checkout a specific commit or branch.
     /// Format: "branch_name" or "branch_name:commit_id"
-    pub fn checkout(context: &Context, spec: &str) -> Result<(), TreeError> {
+    /// Returns what was done to the files of the checkout.
+    pub fn checkout(context: &Context, spec: &str) -> Result<CheckoutReport, TreeError> {
         // Parse the target string
-        let commit_id = CommitID::resolve(context, spec)
-            .map_err(|e| TreeError::Other(format!("Failed to resolve commit: {:?}", e)))?;
+        let commit_id = CommitID::resolve(context, spec).map_err(TreeError::Resolve)?;
 
         // Call the implementation function with the parsed values
-        perform_checkout(context, commit_id)?;
-        Ok(())
+        let progress = Progress::abort(context);
+        perform_checkout(context, commit_id, false, &progress)?;
+        progress.into_report()
+    }
+
+    /// Same as `checkout`, for a commit already resolved from its specification.
+    pub(crate) fn checkout_id(
+        context: &Context,
+        commit_id: CommitID,
+    ) -> Result<CheckoutReport, TreeError> {
+        let progress = Progress::abort(context);
+        perform_checkout(context, commit_id, false, &progress)?;
+        progress.into_report()
+    }
+
+    /// Same as `checkout`, but instead of comparing the checkout with the target commit, removes
+    /// everything in the checkout except the workspace, ignored files included, and writes the tree
+    /// of the commit from scratch. Slower, but a way out of a checkout too damaged to be compared,
+    /// e.g. with partially written files or unreadable folders. The report lists every file as
+    /// created, and everything removed before as deleted.
+    pub fn checkout_clean(context: &Context, spec: &str) -> Result<CheckoutReport, TreeError> {
+        let commit_id = CommitID::resolve(context, spec).map_err(TreeError::Resolve)?;
+
+        let progress = Progress::abort(context);
+        perform_checkout(context, commit_id, true, &progress)?;
+        progress.into_report()
+    }
+
+    /// Same as `checkout`, or `checkout_clean` if `clean` is set, but files and folders that fail
+    /// to be written or removed, e.g. for a lack of permissions, do not stop the checkout. The rest
+    /// of the tree is written and the failures are reported, in which case the current commit is
+    /// left as it was and the checkout stays interrupted, to be finished with `continue_checkout`
+    /// once they are fixed.
+    pub fn checkout_keep_going(
+        context: &Context,
+        spec: &str,
+        clean: bool,
+    ) -> Result<CheckoutReport, TreeError> {
+        let commit_id = CommitID::resolve(context, spec).map_err(TreeError::Resolve)?;
+
+        let progress = Progress::keep_going(context);
+        perform_checkout(context, commit_id, clean, &progress)?;
+        progress.into_report()
+    }
+
+    /// Starts a new foundational branch with no history and makes it current.
+    /// The checkout is left as is, so the next commit captures everything present as the initial
+    /// content of the branch.
+    pub fn checkout_orphan(context: &Context, name: &str) -> Result<Branch, TreeError> {
+        check_no_rebuild(context)?;
+        // the tree store is opened again to create the empty tree
+        check_no_pending_checkout(&treestore::open(context)?)?;
+
+        Branch::new_orphan(context, name.to_string())
+            .map_err(|e| TreeError::Other(format!("Failed to create branch: {}", e)))
+    }
+
+    /// Completes a checkout interrupted by a failure, e.g. an I/O error, by checking out its target
+    /// commit again. Returns the commit that was checked out.
+    pub fn continue_checkout(context: &Context) -> Result<CommitID, TreeError> {
+        let commit_id = Self::get_pending_checkout(context)?
+            .ok_or_else(|| TreeError::Other("No checkout to continue".to_string()))?;
+
+        perform_checkout(context, commit_id, false, &Progress::abort(context))?;
+        Ok(commit_id)
+    }
+
+    /// Retrieves the commit of an interrupted checkout, if any.
+    pub fn get_pending_checkout(context: &Context) -> Result<Option<CommitID>, TreeError> {
+        let db = treestore::open(context)?;
+        Ok(treestore::get_checkout_intent(&db)?.map(|pending| pending.commit_id))
+    }
+
+    /// Checkout a specific commit or branch, keeping local changes.
+    /// Files changed only locally stay, files changed only in the target commit are updated, and files
+    /// changed on both sides are merged line by line against the current commit as a common base.
+    /// Returns the conflicts that could not be merged cleanly.
+    pub fn checkout_merge(context: &Context, spec: &str) -> Result<Vec<Conflict>, TreeError> {
+        let commit_id = CommitID::resolve(context, spec).map_err(TreeError::Resolve)?;
+
+        perform_checkout_merge(context, commit_id)
+    }
+
+    /// Writes a vx tree into the checkout, ignored files are left in place.
+    /// Unlike a checkout, the current commit is not changed.
+    pub(crate) fn materialize(context: &Context, treehash: Digest) -> Result<(), TreeError> {
+        let db = treestore::open(context)?;
+        let blob_db = Blob::open(context)
+            .map_err(|e| TreeError::from_blob("Failed to open blob store", e))?;
+        let ignore = load_ignore(context)?;
+        let attributes = load_tree_attributes(context, &db, &blob_db, treehash)?;
+        let progress = Progress::abort(context);
+        write_vx_tree_to_filesystem_tree(
+            context,
+            &db,
+            &blob_db,
+            &ignore,
+            &attributes,
+            &progress,
+            treehash,
+        )
+    }
+
+    /// Counts all vx trees in the repository.
+    pub fn count(context: &Context) -> Result<u64, TreeError> {
+        let db = treestore::open(context)?;
+        treestore::count(&db)
+    }
+
+    /// Lists hashes of all stored vx trees.
+    pub(crate) fn list_hashes(context: &Context) -> Result<Vec<Digest>, TreeError> {
+        let db = treestore::open(context)?;
+        treestore::list_hashes(&db)
+    }
+
+    /// Deletes vx trees from the store, e.g. unreachable ones found by a garbage collection.
+    pub(crate) fn delete(context: &Context, hashes: &[Digest]) -> Result<(), TreeError> {
+        let db = treestore::open(context)?;
+        treestore::delete(context, &db, hashes)
+    }
+
+    /// Marks vx trees and blobs reachable from the specified root trees, along with the blobs
+    /// referenced by the index of the checkout.
+    pub(crate) fn mark_reachable(
+        context: &Context,
+        roots: &[Digest],
+    ) -> Result<Reachable, TreeError> {
+        let db = treestore::open(context)?;
+        let mut reachable = Reachable::default();
+
+        // shared subtrees are only walked once
+        let mut stack: Vec<Digest> = roots.to_vec();
+        while let Some(hash) = stack.pop() {
+            if !reachable.trees.insert(hash) {
+                continue;
+            }
+            let tree = treestore::get(&db, hash)?;
+            stack.extend(tree.folders.iter().map(|folder| folder.hash));
+            reachable
+                .blobs
+                .extend(tree.files.iter().map(|file| file.blob.contenthash));
+        }
+
+        // the index promises that its blobs are stored, so they must be kept
+        let index = treestore::open_index(&db)?;
+        reachable
+            .blobs
+            .extend(treestore::list_index_hashes(&index)?);
+
+        Ok(reachable)
+    }
+
+    /// Walks vx trees from the specified root trees and finds the trees and blobs they refer to
+    /// that are missing from their stores, along with the missing ones of the specified blobs.
+    /// Unlike `mark_reachable`, a missing tree does not stop the walk, its subtrees are skipped.
+    pub(crate) fn find_missing(
+        context: &Context,
+        roots: &[Digest],
+        blobs: &[Digest],
+    ) -> Result<Missing, TreeError> {
+        let db = treestore::open(context)?;
+        let blob_db = Blob::open(context)
+            .map_err(|e| TreeError::from_blob("Failed to open blob store", e))?;
+        let mut missing = Missing::default();
+
+        let mut walked = HashSet::new();
+        let mut referenced: HashSet<Digest> = blobs.iter().copied().collect();
+        let mut stack: Vec<Digest> = roots.to_vec();
+        while let Some(hash) = stack.pop() {
+            if !walked.insert(hash) {
+                continue;
+            }
+            let tree = match treestore::get(&db, hash) {
+                Ok(tree) => tree,
+                Err(TreeError::TreeNotFound) => {
+                    missing.trees.push(hash);
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+            missing.checked_trees += 1;
+            stack.extend(tree.folders.iter().map(|folder| folder.hash));
+            referenced.extend(tree.files.iter().map(|file| file.blob.contenthash));
+        }
+
+        for contenthash in referenced {
+            match Blob::get(&blob_db, contenthash) {
+                Ok(_) => (),
+                Err(BlobError::BlobNotFound(_)) => missing.blobs.push(contenthash),
+                Err(e) => return Err(TreeError::from_blob("Failed to get blob", e)),
+            }
+        }
+        missing.trees.sort();
+        missing.blobs.sort();
+        Ok(missing)
+    }
+
+    /// Sums up the total size of the files in the specified vx trees, recursively.
+    pub(crate) fn total_size(context: &Context, hashes: &[Digest]) -> Result<u64, TreeError> {
+        let db = treestore::open(context)?;
+        let mut size = 0;
+        for hash in hashes {
+            size += treestore::get(&db, *hash)?.size;
+        }
+        Ok(size)
+    }
+
+    /// Merges changes between the base and the incoming vx trees into the local vx tree, file by file.
+    /// Files changed on both sides are merged line by line; the merged tree is saved even if there
+    /// are conflicts, with conflicting files containing conflict markers or the content kept as
+    /// described by the conflict kind.
+    pub(crate) fn merge(
+        context: &Context,
+        base: Digest,
+        local: Digest,
+        incoming: Digest,
+    ) -> Result<TreeMerge, TreeError> {
+        let db = treestore::open(context)?;
+
+        // trivial cases do not need any merging
+        if incoming == base || incoming == local {
+            return Ok(TreeMerge {
+                hash: local,
+                conflicts: Vec::new(),
+            });
+        }
+        if local == base {
+            return Ok(TreeMerge {
+                hash: incoming,
+                conflicts: Vec::new(),
+            });
+        }
+
+        let blob_db = Blob::open(context)
+            .map_err(|e| TreeError::from_blob("Failed to open blob store", e))?;
+
+        let base = FlatTree::load(&db, base)?;
+        let local = FlatTree::load(&db, local)?;
+        let incoming = FlatTree::load(&db, incoming)?;
+
+        let mut merged = FlatTree::default();
+        let mut conflicts = Vec::new();
+
+        let paths: BTreeSet<&PathBuf> = base
+            .files
+            .keys()
+            .chain(local.files.keys())
+            .chain(incoming.files.keys())
+            .collect();
+
+        for path in paths {
+            // files are taken along with whether they are links, which changes them as well
+            let side = |tree: &FlatTree| {
+                tree.files
+                    .get(path)
+                    .map(|blob| (blob.clone(), tree.symlinks.contains(path)))
+            };
+            let (b, l, i) = (side(&base), side(&local), side(&incoming));
+
+            let same = |x: &Option<(Blob, bool)>, y: &Option<(Blob, bool)>| {
+                x.as_ref().map(|(blob, link)| (blob.contenthash, *link))
+                    == y.as_ref().map(|(blob, link)| (blob.contenthash, *link))
+            };
+
+            let result = if same(&l, &i) || same(&b, &i) {
+                l
+            } else if same(&b, &l) {
+                i
+            } else {
+                match (l, i) {
+                    // the targets of links are not merged line by line, the local one is kept
+                    (Some(l), Some(i)) if l.1 || i.1 => {
+                        conflicts.push(Conflict {
+                            path: path.clone(),
+                            kind: ConflictKind::Binary,
+                        });
+                        Some(l)
+                    }
+                    (Some(l), Some(i)) => {
+                        let b = b.as_ref().map(|(blob, _)| blob);
+                        let (blob, kind) = merge_blobs(context, &blob_db, b, &l.0, &i.0)?;
+                        if let Some(kind) = kind {
+                            conflicts.push(Conflict {
+                                path: path.clone(),
+                                kind,
+                            });
+                        }
+                        Some((blob, false))
+                    }
+                    (Some(l), None) => {
+                        conflicts.push(Conflict {
+                            path: path.clone(),
+                            kind: ConflictKind::ModifiedDeleted,
+                        });
+                        Some(l)
+                    }
+                    (None, Some(i)) => {
+                        conflicts.push(Conflict {
+                            path: path.clone(),
+                            kind: ConflictKind::DeletedModified,
+                        });
+                        Some(i)
+                    }
+                    (None, None) => None,
+                }
+            };
+
+            if let Some((blob, symlink)) = result {
+                if symlink {
+                    merged.symlinks.insert(path.clone());
+                }
+                merged.files.insert(path.clone(), blob);
+            }
+        }
+
+        // Folders are merged by presence, which only matters for the empty ones.
+        let folders: BTreeSet<&PathBuf> = base
+            .folders
+            .iter()
+            .chain(local.folders.iter())
+            .chain(incoming.folders.iter())
+            .collect();
+        for path in folders {
+            let (b, l, i) = (
+                base.folders.contains(path),
+                local.folders.contains(path),
+                incoming.folders.contains(path),
+            );
+            if (b == l && i) || (b != l && l) {
+                merged.folders.insert(path.clone());
+            }
+        }
+
+        let hash = merged.save(&db)?;
+        context.flush(&db)?;
+        context
+            .flush(&blob_db)
+            .map_err(|e| TreeError::Other(format!("Failed to flush blob store: {:?}", e)))?;
+
+        Ok(TreeMerge { hash, conflicts })
     }
 
     /// Creates a new empty vx tree and saves it to the database.
@@ -114,56 +618,474 @@ impl Tree {
         let db = treestore::open(context)?;
         let tree = new_tree(&db, Vec::new(), Vec::new(), 0, 0, 0)?;
         treestore::save(&db, &tree)?;
-        db.flush()?;
+        context.flush(&db)?;
         Ok(tree)
     }
 
     /// Get file and folder changes between two vx trees.
-    pub(crate) fn get_diff(
+    /// Changes can be restricted to the given paths relative to the checkout root and everything
+    /// below them, all changes are returned if no paths are given.
+    /// The checkout root itself is left out, it is modified whenever anything else is.
+    pub fn get_diff(
         context: &Context,
         tree1_hash: Digest,
         tree2_hash: Digest,
+        paths: &[PathBuf],
     ) -> Result<Vec<Change>, TreeError> {
         let db = treestore::open(context)?;
 
-        let changes = get_changes_between_vx_trees(context, &db, tree1_hash, tree2_hash)?;
+        let filter = PathFilter { paths };
+        let mut changes =
+            get_filtered_changes_between_vx_trees(&db, tree1_hash, tree2_hash, &filter)?;
+        changes.retain(|change| !change.path.as_os_str().is_empty());
 
         Ok(changes)
     }
+
+    /// Same as `get_diff`, with added and deleted folders expanded into the files they contain
+    /// and deleted files paired with added ones of a similar content as renames, see
+    /// `rename::find_renames`. A threshold of 100 only pairs files of the same content, which is
+    /// done without reading any content.
+    pub fn get_diff_renames(
+        context: &Context,
+        tree1_hash: Digest,
+        tree2_hash: Digest,
+        threshold: u8,
+    ) -> Result<RenameDiff, TreeError> {
+        let db = treestore::open(context)?;
+        let blob_db = Blob::open(context)
+            .map_err(|e| TreeError::from_blob("Failed to open blob store", e))?;
+
+        let changes = get_changes_between_vx_trees(&db, tree1_hash, tree2_hash)?;
+        let attributes = Attributes::none(LineEndings::Keep);
+        let expanded = expand_changes(context, &db, &Ignore::none(), &attributes, changes)?;
+
+        rename::find_renames(
+            expanded.files.into_values().collect(),
+            |contenthash| {
+                Blob::get(&blob_db, contenthash)
+                    .map(|blob| blob.size)
+                    .map_err(|e| TreeError::from_blob("Blob error", e))
+            },
+            |contenthash| {
+                Blob::read(context, &blob_db, contenthash)
+                    .map(|content| Signature::new(&content))
+                    .map_err(|e| TreeError::from_blob("Failed to read blob", e))
+            },
+            threshold,
+        )
+    }
+
+    /// Searches text files of a vx tree for lines matching a pattern.
+    /// Files are streamed from the blob store line by line and searched in parallel, binary files
+    /// are skipped. Returns matches sorted by path and line number.
+    pub fn grep(
+        context: &Context,
+        treehash: Digest,
+        regex: &Regex,
+    ) -> Result<Vec<GrepMatch>, TreeError> {
+        let db = treestore::open(context)?;
+        let blob_db = Blob::open(context)
+            .map_err(|e| TreeError::from_blob("Failed to open blob store", e))?;
+
+        let flat = FlatTree::load(&db, treehash)?;
+        // links are skipped, their blobs hold targets rather than content
+        let files: Vec<(PathBuf, Blob)> = flat
+            .files
+            .into_iter()
+            .filter(|(path, _)| !flat.symlinks.contains(path))
+            .collect();
+
+        let matches: Vec<Vec<GrepMatch>> = files
+            .par_iter()
+            .map(|(path, blob)| grep_blob(context, &blob_db, path, blob, regex))
+            .collect::<Result<_, _>>()?;
+
+        // files come sorted by path from the flattened tree
+        Ok(matches.into_iter().flatten().collect())
+    }
+
+    /// Walks a vx tree depth first, yielding its files and folders with their paths relative to the
+    /// root of the tree. Entries of a folder come in name order, a folder comes right before its
+    /// content, so paths are yielded in a deterministic order. Subtrees are loaded as the walk
+    /// reaches them.
+    pub fn walk(context: &Context, treehash: Digest) -> Result<TreeWalk, TreeError> {
+        let db = treestore::open(context)?;
+        Ok(TreeWalk::new(db, treehash))
+    }
+
+    /// Gets the hashes of files and folders of a vx tree by their paths relative to the root, the
+    /// content hash of a file or the tree hash of a folder, and `Digest::NONE` for missing paths.
+    /// Only the trees along the paths are loaded, so comparing the hashes of two trees tells
+    /// whether anything changed at a path without walking the content below it.
+    pub fn get_path_hashes(
+        context: &Context,
+        treehash: Digest,
+        paths: &[PathBuf],
+    ) -> Result<Vec<Digest>, TreeError> {
+        let db = treestore::open(context)?;
+        paths
+            .iter()
+            .map(|path| match lookup_entry(&db, treehash, path)? {
+                Some(Entry::Folder(hash)) => Ok(hash),
+                Some(Entry::File(blob) | Entry::Symlink(blob)) => Ok(blob.contenthash),
+                None => Ok(Digest::NONE),
+            })
+            .collect()
+    }
+
+    /// Writes a file or folder of a vx tree into a directory outside of the checkout, e.g. to take a
+    /// look at an old version of a folder without disturbing the checkout. The path is relative to
+    /// the root of the vx tree, the destination directory must not exist or be empty.
+    /// Neither the checkout nor the current commit are touched.
+    pub fn extract(
+        context: &Context,
+        treehash: Digest,
+        path: &Path,
+        dest: &Path,
+    ) -> Result<(), TreeError> {
+        let dest = crate::context::resolve_lenient(&std::env::current_dir()?.join(dest))?;
+        if dest.starts_with(context.checkout_path.canonicalize()?) {
+            return Err(TreeError::Other(format!(
+                "Destination '{}' is inside the checkout",
+                dest.display()
+            )));
+        }
+        if dest.exists() && std::fs::read_dir(&dest)?.next().is_some() {
+            return Err(TreeError::Other(format!(
+                "Destination '{}' is not empty",
+                dest.display()
+            )));
+        }
+
+        let db = treestore::open(context)?;
+        let blob_db = Blob::open(context)
+            .map_err(|e| TreeError::from_blob("Failed to open blob store", e))?;
+
+        let attributes = load_tree_attributes(context, &db, &blob_db, treehash)?;
+        let (blob, symlink) = match find_entry(&db, treehash, path)? {
+            Entry::Folder(hash) => {
+                let writer = CheckoutWriter {
+                    context,
+                    db: &db,
+                    blob_db: &blob_db,
+                    attributes: &attributes,
+                    progress: &Progress::abort(context),
+                    max_depth: load_max_depth(context)?,
+                };
+                return materialize_folder_without_checks(&writer, hash, path, &dest);
+            }
+            Entry::File(blob) => (blob, false),
+            Entry::Symlink(blob) => (blob, true),
+        };
+        std::fs::create_dir_all(&dest)?;
+        let write = FileWrite {
+            contenthash: blob.contenthash,
+            // a file is found by a path with at least one component
+            path: dest.join(path.file_name().unwrap_or_default()),
+            conversion: attributes.resolve(path)?,
+            check: false,
+            xattrs: Vec::new(),
+            symlink,
+        };
+        write_files(context, &blob_db, &Progress::abort(context), &[write])
+    }
+
+    /// Writes a file or folder of a vx tree back into the checkout at its path, e.g. to bring back a
+    /// file that was deleted or broken, without touching anything else. The files of a folder are
+    /// all written, files of the directory that are not in the vx tree are left as they are. A
+    /// file of the same content is left untouched. Neither the other files nor the current commit
+    /// are touched, so the written files show up as local changes unless they match the current
+    /// commit. A path missing from the vx tree is a `TreeError::PathNotFound`.
+    pub fn restore(context: &Context, treehash: Digest, path: &Path) -> Result<(), TreeError> {
+        let db = treestore::open(context)?;
+        check_no_pending_checkout(&db)?;
+        let blob_db = Blob::open(context)
+            .map_err(|e| TreeError::from_blob("Failed to open blob store", e))?;
+
+        let attributes = load_tree_attributes(context, &db, &blob_db, treehash)?;
+        let restored = |path: PathBuf, blob: &Blob, xattrs: Vec<Xattr>, symlink: bool| {
+            let abs_path = context.checkout_path.join(&path);
+            Ok::<_, TreeError>(FileWrite {
+                contenthash: blob.contenthash,
+                // files in place are compared rather than written over
+                check: std::fs::symlink_metadata(&abs_path).is_ok(),
+                path: abs_path,
+                conversion: attributes.resolve(&path)?,
+                xattrs,
+                symlink,
+            })
+        };
+        let mut writes = Vec::new();
+        let mut folders = Vec::new();
+        let entry = find_entry(&db, treehash, path)?;
+        if let Some(parent) = path.parent().filter(|_| !matches!(entry, Entry::Folder(_))) {
+            std::fs::create_dir_all(context.checkout_path.join(parent))?;
+        }
+        match entry {
+            Entry::Folder(hash) => folders.push((hash, path.to_path_buf())),
+            Entry::File(blob) => {
+                let xattrs = file_xattrs(&db, treehash, path)?;
+                writes.push(restored(path.to_path_buf(), &blob, xattrs, false)?);
+            }
+            Entry::Symlink(blob) => {
+                writes.push(restored(path.to_path_buf(), &blob, Vec::new(), true)?);
+            }
+        }
+        while let Some((hash, folder)) = folders.pop() {
+            std::fs::create_dir_all(context.checkout_path.join(&folder))?;
+            let tree = treestore::get(&db, hash)?;
+            for file in &tree.files {
+                let path = folder.join(&file.name);
+                writes.push(restored(
+                    path,
+                    &file.blob,
+                    file.xattrs.clone(),
+                    file.symlink,
+                )?);
+            }
+            folders.extend(
+                tree.folders
+                    .iter()
+                    .map(|sub| (sub.hash, folder.join(&sub.name))),
+            );
+        }
+        write_files(context, &blob_db, &Progress::abort(context), &writes)
+    }
+
+    /// Same as `extract` for a single file, writing its content to a stream, e.g. standard output.
+    pub fn extract_file(
+        context: &Context,
+        treehash: Digest,
+        path: &Path,
+        out: &mut impl Write,
+    ) -> Result<(), TreeError> {
+        let db = treestore::open(context)?;
+        let blob_db = Blob::open(context)
+            .map_err(|e| TreeError::from_blob("Failed to open blob store", e))?;
+
+        let attributes = load_tree_attributes(context, &db, &blob_db, treehash)?;
+        match find_entry(&db, treehash, path)? {
+            Entry::Folder(_) => Err(TreeError::Other(format!(
+                "'{}' is a folder, only files can be written to a stream",
+                path.display()
+            ))),
+            Entry::File(blob) => Blob::to_writer(
+                context,
+                &blob_db,
+                blob.contenthash,
+                out,
+                attributes.resolve(path)?,
+            )
+            .map_err(|e| TreeError::from_blob("Failed to write file", e)),
+            // the target of a link is written as it is stored
+            Entry::Symlink(blob) => Blob::to_writer(
+                context,
+                &blob_db,
+                blob.contenthash,
+                out,
+                Conversion::default(),
+            )
+            .map_err(|e| TreeError::from_blob("Failed to write file", e)),
+        }
+    }
+
+    /// Get sizes of changed files between two vx trees, sorted by path.
+    /// Added and deleted folders are expanded into the files they contain.
+    pub fn get_diff_stat(
+        context: &Context,
+        tree1_hash: Digest,
+        tree2_hash: Digest,
+    ) -> Result<Vec<FileStat>, TreeError> {
+        let db = treestore::open(context)?;
+        let blob_db = Blob::open(context)
+            .map_err(|e| TreeError::from_blob("Failed to open blob store", e))?;
+
+        let changes = get_changes_between_vx_trees(&db, tree1_hash, tree2_hash)?;
+        // vx trees know hashes of all their folders, so the filesystem is never looked at
+        let attributes = Attributes::none(LineEndings::Keep);
+        let expanded = expand_changes(context, &db, &Ignore::none(), &attributes, changes)?;
+
+        let get_blob = |contenthash: Digest| -> Result<Option<Blob>, TreeError> {
+            if contenthash == Digest::NONE {
+                return Ok(None);
+            }
+            let blob = Blob::get(&blob_db, contenthash)
+                .map_err(|e| TreeError::from_blob("Blob error", e))?;
+            Ok(Some(blob))
+        };
+
+        expanded
+            .files
+            .into_values()
+            .map(|change| {
+                let left = get_blob(change.contenthash_left)?;
+                let right = get_blob(change.contenthash_right)?;
+                Ok(FileStat {
+                    size_left: left.as_ref().map_or(0, |blob| blob.size),
+                    size_right: right.as_ref().map_or(0, |blob| blob.size),
+                    binary: [left, right]
+                        .iter()
+                        .flatten()
+                        .any(|blob| blob.kind == ContentKind::Binary),
+                    action: change.action,
+                    path: change.path,
+                })
+            })
+            .collect()
+    }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "lowercase")]
 pub enum ChangeAction {
     Added,
     Deleted,
     Modified,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "lowercase")]
 pub enum ChangeType {
     File,
     Folder,
 }
 
+/// How files and folders missing from the vx tree are reported when comparing it with the checkout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Untracked {
+    /// Added files and folders are not reported.
+    No,
+    /// Added files are reported, and so are added folders, without descending into them.
+    #[default]
+    Normal,
+    /// Added folders are reported along with everything inside them.
+    All,
+}
+
 /// Represents a change for a single file or folder in a tree.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct Change {
     /// Action performed on the file or folder.
     pub action: ChangeAction,
     /// Path to the file or folder.
     pub path: PathBuf,
     /// Type of the change (file or folder).
+    #[serde(rename = "type")]
     pub change_type: ChangeType,
     /// Hash of the original file or folder's content ("left"), NONE if the file or folder is added
+    #[serde(serialize_with = "digest::serialize_hex")]
     pub contenthash_left: Digest,
     /// Hash of the destination file or folder's content ("right"), NONE if the file or folder is deleted
+    #[serde(serialize_with = "digest::serialize_hex")]
     pub contenthash_right: Digest,
 }
 
-fn new_file(context: &Context, db_blob: &Db, name: String, path: &Path) -> Result<File, TreeError> {
-    let blob = Blob::from_file(context, db_blob, path)
-        .map_err(|e| TreeError::Other(format!("Blob error for path {:?}: {:?}", path, e)))?;
-    let file = File { name: name, blob };
+/// Hashes of vx trees and blobs reachable from a set of roots.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Reachable {
+    pub(crate) trees: HashSet<Digest>,
+    pub(crate) blobs: HashSet<Digest>,
+}
+
+/// Hashes of vx trees and blobs referenced from a set of roots but missing from their stores.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Missing {
+    /// Number of trees found in the store and walked.
+    pub(crate) checked_trees: u64,
+    pub(crate) trees: Vec<Digest>,
+    pub(crate) blobs: Vec<Digest>,
+}
+
+/// Represents a line of a file matching a searched pattern.
+#[derive(Debug, Clone)]
+pub struct GrepMatch {
+    /// Path to the file.
+    pub path: PathBuf,
+    /// Number of the line, starting from 1.
+    pub line_number: u64,
+    /// Content of the line without the line break.
+    pub line: String,
+}
+
+/// Searches a single blob for lines matching a pattern, returns no matches for binary content.
+fn grep_blob(
+    context: &Context,
+    blob_db: &Db,
+    path: &Path,
+    blob: &Blob,
+    regex: &Regex,
+) -> Result<Vec<GrepMatch>, TreeError> {
+    // binary blobs are skipped without being opened
+    if blob.kind == ContentKind::Binary {
+        return Ok(Vec::new());
+    }
+
+    let file = Blob::open_content(context, blob_db, blob.contenthash)
+        .map_err(|e| TreeError::from_blob(&format!("Blob error for path {:?}", path), e))?;
+    let mut reader = BufReader::with_capacity(64 * 1024, file);
+
+    // blobs stored before kinds were detected are checked on the fly
+    if blob.kind == ContentKind::Unknown
+        && ContentKind::detect(reader.fill_buf()?) == ContentKind::Binary
+    {
+        return Ok(Vec::new());
+    }
+
+    let mut matches = Vec::new();
+    let mut buf = Vec::new();
+    let mut line_number = 0;
+    loop {
+        buf.clear();
+        if reader.read_until(b'\n', &mut buf)? == 0 {
+            break;
+        }
+        line_number += 1;
+
+        let line = String::from_utf8_lossy(&buf);
+        let line = line.trim_end_matches(['\n', '\r']);
+        if regex.is_match(line) {
+            matches.push(GrepMatch {
+                path: path.to_path_buf(),
+                line_number,
+                line: line.to_string(),
+            });
+        }
+    }
+
+    Ok(matches)
+}
+
+/// Represents a changed file with sizes of both its versions.
+#[derive(Debug, Clone)]
+pub struct FileStat {
+    /// Action performed on the file.
+    pub action: ChangeAction,
+    /// Path to the file.
+    pub path: PathBuf,
+    /// Size of the original file in bytes, 0 if the file is added.
+    pub size_left: u64,
+    /// Size of the destination file in bytes, 0 if the file is deleted.
+    pub size_right: u64,
+    /// Whether either version of the file is binary.
+    pub binary: bool,
+}
+
+fn new_file(
+    context: &Context,
+    db_blob: &Db,
+    name: String,
+    path: &Path,
+    conversion: Conversion,
+) -> Result<File, TreeError> {
+    let blob = Blob::from_file(context, db_blob, path, conversion)
+        .map_err(|e| TreeError::from_blob(&format!("Blob error for path {:?}", path), e))?;
+    let file = File {
+        name,
+        blob,
+        xattrs: Vec::new(),
+        symlink: false,
+    };
     Ok(file)
 }
 
@@ -176,33 +1098,49 @@ fn new_tree(
     file_count: u64,
     folder_count: u64,
 ) -> Result<Tree, TreeError> {
-    // Calculate hash based on contents
+    let tree = Tree {
+        hash: hash_tree(&folders, &files),
+        folders,
+        files,
+        size,
+        file_count,
+        folder_count,
+    };
+
+    treestore::save(db, &tree)?;
+
+    Ok(tree)
+}
+
+/// Added to the hash of a vx tree after the content hash of a symbolic link.
+const SYMLINK_MARKER: &[u8] = b"\0symlink";
+
+/// Calculates the hash of a vx tree based on its contents.
+fn hash_tree(folders: &[Folder], files: &[File]) -> Digest {
     let mut hasher = Xxh3::new();
 
     // Add folder names and hashes to the hash calculation
-    for folder in &folders {
+    for folder in folders {
         hasher.update(folder.name.as_bytes());
         hasher.update(&folder.hash.to_be_bytes());
     }
 
     // Add file names and hashes to the hash calculation
-    for file in &files {
+    for file in files {
         hasher.update(file.name.as_bytes());
         hasher.update(&file.blob.contenthash.to_be_bytes());
+        // files without attributes hash as they did before attributes were recorded
+        for xattr in &file.xattrs {
+            hasher.update(xattr.name.as_bytes());
+            hasher.update(&xattr.value);
+        }
+        // regular files hash as they did before links were recorded
+        if file.symlink {
+            hasher.update(SYMLINK_MARKER);
+        }
     }
 
-    let tree = Tree {
-        hash: hasher.digest128(),
-        folders,
-        files,
-        size,
-        file_count,
-        folder_count,
-    };
-
-    treestore::save(db, &tree)?;
-
-    Ok(tree)
+    hasher.digest128()
 }
 
 // Walk the file tree and vx tree in parallel, identifying differences.
@@ -211,10 +1149,19 @@ fn new_tree(
 fn get_changes_between_vx_tree_and_filesystem_tree(
     context: &Context,
     db: &Db,
+    ignore: &Ignore,
+    attributes: &Attributes,
+    untracked: Untracked,
     treehash: Digest,
 ) -> Result<Vec<Change>, TreeError> {
-    // TODO: use mtime/size index and parallelize
+    // TODO: parallelize
+    let index = StatusIndex {
+        index: treestore::open_index(db)?,
+        started: SystemTime::now(),
+        trust_size: load_trust_size(context)?,
+    };
 
+    let limits = WalkLimits::load(context)?;
     let mut changed_paths = Vec::new();
     let mut level = 1;
 
@@ -229,14 +1176,17 @@ fn get_changes_between_vx_tree_and_filesystem_tree(
         // this loops moves up and down the file tree
 
         if drill {
-            new_level(
+            let state = new_level(
                 context,
                 db,
-                &mut level_states,
-                level,
+                ignore,
+                &limits.lister,
                 current_dir.clone(),
                 current_hash,
             )?;
+            // states left below the new level belong to folders walked before, drop their listings
+            level_states.truncate(level - 1);
+            level_states.push(state);
 
             drill = false;
         }
@@ -250,7 +1200,7 @@ fn get_changes_between_vx_tree_and_filesystem_tree(
             // equal names: advance both iters, proceed down
             // fs < vx: added, advance fs
             // fs > vx: deleted, advance vx
-            if state.fs_pos >= state.dirs.len() {
+            let Some(fs_name) = state.dirs.peek() else {
                 // no more dirs to process in filesystem, the remaining ones from vx are deleted from checkout
                 while state.vx_pos < state.vx_tree.folders.len() {
                     let folder = &state.vx_tree.folders[state.vx_pos];
@@ -264,62 +1214,80 @@ fn get_changes_between_vx_tree_and_filesystem_tree(
                     state.vx_pos += 1;
                 }
 
-                process_files(context, &state, &mut changed_paths)?;
+                process_files(
+                    context,
+                    attributes,
+                    &index,
+                    untracked,
+                    state,
+                    &mut changed_paths,
+                )?;
 
                 // drill up
                 level -= 1;
                 continue 'vertical;
-            }
+            };
 
             if state.vx_pos >= state.vx_tree.folders.len() {
                 // no more folder to process in vx, the remaining ones from fs are added to checkout
-                while state.fs_pos < state.dirs.len() {
-                    changed_paths.push(Change {
-                        action: ChangeAction::Added,
-                        path: state.current_dir.join(&state.dirs[state.fs_pos]),
-                        change_type: ChangeType::Folder,
-                        contenthash_left: Digest::NONE,
-                        // TODO: compute hash of the folder?
-                        contenthash_right: Digest::NONE,
-                    });
-                    state.fs_pos += 1;
+                while let Some(name) = state.dirs.peek() {
+                    let path = state.current_dir.join(name);
+                    push_added_folder(
+                        context,
+                        ignore,
+                        attributes,
+                        untracked,
+                        &limits,
+                        &mut changed_paths,
+                        path,
+                    )?;
+                    state.dirs.advance()?;
                 }
 
-                process_files(context, &state, &mut changed_paths)?;
+                process_files(
+                    context,
+                    attributes,
+                    &index,
+                    untracked,
+                    state,
+                    &mut changed_paths,
+                )?;
 
                 // drill up
                 level -= 1;
                 continue 'vertical;
             }
 
-            let fs_name = &state.dirs[state.fs_pos];
             let vx_dir = &state.vx_tree.folders[state.vx_pos];
 
             match fs_name.cmp(&vx_dir.name) {
                 Ordering::Equal => {
-                    // equal names: advance both iters, drill down
-                    state.fs_pos += 1;
-                    state.vx_pos += 1;
-
                     // drill down the file tree by breaking into outer loop
                     // keep the current state to return to it later
                     level += 1;
                     current_dir = state.current_dir.join(fs_name);
                     current_hash = vx_dir.hash;
+
+                    // equal names: advance both iters, drill down
+                    state.dirs.advance()?;
+                    state.vx_pos += 1;
+                    check_depth(limits.max_depth, &current_dir)?;
                     drill = true;
                     continue 'vertical;
                 }
                 Ordering::Less => {
                     // fs < vx: added, advance fs
-                    changed_paths.push(Change {
-                        action: ChangeAction::Added,
-                        path: state.current_dir.join(fs_name),
-                        change_type: ChangeType::Folder,
-                        contenthash_left: Digest::NONE,
-                        // TODO: compute hash of the folder or do not use the structure with contenthash_right
-                        contenthash_right: Digest::NONE,
-                    });
-                    state.fs_pos += 1;
+                    let path = state.current_dir.join(fs_name);
+                    push_added_folder(
+                        context,
+                        ignore,
+                        attributes,
+                        untracked,
+                        &limits,
+                        &mut changed_paths,
+                        path,
+                    )?;
+                    state.dirs.advance()?;
                     continue 'horizontal;
                 }
                 Ordering::Greater => {
@@ -341,85 +1309,169 @@ fn get_changes_between_vx_tree_and_filesystem_tree(
     Ok(changed_paths)
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 struct LevelState {
     current_dir: PathBuf,
-    dirs: Vec<String>,
-    files: Vec<String>,
+    dirs: SortedNames,
+    files: SortedNames,
     vx_tree: Tree,
-    // simple index pointers instead of iterators because Rust ownership rules become hard
-    fs_pos: usize,
+    // simple index pointer instead of an iterator because Rust ownership rules become hard
     vx_pos: usize,
 }
 
+/// Limits of a walk over the checkout, see `MAX_DEPTH_KEY` and `listing::DIR_BATCH_SIZE_KEY`.
+struct WalkLimits {
+    max_depth: usize,
+    lister: Lister,
+}
+
+impl WalkLimits {
+    fn load(context: &Context) -> Result<Self, TreeError> {
+        let metadata = Repo::get_metadata(context)
+            .map_err(|e| TreeError::Other(format!("Failed to load tree settings: {:?}", e)))?;
+        let invalid = |e| TreeError::Other(format!("Failed to load tree settings: {:?}", e));
+        Ok(WalkLimits {
+            max_depth: max_depth(&metadata).map_err(invalid)?,
+            lister: Lister::new(
+                context,
+                listing::dir_batch_size(&metadata).map_err(invalid)?,
+            ),
+        })
+    }
+}
+
 fn new_level(
     context: &Context,
     db: &Db,
-    level_states: &mut Vec<LevelState>,
-    level: usize,
+    ignore: &Ignore,
+    lister: &Lister,
     current_dir: PathBuf,
     current_hash: Digest,
-) -> Result<(), TreeError> {
+) -> Result<LevelState, TreeError> {
     // we just went down the file tree, so we need to obtain the current state
-    if level_states.len() < level {
-        level_states.push(LevelState {
-            current_dir,
-            dirs: Vec::with_capacity(128),
-            files: Vec::with_capacity(128),
-            vx_tree: default_tree(),
-            fs_pos: 0,
-            vx_pos: 0,
-        });
-    } else {
-        let state = &mut level_states[level - 1];
-        state.current_dir = current_dir;
-        state.dirs.clear();
-        state.files.clear();
-        state.fs_pos = 0;
-        state.vx_pos = 0;
-        // state.vx_tree will be setup later
-    }
+    let current_dir_abs = context.checkout_path.join(&current_dir);
+    let mut entries = std::fs::read_dir(&current_dir_abs)?;
 
-    let state = &mut level_states[level - 1];
+    let vx_tree = treestore::get(db, current_hash)?;
+    let (dirs, files) = list_entries(lister, &mut entries, ignore, Some(&vx_tree))?;
 
-    let current_dir_abs = context.checkout_path.join(&state.current_dir);
-    let mut entries = std::fs::read_dir(&current_dir_abs)?;
+    Ok(LevelState {
+        current_dir,
+        dirs,
+        files,
+        vx_tree,
+        vx_pos: 0,
+    })
+}
+
+/// Loads the line ending policy of the repository.
+fn load_eol(context: &Context) -> Result<LineEndings, TreeError> {
+    LineEndings::load(context)
+        .map_err(|e| TreeError::Other(format!("Failed to load line ending policy: {:?}", e)))
+}
+
+/// Loads the size from which the index is trusted without reading files, if it is configured.
+fn load_trust_size(context: &Context) -> Result<Option<u64>, TreeError> {
+    Repo::get_metadata(context)
+        .and_then(|metadata| trust_index_size(&metadata))
+        .map_err(|e| TreeError::Other(format!("Failed to load index settings: {:?}", e)))
+}
 
-    // Reusing vectors from state object to avoid allocations
-    parse_entries(&mut entries, &mut state.dirs, &mut state.files)?;
+fn load_xattr_names(context: &Context) -> Result<Vec<String>, TreeError> {
+    Repo::get_metadata(context)
+        .and_then(|metadata| xattr::capture_names(&metadata))
+        .map_err(|e| TreeError::Other(format!("Failed to load attribute settings: {:?}", e)))
+}
+
+/// Loads how many folders deep trees may be nested.
+fn load_max_depth(context: &Context) -> Result<usize, TreeError> {
+    Repo::get_metadata(context)
+        .and_then(|metadata| max_depth(&metadata))
+        .map_err(|e| TreeError::Other(format!("Failed to load tree settings: {:?}", e)))
+}
 
-    state.vx_tree = treestore::get(db, current_hash)?;
+/// Reads how many folders deep trees may be nested from repository metadata.
+pub fn max_depth(metadata: &HashMap<String, String>) -> Result<usize, RepoError> {
+    match metadata.get(MAX_DEPTH_KEY) {
+        Some(value) => match value.parse() {
+            Ok(depth) if depth > 0 => Ok(depth),
+            _ => Err(RepoError::Other(format!(
+                "Invalid value '{}' of {}, expected a positive number of folders",
+                value, MAX_DEPTH_KEY
+            ))),
+        },
+        None => Ok(DEFAULT_MAX_DEPTH),
+    }
+}
 
+/// Refuses a folder nested deeper than the limit, `path` is relative to the root of the tree.
+fn check_depth(max_depth: usize, path: &Path) -> Result<(), TreeError> {
+    let depth = path.components().count();
+    if depth > max_depth {
+        return Err(TreeError::Other(format!(
+            "Folder '{}' is nested {} folders deep, beyond the limit of {} set by {}",
+            path.display(),
+            depth,
+            max_depth,
+            MAX_DEPTH_KEY
+        )));
+    }
     Ok(())
 }
 
+/// Reads the size from which the index is trusted without reading files from repository metadata.
+pub fn trust_index_size(metadata: &HashMap<String, String>) -> Result<Option<u64>, RepoError> {
+    match metadata.get(TRUST_INDEX_SIZE_KEY) {
+        Some(value) => value.parse().map(Some).map_err(|_| {
+            RepoError::Other(format!(
+                "Invalid value '{}' of {}, expected a size in bytes",
+                value, TRUST_INDEX_SIZE_KEY
+            ))
+        }),
+        None => Ok(None),
+    }
+}
+
+/// Loads attributes of the files in the checkout, e.g. to commit them.
+fn load_attributes(context: &Context) -> Result<Attributes<'_>, TreeError> {
+    Ok(Attributes::from_checkout(context, load_eol(context)?))
+}
+
+/// Loads attributes of the files of a vx tree, e.g. to write them into the checkout.
+fn load_tree_attributes<'a>(
+    context: &'a Context,
+    db: &'a Db,
+    blob_db: &'a Db,
+    treehash: Digest,
+) -> Result<Attributes<'a>, TreeError> {
+    let read = move |dir: &Path| match lookup_entry(db, treehash, &dir.join(ATTRIBUTES_FILE_NAME))?
+    {
+        Some(Entry::File(blob)) => Ok(Some(read_blob(context, blob_db, blob.contenthash)?)),
+        _ => Ok(None),
+    };
+    Ok(Attributes::from_reader(load_eol(context)?, read))
+}
+
+/// Loads ignore rules of the repository.
+fn load_ignore(context: &Context) -> Result<Ignore, TreeError> {
+    Ignore::load(context)
+        .map_err(|e| TreeError::Other(format!("Failed to load ignore rules: {:?}", e)))
+}
+
+/// Lists subfolders and files of a folder, sorted by name.
+/// Ignored entries are skipped unless they are already tracked in the given vx tree.
 fn parse_entries(
     entries: &mut std::fs::ReadDir,
+    ignore: &Ignore,
+    tracked: Option<&Tree>,
     dirs: &mut Vec<String>,
     files: &mut Vec<String>,
 ) -> Result<(), TreeError> {
     for entry in entries {
-        let entry = entry?; // Unwrap the Result<DirEntry, Error>
-        let file_name = entry.file_name();
-
-        // Skip .vx and .vxtemp directories
-        // TODO: process .gitignore etc
-        if file_name == DATA_FOLDER || file_name == TEMP_FOLDER {
-            continue;
-        }
-
-        let ftype = entry.file_type()?;
-        if ftype.is_dir() {
-            dirs.push(file_name.into_string().unwrap());
-        } else {
-            if ftype.is_symlink() {
-                // Skip symlinks and return an error
-                return Err(TreeError::Other(format!(
-                    "Symlinks are not supported as of yet: {:?}",
-                    entry.path()
-                )));
-            }
-            files.push(file_name.into_string().unwrap());
+        match classify_entry(entry?, ignore, tracked)? {
+            Some((name, true)) => dirs.push(name),
+            Some((name, false)) => files.push(name),
+            None => (),
         }
     }
 
@@ -429,22 +1481,137 @@ fn parse_entries(
     Ok(())
 }
 
+/// Same as `parse_entries`, with the names sorted in batches of bounded size so that a folder
+/// with millions of entries is not held in memory at once, see `listing`.
+fn list_entries(
+    lister: &Lister,
+    entries: &mut std::fs::ReadDir,
+    ignore: &Ignore,
+    tracked: Option<&Tree>,
+) -> Result<(SortedNames, SortedNames), TreeError> {
+    let mut dirs = lister.sorter();
+    let mut files = lister.sorter();
+    for entry in entries {
+        match classify_entry(entry?, ignore, tracked)? {
+            Some((name, true)) => dirs.push(name)?,
+            Some((name, false)) => files.push(name)?,
+            None => (),
+        }
+    }
+    Ok((dirs.finish()?, files.finish()?))
+}
+
+/// Gets the name of an entry of a folder along with whether it is a folder, or nothing if the
+/// entry is skipped.
+fn classify_entry(
+    entry: std::fs::DirEntry,
+    ignore: &Ignore,
+    tracked: Option<&Tree>,
+) -> Result<Option<(String, bool)>, TreeError> {
+    let file_name = entry.file_name();
+
+    // Skip .vx and .vxtemp directories
+    if file_name == DATA_FOLDER || file_name == TEMP_FOLDER {
+        return Ok(None);
+    }
+
+    let ftype = entry.file_type()?;
+    if !is_tracked(tracked, &file_name, ftype.is_dir())
+        && ignore.is_entry_ignored(&entry.path(), ftype.is_dir())?
+    {
+        return Ok(None);
+    }
+    // symbolic links are files holding their target, the entry type is not followed
+    Ok(Some((file_name.into_string().unwrap(), ftype.is_dir())))
+}
+
+/// Finds a file or folder in a vx tree by walking its path from the root, one level at a time.
+/// An empty path refers to the root folder.
+fn find_entry(db: &Db, treehash: Digest, path: &Path) -> Result<Entry, TreeError> {
+    lookup_entry(db, treehash, path)?.ok_or_else(|| TreeError::PathNotFound(path.to_path_buf()))
+}
+
+/// Gets the extended attributes of a file in a vx tree, kept by the record of the file in its
+/// folder rather than by its entry.
+fn file_xattrs(db: &Db, treehash: Digest, path: &Path) -> Result<Vec<Xattr>, TreeError> {
+    let parent = path.parent().unwrap_or(Path::new(""));
+    let (Entry::Folder(hash), Some(name)) = (find_entry(db, treehash, parent)?, path.file_name())
+    else {
+        return Ok(Vec::new());
+    };
+    let tree = treestore::get(db, hash)?;
+    Ok(tree
+        .files
+        .iter()
+        .find(|file| file.name.as_str() == name)
+        .map(|file| file.xattrs.clone())
+        .unwrap_or_default())
+}
+
+/// Same as `find_entry`, with a missing path not being an error.
+fn lookup_entry(db: &Db, treehash: Digest, path: &Path) -> Result<Option<Entry>, TreeError> {
+    let mut entry = Entry::Folder(treehash);
+    for component in path.components() {
+        let Entry::Folder(hash) = entry else {
+            // only the last component can be a file
+            return Ok(None);
+        };
+        let Some(name) = component.as_os_str().to_str() else {
+            return Ok(None);
+        };
+        let tree = treestore::get(db, hash)?;
+
+        entry = if let Ok(pos) = tree
+            .folders
+            .binary_search_by(|folder| folder.name.as_str().cmp(name))
+        {
+            Entry::Folder(tree.folders[pos].hash)
+        } else if let Ok(pos) = tree
+            .files
+            .binary_search_by(|file| file.name.as_str().cmp(name))
+        {
+            Entry::from_file(tree.files[pos].clone())
+        } else {
+            return Ok(None);
+        };
+    }
+    Ok(Some(entry))
+}
+
+/// Checks whether a file or folder with the given name is present in a vx tree.
+fn is_tracked(tree: Option<&Tree>, name: &std::ffi::OsStr, is_dir: bool) -> bool {
+    let (Some(tree), Some(name)) = (tree, name.to_str()) else {
+        return false;
+    };
+    if is_dir {
+        tree.folders
+            .binary_search_by(|folder| folder.name.as_str().cmp(name))
+            .is_ok()
+    } else {
+        tree.files
+            .binary_search_by(|file| file.name.as_str().cmp(name))
+            .is_ok()
+    }
+}
+
 /// Process files in the current folder
 fn process_files(
     context: &Context,
-    state: &LevelState,
+    attributes: &Attributes,
+    index: &StatusIndex,
+    untracked: Untracked,
+    state: &mut LevelState,
     changed_paths: &mut Vec<Change>,
 ) -> Result<(), TreeError> {
-    let fs_files = &state.files;
+    let fs_files = &mut state.files;
     let vx_files = &state.vx_tree.files;
 
-    let mut fs_pos = 0;
     let mut vx_pos = 0;
 
     // very much a copy of folder processing routine
     // we do not want to unify because of performance
     loop {
-        if fs_pos >= fs_files.len() {
+        let Some(fs_name) = fs_files.peek() else {
             // no more files to process in filesystem, the remaining ones from vx are deleted from checkout
             while vx_pos < vx_files.len() {
                 changed_paths.push(Change {
@@ -457,46 +1624,36 @@ fn process_files(
                 vx_pos += 1;
             }
             break;
-        }
+        };
 
         if vx_pos >= vx_files.len() {
             // no more files to process in vx, the remaining ones from fs are added to checkout
-            while fs_pos < fs_files.len() {
-                let fs_file_name = &fs_files[fs_pos];
-                let fs_file_path = state.current_dir.join(fs_file_name);
-
-                let (fs_hash, _) =
-                    Digest::compute_hash(&context.checkout_path.join(&fs_file_path))?;
-                changed_paths.push(Change {
-                    action: ChangeAction::Added,
-                    path: fs_file_path,
-                    change_type: ChangeType::File,
-                    contenthash_left: Digest::NONE,
-                    contenthash_right: fs_hash,
-                });
-                fs_pos += 1;
+            while let Some(fs_name) = fs_files.peek() {
+                let fs_file_path = state.current_dir.join(fs_name);
+                push_added_file(context, attributes, untracked, changed_paths, fs_file_path)?;
+                fs_files.advance()?;
             }
             break;
         }
 
-        let fs_name = &fs_files[fs_pos];
         let vx_name = &vx_files[vx_pos].name;
 
         match fs_name.cmp(vx_name) {
             Ordering::Equal => {
                 // equal names: advance both iters and check file contents
-                let fs_file_name = &fs_files[fs_pos];
-                let fs_file_path = state.current_dir.join(fs_file_name);
+                let fs_file_path = state.current_dir.join(fs_name);
 
-                // Compute hash for the filesystem file
-                let (fs_hash, _) =
-                    Digest::compute_hash(&context.checkout_path.join(&fs_file_path))?;
+                // Compute hash for the filesystem file, as it would be stored
+                let conversion = attributes.resolve(&fs_file_path)?;
+                let vx_file = &vx_files[vx_pos];
+                let (fs_hash, fs_symlink) =
+                    hash_tracked_file(context, index, &fs_file_path, conversion, &vx_file.blob)?;
 
                 // Get hash from the VX state
-                let vx_hash = vx_files[vx_pos].blob.contenthash;
+                let vx_hash = vx_file.blob.contenthash;
 
-                // If hashes don't match, file has changed
-                if fs_hash != vx_hash {
+                // If hashes don't match or a file was replaced by a link, file has changed
+                if fs_hash != vx_hash || fs_symlink != vx_file.symlink {
                     changed_paths.push(Change {
                         action: ChangeAction::Modified,
                         path: fs_file_path,
@@ -506,22 +1663,14 @@ fn process_files(
                     });
                 }
 
-                fs_pos += 1;
+                fs_files.advance()?;
                 vx_pos += 1;
             }
             Ordering::Less => {
                 // fs < vx: added, advance fs
                 let fs_file_path = state.current_dir.join(fs_name);
-                let (fs_hash, _) =
-                    Digest::compute_hash(&context.checkout_path.join(&fs_file_path))?;
-                changed_paths.push(Change {
-                    action: ChangeAction::Added,
-                    path: fs_file_path,
-                    change_type: ChangeType::File,
-                    contenthash_left: Digest::NONE,
-                    contenthash_right: fs_hash,
-                });
-                fs_pos += 1;
+                push_added_file(context, attributes, untracked, changed_paths, fs_file_path)?;
+                fs_files.advance()?;
             }
             Ordering::Greater => {
                 // fs > vx: deleted, advance vx
@@ -540,6 +1689,141 @@ fn process_files(
     Ok(())
 }
 
+/// Hashes a tracked file of the checkout as it would be stored, along with whether it is a
+/// symbolic link. Files are taken from the index without reading them if their mtime and size
+/// did not change, and a file read with the content of the vx tree is indexed for the next run,
+/// e.g. once a file written by a checkout settled. Links are never indexed.
+fn hash_tracked_file(
+    context: &Context,
+    index: &StatusIndex,
+    path: &Path,
+    conversion: Conversion,
+    tracked: &Blob,
+) -> Result<(Digest, bool), TreeError> {
+    let abs_path = context.checkout_path.join(path);
+    let metadata = std::fs::symlink_metadata(&abs_path)?;
+    if metadata.file_type().is_symlink() {
+        return Ok((
+            Digest::compute_hash_bytes(&read_link_target(&abs_path)?),
+            true,
+        ));
+    }
+    let modified = metadata.modified()?;
+    if let Some(entry) = treestore::get_index_entry(&index.index, path)? {
+        if is_unchanged(&entry, mtime_nanos(modified), metadata.len(), conversion) {
+            return Ok((entry.contenthash, false));
+        }
+    }
+    let hash = Blob::hash_file(&abs_path, conversion)?;
+    if hash == tracked.contenthash {
+        let entry = index_entry(
+            index.started,
+            index.trust_size,
+            modified,
+            metadata.len(),
+            tracked,
+            conversion,
+        );
+        if let Some(entry) = entry {
+            treestore::save_index_entry(&index.index, path, &entry)?;
+        }
+    }
+    Ok((hash, false))
+}
+
+/// Hashes a file of the checkout as it would be stored, the target of a symbolic link is hashed
+/// instead of the content it points to.
+fn hash_checkout_file(abs_path: &Path, conversion: Conversion) -> Result<Digest, TreeError> {
+    if std::fs::symlink_metadata(abs_path)?
+        .file_type()
+        .is_symlink()
+    {
+        return Ok(Digest::compute_hash_bytes(&read_link_target(abs_path)?));
+    }
+    Ok(Blob::hash_file(abs_path, conversion)?)
+}
+
+/// Reports a file missing from the vx tree as added, unless untracked files are hidden.
+fn push_added_file(
+    context: &Context,
+    attributes: &Attributes,
+    untracked: Untracked,
+    changed_paths: &mut Vec<Change>,
+    path: PathBuf,
+) -> Result<(), TreeError> {
+    if untracked == Untracked::No {
+        return Ok(());
+    }
+
+    let conversion = attributes.resolve(&path)?;
+    let fs_hash = hash_checkout_file(&context.checkout_path.join(&path), conversion)?;
+    changed_paths.push(Change {
+        action: ChangeAction::Added,
+        path,
+        change_type: ChangeType::File,
+        contenthash_left: Digest::NONE,
+        contenthash_right: fs_hash,
+    });
+    Ok(())
+}
+
+/// Reports a folder missing from the vx tree as added according to the untracked mode: not at all,
+/// as a single entry, or along with all files and folders inside it.
+fn push_added_folder(
+    context: &Context,
+    ignore: &Ignore,
+    attributes: &Attributes,
+    untracked: Untracked,
+    limits: &WalkLimits,
+    changed_paths: &mut Vec<Change>,
+    path: PathBuf,
+) -> Result<(), TreeError> {
+    if untracked == Untracked::No {
+        return Ok(());
+    }
+    check_depth(limits.max_depth, &path)?;
+
+    changed_paths.push(Change {
+        action: ChangeAction::Added,
+        path: path.clone(),
+        change_type: ChangeType::Folder,
+        contenthash_left: Digest::NONE,
+        // TODO: compute hash of the folder or do not use the structure with contenthash_right
+        contenthash_right: Digest::NONE,
+    });
+    if untracked == Untracked::Normal {
+        return Ok(());
+    }
+
+    let mut entries = std::fs::read_dir(context.checkout_path.join(&path))?;
+    let (mut dirs, mut files) = list_entries(&limits.lister, &mut entries, ignore, None)?;
+
+    while let Some(dir) = dirs.peek() {
+        let path = path.join(dir);
+        push_added_folder(
+            context,
+            ignore,
+            attributes,
+            untracked,
+            limits,
+            changed_paths,
+            path,
+        )?;
+        dirs.advance()?;
+    }
+    while let Some(file) = files.peek() {
+        push_added_file(
+            context,
+            attributes,
+            untracked,
+            changed_paths,
+            path.join(file),
+        )?;
+        files.advance()?;
+    }
+    Ok(())
+}
+
 #[derive(Debug, Clone, Copy)]
 struct TreeStats {
     /// Hash of the folder's content, recursively.
@@ -552,31 +1836,125 @@ struct TreeStats {
     folder_count: u64,
 }
 
+/// Shared state of a single run of writing a filesystem tree to a vx tree.
+struct TreeWriter<'a> {
+    context: &'a Context,
+    db: &'a Db,
+    blob_db: &'a Db,
+    index: &'a treestore::Index,
+    /// Index of the folders, see `FolderEntry`.
+    folder_index: &'a treestore::Index,
+    ignore: &'a Ignore,
+    attributes: &'a Attributes<'a>,
+    /// Time the run started, used to detect files modified too recently to be trusted to the index.
+    started: SystemTime,
+    /// Size from which files are indexed even if modified recently, see `TRUST_INDEX_SIZE_KEY`.
+    trust_size: Option<u64>,
+    /// Names of the extended attributes recorded with files, see `xattr::XATTR_CAPTURE_KEY`.
+    xattr_names: Vec<String>,
+    /// How many folders deep the tree may be nested, see `MAX_DEPTH_KEY`.
+    max_depth: usize,
+}
+
+/// Index of the checkout consulted and refreshed when looking for changes.
+struct StatusIndex {
+    index: treestore::Index,
+    /// Time the run started, used to detect files modified too recently to be trusted to the index.
+    started: SystemTime,
+    /// Size from which files are indexed even if modified recently, see `TRUST_INDEX_SIZE_KEY`.
+    trust_size: Option<u64>,
+}
+
+/// Files modified within this window before the index entry is written are not indexed, as their
+/// further modifications may not change the mtime on filesystems with coarse timestamps.
+const RACY_WINDOW: Duration = Duration::from_secs(2);
+
 // Creates a vx tree from a directory, saving entities to storage on the go, using a configured
-// level of concurrency.
+// level of concurrency. Compares the directory with its counterpart from the previous vx tree
+// (if any, otherwise `Digest::NONE`) to avoid rebuilding unchanged subtrees, and takes its entries
+// from the previous vx tree instead of listing it if the folder index shows they are the same.
 fn write_filesystem_tree_to_vx_tree(
-    context: &Context,
-    db: &Db,
-    blob_db: &Db,
+    writer: &TreeWriter,
     path: &Path,
+    prev_hash: Digest,
 ) -> Result<TreeStats, TreeError> {
+    // the recursion is bounded by the depth limit, checked before anything is read
+    check_depth(writer.max_depth, path)?;
+
     // Get the absolute path to work with
-    let abs_path = context.checkout_path.join(path);
+    let abs_path = writer.context.checkout_path.join(path);
 
     // If it's a directory, process its contents
     let mut dirs = Vec::new();
     let mut files = Vec::new();
 
-    // Read directory entries
-    let mut entries = std::fs::read_dir(&abs_path)?;
+    let prev_tree = if prev_hash == Digest::NONE {
+        None
+    } else {
+        Some(treestore::get(writer.db, prev_hash)?)
+    };
+
+    // the folder is looked at before it is listed, so an entry added meanwhile changes its mtime
+    // after the one recorded
+    let metadata = std::fs::metadata(&abs_path)?;
+    let modified = metadata.modified()?;
+    let mtime = mtime_nanos(modified);
+    let ignore_digest = writer.ignore.digest(path)?;
+    let listed = match (
+        &prev_tree,
+        treestore::get_folder_entry(writer.folder_index, path)?,
+    ) {
+        (Some(tree), Some(entry)) => {
+            entry.treehash == tree.hash
+                && entry.mtime == mtime
+                && entry.size == metadata.len()
+                && entry.ignore == ignore_digest
+        }
+        _ => false,
+    };
 
-    // parse entries
-    parse_entries(&mut entries, &mut dirs, &mut files)?;
+    match prev_tree.as_ref().filter(|_| listed) {
+        Some(tree) => {
+            // already sorted by name
+            dirs.extend(tree.folders.iter().map(|folder| folder.name.clone()));
+            files.extend(tree.files.iter().map(|file| file.name.clone()));
+        }
+        None => {
+            // Read directory entries
+            let mut entries = std::fs::read_dir(&abs_path)?;
+            parse_entries(
+                &mut entries,
+                writer.ignore,
+                prev_tree.as_ref(),
+                &mut dirs,
+                &mut files,
+            )?;
+        }
+    }
+    // a folder modified within `RACY_WINDOW` may change again without its mtime changing
+    let folder_entry = writer
+        .started
+        .duration_since(modified)
+        .is_ok_and(|age| age > RACY_WINDOW)
+        .then_some(FolderEntry {
+            mtime,
+            size: metadata.len(),
+            treehash: Digest::NONE,
+            ignore: ignore_digest,
+        });
 
-    // Threshold for parallel processing - don't parallelize tiny directories
-    // Should be set at least to 2. In practice it does not seem to make much difference,
-    // most time consuming part is the IO bound file processing.
-    const PARALLEL_THRESHOLD: usize = 4;
+    // Finds the hash of the same subfolder in the previous vx tree.
+    let prev_folder_hash = |dir: &String| -> Digest {
+        prev_tree
+            .as_ref()
+            .and_then(|tree| {
+                tree.folders
+                    .binary_search_by(|folder| folder.name.cmp(dir))
+                    .ok()
+                    .map(|pos| tree.folders[pos].hash)
+            })
+            .unwrap_or(Digest::NONE)
+    };
 
     // Process directories in parallel if there are enough of them
     let folder_results: Vec<Result<(String, TreeStats), TreeError>> =
@@ -584,7 +1962,8 @@ fn write_filesystem_tree_to_vx_tree(
             dirs.par_iter()
                 .map(|dir| {
                     let dir_path = path.join(dir);
-                    let stats = write_filesystem_tree_to_vx_tree(context, db, blob_db, &dir_path)?;
+                    let stats =
+                        write_filesystem_tree_to_vx_tree(writer, &dir_path, prev_folder_hash(dir))?;
                     Ok((dir.clone(), stats))
                 })
                 .collect()
@@ -593,7 +1972,8 @@ fn write_filesystem_tree_to_vx_tree(
             dirs.iter()
                 .map(|dir| {
                     let dir_path = path.join(dir);
-                    let stats = write_filesystem_tree_to_vx_tree(context, db, blob_db, &dir_path)?;
+                    let stats =
+                        write_filesystem_tree_to_vx_tree(writer, &dir_path, prev_folder_hash(dir))?;
                     Ok((dir.clone(), stats))
                 })
                 .collect()
@@ -604,8 +1984,7 @@ fn write_filesystem_tree_to_vx_tree(
     let mut total_size: u64 = 0;
 
     for file in files.iter() {
-        let file_path = abs_path.join(file);
-        let vx_file = new_file(context, blob_db, file.clone(), &file_path)?;
+        let vx_file = write_file(writer, file.clone(), &path.join(file))?;
         total_size += vx_file.blob.size;
         vx_files.push(vx_file);
     }
@@ -630,40 +2009,219 @@ fn write_filesystem_tree_to_vx_tree(
         });
     }
 
-    // Create and store the tree
-    let tree = new_tree(
-        db,
-        vx_folders,
-        vx_files,
-        total_size,
-        total_file_count,
-        total_folder_count,
-    )?;
+    // If the folder did not change, the previous vx tree is already stored, no need to save it again
+    let stats = match prev_tree {
+        Some(prev_tree) if hash_tree(&vx_folders, &vx_files) == prev_tree.hash => TreeStats {
+            hash: prev_tree.hash,
+            size: prev_tree.size,
+            file_count: prev_tree.file_count,
+            folder_count: prev_tree.folder_count,
+        },
+        _ => {
+            // Create and store the tree
+            let tree = new_tree(
+                writer.db,
+                vx_folders,
+                vx_files,
+                total_size,
+                total_file_count,
+                total_folder_count,
+            )?;
+            TreeStats {
+                hash: tree.hash,
+                size: total_size,
+                file_count: total_file_count,
+                folder_count: total_folder_count,
+            }
+        }
+    };
+
+    // an entry that still holds is not written again
+    if let Some(entry) = folder_entry.filter(|_| !listed || stats.hash != prev_hash) {
+        let entry = FolderEntry {
+            treehash: stats.hash,
+            ..entry
+        };
+        treestore::save_folder_entry(writer.folder_index, path, &entry)?;
+    }
+
+    Ok(stats)
+}
+
+/// Creates a vx file from a file in the checkout, saving its content to the blob store.
+/// Files which did not change since they were indexed are neither read nor copied.
+/// Extended attributes are not indexed, as changing them leaves the mtime as it is.
+fn write_file(writer: &TreeWriter, name: String, path: &Path) -> Result<File, TreeError> {
+    let abs_path = writer.context.checkout_path.join(path);
+    let metadata = std::fs::symlink_metadata(&abs_path)?;
+    if metadata.file_type().is_symlink() {
+        // the target of a link is small and not converted, so it is neither indexed nor given
+        // attributes, which would be the ones of the file it points to
+        let blob = Blob::from_bytes(
+            writer.context,
+            writer.blob_db,
+            &read_link_target(&abs_path)?,
+        )
+        .map_err(|e| TreeError::from_blob(&format!("Blob error for path {:?}", path), e))?;
+        return Ok(File {
+            name,
+            blob,
+            xattrs: Vec::new(),
+            symlink: true,
+        });
+    }
+    let modified = metadata.modified()?;
+    let mtime = mtime_nanos(modified);
+    let xattrs = xattr::read(&abs_path, &writer.xattr_names)?;
+
+    let conversion = writer.attributes.resolve(path)?;
+    if let Some(entry) = treestore::get_index_entry(writer.index, path)? {
+        if is_unchanged(&entry, mtime, metadata.len(), conversion) {
+            return Ok(File {
+                name,
+                blob: Blob {
+                    contenthash: entry.contenthash,
+                    size: entry.size,
+                    kind: entry.kind,
+                },
+                xattrs,
+                symlink: false,
+            });
+        }
+    }
+
+    let mut file = new_file(writer.context, writer.blob_db, name, &abs_path, conversion)?;
+    file.xattrs = xattrs;
+
+    let entry = index_entry(
+        writer.started,
+        writer.trust_size,
+        modified,
+        metadata.len(),
+        &file.blob,
+        conversion,
+    );
+    if let Some(entry) = entry {
+        treestore::save_index_entry(writer.index, path, &entry)?;
+    }
+
+    Ok(file)
+}
 
-    Ok(TreeStats {
-        hash: tree.hash,
-        size: total_size,
-        file_count: total_file_count,
-        folder_count: total_folder_count,
+/// Makes the index entry of a file of the given mtime and size holding the content of a blob,
+/// None if the file may not be indexed: if it was modified within `RACY_WINDOW` before the run
+/// started and is below the trusted size, if its content is converted when stored, or if the kind
+/// of the blob is unknown, as such entries are never used.
+fn index_entry(
+    started: SystemTime,
+    trust_size: Option<u64>,
+    modified: SystemTime,
+    size: u64,
+    blob: &Blob,
+    conversion: Conversion,
+) -> Option<IndexEntry> {
+    let settled = started
+        .duration_since(modified)
+        .is_ok_and(|age| age > RACY_WINDOW);
+    let trusted = trust_size.is_some_and(|trust_size| size >= trust_size);
+    if !(settled || trusted)
+        || blob.size != size
+        || blob.kind == ContentKind::Unknown
+        || conversion.kind.is_some()
+    {
+        return None;
+    }
+    Some(IndexEntry {
+        mtime: mtime_nanos(modified),
+        size,
+        contenthash: blob.contenthash,
+        kind: blob.kind,
+        normalized: conversion.eol.normalizes(),
     })
 }
 
+/// Modification time of a file in nanoseconds since the Unix epoch, as stored in the index.
+fn mtime_nanos(modified: SystemTime) -> u128 {
+    modified
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or(0)
+}
+
+/// Checks whether a file with the given mtime and size still has the content of an index entry.
+/// Entries indexed before kinds were detected are refreshed by reading the file once, and so are
+/// entries hashed under a different line ending policy, files of a forced kind are never indexed
+/// as the entry would not tell the forced kind from the detected one.
+fn is_unchanged(entry: &IndexEntry, mtime: u128, size: u64, conversion: Conversion) -> bool {
+    entry.mtime == mtime
+        && entry.size == size
+        && entry.kind != ContentKind::Unknown
+        && entry.normalized == conversion.eol.normalizes()
+        && conversion.kind.is_none()
+}
+
 /// Performs the checkout operation for a specific commit.
-/// This function materializes files on the filesystem according to what's stored in the vx tree.
-fn perform_checkout(context: &Context, commit_id: CommitID) -> Result<(), TreeError> {
+/// This function materializes files on the filesystem according to what's stored in the vx tree,
+/// into an emptied checkout if `clean` is set.
+fn perform_checkout(
+    context: &Context,
+    commit_id: CommitID,
+    clean: bool,
+    progress: &Progress,
+) -> Result<(), TreeError> {
+    check_no_rebuild(context)?;
+
     // Get the commit
     let commit = Commit::get(context, commit_id)
         .map_err(|e| TreeError::Other(format!("Failed to get commit: {:?}", e)))?;
 
     // Open the tree store
     let db = treestore::open(context)?;
-    let blob_db = Blob::open(context)
-        .map_err(|e| TreeError::Other(format!("Failed to open blob store: {:?}", e)))?;
+    let blob_db =
+        Blob::open(context).map_err(|e| TreeError::from_blob("Failed to open blob store", e))?;
     // Get the root vx tree from the commit
     let root_tree = treestore::get(&db, commit.treehash)?;
 
-    // Recursively materialize the vx tree
-    write_vx_tree_to_filesystem_tree(context, &db, &blob_db, root_tree.hash)?;
+    // Record the intent before touching any file, the checkout is repeated from scratch if
+    // interrupted, since writing the tree of the target commit is idempotent.
+    treestore::save_checkout_intent(&db, &PendingCheckout { commit_id })?;
+
+    let attributes = load_tree_attributes(context, &db, &blob_db, root_tree.hash)?;
+    if clean {
+        clear_checkout(context, progress)?;
+        // every file is rewritten, so no index entry holds anymore
+        treestore::clear_index(&treestore::open_index(&db)?)?;
+        let writer = CheckoutWriter {
+            context,
+            db: &db,
+            blob_db: &blob_db,
+            attributes: &attributes,
+            progress,
+            max_depth: load_max_depth(context)?,
+        };
+        materialize_folder_without_checks(
+            &writer,
+            root_tree.hash,
+            Path::new(""),
+            &context.checkout_path,
+        )?;
+    } else {
+        // Recursively materialize the vx tree, ignored files are left in place
+        let ignore = load_ignore(context)?;
+        write_vx_tree_to_filesystem_tree(
+            context,
+            &db,
+            &blob_db,
+            &ignore,
+            &attributes,
+            progress,
+            root_tree.hash,
+        )?;
+    }
+    if progress.any_failed()? {
+        // the intent is kept, so the checkout is finished once the failures are fixed
+        return Ok(());
+    }
 
     let current = CurrentCommitSpec {
         commit_id,
@@ -677,115 +2235,1043 @@ fn perform_checkout(context: &Context, commit_id: CommitID) -> Result<(), TreeEr
         .save(context)
         .map_err(|e| TreeError::Other(format!("Failed to update current commit: {:?}", e)))?;
 
+    treestore::clear_checkout_intent(&db)?;
     Ok(())
 }
 
-/// Recursively materializes a vx tree, overwriting files if needed.
-fn write_vx_tree_to_filesystem_tree(
-    context: &Context,
-    db: &Db,
-    blob_db: &Db,
-    treehash: Digest,
-) -> Result<(), TreeError> {
-    // Pretty much a copy of traverse_tree
-    // TODO: refactor to unify the code
+/// Removes everything in the checkout except the workspace and the temporary folder of vx.
+fn clear_checkout(context: &Context, progress: &Progress) -> Result<(), TreeError> {
+    for entry in std::fs::read_dir(&context.checkout_path)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        if name == DATA_FOLDER || name == TEMP_FOLDER {
+            continue;
+        }
+        // symlinks are removed themselves rather than followed
+        let removed = if entry.file_type()?.is_dir() {
+            std::fs::remove_dir_all(entry.path())
+        } else {
+            std::fs::remove_file(entry.path())
+        };
+        progress.removed(&entry.path(), removed)?;
+    }
+    Ok(())
+}
 
-    // start with root folder's tree and traverse down
+/// Stores and settings used to write vx trees into the checkout or another directory.
+#[derive(Clone, Copy)]
+struct CheckoutWriter<'a> {
+    context: &'a Context,
+    db: &'a Db,
+    blob_db: &'a Db,
+    attributes: &'a Attributes<'a>,
+    progress: &'a Progress,
+    /// How many folders deep the tree may be nested, see `MAX_DEPTH_KEY`.
+    max_depth: usize,
+}
 
-    let mut level = 1;
+/// What a checkout did to the files of the checkout, paths are relative to the checkout root.
+#[derive(Debug, Default)]
+pub struct CheckoutReport {
+    /// Files written where there was no file.
+    pub created: Vec<PathBuf>,
+    /// Files rewritten as their content changed.
+    pub overwritten: Vec<PathBuf>,
+    /// Files and folders removed, a folder removed as a whole is listed alone.
+    pub deleted: Vec<PathBuf>,
+    /// Number of files that already had the content of the target commit.
+    pub unchanged: u64,
+    /// Files and folders that could not be written or removed, only collected by a keep-going
+    /// checkout, see `Tree::checkout_keep_going`.
+    pub failed: Vec<CheckoutFailure>,
+}
 
-    // using 32 as the predicted max depth of the file tree; it is cheap to allocate
-    let mut level_states: Vec<LevelState> = Vec::with_capacity(32);
+/// A file or folder that could not be written or removed by a checkout.
+#[derive(Debug)]
+pub struct CheckoutFailure {
+    /// Path of the file or folder relative to the checkout root.
+    pub path: PathBuf,
+    /// What went wrong.
+    pub error: TreeError,
+}
 
-    let mut current_dir = PathBuf::new();
-    let mut current_hash = treehash;
-    let mut drill = true;
+/// What happened to a file written into the checkout.
+enum Written {
+    Created,
+    Overwritten,
+    Unchanged,
+}
 
-    'vertical: while level > 0 {
-        // this loops moves up and down the file tree
+/// Files and folders written and removed while writing a vx tree, along with the failures. Either
+/// the first failure aborts the whole write, or they are collected so that the rest of the tree is
+/// written anyway.
+struct Progress {
+    /// Root of the checkout, paths are recorded relative to it.
+    root: PathBuf,
+    /// Whether failures are collected rather than aborting.
+    keep_going: bool,
+    report: Mutex<CheckoutReport>,
+}
 
-        if drill {
-            new_level(
-                context,
-                db,
-                &mut level_states,
-                level,
-                current_dir.clone(),
-                current_hash,
-            )?;
+impl Progress {
+    fn abort(context: &Context) -> Self {
+        Progress {
+            root: context.checkout_path.clone(),
+            keep_going: false,
+            report: Mutex::new(CheckoutReport::default()),
+        }
+    }
 
-            drill = false;
+    fn keep_going(context: &Context) -> Self {
+        Progress {
+            keep_going: true,
+            ..Self::abort(context)
         }
+    }
 
-        let state = &mut level_states[level - 1];
+    /// Passes the result of writing or removing a file or folder by its absolute path through,
+    /// unless failures are collected, in which case a failure is recorded instead.
+    fn check(&self, path: &Path, result: Result<(), TreeError>) -> Result<(), TreeError> {
+        match result {
+            Err(error) if self.keep_going => {
+                let path = self.relative(path);
+                self.lock()?.failed.push(CheckoutFailure { path, error });
+                Ok(())
+            }
+            result => result,
+        }
+    }
 
-        'horizontal: loop {
-            // this loops moves across directores in the same folder
+    /// Records a file written by its absolute path, see `check` for failures.
+    fn written(&self, path: &Path, result: Result<Written, TreeError>) -> Result<(), TreeError> {
+        let written = match result {
+            Ok(written) => written,
+            Err(e) => return self.check(path, Err(e)),
+        };
+        let mut report = self.lock()?;
+        match written {
+            Written::Created => report.created.push(self.relative(path)),
+            Written::Overwritten => report.overwritten.push(self.relative(path)),
+            Written::Unchanged => report.unchanged += 1,
+        }
+        Ok(())
+    }
 
-            // Process folders, compare two sorted lists
-            // equal names: advance both iters, proceed down
-            // fs < vx: added, advance fs
-            // fs > vx: deleted, advance vx
-            if state.fs_pos >= state.dirs.len() {
-                // no more dirs to process in filesystem, the remaining ones from vx are to be materialized unconditionally
-                while state.vx_pos < state.vx_tree.folders.len() {
-                    let vx_dir = &state.vx_tree.folders[state.vx_pos];
-                    let path = state.current_dir.join(&vx_dir.name);
+    /// Records a file or folder removed by its absolute path, see `check` for failures.
+    fn removed(&self, path: &Path, result: std::io::Result<()>) -> Result<(), TreeError> {
+        match result {
+            Ok(()) => {
+                self.lock()?.deleted.push(self.relative(path));
+                Ok(())
+            }
+            Err(e) => self.check(path, Err(e.into())),
+        }
+    }
 
-                    materialize_folder_without_checks(context, db, blob_db, vx_dir.hash, &path)?;
+    fn any_failed(&self) -> Result<bool, TreeError> {
+        Ok(!self.lock()?.failed.is_empty())
+    }
 
-                    state.vx_pos += 1;
-                }
+    /// Returns the report with all paths sorted.
+    fn into_report(self) -> Result<CheckoutReport, TreeError> {
+        let mut report = self
+            .report
+            .into_inner()
+            .map_err(|_| TreeError::Other("Checkout report is poisoned".to_string()))?;
+        report.created.sort();
+        report.overwritten.sort();
+        report.deleted.sort();
+        report.failed.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(report)
+    }
 
-                materialize_files(context, blob_db, &state)?;
+    fn relative(&self, path: &Path) -> PathBuf {
+        path.strip_prefix(&self.root).unwrap_or(path).to_path_buf()
+    }
 
-                // drill up
+    fn lock(&self) -> Result<std::sync::MutexGuard<'_, CheckoutReport>, TreeError> {
+        self.report
+            .lock()
+            .map_err(|_| TreeError::Other("Checkout report is poisoned".to_string()))
+    }
+}
+
+/// Refuses to compare the checkout against the current commit while a checkout is interrupted,
+/// as the files are a mix of two commits.
+fn check_no_pending_checkout(db: &Db) -> Result<(), TreeError> {
+    match treestore::get_checkout_intent(db)? {
+        Some(pending) => Err(TreeError::CheckoutInterrupted(format!(
+            "{}:{}",
+            pending.commit_id.branch, pending.commit_id.seq
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Refuses to check out another commit while a rebuild is paused on a conflict, as that would lose it.
+fn check_no_rebuild(context: &Context) -> Result<(), TreeError> {
+    let current = CurrentCommitSpec::get(context)
+        .map_err(|e| TreeError::Other(format!("Failed to get current commit: {:?}", e)))?;
+    if current.is_rebuild() {
+        return Err(TreeError::Other(format!(
+            "Rebuild of commit {} is in progress, continue or abort it first",
+            current.rebuild_seq
+        )));
+    }
+    Ok(())
+}
+
+/// Performs the checkout operation for a specific commit, merging local changes into it.
+fn perform_checkout_merge(
+    context: &Context,
+    commit_id: CommitID,
+) -> Result<Vec<Conflict>, TreeError> {
+    check_no_rebuild(context)?;
+
+    let base = Commit::get_current(context)
+        .map_err(|e| TreeError::Other(format!("Failed to get current commit: {:?}", e)))?;
+    let target = Commit::get(context, commit_id)
+        .map_err(|e| TreeError::Other(format!("Failed to get commit: {:?}", e)))?;
+
+    let db = treestore::open(context)?;
+    check_no_pending_checkout(&db)?;
+    let blob_db =
+        Blob::open(context).map_err(|e| TreeError::from_blob("Failed to open blob store", e))?;
+
+    let ignore = load_ignore(context)?;
+    // local changes are found with the attributes of the checkout, incoming files are written
+    // with the ones of the target commit
+    let local_attributes = load_attributes(context)?;
+    let attributes = load_tree_attributes(context, &db, &blob_db, target.treehash)?;
+
+    let local = expand_changes(
+        context,
+        &db,
+        &ignore,
+        &local_attributes,
+        get_changes_between_vx_tree_and_filesystem_tree(
+            context,
+            &db,
+            &ignore,
+            &local_attributes,
+            Untracked::Normal,
+            base.treehash,
+        )?,
+    )?;
+    let incoming = expand_changes(
+        context,
+        &db,
+        &ignore,
+        &local_attributes,
+        get_changes_between_vx_trees(&db, base.treehash, target.treehash)?,
+    )?;
+
+    let mut conflicts = Vec::new();
+
+    // deletions go first, so a file or a link replacing a folder, or a folder replacing them,
+    // finds the place free
+    let (deletions, writes): (Vec<_>, Vec<_>) = incoming
+        .files
+        .iter()
+        .partition(|(_, change)| matches!(change.action, ChangeAction::Deleted));
+    for (path, change) in deletions.into_iter().chain(writes) {
+        let abs_path = context.checkout_path.join(path);
+        let local_change = local.files.get(path);
+        let conversion = attributes.resolve(path)?;
+        // links of the target commit are written as links
+        let symlink = !matches!(change.action, ChangeAction::Deleted)
+            && matches!(
+                lookup_entry(&db, target.treehash, path)?,
+                Some(Entry::Symlink(_))
+            );
+        let write = |contenthash| {
+            write_blob(
+                context,
+                &blob_db,
+                conversion,
+                contenthash,
+                &abs_path,
+                symlink,
+            )
+        };
+
+        match (local_change.map(|c| &c.action), &change.action) {
+            (None, ChangeAction::Deleted) => remove_file_if_exists(&abs_path)?,
+            (None, _) => write(change.contenthash_right)?,
+            (Some(ChangeAction::Deleted), ChangeAction::Deleted) => (),
+            (Some(_), ChangeAction::Deleted) => conflicts.push(Conflict {
+                path: path.clone(),
+                kind: ConflictKind::ModifiedDeleted,
+            }),
+            (Some(ChangeAction::Deleted), _) => {
+                write(change.contenthash_right)?;
+                conflicts.push(Conflict {
+                    path: path.clone(),
+                    kind: ConflictKind::DeletedModified,
+                });
+            }
+            (Some(_), _) => {
+                if local_change.unwrap().contenthash_right == change.contenthash_right {
+                    // both sides made the same change
+                    continue;
+                }
+                if symlink || is_symlink(&abs_path) {
+                    // the targets of links are not merged line by line, the local one is kept
+                    conflicts.push(Conflict {
+                        path: path.clone(),
+                        kind: ConflictKind::Binary,
+                    });
+                } else if let Some(kind) =
+                    merge_file(context, &blob_db, conversion, change, &abs_path)?
+                {
+                    conflicts.push(Conflict {
+                        path: path.clone(),
+                        kind,
+                    });
+                }
+            }
+        }
+    }
+
+    // Files are written along with their parent folders, but empty folders have to be created.
+    for path in &incoming.added_folders {
+        std::fs::create_dir_all(context.checkout_path.join(path))?;
+    }
+
+    // Remove deleted folders unless they still contain files kept locally, deepest first.
+    let mut deleted_folders = incoming.deleted_folders;
+    deleted_folders.sort_by_key(|path| std::cmp::Reverse(path.components().count()));
+    for path in &deleted_folders {
+        let _ = std::fs::remove_dir(context.checkout_path.join(path));
+    }
+
+    let current = CurrentCommitSpec {
+        commit_id,
+        ver: target.ver,
+        rebuild_seq: CurrentCommitSpec::NO_REBUILD,
+        rebuild_ver: CurrentCommitSpec::NO_REBUILD,
+    };
+
+    current
+        .save(context)
+        .map_err(|e| TreeError::Other(format!("Failed to update current commit: {:?}", e)))?;
+
+    Ok(conflicts)
+}
+
+/// Merges local and incoming modifications of a file in place, using the original content as a base.
+/// Returns the kind of a conflict if the file could not be merged cleanly.
+fn merge_file(
+    context: &Context,
+    blob_db: &Db,
+    conversion: Conversion,
+    change: &Change,
+    abs_path: &Path,
+) -> Result<Option<ConflictKind>, TreeError> {
+    let base = if change.contenthash_left == Digest::NONE {
+        Vec::new()
+    } else {
+        read_blob(context, blob_db, change.contenthash_left)?
+    };
+    let incoming = read_blob(context, blob_db, change.contenthash_right)?;
+    let local = std::fs::read(abs_path)?;
+    // the local file is merged with the stored content as it would be stored itself
+    let local = conversion.to_stored(&local).into_owned();
+
+    let (Ok(base), Ok(local), Ok(incoming)) = (
+        String::from_utf8(base),
+        String::from_utf8(local),
+        String::from_utf8(incoming),
+    ) else {
+        // binary files can not be merged line by line, keep the local one
+        return Ok(Some(ConflictKind::Binary));
+    };
+
+    let merged = merge::merge_lines(&base, &local, &incoming);
+    std::fs::write(abs_path, conversion.to_checkout(merged.content.as_bytes()))?;
+
+    if merged.conflicts > 0 {
+        Ok(Some(ConflictKind::Content))
+    } else {
+        Ok(None)
+    }
+}
+
+fn read_blob(context: &Context, blob_db: &Db, contenthash: Digest) -> Result<Vec<u8>, TreeError> {
+    Blob::read(context, blob_db, contenthash)
+        .map_err(|e| TreeError::from_blob("Failed to read blob", e))
+}
+
+/// Checks whether the checkout version of a modified text file differs from the stored one only
+/// by whitespace at the end of lines.
+fn is_whitespace_change(
+    context: &Context,
+    blob_db: &Db,
+    change: &Change,
+) -> Result<bool, TreeError> {
+    let abs_path = context.checkout_path.join(&change.path);
+    // a link is changed by its target, which is not a whitespace change
+    if is_symlink(&abs_path) {
+        return Ok(false);
+    }
+    let stored = read_blob(context, blob_db, change.contenthash_left)?;
+    let local = std::fs::read(abs_path)?;
+
+    let (Ok(stored), Ok(local)) = (String::from_utf8(stored), String::from_utf8(local)) else {
+        return Ok(false);
+    };
+    if stored.contains('\0') || local.contains('\0') {
+        return Ok(false);
+    }
+    Ok(stored
+        .lines()
+        .map(str::trim_end)
+        .eq(local.lines().map(str::trim_end)))
+}
+
+/// Writes a file of the checkout from the blob store, or creates a symbolic link with the blob as
+/// its target, replacing a link or a file in its place.
+fn write_blob(
+    context: &Context,
+    blob_db: &Db,
+    conversion: Conversion,
+    contenthash: Digest,
+    abs_path: &Path,
+    symlink: bool,
+) -> Result<(), TreeError> {
+    check_no_link_above(context, abs_path)?;
+    if std::fs::symlink_metadata(abs_path).is_ok_and(|metadata| metadata.is_dir()) {
+        // a folder whose files were all deleted gives its place to the file
+        std::fs::remove_dir(abs_path)?;
+    } else if symlink || is_symlink(abs_path) {
+        remove_file_if_exists(abs_path)?;
+    }
+    if symlink {
+        return create_symlink(&read_blob(context, blob_db, contenthash)?, abs_path);
+    }
+    Blob::to_file(context, blob_db, contenthash, abs_path, conversion)
+        .map_err(|e| TreeError::from_blob("Failed to write file", e))
+}
+
+/// Checks that no folder between the checkout root and a path is a symbolic link, which a write
+/// of the path would follow outside of the checkout.
+fn check_no_link_above(context: &Context, abs_path: &Path) -> Result<(), TreeError> {
+    let Ok(path) = abs_path.strip_prefix(&context.checkout_path) else {
+        return Ok(());
+    };
+    let mut folder = context.checkout_path.clone();
+    for component in path.parent().into_iter().flat_map(Path::components) {
+        folder.push(component);
+        if is_symlink(&folder) {
+            return Err(TreeError::Other(format!(
+                "Refusing to write {:?} through the symbolic link {:?}",
+                path, component
+            )));
+        }
+    }
+    Ok(())
+}
+
+fn remove_file_if_exists(abs_path: &Path) -> Result<(), TreeError> {
+    match std::fs::remove_file(abs_path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+/// Checks whether a path is a symbolic link, without following it.
+fn is_symlink(abs_path: &Path) -> bool {
+    std::fs::symlink_metadata(abs_path).is_ok_and(|metadata| metadata.file_type().is_symlink())
+}
+
+/// Reads the target of a symbolic link as it is stored, the link is not followed.
+#[cfg(unix)]
+fn read_link_target(abs_path: &Path) -> Result<Vec<u8>, TreeError> {
+    use std::os::unix::ffi::OsStrExt;
+    Ok(std::fs::read_link(abs_path)?
+        .as_os_str()
+        .as_bytes()
+        .to_vec())
+}
+
+#[cfg(windows)]
+fn read_link_target(abs_path: &Path) -> Result<Vec<u8>, TreeError> {
+    // targets are stored with forward slashes, so they can be checked out on other platforms
+    let target = std::fs::read_link(abs_path)?;
+    Ok(target.to_string_lossy().replace('\\', "/").into_bytes())
+}
+
+/// Creates a symbolic link pointing to a stored target, the target does not need to exist.
+#[cfg(unix)]
+fn create_symlink(target: &[u8], abs_path: &Path) -> Result<(), TreeError> {
+    use std::os::unix::ffi::OsStrExt;
+    let target = std::ffi::OsStr::from_bytes(target);
+    if let Err(e) = std::os::unix::fs::symlink(target, abs_path) {
+        if e.kind() != std::io::ErrorKind::NotFound {
+            return Err(e.into());
+        }
+        if let Some(parent) = abs_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::os::unix::fs::symlink(target, abs_path)?;
+    }
+    Ok(())
+}
+
+#[cfg(windows)]
+fn create_symlink(target: &[u8], abs_path: &Path) -> Result<(), TreeError> {
+    let target = PathBuf::from(String::from_utf8_lossy(target).replace('/', "\\"));
+    if let Some(parent) = abs_path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    // links to folders are different from the ones to files on Windows, a link to a missing
+    // target is taken for a file one
+    let points_to_dir = abs_path
+        .parent()
+        .unwrap_or(Path::new(""))
+        .join(&target)
+        .is_dir();
+    if points_to_dir {
+        std::os::windows::fs::symlink_dir(&target, abs_path)?;
+    } else {
+        std::os::windows::fs::symlink_file(&target, abs_path)?;
+    }
+    Ok(())
+}
+
+/// Changes expanded down to individual files.
+struct FileChanges {
+    /// File changes by path.
+    files: BTreeMap<PathBuf, Change>,
+    /// Folders that were added, including the nested ones.
+    added_folders: Vec<PathBuf>,
+    /// Folders that were deleted, including the nested ones.
+    deleted_folders: Vec<PathBuf>,
+}
+
+/// Expands folder additions and deletions into changes of individual files.
+/// Added folders are looked up in the vx tree if their hash is known, otherwise on the filesystem.
+fn expand_changes(
+    context: &Context,
+    db: &Db,
+    ignore: &Ignore,
+    attributes: &Attributes,
+    changes: Vec<Change>,
+) -> Result<FileChanges, TreeError> {
+    let mut expanded = FileChanges {
+        files: BTreeMap::new(),
+        added_folders: Vec::new(),
+        deleted_folders: Vec::new(),
+    };
+
+    for change in changes {
+        match (&change.change_type, &change.action) {
+            (ChangeType::File, _) => {
+                expanded.files.insert(change.path.clone(), change);
+            }
+            (ChangeType::Folder, ChangeAction::Modified) => {
+                // folder modifications consist of changes to their content, which are listed separately
+            }
+            (ChangeType::Folder, ChangeAction::Deleted) => {
+                expand_vx_folder(
+                    db,
+                    &mut expanded,
+                    &change.path,
+                    change.contenthash_left,
+                    true,
+                )?;
+            }
+            (ChangeType::Folder, ChangeAction::Added) => {
+                if change.contenthash_right == Digest::NONE {
+                    expand_filesystem_folder(
+                        context,
+                        ignore,
+                        attributes,
+                        &mut expanded,
+                        &change.path,
+                    )?;
+                } else {
+                    expand_vx_folder(
+                        db,
+                        &mut expanded,
+                        &change.path,
+                        change.contenthash_right,
+                        false,
+                    )?;
+                }
+            }
+        }
+    }
+
+    Ok(expanded)
+}
+
+/// Lists all files of a vx folder as added or deleted.
+fn expand_vx_folder(
+    db: &Db,
+    expanded: &mut FileChanges,
+    path: &Path,
+    hash: Digest,
+    deleted: bool,
+) -> Result<(), TreeError> {
+    let tree = treestore::get(db, hash)?;
+
+    if deleted {
+        expanded.deleted_folders.push(path.to_path_buf());
+    } else {
+        expanded.added_folders.push(path.to_path_buf());
+    }
+
+    for folder in &tree.folders {
+        expand_vx_folder(db, expanded, &path.join(&folder.name), folder.hash, deleted)?;
+    }
+
+    for file in &tree.files {
+        let file_path = path.join(&file.name);
+        let (action, contenthash_left, contenthash_right) = if deleted {
+            (ChangeAction::Deleted, file.blob.contenthash, Digest::NONE)
+        } else {
+            (ChangeAction::Added, Digest::NONE, file.blob.contenthash)
+        };
+        expanded.files.insert(
+            file_path.clone(),
+            Change {
+                action,
+                path: file_path,
+                change_type: ChangeType::File,
+                contenthash_left,
+                contenthash_right,
+            },
+        );
+    }
+
+    Ok(())
+}
+
+/// Lists all files of a folder on the filesystem as added.
+fn expand_filesystem_folder(
+    context: &Context,
+    ignore: &Ignore,
+    attributes: &Attributes,
+    expanded: &mut FileChanges,
+    path: &Path,
+) -> Result<(), TreeError> {
+    let mut dirs = Vec::new();
+    let mut files = Vec::new();
+    let mut entries = std::fs::read_dir(context.checkout_path.join(path))?;
+    parse_entries(&mut entries, ignore, None, &mut dirs, &mut files)?;
+
+    expanded.added_folders.push(path.to_path_buf());
+
+    for dir in &dirs {
+        expand_filesystem_folder(context, ignore, attributes, expanded, &path.join(dir))?;
+    }
+
+    for file in &files {
+        let file_path = path.join(file);
+        let conversion = attributes.resolve(&file_path)?;
+        let contenthash = hash_checkout_file(&context.checkout_path.join(&file_path), conversion)?;
+        expanded.files.insert(
+            file_path.clone(),
+            Change {
+                action: ChangeAction::Added,
+                path: file_path,
+                change_type: ChangeType::File,
+                contenthash_left: Digest::NONE,
+                contenthash_right: contenthash,
+            },
+        );
+    }
+
+    Ok(())
+}
+
+/// Result of a three-way merge of vx trees.
+#[derive(Debug, Clone)]
+pub struct TreeMerge {
+    /// Hash of the merged vx tree.
+    pub hash: Digest,
+    /// Files that could not be merged cleanly.
+    pub conflicts: Vec<Conflict>,
+}
+
+/// Merges two modified versions of a file stored in the blob store.
+/// Returns the merged blob and the kind of a conflict, if the file could not be merged cleanly.
+fn merge_blobs(
+    context: &Context,
+    blob_db: &Db,
+    base: Option<&Blob>,
+    local: &Blob,
+    incoming: &Blob,
+) -> Result<(Blob, Option<ConflictKind>), TreeError> {
+    // known binaries are not even read
+    if [base, Some(local), Some(incoming)]
+        .iter()
+        .flatten()
+        .any(|blob| blob.kind == ContentKind::Binary)
+    {
+        return Ok((local.clone(), Some(ConflictKind::Binary)));
+    }
+
+    let base_content = match base {
+        Some(blob) => read_blob(context, blob_db, blob.contenthash)?,
+        None => Vec::new(),
+    };
+    let local_content = read_blob(context, blob_db, local.contenthash)?;
+    let incoming_content = read_blob(context, blob_db, incoming.contenthash)?;
+
+    let (Ok(base_content), Ok(local_content), Ok(incoming_content)) = (
+        String::from_utf8(base_content),
+        String::from_utf8(local_content),
+        String::from_utf8(incoming_content),
+    ) else {
+        // binary files can not be merged line by line, keep the local one
+        return Ok((local.clone(), Some(ConflictKind::Binary)));
+    };
+
+    let merged = merge::merge_lines(&base_content, &local_content, &incoming_content);
+    let blob = Blob::from_bytes(context, blob_db, merged.content.as_bytes())
+        .map_err(|e| TreeError::Other(format!("Failed to save merged blob: {:?}", e)))?;
+
+    let kind = (merged.conflicts > 0).then_some(ConflictKind::Content);
+    Ok((blob, kind))
+}
+
+/// A file or folder of a vx tree.
+#[derive(Debug, Clone)]
+pub enum Entry {
+    /// A folder, with the hash of its vx tree.
+    Folder(Digest),
+    /// A file, with the blob of its content.
+    File(Blob),
+    /// A symbolic link, with the blob of its target.
+    Symlink(Blob),
+}
+
+impl Entry {
+    fn from_file(file: File) -> Self {
+        if file.symlink {
+            Entry::Symlink(file.blob)
+        } else {
+            Entry::File(file.blob)
+        }
+    }
+}
+
+/// Iterator over the files and folders of a vx tree, see `Tree::walk`.
+pub struct TreeWalk {
+    db: Db,
+    /// Entries of the folders being walked that are yet to be yielded, the deepest one last.
+    levels: Vec<(PathBuf, std::vec::IntoIter<(String, Entry)>)>,
+    /// Folder to be loaded on the next step, the root one initially.
+    pending: Option<(PathBuf, Digest)>,
+}
+
+impl TreeWalk {
+    fn new(db: Db, treehash: Digest) -> Self {
+        TreeWalk {
+            db,
+            levels: Vec::new(),
+            pending: Some((PathBuf::new(), treehash)),
+        }
+    }
+
+    /// Loads the entries of a folder, sorted by name.
+    fn load(&self, hash: Digest) -> Result<Vec<(String, Entry)>, TreeError> {
+        let tree = treestore::get(&self.db, hash)?;
+        let mut entries: Vec<(String, Entry)> = tree
+            .folders
+            .into_iter()
+            .map(|folder| (folder.name, Entry::Folder(folder.hash)))
+            .chain(
+                tree.files
+                    .into_iter()
+                    .map(|file| (file.name.clone(), Entry::from_file(file))),
+            )
+            .collect();
+        entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+        Ok(entries)
+    }
+}
+
+impl Iterator for TreeWalk {
+    type Item = Result<(PathBuf, Entry), TreeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // a folder is only loaded once the previous step yielded it
+        if let Some((path, hash)) = self.pending.take() {
+            match self.load(hash) {
+                Ok(entries) => self.levels.push((path, entries.into_iter())),
+                Err(err) => {
+                    // the walk can not go on without the folder
+                    self.levels.clear();
+                    return Some(Err(err));
+                }
+            }
+        }
+
+        while let Some((path, entries)) = self.levels.last_mut() {
+            match entries.next() {
+                Some((name, entry)) => {
+                    let entry_path = path.join(name);
+                    if let Entry::Folder(hash) = entry {
+                        self.pending = Some((entry_path.clone(), hash));
+                    }
+                    return Some(Ok((entry_path, entry)));
+                }
+                None => {
+                    self.levels.pop();
+                }
+            }
+        }
+        None
+    }
+}
+
+/// A vx tree flattened into the list of files and folders by their path.
+#[derive(Debug, Default)]
+struct FlatTree {
+    files: BTreeMap<PathBuf, Blob>,
+    folders: BTreeSet<PathBuf>,
+    /// Paths of the files that are symbolic links.
+    symlinks: BTreeSet<PathBuf>,
+}
+
+impl FlatTree {
+    /// Loads a vx tree from the database recursively.
+    fn load(db: &Db, hash: Digest) -> Result<Self, TreeError> {
+        let mut flat = FlatTree::default();
+        for item in TreeWalk::new(db.clone(), hash) {
+            match item? {
+                (path, Entry::Folder(_)) => {
+                    flat.folders.insert(path);
+                }
+                (path, Entry::File(blob)) => {
+                    flat.files.insert(path, blob);
+                }
+                (path, Entry::Symlink(blob)) => {
+                    flat.symlinks.insert(path.clone());
+                    flat.files.insert(path, blob);
+                }
+            }
+        }
+        Ok(flat)
+    }
+
+    /// Saves the flattened tree as vx trees to the database and returns the hash of the root.
+    fn save(&self, db: &Db) -> Result<Digest, TreeError> {
+        let mut root = FlatNode::default();
+        for path in &self.folders {
+            root.folder(path);
+        }
+        for (path, blob) in &self.files {
+            let parent = path.parent().unwrap_or(Path::new(""));
+            let name = path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            let node = root.folder(parent);
+            if self.symlinks.contains(path) {
+                node.symlinks.insert(name.clone());
+            }
+            node.files.insert(name, blob.clone());
+        }
+        Ok(root.save(db)?.hash)
+    }
+}
+
+/// A folder of a flattened tree being converted back into vx trees.
+#[derive(Debug, Default)]
+struct FlatNode {
+    folders: BTreeMap<String, FlatNode>,
+    files: BTreeMap<String, Blob>,
+    /// Names of the files that are symbolic links.
+    symlinks: BTreeSet<String>,
+}
+
+impl FlatNode {
+    /// Returns a nested folder by its path, creating it if needed.
+    fn folder(&mut self, path: &Path) -> &mut FlatNode {
+        let mut node = self;
+        for component in path.components() {
+            let name = component.as_os_str().to_string_lossy().into_owned();
+            node = node.folders.entry(name).or_default();
+        }
+        node
+    }
+
+    fn save(&self, db: &Db) -> Result<TreeStats, TreeError> {
+        let mut folders = Vec::with_capacity(self.folders.len());
+        let mut size = 0;
+        let mut file_count = self.files.len() as u64;
+        let mut folder_count = self.folders.len() as u64;
+
+        for (name, node) in &self.folders {
+            let stats = node.save(db)?;
+            size += stats.size;
+            file_count += stats.file_count;
+            folder_count += stats.folder_count;
+            folders.push(Folder {
+                name: name.clone(),
+                hash: stats.hash,
+            });
+        }
+
+        let files: Vec<File> = self
+            .files
+            .iter()
+            .map(|(name, blob)| File {
+                name: name.clone(),
+                blob: blob.clone(),
+                xattrs: Vec::new(),
+                symlink: self.symlinks.contains(name),
+            })
+            .collect();
+        size += files.iter().map(|file| file.blob.size).sum::<u64>();
+
+        let tree = new_tree(db, folders, files, size, file_count, folder_count)?;
+        Ok(TreeStats {
+            hash: tree.hash,
+            size,
+            file_count,
+            folder_count,
+        })
+    }
+}
+
+/// Recursively materializes a vx tree, overwriting files if needed.
+fn write_vx_tree_to_filesystem_tree(
+    context: &Context,
+    db: &Db,
+    blob_db: &Db,
+    ignore: &Ignore,
+    attributes: &Attributes,
+    progress: &Progress,
+    treehash: Digest,
+) -> Result<(), TreeError> {
+    // Pretty much a copy of traverse_tree
+    // TODO: refactor to unify the code
+
+    let limits = WalkLimits::load(context)?;
+    let max_depth = limits.max_depth;
+    let writer = CheckoutWriter {
+        context,
+        db,
+        blob_db,
+        attributes,
+        progress,
+        max_depth,
+    };
+
+    // start with root folder's tree and traverse down
+
+    let mut level = 1;
+
+    // using 32 as the predicted max depth of the file tree; it is cheap to allocate
+    let mut level_states: Vec<LevelState> = Vec::with_capacity(32);
+
+    let mut current_dir = PathBuf::new();
+    let mut current_hash = treehash;
+    let mut drill = true;
+
+    'vertical: while level > 0 {
+        // this loops moves up and down the file tree
+
+        if drill {
+            let state = new_level(
+                context,
+                db,
+                ignore,
+                &limits.lister,
+                current_dir.clone(),
+                current_hash,
+            )?;
+            // states left below the new level belong to folders walked before, drop their listings
+            level_states.truncate(level - 1);
+            level_states.push(state);
+
+            drill = false;
+        }
+
+        let state = &mut level_states[level - 1];
+
+        'horizontal: loop {
+            // this loops moves across directores in the same folder
+
+            // Process folders, compare two sorted lists
+            // equal names: advance both iters, proceed down
+            // fs < vx: added, advance fs
+            // fs > vx: deleted, advance vx
+            let Some(fs_name) = state.dirs.peek() else {
+                // no more dirs to process in filesystem, the remaining ones from vx are to be materialized unconditionally
+                let materialize_folder = |vx_dir: &Folder| {
+                    let path = state.current_dir.join(&vx_dir.name);
+                    let abs_path = context.checkout_path.join(&path);
+                    materialize_folder_without_checks(&writer, vx_dir.hash, &path, &abs_path)
+                };
+                let remaining = &state.vx_tree.folders[state.vx_pos..];
+                if remaining.len() >= PARALLEL_THRESHOLD {
+                    remaining.par_iter().try_for_each(materialize_folder)?;
+                } else {
+                    remaining.iter().try_for_each(materialize_folder)?;
+                }
+                state.vx_pos = state.vx_tree.folders.len();
+
+                materialize_files(context, blob_db, attributes, progress, state)?;
+
+                // drill up
                 level -= 1;
                 continue 'vertical;
-            }
+            };
 
             if state.vx_pos >= state.vx_tree.folders.len() {
                 // no more folder to process in vx, the remaining ones from fs should be removed
-                while state.fs_pos < state.dirs.len() {
-                    let path = state.current_dir.join(&state.dirs[state.fs_pos]);
-                    std::fs::remove_dir_all(&path)?;
-                    state.fs_pos += 1;
+                while let Some(name) = state.dirs.peek() {
+                    let path = context.checkout_path.join(&state.current_dir).join(name);
+                    progress.removed(&path, std::fs::remove_dir_all(&path))?;
+                    state.dirs.advance()?;
                 }
 
-                materialize_files(context, blob_db, &state)?;
+                materialize_files(context, blob_db, attributes, progress, state)?;
 
                 // drill up
                 level -= 1;
                 continue 'vertical;
             }
 
-            let fs_name = &state.dirs[state.fs_pos];
             let vx_dir = &state.vx_tree.folders[state.vx_pos];
 
             match fs_name.cmp(&vx_dir.name) {
                 Ordering::Equal => {
-                    // equal names: advance both iters, drill down
-                    state.fs_pos += 1;
-                    state.vx_pos += 1;
-
                     // drill down the file tree by breaking into outer loop
                     // keep the current state to return to it later
                     level += 1;
                     current_dir = state.current_dir.join(fs_name);
                     current_hash = vx_dir.hash;
+
+                    // equal names: advance both iters, drill down
+                    state.dirs.advance()?;
+                    state.vx_pos += 1;
+                    check_depth(max_depth, &current_dir)?;
                     drill = true;
                     continue 'vertical;
                 }
                 Ordering::Less => {
                     // fs < vx: added, advance fs
-                    std::fs::remove_dir_all(state.current_dir.join(fs_name))?;
-                    state.fs_pos += 1;
+                    let path = context.checkout_path.join(&state.current_dir).join(fs_name);
+                    progress.removed(&path, std::fs::remove_dir_all(&path))?;
+                    state.dirs.advance()?;
                     continue 'horizontal;
                 }
                 Ordering::Greater => {
                     // fs > vx: deleted, advance vx
                     let path = state.current_dir.join(&vx_dir.name);
-                    materialize_folder_without_checks(context, db, blob_db, vx_dir.hash, &path)?;
+                    materialize_folder_without_checks(
+                        &writer,
+                        vx_dir.hash,
+                        &path,
+                        &context.checkout_path.join(&path),
+                    )?;
                     state.vx_pos += 1;
                     continue 'horizontal;
                 }
@@ -796,162 +3282,386 @@ fn write_vx_tree_to_filesystem_tree(
     Ok(())
 }
 
-fn materialize_files(context: &Context, blob_db: &Db, state: &LevelState) -> Result<(), TreeError> {
+fn materialize_files(
+    context: &Context,
+    blob_db: &Db,
+    attributes: &Attributes,
+    progress: &Progress,
+    state: &mut LevelState,
+) -> Result<(), TreeError> {
     // pretty much a copy of process_files()
     // TODO: refactor to unify the code
 
-    let fs_files = &state.files;
+    let fs_files = &mut state.files;
     let vx_files = &state.vx_tree.files;
+    let abs_dir = context.checkout_path.join(&state.current_dir);
 
-    let mut fs_pos = 0;
     let mut vx_pos = 0;
 
+    // Files to be removed are removed right away, files to be written are collected to be written
+    // in parallel afterwards.
+    let mut writes = Vec::new();
+
     // very much a copy of folder processing routine
     // we do not want to unify because of performance
     loop {
-        if fs_pos >= fs_files.len() {
+        let Some(fs_name) = fs_files.peek() else {
             // no more files to process in filesystem, the remaining ones from vx are deleted from checkout
             while vx_pos < vx_files.len() {
                 let vx_file = &vx_files[vx_pos];
-                let path = state.current_dir.join(&vx_file.name);
-
-                Blob::to_file(context, blob_db, vx_file.blob.contenthash, &path)
-                    .map_err(|e| TreeError::Other(format!("Failed to write file: {:?}", e)))?;
+                writes.push(FileWrite {
+                    contenthash: vx_file.blob.contenthash,
+                    path: abs_dir.join(&vx_file.name),
+                    conversion: attributes.resolve(&state.current_dir.join(&vx_file.name))?,
+                    check: false,
+                    xattrs: vx_file.xattrs.clone(),
+                    symlink: vx_file.symlink,
+                });
 
                 vx_pos += 1;
             }
             break;
-        }
+        };
 
         if vx_pos >= vx_files.len() {
             // no more files to process in vx, the remaining ones from fs are added to checkout
-            while fs_pos < fs_files.len() {
-                let fs_file_name = &fs_files[fs_pos];
-                let fs_file_path = state.current_dir.join(fs_file_name);
-
+            while let Some(fs_name) = fs_files.peek() {
                 // Delete the file from the filesystem
-                std::fs::remove_file(&fs_file_path)?;
+                remove_file(progress, &abs_dir.join(fs_name))?;
 
-                fs_pos += 1;
+                fs_files.advance()?;
             }
             break;
         }
 
-        let fs_name = &fs_files[fs_pos];
         let vx_name = &vx_files[vx_pos].name;
 
         match fs_name.cmp(vx_name) {
             Ordering::Equal => {
-                // equal names: advance both iters and check file contents
-                let fs_file_name = &fs_files[fs_pos];
-                let fs_file_path = state.current_dir.join(fs_file_name);
-
-                // Compute hash for the filesystem file
-                let (fs_hash, _) =
-                    Digest::compute_hash(&context.checkout_path.join(&fs_file_path))?;
-
-                // Get hash from the VX state
-                let vx_hash = vx_files[vx_pos].blob.contenthash;
-
-                // If hashes don't match, file has changed
-                if fs_hash != vx_hash {
-                    // only copy if files are different, this might be slow but prevents recycling
-                    // inodes used by external file watchers
-                    Blob::to_file(
-                        context,
-                        blob_db,
-                        vx_files[vx_pos].blob.contenthash,
-                        &fs_file_path,
-                    )
-                    .map_err(|e| TreeError::Other(format!("Failed to write file: {:?}", e)))?;
-                }
+                // equal names: advance both iters, the file contents are checked when written
+                writes.push(FileWrite {
+                    contenthash: vx_files[vx_pos].blob.contenthash,
+                    path: abs_dir.join(fs_name),
+                    conversion: attributes.resolve(&state.current_dir.join(fs_name))?,
+                    check: true,
+                    xattrs: vx_files[vx_pos].xattrs.clone(),
+                    symlink: vx_files[vx_pos].symlink,
+                });
 
-                fs_pos += 1;
+                fs_files.advance()?;
                 vx_pos += 1;
             }
             Ordering::Less => {
                 // fs < vx: added, advance fs
-                let fs_file_path = state.current_dir.join(fs_name);
-
                 // Delete the file from the filesystem
-                std::fs::remove_file(&fs_file_path)?;
+                remove_file(progress, &abs_dir.join(fs_name))?;
 
-                fs_pos += 1;
+                fs_files.advance()?;
             }
             Ordering::Greater => {
                 // fs > vx: deleted, advance vx
-                let fs_file_path = state.current_dir.join(vx_name);
-                Blob::to_file(
-                    context,
-                    blob_db,
-                    vx_files[vx_pos].blob.contenthash,
-                    &fs_file_path,
-                )
-                .map_err(|e| TreeError::Other(format!("Failed to write file: {:?}", e)))?;
+                writes.push(FileWrite {
+                    contenthash: vx_files[vx_pos].blob.contenthash,
+                    path: abs_dir.join(vx_name),
+                    conversion: attributes.resolve(&state.current_dir.join(vx_name))?,
+                    check: false,
+                    xattrs: vx_files[vx_pos].xattrs.clone(),
+                    symlink: vx_files[vx_pos].symlink,
+                });
 
                 vx_pos += 1;
             }
         }
     }
 
-    Ok(())
+    write_files(context, blob_db, progress, &writes)
 }
 
-/// Materializes a folder without checking if it exists.
-/// This function is used when we know the folder doesn't exist and needs to be created.
+fn remove_file(progress: &Progress, path: &Path) -> Result<(), TreeError> {
+    // a file or a link in place of a folder is already replaced by the folder
+    if std::fs::symlink_metadata(path).is_ok_and(|metadata| metadata.is_dir()) {
+        return Ok(());
+    }
+    progress.removed(path, std::fs::remove_file(path))
+}
+
+/// Writes a folder of a vx tree to a directory, `path` is the path of the folder in the vx tree
+/// and `abs_path` the one of the directory.
 fn materialize_folder_without_checks(
-    context: &Context,
-    db: &Db,
-    blob_db: &Db,
+    writer: &CheckoutWriter,
     hash: Digest,
+    path: &Path,
     abs_path: &Path,
 ) -> Result<(), TreeError> {
-    std::fs::create_dir_all(&abs_path)?;
-
-    // Get the tree for this folder
-    let tree = treestore::get(db, hash)?;
+    // Folders are written level by level instead of recursively, so the stack does not grow with
+    // the depth of the tree. The folders of a level are written in parallel if there are enough
+    // of them, and only one level of trees is loaded at a time by every worker.
+    let mut level = vec![FolderWrite {
+        hash,
+        path: path.to_path_buf(),
+        abs_path: abs_path.to_path_buf(),
+    }];
+    while !level.is_empty() {
+        let write_folder = |folder: &FolderWrite| write_folder_files(writer, folder);
+        let subfolders: Vec<Vec<FolderWrite>> = if level.len() >= PARALLEL_THRESHOLD {
+            level
+                .par_iter()
+                .map(write_folder)
+                .collect::<Result<_, _>>()?
+        } else {
+            level.iter().map(write_folder).collect::<Result<_, _>>()?
+        };
+        level = subfolders.into_iter().flatten().collect();
+    }
+    Ok(())
+}
 
-    // Create all subfolders
-    for folder in &tree.folders {
-        let folder_path = abs_path.join(&folder.name);
-        materialize_folder_without_checks(context, db, blob_db, folder.hash, &folder_path)?;
+/// Creates the directory of a folder of a vx tree and writes its files, returns its subfolders to
+/// be written next.
+fn write_folder_files(
+    writer: &CheckoutWriter,
+    folder: &FolderWrite,
+) -> Result<Vec<FolderWrite>, TreeError> {
+    let CheckoutWriter {
+        context,
+        db,
+        blob_db,
+        attributes,
+        progress,
+        max_depth,
+    } = *writer;
+    let FolderWrite {
+        hash,
+        path,
+        abs_path,
+    } = folder;
+    check_depth(max_depth, path)?;
+    // a file or a link in place of the folder is removed first, links are never followed so
+    // nothing is written outside of the checkout
+    let replaced = std::fs::symlink_metadata(abs_path).is_ok_and(|metadata| !metadata.is_dir());
+    if replaced {
+        if let Err(e) = std::fs::remove_file(abs_path) {
+            progress.check(abs_path, Err(e.into()))?;
+            return Ok(Vec::new());
+        }
+        progress.removed(abs_path, Ok(()))?;
+    }
+    if let Err(e) = std::fs::create_dir_all(abs_path) {
+        // nothing below a folder that can not be created can be written
+        progress.check(abs_path, Err(e.into()))?;
+        return Ok(Vec::new());
     }
 
+    // Get the tree for this folder
+    let tree = treestore::get(db, *hash)?;
+
     // Create all files
-    for file in &tree.files {
-        let file_path = abs_path.join(&file.name);
-        Blob::to_file(context, blob_db, file.blob.contenthash, &file_path)
-            .map_err(|e| TreeError::Other(format!("Failed to write file: {:?}", e)))?;
+    let writes = tree
+        .files
+        .iter()
+        .map(|file| {
+            Ok(FileWrite {
+                contenthash: file.blob.contenthash,
+                path: abs_path.join(&file.name),
+                conversion: attributes.resolve(&path.join(&file.name))?,
+                check: false,
+                xattrs: file.xattrs.clone(),
+                symlink: file.symlink,
+            })
+        })
+        .collect::<Result<Vec<_>, TreeError>>()?;
+    write_files(context, blob_db, progress, &writes)?;
+
+    Ok(tree
+        .folders
+        .iter()
+        .map(|subfolder| FolderWrite {
+            hash: subfolder.hash,
+            path: path.join(&subfolder.name),
+            abs_path: abs_path.join(&subfolder.name),
+        })
+        .collect())
+}
+
+/// A folder to be written to a directory from the vx tree.
+struct FolderWrite {
+    /// Hash of the folder's content.
+    hash: Digest,
+    /// Path of the folder in the vx tree.
+    path: PathBuf,
+    /// Absolute path to the directory.
+    abs_path: PathBuf,
+}
+
+/// A file to be written to the checkout from the blob store.
+struct FileWrite {
+    /// Hash of the file's content.
+    contenthash: Digest,
+    /// Absolute path to the file.
+    path: PathBuf,
+    /// Conversion of the file's content, resolved for its path in the vx tree.
+    conversion: Conversion,
+    /// Whether the file may already exist with the same content, in which case it is left untouched.
+    check: bool,
+    /// Extended attributes to set on the file, whether its content was written or not.
+    xattrs: Vec<Xattr>,
+    /// Whether the file is a symbolic link to be created with the content as its target.
+    symlink: bool,
+}
+
+/// Minimal number of entries in a folder to process them in parallel, tiny folders are not worth it.
+/// Should be set at least to 2. In practice it does not seem to make much difference,
+/// most time consuming part is the IO bound file processing.
+const PARALLEL_THRESHOLD: usize = 4;
+
+/// Writes files to the checkout, in parallel on the rayon pool if there are enough of them.
+/// Every destination path is listed once, which keeps a single writer per destination file as
+/// required by `Blob::to_file`.
+fn write_files(
+    context: &Context,
+    blob_db: &Db,
+    progress: &Progress,
+    writes: &[FileWrite],
+) -> Result<(), TreeError> {
+    let write_file = |write: &FileWrite| -> Result<Written, TreeError> {
+        if write.symlink {
+            return write_symlink(context, blob_db, write);
+        }
+        // a link in place of the file is replaced rather than written through
+        if write.check && is_symlink(&write.path) {
+            std::fs::remove_file(&write.path)?;
+            Blob::to_file(
+                context,
+                blob_db,
+                write.contenthash,
+                &write.path,
+                write.conversion,
+            )
+            .map_err(|e| TreeError::from_blob("Failed to write file", e))?;
+            xattr::write(&write.path, &write.xattrs)?;
+            return Ok(Written::Overwritten);
+        }
+        // only copy if files are different, this might be slow but prevents recycling inodes used
+        // by external file watchers
+        let unchanged =
+            write.check && Blob::hash_file(&write.path, write.conversion)? == write.contenthash;
+        if !unchanged {
+            Blob::to_file(
+                context,
+                blob_db,
+                write.contenthash,
+                &write.path,
+                write.conversion,
+            )
+            .map_err(|e| TreeError::from_blob("Failed to write file", e))?;
+        }
+        xattr::write(&write.path, &write.xattrs)?;
+        Ok(match (unchanged, write.check) {
+            (true, _) => Written::Unchanged,
+            (false, true) => Written::Overwritten,
+            (false, false) => Written::Created,
+        })
+    };
+
+    let write_file = |write: &FileWrite| progress.written(&write.path, write_file(write));
+    if writes.len() >= PARALLEL_THRESHOLD {
+        writes.par_iter().try_for_each(write_file)
+    } else {
+        writes.iter().try_for_each(write_file)
     }
+}
 
-    Ok(())
+/// Creates a symbolic link of the checkout, replacing whatever file is in its place unless it is
+/// a link to the same target already.
+fn write_symlink(context: &Context, blob_db: &Db, write: &FileWrite) -> Result<Written, TreeError> {
+    let target = read_blob(context, blob_db, write.contenthash)?;
+    let existing = write.check && std::fs::symlink_metadata(&write.path).is_ok();
+    if existing {
+        if is_symlink(&write.path) && read_link_target(&write.path)? == target {
+            return Ok(Written::Unchanged);
+        }
+        std::fs::remove_file(&write.path)?;
+    }
+    create_symlink(&target, &write.path)?;
+    Ok(if existing {
+        Written::Overwritten
+    } else {
+        Written::Created
+    })
 }
 
-/// Gets changes between two vx trees.
-/// This function compares two vx trees recursively and returns a list of changes between them.
 fn get_changes_between_vx_trees(
-    context: &Context,
     db: &Db,
     tree1_hash: Digest,
     tree2_hash: Digest,
+) -> Result<Vec<Change>, TreeError> {
+    get_filtered_changes_between_vx_trees(db, tree1_hash, tree2_hash, &PathFilter { paths: &[] })
+}
+
+/// Compares two vx trees within the paths of the filter, subtrees outside of it are not loaded.
+fn get_filtered_changes_between_vx_trees(
+    db: &Db,
+    tree1_hash: Digest,
+    tree2_hash: Digest,
+    filter: &PathFilter,
 ) -> Result<Vec<Change>, TreeError> {
     // If vx trees are identical, return empty changes
     if tree1_hash == tree2_hash {
         return Ok(Vec::new());
     }
 
-    let mut changes = Vec::new();
+    let mut changes = ChangeSink {
+        changes: Vec::new(),
+        filter,
+    };
 
     // Start the recursive comparison from the root path
     compare_vx_trees_recursively(db, &mut changes, &PathBuf::new(), tree1_hash, tree2_hash)?;
 
-    Ok(changes)
+    Ok(changes.changes)
+}
+
+/// Restricts a walk over vx trees to the given paths and everything below them.
+/// An empty filter includes everything.
+struct PathFilter<'a> {
+    /// Paths relative to the checkout root.
+    paths: &'a [PathBuf],
+}
+
+impl PathFilter<'_> {
+    /// Checks whether the path is within one of the filter paths.
+    fn includes(&self, path: &Path) -> bool {
+        self.paths.is_empty() || self.paths.iter().any(|filter| path.starts_with(filter))
+    }
+
+    /// Checks whether the path or anything below it is included, i.e. whether a walk has to enter it.
+    fn reaches(&self, path: &Path) -> bool {
+        self.includes(path) || self.paths.iter().any(|filter| filter.starts_with(path))
+    }
+}
+
+/// Collects changes within a path filter.
+struct ChangeSink<'a> {
+    changes: Vec<Change>,
+    filter: &'a PathFilter<'a>,
+}
+
+impl ChangeSink<'_> {
+    fn push(&mut self, change: Change) {
+        // A folder added or deleted as a whole is reported even if the filter points inside it
+        let whole_folder = matches!(change.change_type, ChangeType::Folder)
+            && !matches!(change.action, ChangeAction::Modified);
+        if self.filter.includes(&change.path) || (whole_folder && self.filter.reaches(&change.path))
+        {
+            self.changes.push(change);
+        }
+    }
 }
 
 /// Recursively compares two vx trees and collects the changes between them.
 fn compare_vx_trees_recursively(
     db: &Db,
-    changes: &mut Vec<Change>,
+    changes: &mut ChangeSink,
     path: &Path,
     hash1: Digest,
     hash2: Digest,
@@ -979,8 +3689,16 @@ fn compare_vx_trees_recursively(
     while let (Some(folder1), Some(folder2)) = (iter1.peek(), iter2.peek()) {
         match folder1.name.cmp(&folder2.name) {
             Ordering::Equal => {
-                if folder1.hash != folder2.hash {
-                    compare_vx_trees_recursively(db, changes, path, folder1.hash, folder2.hash)?;
+                let folder_path = path.join(&folder1.name);
+                // subtrees outside of the filter are never loaded
+                if folder1.hash != folder2.hash && changes.filter.reaches(&folder_path) {
+                    compare_vx_trees_recursively(
+                        db,
+                        changes,
+                        &folder_path,
+                        folder1.hash,
+                        folder2.hash,
+                    )?;
                 }
                 iter1.next();
                 iter2.next();
@@ -1011,7 +3729,7 @@ fn compare_vx_trees_recursively(
     }
 
     // Handle remaining folders in tree1 (all deleted)
-    while let Some(folder1) = iter1.next() {
+    for folder1 in iter1 {
         changes.push(Change {
             action: ChangeAction::Deleted,
             path: path.join(&folder1.name),
@@ -1022,7 +3740,7 @@ fn compare_vx_trees_recursively(
     }
 
     // Handle remaining folders in tree2 (all added)
-    while let Some(folder2) = iter2.next() {
+    for folder2 in iter2 {
         changes.push(Change {
             action: ChangeAction::Added,
             path: path.join(&folder2.name),
@@ -1039,7 +3757,9 @@ fn compare_vx_trees_recursively(
     while let (Some(file1), Some(file2)) = (iter1.peek(), iter2.peek()) {
         match file1.name.cmp(&file2.name) {
             Ordering::Equal => {
-                if file1.blob.contenthash != file2.blob.contenthash {
+                if file1.blob.contenthash != file2.blob.contenthash
+                    || file1.symlink != file2.symlink
+                {
                     changes.push(Change {
                         action: ChangeAction::Modified,
                         path: path.join(&file1.name),
@@ -1077,7 +3797,7 @@ fn compare_vx_trees_recursively(
     }
 
     // Handle remaining files in tree1 (all deleted)
-    while let Some(file1) = iter1.next() {
+    for file1 in iter1 {
         changes.push(Change {
             action: ChangeAction::Deleted,
             path: path.join(&file1.name),
@@ -1088,7 +3808,7 @@ fn compare_vx_trees_recursively(
     }
 
     // Handle remaining files in tree2 (all added)
-    while let Some(file2) = iter2.next() {
+    for file2 in iter2 {
         changes.push(Change {
             action: ChangeAction::Added,
             path: path.join(&file2.name),
@@ -0,0 +1,151 @@
+use crate::context::Context;
+use crate::core::branch::Branch;
+use crate::core::commit::{Commit, CommitOutcome};
+use crate::core::repo::{Repo, RepoStatus};
+use crate::core::tree::{Change, CheckoutReport, Tree};
+use crate::storage::branch::BranchError;
+use crate::storage::commit::CommitError;
+use crate::storage::repo::RepoError;
+use crate::storage::tree::TreeError;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// Represents errors returned by a `Workspace`.
+#[derive(Error, Debug)]
+pub enum WorkspaceError {
+    #[error("IO error: {0}")]
+    IoError(#[from] std::io::Error),
+
+    #[error("Repository error: {0}")]
+    Repo(#[from] RepoError),
+
+    #[error("Branch error: {0}")]
+    Branch(#[from] BranchError),
+
+    #[error("Commit error: {0}")]
+    Commit(#[from] CommitError),
+
+    #[error("Tree error: {0}")]
+    Tree(#[from] TreeError),
+
+    #[error("Changes outside of the committed paths: {0:?}")]
+    OutsidePaths(Vec<PathBuf>),
+}
+
+/// Handle of a repository checkout for programs embedding vx, e.g. a GUI or a server.
+/// It wraps the workflows of the library behind methods returning structured results, so callers
+/// do not need to deal with contexts or the storage layer.
+#[derive(Debug, Clone)]
+pub struct Workspace {
+    context: Context,
+}
+
+impl Workspace {
+    /// Opens the repository the given directory belongs to, searching upwards for its `.vx` folder.
+    /// Workflows interrupted by a failure in a previous run are completed first.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, WorkspaceError> {
+        let context = Context::open(path.as_ref())?;
+        Ok(Workspace { context })
+    }
+
+    /// Creates a new repository in a folder named after it in the current directory.
+    pub fn create(name: &str, metadata: HashMap<String, String>) -> Result<Self, WorkspaceError> {
+        let (_, context) = Repo::new(name.to_string(), metadata)?;
+        Ok(Workspace { context })
+    }
+
+    /// Creates a throwaway repository, removed once the handle and all its clones are dropped.
+    pub fn create_temporary(
+        name: &str,
+        metadata: HashMap<String, String>,
+    ) -> Result<Self, WorkspaceError> {
+        let (_, context) = Repo::new_temporary(name.to_string(), metadata)?;
+        Ok(Workspace { context })
+    }
+
+    /// Context of the repository, for the lower level APIs of the library.
+    pub fn context(&self) -> &Context {
+        &self.context
+    }
+
+    /// Root folder of the checkout.
+    pub fn path(&self) -> &Path {
+        &self.context.checkout_path
+    }
+
+    /// Changes of the checkout since the current commit.
+    pub fn status(&self) -> Result<Vec<Change>, WorkspaceError> {
+        Ok(Tree::get_changed_files(&self.context)?)
+    }
+
+    /// Position of the checkout in its branch, along with a paused rebuild if there is one.
+    pub fn repo_status(&self) -> Result<RepoStatus, WorkspaceError> {
+        Ok(Repo::status(&self.context)?)
+    }
+
+    /// Commits the checkout on top of the current commit.
+    /// A commit always captures the whole checkout, so if paths relative to the checkout root are
+    /// given, the commit is refused when anything outside of them has changed.
+    pub fn commit(
+        &self,
+        message: &str,
+        paths: &[PathBuf],
+    ) -> Result<CommitOutcome, WorkspaceError> {
+        if !paths.is_empty() {
+            let outside: Vec<PathBuf> = self
+                .status()?
+                .into_iter()
+                .map(|change| change.path)
+                .filter(|path| !paths.iter().any(|allowed| path.starts_with(allowed)))
+                .collect();
+            if !outside.is_empty() {
+                return Err(WorkspaceError::OutsidePaths(outside));
+            }
+        }
+        Ok(Commit::new(
+            &self.context,
+            message.to_string(),
+            None,
+            false,
+            false,
+        )?)
+    }
+
+    /// Checks out a commit, see `Commit::get_by_spec` for the format of the specification.
+    /// Returns what was done to the files of the checkout.
+    pub fn checkout(&self, spec: &str) -> Result<CheckoutReport, WorkspaceError> {
+        Ok(Tree::checkout(&self.context, spec)?)
+    }
+
+    /// Lists all branches of the repository.
+    pub fn branches(&self) -> Result<Vec<Branch>, WorkspaceError> {
+        Ok(Branch::list(&self.context)?)
+    }
+
+    /// Retrieves the current commit.
+    pub fn current(&self) -> Result<Commit, WorkspaceError> {
+        Ok(Commit::get_current(&self.context)?)
+    }
+
+    /// Lists commits of the current branch, sorted by sequence number.
+    pub fn log(&self) -> Result<Vec<Commit>, WorkspaceError> {
+        Ok(Commit::list(&self.context)?)
+    }
+
+    /// Lists changes between two commits, or between a commit and the current one if `to` is not
+    /// provided.
+    pub fn diff(&self, from: &str, to: Option<&str>) -> Result<Vec<Change>, WorkspaceError> {
+        let from = Commit::get_by_spec(&self.context, from)?;
+        let to = match to {
+            Some(spec) => Commit::get_by_spec(&self.context, spec)?,
+            None => self.current()?,
+        };
+        Ok(Tree::get_diff(
+            &self.context,
+            from.treehash,
+            to.treehash,
+            &[],
+        )?)
+    }
+}
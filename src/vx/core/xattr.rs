@@ -0,0 +1,186 @@
+use crate::storage::repo::RepoError;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+
+/// Key of the repository metadata entry listing the extended attributes recorded with files,
+/// separated by commas, e.g. `user.xdg.origin.url,security.selinux`.
+/// Attributes are platform specific, so none are recorded by default.
+pub const XATTR_CAPTURE_KEY: &str = "xattr.capture";
+
+/// An extended attribute of a file, recorded along with the file in a vx tree.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Xattr {
+    /// Name of the attribute including its namespace, e.g. `user.mime_type`.
+    pub name: String,
+    /// Raw value of the attribute.
+    pub value: Vec<u8>,
+}
+
+/// Reads the names of the extended attributes to record from repository metadata, sorted and
+/// without duplicates, empty if none are.
+pub fn capture_names(metadata: &HashMap<String, String>) -> Result<Vec<String>, RepoError> {
+    let Some(value) = metadata.get(XATTR_CAPTURE_KEY) else {
+        return Ok(Vec::new());
+    };
+
+    let mut names = Vec::new();
+    for name in value
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+    {
+        if name.contains('\0') {
+            return Err(RepoError::Other(format!(
+                "Invalid attribute name '{}' in {}",
+                name.escape_default(),
+                XATTR_CAPTURE_KEY
+            )));
+        }
+        names.push(name.to_string());
+    }
+    names.sort();
+    names.dedup();
+    Ok(names)
+}
+
+/// Reads the given extended attributes of a file, sorted by name. Attributes the file does not
+/// have are skipped, and so are all of them on platforms and filesystems without support.
+pub(crate) fn read(path: &Path, names: &[String]) -> io::Result<Vec<Xattr>> {
+    let mut xattrs = Vec::new();
+    for name in names {
+        if let Some(value) = sys::get(path, name)? {
+            xattrs.push(Xattr {
+                name: name.clone(),
+                value,
+            });
+        }
+    }
+    Ok(xattrs)
+}
+
+/// Sets extended attributes of a file, other attributes of the file are left as they are.
+pub(crate) fn write(path: &Path, xattrs: &[Xattr]) -> io::Result<()> {
+    for xattr in xattrs {
+        sys::set(path, &xattr.name, &xattr.value)?;
+    }
+    Ok(())
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+mod sys {
+    use std::ffi::CString;
+    use std::io;
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::Path;
+
+    #[cfg(target_os = "linux")]
+    const NO_ATTRIBUTE: i32 = libc::ENODATA;
+    #[cfg(target_os = "macos")]
+    const NO_ATTRIBUTE: i32 = libc::ENOATTR;
+
+    pub(super) fn get(path: &Path, name: &str) -> io::Result<Option<Vec<u8>>> {
+        let path = c_string(path.as_os_str().as_bytes())?;
+        let name = c_string(name.as_bytes())?;
+
+        // the value may grow between asking for its size and reading it, so it is read again then
+        loop {
+            let size = match checked(unsafe { getxattr(&path, &name, std::ptr::null_mut(), 0) }) {
+                Ok(size) => size,
+                Err(e) if is_missing(&e) => return Ok(None),
+                Err(e) => return Err(e),
+            };
+            let mut value = vec![0u8; size];
+            match checked(unsafe { getxattr(&path, &name, value.as_mut_ptr().cast(), size) }) {
+                Ok(read) => {
+                    value.truncate(read);
+                    return Ok(Some(value));
+                }
+                Err(e) if e.raw_os_error() == Some(libc::ERANGE) => continue,
+                Err(e) if is_missing(&e) => return Ok(None),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    pub(super) fn set(path: &Path, name: &str, value: &[u8]) -> io::Result<()> {
+        let path = c_string(path.as_os_str().as_bytes())?;
+        let name = c_string(name.as_bytes())?;
+        checked(unsafe { setxattr(&path, &name, value) as isize }).map(|_| ())
+    }
+
+    #[cfg(target_os = "linux")]
+    unsafe fn getxattr(
+        path: &CString,
+        name: &CString,
+        value: *mut libc::c_void,
+        size: usize,
+    ) -> isize {
+        libc::getxattr(path.as_ptr(), name.as_ptr(), value, size)
+    }
+
+    #[cfg(target_os = "macos")]
+    unsafe fn getxattr(
+        path: &CString,
+        name: &CString,
+        value: *mut libc::c_void,
+        size: usize,
+    ) -> isize {
+        libc::getxattr(path.as_ptr(), name.as_ptr(), value, size, 0, 0)
+    }
+
+    #[cfg(target_os = "linux")]
+    unsafe fn setxattr(path: &CString, name: &CString, value: &[u8]) -> i32 {
+        libc::setxattr(
+            path.as_ptr(),
+            name.as_ptr(),
+            value.as_ptr().cast(),
+            value.len(),
+            0,
+        )
+    }
+
+    #[cfg(target_os = "macos")]
+    unsafe fn setxattr(path: &CString, name: &CString, value: &[u8]) -> i32 {
+        libc::setxattr(
+            path.as_ptr(),
+            name.as_ptr(),
+            value.as_ptr().cast(),
+            value.len(),
+            0,
+            0,
+        )
+    }
+
+    fn checked(result: isize) -> io::Result<usize> {
+        if result < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(result as usize)
+        }
+    }
+
+    /// Whether the attribute is missing, or the filesystem does not support attributes at all.
+    fn is_missing(err: &io::Error) -> bool {
+        matches!(err.raw_os_error(), Some(code) if code == NO_ATTRIBUTE || code == libc::ENOTSUP)
+    }
+
+    fn c_string(bytes: &[u8]) -> io::Result<CString> {
+        CString::new(bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+mod sys {
+    use std::io;
+    use std::path::Path;
+
+    pub(super) fn get(_path: &Path, _name: &str) -> io::Result<Option<Vec<u8>>> {
+        Ok(None)
+    }
+
+    pub(super) fn set(_path: &Path, _name: &str, _value: &[u8]) -> io::Result<()> {
+        Ok(())
+    }
+}
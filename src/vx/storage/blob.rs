@@ -1,18 +1,30 @@
 use crate::context::Context;
-use crate::core::blob::Blob;
+use crate::core::blob::{Blob, BlobStats, ContentKind, VerifyReport, KIND_CHECK_SIZE};
 use crate::core::digest::{Digest, DigestExt};
-use crate::storage::BLOBS_FOLDER_NAME;
+use crate::storage::codec::{self, Versioned};
+use crate::storage::compress::{self, Decompressor};
+use crate::storage::{io_problem, IoProblem, BLOBS_FOLDER_NAME};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
 use sled::Db;
+use std::collections::HashSet;
 use std::fs;
-use std::io;
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
 use thiserror::Error;
 
 /// Represents errors that can occur while handling blobs.
 #[derive(Error, Debug)]
 pub enum BlobError {
     #[error("IO error: {0}")]
-    IoError(#[from] io::Error),
+    IoError(io::Error),
+
+    #[error("Out of disk space: {0}")]
+    OutOfSpace(io::Error),
+
+    #[error("Permission denied: {0}")]
+    PermissionDenied(io::Error),
 
     #[error("Blob not found: {0}")]
     BlobNotFound(String),
@@ -21,7 +33,7 @@ pub enum BlobError {
     FileNotFound(String),
 
     #[error("Database error: {0}")]
-    DatabaseError(#[from] sled::Error),
+    DatabaseError(sled::Error),
 
     #[error("Serialization/Deserialization error: {0}")]
     SerializationError(#[from] bincode::Error),
@@ -30,11 +42,87 @@ pub enum BlobError {
     Other(String),
 }
 
-const BLOB_DB_FILE_NAME: &str = "blob.db";
+impl From<io::Error> for BlobError {
+    fn from(err: io::Error) -> Self {
+        match io_problem(&err) {
+            Some(IoProblem::OutOfSpace) => BlobError::OutOfSpace(err),
+            Some(IoProblem::PermissionDenied) => BlobError::PermissionDenied(err),
+            None => BlobError::IoError(err),
+        }
+    }
+}
+
+impl From<sled::Error> for BlobError {
+    fn from(err: sled::Error) -> Self {
+        match err {
+            sled::Error::Io(err) if io_problem(&err).is_some() => err.into(),
+            err => BlobError::DatabaseError(err),
+        }
+    }
+}
+
+/// Record of a blob in the blob database: the blob along with the way its content is stored.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct StoredBlob {
+    blob: Blob,
+    /// Size of the file holding the content in bytes.
+    stored_size: u64,
+    /// Whether the file holds the content compressed into frames, see `compress`, rather than
+    /// as it is.
+    compressed: bool,
+}
+
+impl StoredBlob {
+    /// Record of a blob whose file holds the content as it is, like every file stored before
+    /// compression was introduced.
+    fn plain(blob: Blob) -> Self {
+        StoredBlob {
+            stored_size: blob.size,
+            blob,
+            compressed: false,
+        }
+    }
+}
+
+impl Versioned for StoredBlob {
+    const VERSION: u8 = 3;
+
+    fn upgrade(version: u8, payload: &[u8]) -> Result<Self, bincode::Error> {
+        match version {
+            1 => Ok(Self::plain(
+                codec::decode_payload::<BlobV1>(payload)?.upgrade(),
+            )),
+            2 => Ok(Self::plain(codec::decode_payload::<Blob>(payload)?)),
+            _ => Err(codec::unsupported(version)),
+        }
+    }
+}
+
+/// Blob layout of schema version 1, before the kind of content was detected.
+#[derive(Deserialize)]
+pub(crate) struct BlobV1 {
+    contenthash: Digest,
+    size: u64,
+}
+
+impl BlobV1 {
+    pub(crate) fn upgrade(self) -> Blob {
+        Blob {
+            contenthash: self.contenthash,
+            size: self.size,
+            kind: ContentKind::Unknown,
+        }
+    }
+}
+
+pub(super) const BLOB_DB_FILE_NAME: &str = "blob.db";
+
+/// Number of leading hexadecimal digits of a content hash naming the folder its blob is stored in.
+pub(crate) const SHARD_DIGITS: usize = 2;
 
 /// Opens the blob database and returns a connection.
 pub fn open(context: &Context) -> Result<Db, BlobError> {
-    let db = sled::open(context.workspace_path.join(BLOB_DB_FILE_NAME))?;
+    let db = context.open_db(BLOB_DB_FILE_NAME)?;
     Ok(db)
 }
 
@@ -47,52 +135,171 @@ fn get_blob_dir(context: &Context) -> PathBuf {
 fn get_blob_path(context: &Context, contenthash: Digest) -> PathBuf {
     let hash_str = contenthash.to_hex_string();
 
-    // Use the first 2 characters as a subdirectory to avoid too many files in one directory
-    let subdir = &hash_str[..2];
-    get_blob_dir(context).join(subdir).join(&hash_str[2..])
+    // Use the first characters as a subdirectory to avoid too many files in one directory
+    let subdir = &hash_str[..SHARD_DIGITS];
+    get_blob_dir(context)
+        .join(subdir)
+        .join(&hash_str[SHARD_DIGITS..])
 }
 
-/// Copies a file to the blob store and returns a Blob object.
+/// Copies a file to the blob store, compressed as the repository metadata says, and returns a
+/// Blob object.
 pub fn from_file(context: &Context, db: &Db, file_path: &Path) -> Result<Blob, BlobError> {
     // Compute the hash of the file
     let (contenthash, size) = Digest::compute_hash(file_path)?;
+    let mut head = Vec::with_capacity(KIND_CHECK_SIZE);
+    fs::File::open(file_path)?
+        .take(KIND_CHECK_SIZE as u64)
+        .read_to_end(&mut head)?;
+    let blob = Blob {
+        contenthash,
+        size,
+        kind: ContentKind::detect(&head),
+    };
+    let level = compression_level(context)?;
+    store(context, db, blob, level > 0, |path| {
+        if level == 0 {
+            return fs::copy(file_path, path);
+        }
+        write_compressed(path, &mut BufReader::new(fs::File::open(file_path)?), level)
+    })
+}
+
+/// Writes an in-memory content to the blob store and returns a Blob object.
+pub fn from_bytes(context: &Context, db: &Db, content: &[u8]) -> Result<Blob, BlobError> {
+    let contenthash = Digest::compute_hash_bytes(content);
+    let size = content.len() as u64;
+    let blob = Blob {
+        contenthash,
+        size,
+        kind: ContentKind::detect(content),
+    };
+    let level = compression_level(context)?;
+    store(context, db, blob, level > 0, |path| {
+        if level == 0 {
+            return fs::write(path, content).map(|_| content.len() as u64);
+        }
+        write_compressed(path, &mut &content[..], level)
+    })
+}
+
+/// Gets the compression level of new blobs.
+fn compression_level(context: &Context) -> Result<u32, BlobError> {
+    context
+        .compression_level()
+        .map_err(|e| BlobError::Other(format!("Failed to read the compression level: {}", e)))
+}
+
+/// Writes content compressed into frames to a new file, returning the size of the file.
+fn write_compressed(path: &Path, content: &mut impl Read, level: u32) -> io::Result<u64> {
+    let mut file = BufWriter::new(fs::File::create(path)?);
+    let size = compress::compress(content, &mut file, level)?;
+    file.flush()?;
+    Ok(size)
+}
 
+/// Stores the content of a blob written by `write`, which returns the size of the written file,
+/// unless the blob is already in the store. Returns the blob as registered in the database, which
+/// may have been stored by someone else.
+///
+/// Workers persisting trees in parallel may store the same content at the same time. Each of them
+/// writes into its own temporary file, which is atomically renamed to the blob path before the blob
+/// is registered in the database, so the blob file is always complete once the record is visible.
+/// The record is inserted with compare and swap, the first writer wins and the others return the
+/// existing blob.
+fn store<F>(
+    context: &Context,
+    db: &Db,
+    blob: Blob,
+    compressed: bool,
+    write: F,
+) -> Result<Blob, BlobError>
+where
+    F: Fn(&Path) -> io::Result<u64>,
+{
     // Check if the blob already exists in the database.
     // Unlike file system, database is atomic so if the record is in the database,
     // the actual blob storage is confirmed to have the blob.
-    let key = contenthash.to_be_bytes();
-    if db.contains_key(&key)? {
-        // The blob is already in the store, no need to copy it.
-        return Ok(Blob { contenthash, size });
+    let key = blob.contenthash.to_be_bytes();
+    if let Some(existing) = db.get(key)? {
+        // The blob is already in the store, no need to write it.
+        let mut existing: StoredBlob = codec::decode(&existing)?;
+        if existing.blob.kind == ContentKind::Unknown {
+            // Blobs stored before kinds were detected get theirs recorded on the next encounter
+            existing.blob.kind = blob.kind;
+            db.insert(key, codec::encode(&existing)?)?;
+        }
+        return Ok(existing.blob);
     }
 
     // Determine the destination path in the blob store
-    let blob_path = get_blob_path(context, contenthash);
+    let blob_path = get_blob_path(context, blob.contenthash);
+    let temp_path = get_temp_path(&blob_path);
 
-    // TODO: handle a potential data race when two threads try to copy to the same destination file.
-    // May be the path to a file in blob store has to be randomly generated and saved as a reference
-    // in the database.
-    if let Err(e) = fs::copy(file_path, &blob_path) {
-        // If the error is not due to missing directory, return early
-        if e.kind() != std::io::ErrorKind::NotFound {
-            return Err(BlobError::IoError(e));
-        }
+    let stored_size = match write(&temp_path) {
+        Ok(size) => size,
+        Err(e) => {
+            // If the error is not due to missing directory, return early
+            if e.kind() != io::ErrorKind::NotFound {
+                let _ = fs::remove_file(&temp_path);
+                return Err(e.into());
+            }
 
-        // Create the directory structure if it doesn't exist
-        if let Some(parent) = blob_path.parent() {
-            fs::create_dir_all(parent)?;
+            // Create the directory structure if it doesn't exist
+            if let Some(parent) = blob_path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            // Retry writing the file after creating the directory
+            write(&temp_path).inspect_err(|_| {
+                let _ = fs::remove_file(&temp_path);
+            })?
         }
-        // Retry copying the file after creating the directory
-        fs::copy(file_path, &blob_path)?;
+    };
+
+    // Another writer may have registered the blob in the meantime, its file is already in place.
+    if let Some(existing) = db.get(key)? {
+        fs::remove_file(&temp_path)?;
+        return Ok(codec::decode::<StoredBlob>(&existing)?.blob);
+    }
+
+    // Concurrent writers of the same blob rename files of identical content, so any of them
+    // may end up at the blob path.
+    if let Err(e) = fs::rename(&temp_path, &blob_path) {
+        let _ = fs::remove_file(&temp_path);
+        return Err(e.into());
     }
 
-    // Store the blob metadata in the database
-    let blob = Blob { contenthash, size };
-    let value = bincode::serialize(&blob)?;
-    db.insert(key, value)?;
+    // Store the blob metadata in the database, only if nobody else did
+    let record = StoredBlob {
+        blob,
+        stored_size,
+        compressed,
+    };
+    let value = codec::encode(&record)?;
     // The caller is responsible for flushing when needed
+    match db.compare_and_swap(key, None as Option<&[u8]>, Some(value))? {
+        Ok(()) => Ok(record.blob),
+        Err(conflict) => match conflict.current {
+            Some(existing) => Ok(codec::decode::<StoredBlob>(&existing)?.blob),
+            None => Err(BlobError::Other(format!(
+                "Failed to register blob {}",
+                record.blob.contenthash.to_hex_string()
+            ))),
+        },
+    }
+}
 
-    Ok(blob)
+/// Gets a unique path of a temporary file next to the blob path, so it can be atomically renamed.
+fn get_temp_path(blob_path: &Path) -> PathBuf {
+    // unique per process and call, so concurrent writers never share a temporary file
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let mut name = blob_path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(
+        ".{}.{}.tmp",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    blob_path.with_file_name(name)
 }
 
 /// Copies a blob from the blob store to the specified file path.
@@ -103,21 +310,26 @@ pub fn to_file(
     dest_path: &Path,
 ) -> Result<(), BlobError> {
     // Check if the blob exists in the database
-    let key = contenthash.to_be_bytes();
-    if !db.contains_key(&key)? {
-        // TODO: is this check really needed?
-        // We should not have concurrent writes and reads at the same time.
-        return Err(BlobError::BlobNotFound(contenthash.to_hex_string()));
-    }
-
+    // TODO: is this check really needed?
+    // We should not have concurrent writes and reads at the same time.
+    let stored = get_stored(db, contenthash)?;
     let blob_path = get_blob_path(context, contenthash);
+    let copy = |dest_path: &Path| {
+        if !stored.compressed {
+            return fs::copy(&blob_path, dest_path).map(|_| ());
+        }
+        let mut content = Decompressor::new(BufReader::new(fs::File::open(&blob_path)?));
+        let mut file = BufWriter::new(fs::File::create(dest_path)?);
+        io::copy(&mut content, &mut file)?;
+        file.flush()
+    };
 
     // Try copying directly to the destination file.
     // The caller should guarantee that only one thread is copying to the same destination file.
     // TODO: handle permissions / attributes.
-    if let Err(e) = fs::copy(&blob_path, dest_path) {
+    if let Err(e) = copy(dest_path) {
         if e.kind() != std::io::ErrorKind::NotFound {
-            return Err(BlobError::IoError(e));
+            return Err(e.into());
         }
 
         // Check if the error is due to the parent directory not existing
@@ -127,21 +339,232 @@ pub fn to_file(
         }
 
         // Retry copying after creating the directory
-        fs::copy(&blob_path, dest_path)?;
+        copy(dest_path)?;
     }
 
     Ok(())
 }
 
+/// Reads the whole content of a blob into memory.
+pub fn read(context: &Context, db: &Db, contenthash: Digest) -> Result<Vec<u8>, BlobError> {
+    let mut content = Vec::new();
+    open_content(context, db, contenthash)?.read_to_end(&mut content)?;
+    Ok(content)
+}
+
+/// Reads up to `len` bytes of the content of a blob starting at `offset`, without reading the rest.
+/// The range is cut at the end of the blob, so it is empty if the offset is beyond it.
+pub fn read_range(
+    context: &Context,
+    db: &Db,
+    contenthash: Digest,
+    offset: u64,
+    len: u64,
+) -> Result<Vec<u8>, BlobError> {
+    let stored = get_stored(db, contenthash)?;
+    let available = stored.blob.size.saturating_sub(offset).min(len);
+
+    let mut file = fs::File::open(get_blob_path(context, contenthash))?;
+    if stored.compressed {
+        // only the frames holding the range are decompressed
+        return Ok(compress::read_range(
+            &mut BufReader::new(file),
+            offset,
+            available,
+        )?);
+    }
+    file.seek(SeekFrom::Start(offset))?;
+    let mut content = Vec::with_capacity(available as usize);
+    file.take(available).read_to_end(&mut content)?;
+    Ok(content)
+}
+
+/// Content of a stored blob opened for streaming, decompressed while it is read.
+pub struct BlobReader(Content);
+
+enum Content {
+    Plain(fs::File),
+    Compressed(Decompressor<BufReader<fs::File>>),
+}
+
+impl Read for BlobReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match &mut self.0 {
+            Content::Plain(file) => file.read(buf),
+            Content::Compressed(content) => content.read(buf),
+        }
+    }
+}
+
+/// Opens the content of a blob for streaming.
+pub fn open_content(
+    context: &Context,
+    db: &Db,
+    contenthash: Digest,
+) -> Result<BlobReader, BlobError> {
+    let stored = get_stored(db, contenthash)?;
+    Ok(open_stored(context, &stored)?)
+}
+
+fn open_stored(context: &Context, stored: &StoredBlob) -> io::Result<BlobReader> {
+    let file = fs::File::open(get_blob_path(context, stored.blob.contenthash))?;
+    Ok(BlobReader(match stored.compressed {
+        false => Content::Plain(file),
+        true => Content::Compressed(Decompressor::new(BufReader::new(file))),
+    }))
+}
+
 /// Retrieves blob metadata from the database.
 pub fn get_blob_metadata(db: &Db, contenthash: Digest) -> Result<Blob, BlobError> {
+    Ok(get_stored(db, contenthash)?.blob)
+}
+
+/// Retrieves the record of a blob from the database.
+fn get_stored(db: &Db, contenthash: Digest) -> Result<StoredBlob, BlobError> {
     let key = contenthash.to_be_bytes();
 
     match db.get(key)? {
-        Some(ivec) => {
-            let blob: Blob = bincode::deserialize(&ivec)?;
-            Ok(blob)
-        }
+        Some(ivec) => Ok(codec::decode(&ivec)?),
         None => Err(BlobError::BlobNotFound(contenthash.to_hex_string())),
     }
 }
+
+/// Deletes blobs from the store, blobs not stored are skipped.
+/// The records are removed and flushed before the files, so a failure may leave orphan files
+/// behind, see `verify_all`, but never a record whose file is gone.
+pub fn delete(context: &Context, db: &Db, hashes: &[Digest]) -> Result<(), BlobError> {
+    for hash in hashes {
+        db.remove(hash.to_be_bytes())?;
+    }
+    context.flush(db)?;
+    for &hash in hashes {
+        match fs::remove_file(get_blob_path(context, hash)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+/// Lists metadata of all stored blobs.
+pub fn list(db: &Db) -> Result<Vec<Blob>, BlobError> {
+    let mut blobs = Vec::with_capacity(db.len());
+    for value in db.iter().values() {
+        let stored: StoredBlob = codec::decode(&value?)?;
+        blobs.push(stored.blob);
+    }
+    Ok(blobs)
+}
+
+/// Aggregates the number and sizes of stored blobs.
+pub fn stats(context: &Context, db: &Db) -> Result<BlobStats, BlobError> {
+    let mut stats = BlobStats::default();
+    for item in db.iter() {
+        let (_key, value) = item?;
+        let stored: StoredBlob = codec::decode(&value)?;
+        stats.count += 1;
+        stats.size += stored.blob.size;
+    }
+
+    let blob_dir = get_blob_dir(context);
+    if blob_dir.is_dir() {
+        for subdir in fs::read_dir(&blob_dir)? {
+            let subdir = subdir?;
+            if !subdir.file_type()?.is_dir() {
+                stats.disk_size += subdir.metadata()?.len();
+                continue;
+            }
+            for file in fs::read_dir(subdir.path())? {
+                stats.disk_size += file?.metadata()?.len();
+            }
+        }
+    }
+
+    Ok(stats)
+}
+
+/// Scans the blob store for inconsistencies between the database and the files on disk.
+pub fn verify_all(context: &Context, db: &Db) -> Result<VerifyReport, BlobError> {
+    let mut hashes = HashSet::new();
+    let mut records = Vec::with_capacity(db.len());
+    for item in db.iter() {
+        let (key, value) = item?;
+        let bytes: [u8; 16] = key
+            .as_ref()
+            .try_into()
+            .map_err(|_| BlobError::Other(format!("Malformed blob key of {} bytes", key.len())))?;
+        hashes.insert(Digest::from_be_bytes(bytes));
+        records.push(codec::decode::<StoredBlob>(&value)?);
+    }
+
+    // Blobs are independent, so verify them in parallel.
+    let results: Vec<Result<Option<BlobStatus>, BlobError>> = records
+        .par_iter()
+        .map(|stored| {
+            let contenthash = stored.blob.contenthash;
+            // content that fails to decompress does not match its hash either
+            let hash = open_stored(context, stored)
+                .and_then(|mut content| Digest::compute_hash_reader(&mut content));
+            match hash {
+                Ok((hash, _)) if hash == contenthash => Ok(None),
+                Ok(_) => Ok(Some(BlobStatus::Mismatched(contenthash))),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                    Ok(Some(BlobStatus::Missing(contenthash)))
+                }
+                Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+                    Ok(Some(BlobStatus::Mismatched(contenthash)))
+                }
+                Err(e) => Err(e.into()),
+            }
+        })
+        .collect();
+
+    let mut report = VerifyReport {
+        checked: hashes.len() as u64,
+        ..Default::default()
+    };
+
+    for result in results {
+        match result? {
+            Some(BlobStatus::Mismatched(hash)) => report.mismatched.push(hash),
+            Some(BlobStatus::Missing(hash)) => report.missing.push(hash),
+            None => (),
+        }
+    }
+
+    // Look for files on disk that are not registered, their names are hashes split into a
+    // subdirectory and a file name, see get_blob_path.
+    let blob_dir = get_blob_dir(context);
+    if blob_dir.is_dir() {
+        for subdir in fs::read_dir(&blob_dir)? {
+            let subdir = subdir?;
+            if !subdir.file_type()?.is_dir() {
+                report.orphans.push(subdir.path());
+                continue;
+            }
+            let prefix = subdir.file_name().to_string_lossy().into_owned();
+            for file in fs::read_dir(subdir.path())? {
+                let file = file?;
+                let hex = format!("{}{}", prefix, file.file_name().to_string_lossy());
+                let registered = u128::from_str_radix(&hex, 16)
+                    .map(|hash| hash.to_hex_string() == hex && hashes.contains(&hash))
+                    .unwrap_or(false);
+                if !registered {
+                    report.orphans.push(file.path());
+                }
+            }
+        }
+    }
+
+    report.mismatched.sort();
+    report.missing.sort();
+    report.orphans.sort();
+
+    Ok(report)
+}
+
+/// Status of a blob that failed the verification.
+enum BlobStatus {
+    Mismatched(Digest),
+    Missing(Digest),
+}
@@ -1,6 +1,9 @@
 use crate::context::Context;
 use crate::core::branch::Branch;
+use crate::storage::codec::{self, Versioned};
+use crate::storage::commit::CommitError;
 use crate::storage::BRANCHES_FILE_NAME;
+use sled::transaction::{ConflictableTransactionError, TransactionError};
 use sled::Db;
 use thiserror::Error;
 use xxhash_rust::xxh3::xxh3_64;
@@ -26,13 +29,25 @@ pub enum BranchError {
     #[error("Invalid parent branch: {0}")]
     InvalidParent(String),
 
+    #[error("Commit error: {0}")]
+    Commit(Box<CommitError>),
+
     #[error("{0}")]
     Other(String),
 }
 
+impl Versioned for Branch {
+    const VERSION: u8 = 1;
+}
+
+/// Computes the id of a branch as a 64-bit hash of its name using xxHash.
+pub fn branch_id(name: &str) -> u64 {
+    xxh3_64(name.as_bytes())
+}
+
 /// Opens branch store.
 fn open(context: &Context) -> Result<Db, BranchError> {
-    let db = sled::open(context.workspace_path.join(BRANCHES_FILE_NAME))?;
+    let db = context.open_db(BRANCHES_FILE_NAME)?;
     Ok(db)
 }
 
@@ -46,8 +61,7 @@ pub fn new(
 ) -> Result<Branch, BranchError> {
     let db = open(context)?;
 
-    // Compute branch id as a 64-bit hash of the branch name using xxHash.
-    let id = xxh3_64(name.as_bytes());
+    let id = branch_id(&name);
     let branch = Branch {
         id,
         name: name.clone(),
@@ -57,20 +71,20 @@ pub fn new(
         ver: 0,
     };
     let key = branch.id.to_be_bytes().to_vec();
-    let value = bincode::serialize(&branch)?;
+    let value = codec::encode(&branch)?;
 
     // Attempt to atomically insert the branch only if no record with the same id exists.
     let result = db.compare_and_swap(key.clone(), None as Option<&[u8]>, Some(value))?;
     match result {
         Ok(()) => {
-            db.flush()?;
+            context.flush(&db)?;
             Ok(branch)
         }
         Err(e) => {
             // A record with the same id already exists.
             match e.current {
                 Some(existing_bytes) => {
-                    let existing_branch: Branch = bincode::deserialize(&existing_bytes)?;
+                    let existing_branch: Branch = codec::decode(&existing_bytes)?;
                     if existing_branch.name == name {
                         Err(BranchError::BranchExists(name))
                     } else {
@@ -100,7 +114,7 @@ pub fn get(context: &Context, id: u64) -> Result<Branch, BranchError> {
     let db = open(context)?;
     match db.get(key)? {
         Some(ivec) => {
-            let branch: Branch = bincode::deserialize(&ivec)?;
+            let branch: Branch = codec::decode(&ivec)?;
             Ok(branch)
         }
         None => Err(BranchError::NotFound),
@@ -109,7 +123,7 @@ pub fn get(context: &Context, id: u64) -> Result<Branch, BranchError> {
 
 /// Gets branch by name.
 pub fn get_by_name(context: &Context, name: &str) -> Result<Branch, BranchError> {
-    let id = xxh3_64(name.as_bytes());
+    let id = branch_id(name);
     // TODO: handle hash collisions.
     get(context, id)
 }
@@ -120,12 +134,68 @@ pub fn list(context: &Context) -> Result<Vec<Branch>, BranchError> {
     let mut branches = Vec::new();
     for item in db.iter() {
         let (_key, value) = item?;
-        let branch: Branch = bincode::deserialize(&value)?;
+        let branch: Branch = codec::decode(&value)?;
         branches.push(branch);
     }
     Ok(branches)
 }
 
+/// Renames a branch, moving its record under the id of the new name and pointing the branches
+/// based on it to the new id, all in one transaction. Fails if a branch with the new name exists.
+pub fn rename(context: &Context, branch: &Branch, new_name: String) -> Result<Branch, BranchError> {
+    let db = open(context)?;
+    let renamed = Branch {
+        id: branch_id(&new_name),
+        name: new_name,
+        ..branch.clone()
+    };
+    let key = renamed.id.to_be_bytes();
+    let value = codec::encode(&renamed)?;
+
+    let mut children = Vec::new();
+    for item in db.iter() {
+        let (child_key, child_value) = item?;
+        let mut child: Branch = codec::decode(&child_value)?;
+        if child.parent == branch.id && child.id != branch.id {
+            child.parent = renamed.id;
+            children.push((child_key, codec::encode(&child)?));
+        }
+    }
+
+    let result = db.transaction(|tx| {
+        // the existing record is returned to tell a taken name from a hash collision
+        if let Some(existing) = tx.get(key)? {
+            return Err(ConflictableTransactionError::Abort(existing));
+        }
+        tx.insert(&key, value.clone())?;
+        tx.remove(&branch.id.to_be_bytes())?;
+        for (child_key, child_value) in &children {
+            tx.insert(child_key, child_value.clone())?;
+        }
+        Ok(())
+    });
+    match result {
+        Ok(()) => {
+            context.flush(&db)?;
+            Ok(renamed)
+        }
+        Err(TransactionError::Abort(existing)) => {
+            let existing: Branch = codec::decode(&existing)?;
+            if existing.name == renamed.name {
+                Err(BranchError::BranchExists(renamed.name))
+            } else {
+                Err(BranchError::DatabaseError(sled::Error::Unsupported(
+                    format!(
+                    "Hash collision! Branch with id {} already exists under different name '{}'",
+                    renamed.id, existing.name
+                ),
+                )))
+            }
+        }
+        Err(TransactionError::Storage(err)) => Err(err.into()),
+    }
+}
+
 /// Updates the head sequence number of a branch.
 pub fn update_headseq(
     context: &Context,
@@ -145,11 +215,11 @@ pub fn update_headseq(
         match current {
             Some(current_bytes) => {
                 // Try to deserialize the branch
-                match bincode::deserialize::<Branch>(current_bytes) {
+                match codec::decode::<Branch>(current_bytes) {
                     Ok(mut branch) => {
                         branch.headseq = new_headseq; // Try to serialize the updated branch
                         branch.ver = new_ver;
-                        match bincode::serialize(&branch) {
+                        match codec::encode(&branch) {
                             Ok(serialized) => {
                                 closure_branch = Some(branch);
                                 Some(serialized)
@@ -181,7 +251,7 @@ pub fn update_headseq(
         return Err(err);
     }
 
-    db.flush()?;
+    context.flush(&db)?;
 
     // If we got here, closure_branch should be Some(_)
     Ok(closure_branch.unwrap())
@@ -0,0 +1,86 @@
+use bincode::Options;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+// Every stored record is wrapped into a small envelope: a two byte magic followed by a version byte
+// and the bincode payload. Records written before the envelope was introduced have no header at all,
+// they are treated as the first version of the schema.
+
+/// Magic bytes marking a versioned record.
+const MAGIC: [u8; 2] = *b"vx";
+/// Schema version of records written before versioning was introduced.
+const LEGACY_VERSION: u8 = 1;
+
+/// A record that is persisted with a schema version, so its layout can evolve without breaking
+/// existing repositories.
+pub(crate) trait Versioned: Serialize + DeserializeOwned {
+    /// Current schema version of the record, bump it on every layout change.
+    const VERSION: u8;
+
+    /// Decodes a payload written with an older schema version and upgrades it to the current one.
+    /// Types implement it once they get a second version, keeping the old layouts around as private structs.
+    fn upgrade(version: u8, _payload: &[u8]) -> Result<Self, bincode::Error> {
+        Err(unsupported(version))
+    }
+}
+
+/// Serializes a record into a versioned envelope.
+pub(crate) fn encode<T: Versioned>(record: &T) -> Result<Vec<u8>, bincode::Error> {
+    let size = bincode::serialized_size(record)? as usize;
+    let mut bytes = Vec::with_capacity(MAGIC.len() + 1 + size);
+    bytes.extend_from_slice(&MAGIC);
+    bytes.push(T::VERSION);
+    bincode::serialize_into(&mut bytes, record)?;
+    Ok(bytes)
+}
+
+/// Deserializes a record from a versioned envelope, upgrading it if it was written with an older schema.
+/// Upgraded records are not written back, they get persisted in the new layout on the next save.
+pub(crate) fn decode<T: Versioned>(bytes: &[u8]) -> Result<T, bincode::Error> {
+    match bytes {
+        [m0, m1, version, payload @ ..] if [*m0, *m1] == MAGIC => {
+            match decode_version(*version, payload) {
+                Ok(record) => Ok(record),
+                // A legacy record may start with the magic bytes by accident, e.g. a hash.
+                Err(err) => decode_version(LEGACY_VERSION, bytes).map_err(|_| err),
+            }
+        }
+        _ => decode_version(LEGACY_VERSION, bytes),
+    }
+}
+
+fn decode_version<T: Versioned>(version: u8, payload: &[u8]) -> Result<T, bincode::Error> {
+    if version == T::VERSION {
+        decode_payload(payload)
+    } else if version < T::VERSION {
+        T::upgrade(version, payload)
+    } else {
+        Err(unsupported(version))
+    }
+}
+
+/// Deserializes a bare payload of the given layout, to be used by `Versioned::upgrade` implementations.
+pub(crate) fn decode_payload<T: DeserializeOwned>(payload: &[u8]) -> Result<T, bincode::Error> {
+    // Same encoding as bincode::deserialize, but strict about trailing bytes to reliably detect
+    // misinterpreted records.
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .reject_trailing_bytes()
+        .deserialize(payload)
+}
+
+pub(crate) fn unsupported(version: u8) -> bincode::Error {
+    Box::new(bincode::ErrorKind::Custom(format!(
+        "{} {}, the repository may have been written by a newer vx",
+        UNSUPPORTED_VERSION, version
+    )))
+}
+
+const UNSUPPORTED_VERSION: &str = "Unsupported record version";
+
+/// Checks whether a record failed to decode because it was written by a newer vx, rather than
+/// because it is damaged.
+pub(crate) fn is_unsupported(err: &bincode::Error) -> bool {
+    matches!(err.as_ref(), bincode::ErrorKind::Custom(message)
+        if message.starts_with(UNSUPPORTED_VERSION))
+}
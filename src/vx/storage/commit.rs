@@ -1,21 +1,34 @@
 use crate::context::Context;
-use crate::core::commit::{Commit, CommitID, CurrentCommitSpec};
-use crate::storage::COMMITS_FILE_NAME;
+use crate::core::bisect::Bisect;
+use crate::core::commit::{Commit, CommitID, CurrentCommitSpec, PendingCommit, PendingRebuild};
+use crate::core::digest::{Digest, DigestExt};
+use crate::storage::branch::BranchError;
+use crate::storage::codec::{self, Versioned};
+use crate::storage::tree::TreeError;
+use crate::storage::{io_problem, IoProblem, COMMITS_FILE_NAME};
+use serde::Deserialize;
 use sled::Tree;
 use std::io;
+use std::path::PathBuf;
 use thiserror::Error;
 
 /// Represents errors that can occur while handling commits.
 #[derive(Error, Debug)]
 pub enum CommitError {
     #[error("Database error: {0}")]
-    DatabaseError(#[from] sled::Error),
+    DatabaseError(sled::Error),
 
     #[error("Serialization/Deserialization error: {0}")]
     SerializationError(#[from] bincode::Error),
 
     #[error("Filesystem error: {0}")]
-    IoError(#[from] io::Error),
+    IoError(io::Error),
+
+    #[error("Out of disk space: {0}")]
+    OutOfSpace(io::Error),
+
+    #[error("Permission denied: {0}")]
+    PermissionDenied(io::Error),
 
     #[error("Commit not found")]
     NotFound,
@@ -23,21 +36,403 @@ pub enum CommitError {
     #[error("No branch selected")]
     NoBranchSelected,
 
+    #[error("No branch or tag named '{0}'")]
+    UnknownSpec(String),
+
+    #[error("Branch error: {0}")]
+    Branch(BranchError),
+
     #[error("No changes to commit")]
     NoChanges,
 
+    #[error("Branch '{0}' is protected from direct commits")]
+    BranchProtected(String),
+
+    #[error("Conflict while rebuilding commit {0}: {1:?}")]
+    Conflict(u64, Vec<PathBuf>),
+
+    #[error("Conflict while reordering commit {0}: {1:?}")]
+    MoveConflict(u64, Vec<PathBuf>),
+
+    #[error("Conflict while reverting commit {0}: {1:?}")]
+    RevertConflict(u64, Vec<PathBuf>),
+
+    #[error("Conflicts are not resolved: {0:?}")]
+    Unresolved(Vec<PathBuf>),
+
+    #[error("Rebuild of commit {0} is in progress")]
+    RebuildInProgress(u64),
+
     #[error("{0}")]
     Other(String),
 }
 
+impl From<io::Error> for CommitError {
+    fn from(err: io::Error) -> Self {
+        match io_problem(&err) {
+            Some(IoProblem::OutOfSpace) => CommitError::OutOfSpace(err),
+            Some(IoProblem::PermissionDenied) => CommitError::PermissionDenied(err),
+            None => CommitError::IoError(err),
+        }
+    }
+}
+
+impl From<sled::Error> for CommitError {
+    fn from(err: sled::Error) -> Self {
+        match err {
+            sled::Error::Io(err) if io_problem(&err).is_some() => err.into(),
+            err => CommitError::DatabaseError(err),
+        }
+    }
+}
+
+impl CommitError {
+    /// Wraps a failure of the tree store with a description of what failed, failures calling for
+    /// an action of the user are kept as they are.
+    pub fn from_tree(description: &str, err: TreeError) -> Self {
+        match err {
+            TreeError::OutOfSpace(err) => CommitError::OutOfSpace(err),
+            TreeError::PermissionDenied(err) => CommitError::PermissionDenied(err),
+            err => CommitError::Other(format!("{}: {:?}", description, err)),
+        }
+    }
+}
+
+/// All versions of a commit are stored together as a single record.
+impl Versioned for Vec<Commit> {
+    const VERSION: u8 = 5;
+
+    fn upgrade(version: u8, payload: &[u8]) -> Result<Self, bincode::Error> {
+        match version {
+            1 => {
+                let commits: Vec<CommitV1> = codec::decode_payload(payload)?;
+                Ok(commits
+                    .into_iter()
+                    .map(|commit| commit.upgrade().upgrade().upgrade().upgrade())
+                    .collect())
+            }
+            2 => {
+                let commits: Vec<CommitV2> = codec::decode_payload(payload)?;
+                Ok(commits
+                    .into_iter()
+                    .map(|commit| commit.upgrade().upgrade().upgrade())
+                    .collect())
+            }
+            3 => {
+                let commits: Vec<CommitV3> = codec::decode_payload(payload)?;
+                Ok(commits
+                    .into_iter()
+                    .map(|commit| commit.upgrade().upgrade())
+                    .collect())
+            }
+            4 => {
+                let commits: Vec<CommitV4> = codec::decode_payload(payload)?;
+                Ok(commits.into_iter().map(CommitV4::upgrade).collect())
+            }
+            _ => Err(codec::unsupported(version)),
+        }
+    }
+}
+
+impl Versioned for CurrentCommitSpec {
+    const VERSION: u8 = 1;
+}
+
+impl Versioned for PendingCommit {
+    const VERSION: u8 = 6;
+
+    fn upgrade(version: u8, payload: &[u8]) -> Result<Self, bincode::Error> {
+        match version {
+            1 => {
+                let pending: PendingCommitV1 = codec::decode_payload(payload)?;
+                Ok(PendingCommitV2 {
+                    commit: pending.commit.upgrade(),
+                    current: pending.current,
+                }
+                .upgrade()
+                .upgrade())
+            }
+            2 => {
+                let pending: PendingCommitV2 = codec::decode_payload(payload)?;
+                Ok(pending.upgrade().upgrade())
+            }
+            3 => {
+                let pending: PendingCommitV3 = codec::decode_payload(payload)?;
+                Ok(pending.upgrade())
+            }
+            4 => {
+                let pending: PendingCommitV4 = codec::decode_payload(payload)?;
+                Ok(pending.upgrade())
+            }
+            5 => {
+                let pending: PendingCommitV5 = codec::decode_payload(payload)?;
+                Ok(pending.upgrade())
+            }
+            _ => Err(codec::unsupported(version)),
+        }
+    }
+}
+
+impl Versioned for PendingRebuild {
+    const VERSION: u8 = 3;
+
+    fn upgrade(version: u8, payload: &[u8]) -> Result<Self, bincode::Error> {
+        match version {
+            1 => {
+                let pending: PendingRebuildV1 = codec::decode_payload(payload)?;
+                Ok(pending.upgrade())
+            }
+            2 => {
+                let pending: PendingRebuildV2 = codec::decode_payload(payload)?;
+                Ok(pending.upgrade())
+            }
+            _ => Err(codec::unsupported(version)),
+        }
+    }
+}
+
+impl Versioned for Bisect {
+    const VERSION: u8 = 1;
+}
+
+/// Commit layout of schema version 1, before authors were introduced.
+#[derive(Deserialize)]
+struct CommitV1 {
+    id: CommitID,
+    ver: u64,
+    hash: Digest,
+    treehash: Digest,
+    message: String,
+}
+
+impl CommitV1 {
+    fn upgrade(self) -> CommitV2 {
+        CommitV2 {
+            id: self.id,
+            ver: self.ver,
+            hash: self.hash,
+            treehash: self.treehash,
+            message: self.message,
+            author: String::new(),
+        }
+    }
+}
+
+/// Commit layout of schema version 2, before timestamps were introduced.
+#[derive(Deserialize)]
+struct CommitV2 {
+    id: CommitID,
+    ver: u64,
+    hash: Digest,
+    treehash: Digest,
+    message: String,
+    author: String,
+}
+
+impl CommitV2 {
+    fn upgrade(self) -> CommitV3 {
+        CommitV3 {
+            id: self.id,
+            ver: self.ver,
+            hash: self.hash,
+            treehash: self.treehash,
+            message: self.message,
+            author: self.author,
+            created_at: Commit::UNKNOWN_TIME,
+        }
+    }
+}
+
+/// Commit layout of schema version 3, before messages could be stored as blobs.
+#[derive(Deserialize)]
+struct CommitV3 {
+    id: CommitID,
+    ver: u64,
+    hash: Digest,
+    treehash: Digest,
+    message: String,
+    author: String,
+    created_at: u64,
+}
+
+impl CommitV3 {
+    fn upgrade(self) -> CommitV4 {
+        CommitV4 {
+            id: self.id,
+            ver: self.ver,
+            hash: self.hash,
+            treehash: self.treehash,
+            message: self.message,
+            message_hash: Digest::NONE,
+            author: self.author,
+            created_at: self.created_at,
+        }
+    }
+}
+
+/// Commit layout of schema version 4, before commits could be signed.
+#[derive(Deserialize)]
+struct CommitV4 {
+    id: CommitID,
+    ver: u64,
+    hash: Digest,
+    treehash: Digest,
+    message: String,
+    message_hash: Digest,
+    author: String,
+    created_at: u64,
+}
+
+impl CommitV4 {
+    fn upgrade(self) -> Commit {
+        Commit {
+            id: self.id,
+            ver: self.ver,
+            hash: self.hash,
+            treehash: self.treehash,
+            message: self.message,
+            message_hash: self.message_hash,
+            author: self.author,
+            created_at: self.created_at,
+            signature: None,
+        }
+    }
+}
+
+/// Pending commit layout of schema version 1.
+#[derive(Deserialize)]
+struct PendingCommitV1 {
+    commit: CommitV1,
+    current: CurrentCommitSpec,
+}
+
+/// Pending commit layout of schema version 2, before commits above the pending one could be rebuilt.
+#[derive(Deserialize)]
+struct PendingCommitV2 {
+    commit: CommitV2,
+    current: CurrentCommitSpec,
+}
+
+impl PendingCommitV2 {
+    fn upgrade(self) -> PendingCommitV3 {
+        PendingCommitV3 {
+            headseq: self.commit.id.seq,
+            commit: self.commit,
+            current: self.current,
+        }
+    }
+}
+
+/// Pending commit layout of schema version 3, before commits carried timestamps.
+#[derive(Deserialize)]
+struct PendingCommitV3 {
+    commit: CommitV2,
+    current: CurrentCommitSpec,
+    headseq: u64,
+}
+
+impl PendingCommitV3 {
+    fn upgrade(self) -> PendingCommit {
+        PendingCommit {
+            commit: self.commit.upgrade().upgrade().upgrade(),
+            current: self.current,
+            headseq: self.headseq,
+        }
+    }
+}
+
+/// Pending commit layout of schema version 4, before messages could be stored as blobs.
+#[derive(Deserialize)]
+struct PendingCommitV4 {
+    commit: CommitV3,
+    current: CurrentCommitSpec,
+    headseq: u64,
+}
+
+impl PendingCommitV4 {
+    fn upgrade(self) -> PendingCommit {
+        PendingCommit {
+            commit: self.commit.upgrade().upgrade(),
+            current: self.current,
+            headseq: self.headseq,
+        }
+    }
+}
+
+/// Pending commit layout of schema version 5, before commits could be signed.
+#[derive(Deserialize)]
+struct PendingCommitV5 {
+    commit: CommitV4,
+    current: CurrentCommitSpec,
+    headseq: u64,
+}
+
+impl PendingCommitV5 {
+    fn upgrade(self) -> PendingCommit {
+        PendingCommit {
+            commit: self.commit.upgrade(),
+            current: self.current,
+            headseq: self.headseq,
+        }
+    }
+}
+
+/// Rebuild state layout of schema version 1, before messages could be stored as blobs.
+#[derive(Deserialize)]
+struct PendingRebuildV1 {
+    commit: CommitV3,
+    headseq: u64,
+    shift: u64,
+    treehash: Digest,
+    conflicts: Vec<PathBuf>,
+}
+
+impl PendingRebuildV1 {
+    fn upgrade(self) -> PendingRebuild {
+        PendingRebuild {
+            commit: self.commit.upgrade().upgrade(),
+            headseq: self.headseq,
+            shift: self.shift,
+            treehash: self.treehash,
+            conflicts: self.conflicts,
+        }
+    }
+}
+
+/// Rebuild state layout of schema version 2, before commits could be signed.
+#[derive(Deserialize)]
+struct PendingRebuildV2 {
+    commit: CommitV4,
+    headseq: u64,
+    shift: u64,
+    treehash: Digest,
+    conflicts: Vec<PathBuf>,
+}
+
+impl PendingRebuildV2 {
+    fn upgrade(self) -> PendingRebuild {
+        PendingRebuild {
+            commit: self.commit.upgrade(),
+            headseq: self.headseq,
+            shift: self.shift,
+            treehash: self.treehash,
+            conflicts: self.conflicts,
+        }
+    }
+}
+
 const CURRENT_COMMIT_KEY: &[u8] = b"current";
+const PREVIOUS_COMMIT_KEY: &[u8] = b"current.prev";
+const INTENT_KEY: &[u8] = b"intent";
+const REBUILD_KEY: &[u8] = b"rebuild";
+const BISECT_KEY: &[u8] = b"bisect";
 
 const COMMITS_TREE: &str = "commits";
 const METADATA: &str = "sequences";
 
 /// Opens the database and returns a specific tree.
 fn open_tree(context: &Context, name: &str) -> Result<Tree, CommitError> {
-    let db = sled::open(context.workspace_path.join(COMMITS_FILE_NAME))?;
+    let db = context.open_db(COMMITS_FILE_NAME)?;
     let tree = db.open_tree(name)?;
     Ok(tree)
 }
@@ -62,7 +457,7 @@ pub fn save(context: &Context, commit: &Commit) -> Result<(), CommitError> {
         match existing {
             Some(existing_bytes) => {
                 // Try to deserialize existing commits array
-                match bincode::deserialize::<Vec<Commit>>(existing_bytes) {
+                match codec::decode::<Vec<Commit>>(existing_bytes) {
                     Ok(mut commits) => {
                         // Sort by version in descending order
                         // The array is already sorted by version in descending order
@@ -85,7 +480,7 @@ pub fn save(context: &Context, commit: &Commit) -> Result<(), CommitError> {
                         }
 
                         // Serialize the updated array
-                        match bincode::serialize(&commits) {
+                        match codec::encode(&commits) {
                             Ok(serialized) => Some(serialized),
                             Err(err) => {
                                 closure_error = Some(CommitError::SerializationError(err));
@@ -102,7 +497,7 @@ pub fn save(context: &Context, commit: &Commit) -> Result<(), CommitError> {
             None => {
                 // No existing commits for this key, create a new array with just this commit
                 let commits = vec![commit.clone()];
-                match bincode::serialize(&commits) {
+                match codec::encode(&commits) {
                     Ok(serialized) => Some(serialized),
                     Err(err) => {
                         closure_error = Some(CommitError::SerializationError(err));
@@ -118,7 +513,7 @@ pub fn save(context: &Context, commit: &Commit) -> Result<(), CommitError> {
         return Err(err);
     }
 
-    commit_tree.flush()?;
+    context.flush(&commit_tree)?;
     Ok(())
 }
 
@@ -129,7 +524,7 @@ pub fn get(context: &Context, commit_id: CommitID, ver: u64) -> Result<Commit, C
 
     match commit_tree.get(key)? {
         Some(ivec) => {
-            let commits: Vec<Commit> = bincode::deserialize(&ivec)?;
+            let commits: Vec<Commit> = codec::decode(&ivec)?;
 
             // Since commits are already sorted by descending version,
             // find the first commit with version <= ver
@@ -142,13 +537,30 @@ pub fn get(context: &Context, commit_id: CommitID, ver: u64) -> Result<Commit, C
     }
 }
 
+/// Gets all stored versions of a commit, sorted by descending version.
+pub fn get_versions(context: &Context, commit_id: CommitID) -> Result<Vec<Commit>, CommitError> {
+    let key = compose_key(commit_id);
+    let commit_tree = open_tree(context, COMMITS_TREE)?;
+
+    match commit_tree.get(key)? {
+        Some(ivec) => Ok(codec::decode(&ivec)?),
+        None => Err(CommitError::NotFound),
+    }
+}
+
+/// Counts commits stored in the database, all versions of a commit are counted once.
+pub fn count(context: &Context) -> Result<u64, CommitError> {
+    let commit_tree = open_tree(context, COMMITS_TREE)?;
+    Ok(commit_tree.len() as u64)
+}
+
 /// Gets the current commit's branch ID, sequence number, and other metadata.
 pub fn get_current(context: &Context) -> Result<CurrentCommitSpec, CommitError> {
     let seq_tree = open_tree(context, METADATA)?;
 
     match seq_tree.get(CURRENT_COMMIT_KEY)? {
         Some(ivec) => {
-            let current: CurrentCommitSpec = bincode::deserialize(&ivec)?;
+            let current: CurrentCommitSpec = codec::decode(&ivec)?;
             Ok(current)
         }
         None => Err(CommitError::NotFound), // Return NotFound error if no current commit exists
@@ -156,14 +568,200 @@ pub fn get_current(context: &Context) -> Result<CurrentCommitSpec, CommitError>
 }
 
 /// Saves the current commit's branch ID and sequence number and other metadata.
+/// The value it replaces is kept in the same atomic batch, so that a current commit found to be
+/// broken can be rolled back to it.
 pub fn save_current(context: &Context, current: CurrentCommitSpec) -> Result<(), CommitError> {
     let seq_tree = open_tree(context, METADATA)?;
-    let value = bincode::serialize(&current)?;
-    seq_tree.insert(CURRENT_COMMIT_KEY, value)?;
+    let value = codec::encode(&current)?;
+    let mut batch = sled::Batch::default();
+    if let Some(previous) = seq_tree.get(CURRENT_COMMIT_KEY)? {
+        // saving the same value again would leave nothing to roll back to
+        if previous != value.as_slice() {
+            batch.insert(PREVIOUS_COMMIT_KEY, previous);
+        }
+    }
+    batch.insert(CURRENT_COMMIT_KEY, value);
+    seq_tree.apply_batch(batch)?;
+    context.flush(&seq_tree)?;
+    Ok(())
+}
+
+/// Checks whether an error reading a record comes from the record being damaged, e.g. by a torn
+/// write, rather than from the store or from a record written by a newer vx.
+pub fn is_damaged(err: &CommitError) -> bool {
+    matches!(err, CommitError::SerializationError(err) if !codec::is_unsupported(err))
+}
+
+/// Gets the current commit as it was before its latest update, if it was ever updated.
+pub fn get_previous_current(context: &Context) -> Result<Option<CurrentCommitSpec>, CommitError> {
+    let seq_tree = open_tree(context, METADATA)?;
+    match seq_tree.get(PREVIOUS_COMMIT_KEY)? {
+        Some(ivec) => Ok(Some(codec::decode(&ivec)?)),
+        None => Ok(None),
+    }
+}
+
+/// Rolls the current commit back to the one before its latest update. The previous value is
+/// consumed, so a second rollback fails with `NotFound` rather than going back and forth.
+pub fn restore_previous_current(context: &Context) -> Result<CurrentCommitSpec, CommitError> {
+    let seq_tree = open_tree(context, METADATA)?;
+    let Some(previous) = seq_tree.get(PREVIOUS_COMMIT_KEY)? else {
+        return Err(CommitError::NotFound);
+    };
+    let current: CurrentCommitSpec = codec::decode(&previous)?;
+
+    let mut batch = sled::Batch::default();
+    batch.insert(CURRENT_COMMIT_KEY, previous);
+    batch.remove(PREVIOUS_COMMIT_KEY);
+    seq_tree.apply_batch(batch)?;
+    seq_tree.flush()?;
+    Ok(current)
+}
+
+/// Saves the write-ahead intent record describing a pending commit.
+pub fn save_intent(context: &Context, pending: &PendingCommit) -> Result<(), CommitError> {
+    let seq_tree = open_tree(context, METADATA)?;
+    let value = codec::encode(pending)?;
+    seq_tree.insert(INTENT_KEY, value)?;
+    // the intent has to be durable before any of its changes, so it is never deferred by a batch
     seq_tree.flush()?;
     Ok(())
 }
 
+/// Gets the write-ahead intent record, if there is a pending commit.
+pub fn get_intent(context: &Context) -> Result<Option<PendingCommit>, CommitError> {
+    let seq_tree = open_tree(context, METADATA)?;
+    match seq_tree.get(INTENT_KEY)? {
+        Some(ivec) => Ok(Some(codec::decode(&ivec)?)),
+        None => Ok(None),
+    }
+}
+
+/// Clears the write-ahead intent record once the pending commit is fully applied.
+pub fn clear_intent(context: &Context) -> Result<(), CommitError> {
+    let seq_tree = open_tree(context, METADATA)?;
+    seq_tree.remove(INTENT_KEY)?;
+    seq_tree.flush()?;
+    Ok(())
+}
+
+/// Saves the state of a rebuild paused on a conflict.
+pub fn save_rebuild(context: &Context, pending: &PendingRebuild) -> Result<(), CommitError> {
+    let seq_tree = open_tree(context, METADATA)?;
+    let value = codec::encode(pending)?;
+    seq_tree.insert(REBUILD_KEY, value)?;
+    context.flush(&seq_tree)?;
+    Ok(())
+}
+
+/// Gets the state of a paused rebuild, if there is one.
+pub fn get_rebuild(context: &Context) -> Result<Option<PendingRebuild>, CommitError> {
+    let seq_tree = open_tree(context, METADATA)?;
+    match seq_tree.get(REBUILD_KEY)? {
+        Some(ivec) => Ok(Some(codec::decode(&ivec)?)),
+        None => Ok(None),
+    }
+}
+
+/// Clears the state of a paused rebuild once it is completed or aborted.
+pub fn clear_rebuild(context: &Context) -> Result<(), CommitError> {
+    let seq_tree = open_tree(context, METADATA)?;
+    seq_tree.remove(REBUILD_KEY)?;
+    context.flush(&seq_tree)?;
+    Ok(())
+}
+
+/// Saves the state of a bisect in progress.
+pub fn save_bisect(context: &Context, bisect: &Bisect) -> Result<(), CommitError> {
+    let seq_tree = open_tree(context, METADATA)?;
+    let value = codec::encode(bisect)?;
+    seq_tree.insert(BISECT_KEY, value)?;
+    context.flush(&seq_tree)?;
+    Ok(())
+}
+
+/// Gets the state of the bisect in progress, if there is one.
+pub fn get_bisect(context: &Context) -> Result<Option<Bisect>, CommitError> {
+    let seq_tree = open_tree(context, METADATA)?;
+    match seq_tree.get(BISECT_KEY)? {
+        Some(ivec) => Ok(Some(codec::decode(&ivec)?)),
+        None => Ok(None),
+    }
+}
+
+/// Clears the state of a bisect once it is reset.
+pub fn clear_bisect(context: &Context) -> Result<(), CommitError> {
+    let seq_tree = open_tree(context, METADATA)?;
+    seq_tree.remove(BISECT_KEY)?;
+    context.flush(&seq_tree)?;
+    Ok(())
+}
+
+/// Copies the records of all commits of a branch, every stored version included, under another
+/// branch id, e.g. the one of the new name of a renamed branch. The original records are kept
+/// until they are removed with `remove_branch`.
+pub fn copy_branch(context: &Context, from: u64, to: u64) -> Result<(), CommitError> {
+    let commit_tree = open_tree(context, COMMITS_TREE)?;
+    let mut batch = sled::Batch::default();
+    // keys start with the branch id, so the commits of a branch are stored next to each other
+    for item in commit_tree.scan_prefix(from.to_be_bytes()) {
+        let (_key, value) = item?;
+        let mut commits: Vec<Commit> = codec::decode(&value)?;
+        for commit in &mut commits {
+            commit.id.branch = to;
+        }
+        if let Some(commit) = commits.first() {
+            batch.insert(&compose_key(commit.id), codec::encode(&commits)?);
+        }
+    }
+    commit_tree.apply_batch(batch)?;
+    context.flush(&commit_tree)?;
+    Ok(())
+}
+
+/// Removes the records of all commits of a branch.
+pub fn remove_branch(context: &Context, id: u64) -> Result<(), CommitError> {
+    let commit_tree = open_tree(context, COMMITS_TREE)?;
+    let mut batch = sled::Batch::default();
+    for key in commit_tree.scan_prefix(id.to_be_bytes()).keys() {
+        batch.remove(key?);
+    }
+    commit_tree.apply_batch(batch)?;
+    context.flush(&commit_tree)?;
+    Ok(())
+}
+
+/// Points the current commit, the one it replaced and the bisect in progress from one branch id
+/// to another in a single batch, the ones on other branches are left as they are.
+pub fn move_branch(context: &Context, from: u64, to: u64) -> Result<(), CommitError> {
+    let seq_tree = open_tree(context, METADATA)?;
+    let mut batch = sled::Batch::default();
+    for key in [CURRENT_COMMIT_KEY, PREVIOUS_COMMIT_KEY] {
+        if let Some(ivec) = seq_tree.get(key)? {
+            let mut current: CurrentCommitSpec = codec::decode(&ivec)?;
+            if current.commit_id.branch == from {
+                current.commit_id.branch = to;
+                batch.insert(key, codec::encode(&current)?);
+            }
+        }
+    }
+    if let Some(ivec) = seq_tree.get(BISECT_KEY)? {
+        let mut bisect: Bisect = codec::decode(&ivec)?;
+        if bisect.branch == from || bisect.origin.branch == from {
+            if bisect.branch == from {
+                bisect.branch = to;
+            }
+            if bisect.origin.branch == from {
+                bisect.origin.branch = to;
+            }
+            batch.insert(BISECT_KEY, codec::encode(&bisect)?);
+        }
+    }
+    seq_tree.apply_batch(batch)?;
+    context.flush(&seq_tree)?;
+    Ok(())
+}
+
 /// Helper function to create composite key from branch ID and sequence number
 fn compose_key(commit_id: CommitID) -> [u8; 16] {
     let mut key = [0u8; 16];
@@ -172,15 +770,19 @@ fn compose_key(commit_id: CommitID) -> [u8; 16] {
     key
 }
 
-/// Lists all commits for a given branch.
+/// Lists the commits of a given branch from the head down, all of them unless limited.
 pub fn list(
     context: &Context,
     branch_id: u64,
     branch_ver: u64,
     branch_headseq: u64,
+    limit: Option<usize>,
 ) -> Result<Vec<Commit>, CommitError> {
     let commit_tree = open_tree(context, COMMITS_TREE)?;
     let mut commits = Vec::with_capacity(16);
+    if limit == Some(0) {
+        return Ok(commits);
+    }
 
     // Start from the head commit and work backwards
     let mut current_seq = branch_headseq;
@@ -196,7 +798,7 @@ pub fn list(
 
         match commit_tree.get(key)? {
             Some(ivec) => {
-                let commit_versions: Vec<Commit> = bincode::deserialize(&ivec)?;
+                let commit_versions: Vec<Commit> = codec::decode(&ivec)?;
 
                 // Find the first commit with version <= branch_ver
                 if let Some(commit) = commit_versions.into_iter().find(|c| c.ver <= branch_ver) {
@@ -210,7 +812,7 @@ pub fn list(
             }
         }
 
-        if current_seq == 0 {
+        if current_seq == 0 || limit.is_some_and(|limit| commits.len() >= limit) {
             break;
         }
         current_seq -= 1;
@@ -0,0 +1,314 @@
+use crate::core::blob::MAX_COMPRESSION_LEVEL;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+// Compressed blobs are a sequence of frames, each holding up to FRAME_SIZE bytes of content:
+// a method byte, the size of the content and the size of the payload as little endian u32, and
+// the payload. Frames are independent, so a range of the content is read by skipping the frames
+// before it without decompressing them.
+//
+// The payload of an LZ frame is a sequence of LZ77 matches in the layout of LZ4 blocks: a token
+// with the number of literals in its high nibble and the length of the match minus MIN_MATCH in
+// its low nibble, either extended by bytes of 255 when it is 15, the literals, and the offset of
+// the match as little endian u16. The last sequence has literals only.
+
+/// Size of the content held by a frame, offsets of matches within a frame fit into a u16.
+const FRAME_SIZE: usize = 64 * 1024;
+/// Size of the header of a frame.
+const HEADER_SIZE: usize = 9;
+/// Frame whose payload is the content as it is, for content that does not compress.
+const METHOD_STORED: u8 = 0;
+/// Frame whose payload is a sequence of LZ77 matches.
+const METHOD_LZ: u8 = 1;
+
+const MIN_MATCH: usize = 4;
+const HASH_BITS: u32 = 14;
+const NO_POSITION: u32 = u32::MAX;
+
+/// Number of earlier positions with the same hash tried for a match at every position, doubled
+/// with every level.
+fn search_depth(level: u32) -> usize {
+    1 << level.clamp(1, MAX_COMPRESSION_LEVEL)
+}
+
+/// Compresses everything read from `reader` into frames written to `writer`.
+/// Returns the number of bytes written.
+pub(crate) fn compress(
+    reader: &mut impl Read,
+    writer: &mut impl Write,
+    level: u32,
+) -> io::Result<u64> {
+    let depth = search_depth(level);
+    let mut content = Vec::with_capacity(FRAME_SIZE);
+    let mut payload = Vec::with_capacity(FRAME_SIZE);
+    let mut written = 0;
+    loop {
+        content.clear();
+        reader.take(FRAME_SIZE as u64).read_to_end(&mut content)?;
+        if content.is_empty() {
+            return Ok(written);
+        }
+
+        payload.clear();
+        compress_frame(&content, depth, &mut payload);
+        let (method, payload) = if payload.len() < content.len() {
+            (METHOD_LZ, &payload)
+        } else {
+            (METHOD_STORED, &content)
+        };
+        writer.write_all(&[method])?;
+        writer.write_all(&(content.len() as u32).to_le_bytes())?;
+        writer.write_all(&(payload.len() as u32).to_le_bytes())?;
+        writer.write_all(payload)?;
+        written += (HEADER_SIZE + payload.len()) as u64;
+    }
+}
+
+fn compress_frame(input: &[u8], depth: usize, out: &mut Vec<u8>) {
+    let mut head = vec![NO_POSITION; 1 << HASH_BITS];
+    let mut prev = vec![NO_POSITION; input.len()];
+    // positions are chained by their hash, from the latest one
+    let insert = |pos: usize, head: &mut [u32], prev: &mut [u32]| {
+        let hash = hash(&input[pos..pos + MIN_MATCH]);
+        prev[pos] = head[hash];
+        head[hash] = pos as u32;
+    };
+
+    let mut anchor = 0;
+    let mut pos = 0;
+    while pos + MIN_MATCH <= input.len() {
+        let (mut best_len, mut best_offset) = (0, 0);
+        let mut candidate = head[hash(&input[pos..pos + MIN_MATCH])];
+        let mut tries = 0;
+        while candidate != NO_POSITION && tries < depth {
+            let start = candidate as usize;
+            let len = input[pos..]
+                .iter()
+                .zip(&input[start..])
+                .take_while(|(a, b)| a == b)
+                .count();
+            if len > best_len {
+                (best_len, best_offset) = (len, pos - start);
+            }
+            candidate = prev[start];
+            tries += 1;
+        }
+
+        if best_len < MIN_MATCH {
+            insert(pos, &mut head, &mut prev);
+            pos += 1;
+            continue;
+        }
+        write_sequence(out, &input[anchor..pos], Some((best_offset, best_len)));
+        for skipped in pos..(pos + best_len).min(input.len() + 1 - MIN_MATCH) {
+            insert(skipped, &mut head, &mut prev);
+        }
+        pos += best_len;
+        anchor = pos;
+    }
+    write_sequence(out, &input[anchor..], None);
+}
+
+fn hash(bytes: &[u8]) -> usize {
+    let value = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    (value.wrapping_mul(2654435761) >> (32 - HASH_BITS)) as usize
+}
+
+fn write_sequence(out: &mut Vec<u8>, literals: &[u8], matched: Option<(usize, usize)>) {
+    let match_len = matched.map_or(0, |(_, len)| len - MIN_MATCH);
+    out.push(((literals.len().min(15) as u8) << 4) | match_len.min(15) as u8);
+    write_length(out, literals.len());
+    out.extend_from_slice(literals);
+    if let Some((offset, _)) = matched {
+        out.extend_from_slice(&(offset as u16).to_le_bytes());
+        write_length(out, match_len);
+    }
+}
+
+fn write_length(out: &mut Vec<u8>, len: usize) {
+    if len < 15 {
+        return;
+    }
+    let mut rest = len - 15;
+    while rest >= 255 {
+        out.push(255);
+        rest -= 255;
+    }
+    out.push(rest as u8);
+}
+
+fn corrupt(what: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Corrupt compressed blob: {}", what),
+    )
+}
+
+/// Position in the payload of a frame being decompressed.
+struct Cursor<'a> {
+    input: &'a [u8],
+    pos: usize,
+}
+
+impl Cursor<'_> {
+    fn byte(&mut self) -> io::Result<u8> {
+        let byte = self.input.get(self.pos).copied();
+        self.pos += 1;
+        byte.ok_or_else(|| corrupt("truncated frame"))
+    }
+
+    fn length(&mut self, nibble: u8) -> io::Result<usize> {
+        let mut len = nibble as usize;
+        if nibble == 15 {
+            loop {
+                let byte = self.byte()?;
+                len += byte as usize;
+                if byte != 255 {
+                    break;
+                }
+            }
+        }
+        Ok(len)
+    }
+}
+
+fn decompress_frame(input: &[u8], size: usize, out: &mut Vec<u8>) -> io::Result<()> {
+    let start = out.len();
+    let mut cursor = Cursor { input, pos: 0 };
+    loop {
+        let token = cursor.byte()?;
+        let literals = cursor.length(token >> 4)?;
+        let end = cursor.pos + literals;
+        if end > input.len() || out.len() - start + literals > size {
+            return Err(corrupt("literals out of bounds"));
+        }
+        out.extend_from_slice(&input[cursor.pos..end]);
+        cursor.pos = end;
+        if out.len() - start == size {
+            return if end == input.len() {
+                Ok(())
+            } else {
+                Err(corrupt("trailing bytes in frame"))
+            };
+        }
+
+        let offset = u16::from_le_bytes([cursor.byte()?, cursor.byte()?]) as usize;
+        let len = cursor.length(token & 15)? + MIN_MATCH;
+        if offset == 0 || offset > out.len() - start || out.len() - start + len > size {
+            return Err(corrupt("match out of bounds"));
+        }
+        // the match may overlap the bytes it produces, so it is copied byte by byte
+        let from = out.len() - offset;
+        for i in 0..len {
+            out.push(out[from + i]);
+        }
+    }
+}
+
+/// Header of a frame.
+struct Header {
+    method: u8,
+    size: usize,
+    payload_size: usize,
+}
+
+/// Reads the header of the next frame, None at the end of the content.
+fn read_header(reader: &mut impl Read) -> io::Result<Option<Header>> {
+    let mut header = [0u8; HEADER_SIZE];
+    let mut filled = 0;
+    while filled < HEADER_SIZE {
+        match reader.read(&mut header[filled..])? {
+            0 if filled == 0 => return Ok(None),
+            0 => return Err(corrupt("truncated frame header")),
+            read => filled += read,
+        }
+    }
+    let size = u32::from_le_bytes([header[1], header[2], header[3], header[4]]) as usize;
+    let payload_size = u32::from_le_bytes([header[5], header[6], header[7], header[8]]) as usize;
+    if size > FRAME_SIZE || payload_size > FRAME_SIZE || header[0] > METHOD_LZ {
+        return Err(corrupt("invalid frame header"));
+    }
+    Ok(Some(Header {
+        method: header[0],
+        size,
+        payload_size,
+    }))
+}
+
+/// Reads the payload of a frame and appends its content to `out`.
+fn read_frame(reader: &mut impl Read, header: &Header, out: &mut Vec<u8>) -> io::Result<()> {
+    let mut payload = vec![0u8; header.payload_size];
+    reader
+        .read_exact(&mut payload)
+        .map_err(|_| corrupt("truncated frame"))?;
+    match header.method {
+        METHOD_STORED if header.payload_size == header.size => {
+            out.extend_from_slice(&payload);
+            Ok(())
+        }
+        METHOD_STORED => Err(corrupt("invalid stored frame")),
+        _ => decompress_frame(&payload, header.size, out),
+    }
+}
+
+/// Streams the content of compressed frames.
+pub(crate) struct Decompressor<R> {
+    reader: R,
+    buffer: Vec<u8>,
+    consumed: usize,
+}
+
+impl<R: Read> Decompressor<R> {
+    pub(crate) fn new(reader: R) -> Self {
+        Decompressor {
+            reader,
+            buffer: Vec::with_capacity(FRAME_SIZE),
+            consumed: 0,
+        }
+    }
+}
+
+impl<R: Read> Read for Decompressor<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.consumed == self.buffer.len() {
+            self.buffer.clear();
+            self.consumed = 0;
+            match read_header(&mut self.reader)? {
+                Some(header) => read_frame(&mut self.reader, &header, &mut self.buffer)?,
+                None => return Ok(0),
+            }
+        }
+        let len = buf.len().min(self.buffer.len() - self.consumed);
+        buf[..len].copy_from_slice(&self.buffer[self.consumed..self.consumed + len]);
+        self.consumed += len;
+        Ok(len)
+    }
+}
+
+/// Reads up to `len` bytes of the content starting at `offset`, seeking over the frames before it.
+pub(crate) fn read_range(
+    reader: &mut (impl Read + Seek),
+    offset: u64,
+    len: u64,
+) -> io::Result<Vec<u8>> {
+    let mut content = Vec::new();
+    let mut position = 0;
+    while (content.len() as u64) < len {
+        let Some(header) = read_header(reader)? else {
+            break;
+        };
+        let end = position + header.size as u64;
+        if end <= offset {
+            reader.seek(SeekFrom::Current(header.payload_size as i64))?;
+            position = end;
+            continue;
+        }
+
+        let mut frame = Vec::with_capacity(header.size);
+        read_frame(reader, &header, &mut frame)?;
+        let from = offset.saturating_sub(position) as usize;
+        let wanted = (len - content.len() as u64).min((frame.len() - from) as u64) as usize;
+        content.extend_from_slice(&frame[from..from + wanted]);
+        position = end;
+    }
+    Ok(content)
+}
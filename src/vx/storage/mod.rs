@@ -1,11 +1,74 @@
 pub mod blob;
 pub mod branch;
+mod codec;
 pub mod commit;
+mod compress;
 pub mod repo;
+pub mod stash;
+pub mod tag;
 pub mod tree;
 
+use crate::core::bisect::Bisect;
+use crate::core::branch::Branch;
+use crate::core::commit::{Commit, CurrentCommitSpec, PendingCommit, PendingRebuild};
+use crate::core::stash::Stash;
+use crate::core::tag::Tag;
+use crate::core::tree::{IndexEntry, PendingCheckout, Tree};
+use codec::Versioned;
+use std::io;
+
 /// The name of the database file.
 const BRANCHES_FILE_NAME: &str = "branches.db";
 const COMMITS_FILE_NAME: &str = "commits.db";
-const REPO_FILE_NAME: &str = "repo.db";
+pub(crate) const REPO_FILE_NAME: &str = "repo.db";
+const STASHES_FILE_NAME: &str = "stashes.db";
+const TAGS_FILE_NAME: &str = "tags.db";
 const BLOBS_FOLDER_NAME: &str = "blobs";
+
+/// Names of all databases of the workspace.
+const DB_FILE_NAMES: &[&str] = &[
+    blob::BLOB_DB_FILE_NAME,
+    BRANCHES_FILE_NAME,
+    COMMITS_FILE_NAME,
+    REPO_FILE_NAME,
+    STASHES_FILE_NAME,
+    TAGS_FILE_NAME,
+    tree::TREE_FILE_NAME,
+];
+
+/// Current schema versions of the stored records by their names, see `codec::Versioned`.
+pub(crate) fn schema_versions() -> Vec<(&'static str, u8)> {
+    vec![
+        ("bisect", Bisect::VERSION),
+        ("blob", blob::StoredBlob::VERSION),
+        ("branch", Branch::VERSION),
+        ("commit", Vec::<Commit>::VERSION),
+        ("currentCommit", CurrentCommitSpec::VERSION),
+        ("indexEntry", IndexEntry::VERSION),
+        ("pendingCheckout", PendingCheckout::VERSION),
+        ("pendingCommit", PendingCommit::VERSION),
+        ("pendingRebuild", PendingRebuild::VERSION),
+        ("stash", Stash::VERSION),
+        ("tag", Tag::VERSION),
+        ("tree", Tree::VERSION),
+    ]
+}
+
+/// Filesystem failures that call for an action of the user rather than a retry, so stores report
+/// them with their own error variants.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum IoProblem {
+    OutOfSpace,
+    PermissionDenied,
+}
+
+/// Tells whether an I/O failure calls for an action of the user.
+pub(crate) fn io_problem(err: &io::Error) -> Option<IoProblem> {
+    match err.kind() {
+        io::ErrorKind::StorageFull | io::ErrorKind::QuotaExceeded => Some(IoProblem::OutOfSpace),
+        io::ErrorKind::PermissionDenied | io::ErrorKind::ReadOnlyFilesystem => {
+            Some(IoProblem::PermissionDenied)
+        }
+        _ => None,
+    }
+}
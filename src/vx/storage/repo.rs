@@ -1,10 +1,12 @@
-use crate::context::Context;
-use crate::core::repo::Repo;
+use crate::context::{Context, DbTuning};
+use crate::core::repo::{PackedDb, Repo};
 use crate::global::DATA_FOLDER;
-use crate::storage::REPO_FILE_NAME;
+use crate::storage::{DB_FILE_NAMES, REPO_FILE_NAME};
 use sled::Error as SledError;
 use std::collections::HashMap;
 use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
 use thiserror::Error;
 
 /// Represents errors that can occur while handling repositories.
@@ -19,6 +21,12 @@ pub enum RepoError {
     #[error("Invalid repository name: {0}")]
     InvalidName(String),
 
+    #[error("Checkout has {0} uncommitted change(s)")]
+    UncommittedChanges(usize),
+
+    #[error("Current directory is inside the checkout of the repository at {0}")]
+    NestedRepo(PathBuf),
+
     #[error("IO error: {0}")]
     IoError(#[from] std::io::Error),
 
@@ -32,10 +40,25 @@ pub enum RepoError {
     Other(String),
 }
 
-/// Creates a new repository.
-pub fn new(name: String, metadata: HashMap<String, String>) -> Result<(Repo, Context), RepoError> {
-    let current_dir = std::env::current_dir()?;
-    let repo_path = current_dir.join(&name);
+/// Creates a new repository in the current directory, or a throwaway one in the system temporary
+/// directory if `temporary` is set.
+pub fn new(
+    name: String,
+    metadata: HashMap<String, String>,
+    temporary: bool,
+) -> Result<(Repo, Context), RepoError> {
+    let repo_path = if temporary {
+        // unique per process and call, so concurrent throwaway repositories do not collide
+        static COUNTER: AtomicU64 = AtomicU64::new(0);
+        std::env::temp_dir().join(format!(
+            "vx-{}-{}-{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed),
+            name.replace('/', "-")
+        ))
+    } else {
+        std::env::current_dir()?.join(&name)
+    };
 
     // Try to create repository directory atomically
     match fs::create_dir(&repo_path) {
@@ -50,10 +73,17 @@ pub fn new(name: String, metadata: HashMap<String, String>) -> Result<(Repo, Con
 
     // Create .vx workspace directory
     let workspace_path = repo_path.join(DATA_FOLDER);
-    fs::create_dir_all(&workspace_path)?;
+    let context = if temporary {
+        Context::new_temporary(workspace_path, repo_path)
+    } else {
+        Context::new(workspace_path, repo_path)
+    };
+    fs::create_dir_all(&context.workspace_path)?;
+    // the metadata is known, reading it back would reopen the repository database right away
+    context.set_db_tuning(DbTuning::from_metadata(&metadata)?);
 
     // Open repo database and create metadata tree
-    let db = sled::open(workspace_path.join(REPO_FILE_NAME))?;
+    let db = context.open_db(REPO_FILE_NAME)?;
     let metadata_tree = db.open_tree("metadata")?;
 
     // Save each metadata key-value pair separately
@@ -61,9 +91,103 @@ pub fn new(name: String, metadata: HashMap<String, String>) -> Result<(Repo, Con
         let full_key = format!("{}:{}", name, key);
         metadata_tree.insert(full_key.as_bytes(), value.as_bytes())?;
     }
-    metadata_tree.flush()?;
-
-    let context = Context::new(workspace_path, repo_path);
+    context.flush(&metadata_tree)?;
 
     Ok((Repo { name, metadata }, context))
 }
+
+/// Sets a metadata entry of the repository, or removes it if `value` is None.
+pub fn set_metadata(context: &Context, key: &str, value: Option<&str>) -> Result<(), RepoError> {
+    let db = context.open_db(REPO_FILE_NAME)?;
+    let metadata_tree = db.open_tree("metadata")?;
+
+    let full_key = format!("{}:{}", get_name(context, &metadata_tree)?, key);
+    match value {
+        Some(value) => metadata_tree.insert(full_key.as_bytes(), value.as_bytes())?,
+        None => metadata_tree.remove(full_key.as_bytes())?,
+    };
+    context.flush(&metadata_tree)?;
+    Ok(())
+}
+
+/// Reads the name of the repository.
+pub fn name(context: &Context) -> Result<String, RepoError> {
+    let db = context.open_db(REPO_FILE_NAME)?;
+    let metadata_tree = db.open_tree("metadata")?;
+    get_name(context, &metadata_tree)
+}
+
+/// Gets the name of the repository that prefixes metadata keys.
+fn get_name(context: &Context, metadata_tree: &sled::Tree) -> Result<String, RepoError> {
+    // The name is only recorded as the prefix of metadata keys, a repository created without
+    // metadata is named after its checkout folder.
+    if let Some(item) = metadata_tree.iter().next() {
+        let (key, _) = item?;
+        if let Some((name, _)) = String::from_utf8_lossy(&key).split_once(':') {
+            return Ok(name.to_string());
+        }
+    }
+    context
+        .checkout_path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .ok_or_else(|| RepoError::Other("Failed to determine the repository name".to_string()))
+}
+
+/// Reads the repository metadata.
+pub fn get_metadata(context: &Context) -> Result<HashMap<String, String>, RepoError> {
+    let db = context.open_db(REPO_FILE_NAME)?;
+    let metadata_tree = db.open_tree("metadata")?;
+
+    let mut metadata = HashMap::new();
+    for item in metadata_tree.iter() {
+        let (key, value) = item?;
+        let key = String::from_utf8_lossy(&key);
+        // keys are prefixed with the repository name, which can not contain ':'
+        if let Some((_, key)) = key.split_once(':') {
+            metadata.insert(
+                key.to_string(),
+                String::from_utf8_lossy(&value).into_owned(),
+            );
+        }
+    }
+    Ok(metadata)
+}
+
+/// Compacts every database of the workspace, skipping the ones that were never created.
+pub fn compact(context: &Context) -> Result<Vec<PackedDb>, RepoError> {
+    let mut packed = Vec::new();
+    for &name in DB_FILE_NAMES {
+        if let Some((size_before, size_after)) = context.compact_db(name)? {
+            packed.push(PackedDb {
+                name: name.to_string(),
+                size_before,
+                size_after,
+            });
+        }
+    }
+    Ok(packed)
+}
+
+/// Opens every database of the workspace once, returning the ones that fail with the reason, e.g.
+/// being held by another process.
+pub fn find_unavailable(context: &Context) -> Vec<(String, String)> {
+    let mut unavailable = Vec::new();
+    for &name in DB_FILE_NAMES {
+        if let Err(e) = context.open_db(name) {
+            unavailable.push((name.to_string(), e.to_string()));
+        }
+    }
+    unavailable
+}
+
+/// Removes the workspace of the repository, or the whole checkout along with it if
+/// `with_checkout` is set.
+pub fn destroy(context: &Context, with_checkout: bool) -> Result<(), RepoError> {
+    if with_checkout {
+        fs::remove_dir_all(&context.checkout_path)?;
+    } else {
+        fs::remove_dir_all(&context.workspace_path)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,137 @@
+use crate::context::Context;
+use crate::core::stash::Stash;
+use crate::storage::codec::{self, Versioned};
+use crate::storage::STASHES_FILE_NAME;
+use sled::Db;
+use thiserror::Error;
+
+/// Represents errors that can occur while handling stashes.
+#[derive(Error, Debug)]
+pub enum StashError {
+    #[error("Database error: {0}")]
+    DatabaseError(#[from] sled::Error),
+
+    #[error("Serialization/Deserialization error: {0}")]
+    SerializationError(#[from] bincode::Error),
+
+    #[error("Stash not found")]
+    NotFound,
+
+    #[error("No local changes to stash")]
+    NoChanges,
+
+    #[error("Stash with id {0} already exists")]
+    StashExists(u64),
+
+    #[error("{0}")]
+    Other(String),
+}
+
+impl Versioned for Stash {
+    const VERSION: u8 = 1;
+}
+
+/// Opens stash store.
+fn open(context: &Context) -> Result<Db, StashError> {
+    let db = context.open_db(STASHES_FILE_NAME)?;
+    Ok(db)
+}
+
+/// Returns the id for the next stash, one above the latest one.
+pub fn next_id(context: &Context) -> Result<u64, StashError> {
+    let db = open(context)?;
+    match db.last()? {
+        Some((key, _value)) => Ok(decode_id(&key)? + 1),
+        None => Ok(1),
+    }
+}
+
+/// Saves a new stash, an existing stash with the same id is never overwritten.
+pub fn new(context: &Context, stash: &Stash) -> Result<(), StashError> {
+    let db = open(context)?;
+    let value = codec::encode(stash)?;
+
+    let result = db.compare_and_swap(stash.id.to_be_bytes(), None as Option<&[u8]>, Some(value))?;
+    match result {
+        Ok(()) => {
+            context.flush(&db)?;
+            Ok(())
+        }
+        Err(_) => Err(StashError::StashExists(stash.id)),
+    }
+}
+
+/// Gets stash by id.
+pub fn get(context: &Context, id: u64) -> Result<Stash, StashError> {
+    let db = open(context)?;
+    match db.get(id.to_be_bytes())? {
+        Some(ivec) => {
+            let stash: Stash = codec::decode(&ivec)?;
+            Ok(stash)
+        }
+        None => Err(StashError::NotFound),
+    }
+}
+
+/// Gets the latest stash.
+pub fn get_latest(context: &Context) -> Result<Stash, StashError> {
+    let db = open(context)?;
+    match db.last()? {
+        Some((_key, value)) => {
+            let stash: Stash = codec::decode(&value)?;
+            Ok(stash)
+        }
+        None => Err(StashError::NotFound),
+    }
+}
+
+/// Lists all stashes, sorted by id.
+pub fn list(context: &Context) -> Result<Vec<Stash>, StashError> {
+    let db = open(context)?;
+    let mut stashes = Vec::new();
+    // ids are stored big endian, so sled iterates them in numeric order
+    for item in db.iter() {
+        let (_key, value) = item?;
+        let stash: Stash = codec::decode(&value)?;
+        stashes.push(stash);
+    }
+    Ok(stashes)
+}
+
+/// Points the stashes based on commits of one branch to the same commits of another branch id,
+/// e.g. the one of the new name of a renamed branch.
+pub fn move_branch(context: &Context, from: u64, to: u64) -> Result<(), StashError> {
+    let db = open(context)?;
+    let mut batch = sled::Batch::default();
+    for item in db.iter() {
+        let (key, value) = item?;
+        let mut stash: Stash = codec::decode(&value)?;
+        if stash.base.branch == from {
+            stash.base.branch = to;
+            batch.insert(key, codec::encode(&stash)?);
+        }
+    }
+    db.apply_batch(batch)?;
+    context.flush(&db)?;
+    Ok(())
+}
+
+/// Deletes a stash by id.
+pub fn delete(context: &Context, id: u64) -> Result<Stash, StashError> {
+    let db = open(context)?;
+    match db.remove(id.to_be_bytes())? {
+        Some(ivec) => {
+            context.flush(&db)?;
+            let stash: Stash = codec::decode(&ivec)?;
+            Ok(stash)
+        }
+        None => Err(StashError::NotFound),
+    }
+}
+
+fn decode_id(key: &[u8]) -> Result<u64, StashError> {
+    let bytes: [u8; 8] = key
+        .try_into()
+        .map_err(|_| StashError::Other("Invalid stash key".to_string()))?;
+    Ok(u64::from_be_bytes(bytes))
+}
@@ -0,0 +1,110 @@
+use crate::context::Context;
+use crate::core::tag::Tag;
+use crate::storage::codec::{self, Versioned};
+use crate::storage::TAGS_FILE_NAME;
+use sled::Db;
+use thiserror::Error;
+
+/// Represents errors that can occur while handling tags.
+#[derive(Error, Debug)]
+pub enum TagError {
+    #[error("Database error: {0}")]
+    DatabaseError(#[from] sled::Error),
+
+    #[error("Serialization/Deserialization error: {0}")]
+    SerializationError(#[from] bincode::Error),
+
+    #[error("Tag not found")]
+    NotFound,
+
+    #[error("Tag with name '{0}' already exists")]
+    TagExists(String),
+
+    #[error("Invalid tag name: {0}")]
+    InvalidName(String),
+
+    #[error("{0}")]
+    Other(String),
+}
+
+impl Versioned for Tag {
+    const VERSION: u8 = 1;
+}
+
+/// Opens tag store.
+fn open(context: &Context) -> Result<Db, TagError> {
+    let db = context.open_db(TAGS_FILE_NAME)?;
+    Ok(db)
+}
+
+/// Saves a new tag. Tags are immutable, so an existing tag with the same name is never overwritten.
+pub fn new(context: &Context, tag: &Tag) -> Result<(), TagError> {
+    let db = open(context)?;
+    let value = codec::encode(tag)?;
+
+    // Attempt to atomically insert the tag only if no record with the same name exists.
+    let result = db.compare_and_swap(tag.name.as_bytes(), None as Option<&[u8]>, Some(value))?;
+    match result {
+        Ok(()) => {
+            context.flush(&db)?;
+            Ok(())
+        }
+        Err(_) => Err(TagError::TagExists(tag.name.clone())),
+    }
+}
+
+/// Gets tag by name.
+pub fn get(context: &Context, name: &str) -> Result<Tag, TagError> {
+    let db = open(context)?;
+    match db.get(name.as_bytes())? {
+        Some(ivec) => {
+            let tag: Tag = codec::decode(&ivec)?;
+            Ok(tag)
+        }
+        None => Err(TagError::NotFound),
+    }
+}
+
+/// Lists all tags, sorted by name.
+pub fn list(context: &Context) -> Result<Vec<Tag>, TagError> {
+    let db = open(context)?;
+    let mut tags = Vec::new();
+    // sled iterates keys in lexicographic order, so tags come out sorted by name
+    for item in db.iter() {
+        let (_key, value) = item?;
+        let tag: Tag = codec::decode(&value)?;
+        tags.push(tag);
+    }
+    Ok(tags)
+}
+
+/// Points the tags of the commits of one branch to the same commits of another branch id, e.g.
+/// the one of the new name of a renamed branch.
+pub fn move_branch(context: &Context, from: u64, to: u64) -> Result<(), TagError> {
+    let db = open(context)?;
+    let mut batch = sled::Batch::default();
+    for item in db.iter() {
+        let (key, value) = item?;
+        let mut tag: Tag = codec::decode(&value)?;
+        if tag.commit_id.branch == from {
+            tag.commit_id.branch = to;
+            batch.insert(key, codec::encode(&tag)?);
+        }
+    }
+    db.apply_batch(batch)?;
+    context.flush(&db)?;
+    Ok(())
+}
+
+/// Deletes a tag by name.
+pub fn delete(context: &Context, name: &str) -> Result<Tag, TagError> {
+    let db = open(context)?;
+    match db.remove(name.as_bytes())? {
+        Some(ivec) => {
+            context.flush(&db)?;
+            let tag: Tag = codec::decode(&ivec)?;
+            Ok(tag)
+        }
+        None => Err(TagError::NotFound),
+    }
+}
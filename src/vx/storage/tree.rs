@@ -1,40 +1,286 @@
 use crate::context::Context;
+use crate::core::blob::{Blob, ContentKind};
 use crate::core::digest::Digest;
-use crate::core::tree::Tree as VxTree;
+use crate::core::tree::{File, Folder, FolderEntry, IndexEntry, PendingCheckout, Tree as VxTree};
+use crate::core::xattr::Xattr;
+use crate::storage::blob::{BlobError, BlobV1};
+use crate::storage::codec::{self, Versioned};
+use crate::storage::commit::CommitError;
+use crate::storage::{io_problem, IoProblem};
+use serde::Deserialize;
 use sled::Db;
+use std::path::{Path, PathBuf};
 use thiserror::Error;
 
 /// Represents errors that can occur while handling tree operations.
 #[derive(Error, Debug)]
 pub enum TreeError {
     #[error("Database error: {0}")]
-    DatabaseError(#[from] sled::Error),
+    DatabaseError(sled::Error),
 
     #[error("Serialization/Deserialization error: {0}")]
     SerializationError(#[from] bincode::Error),
 
     #[error("Filesystem error: {0}")]
-    IoError(#[from] std::io::Error),
+    IoError(std::io::Error),
+
+    #[error("Out of disk space: {0}")]
+    OutOfSpace(std::io::Error),
+
+    #[error("Permission denied: {0}")]
+    PermissionDenied(std::io::Error),
 
     #[error("Tree not found")]
     TreeNotFound,
 
+    #[error("Path '{}' not found", .0.display())]
+    PathNotFound(PathBuf),
+
+    #[error("Failed to resolve commit: {0}")]
+    Resolve(CommitError),
+
+    #[error("Checkout of commit {0} was interrupted, finish it before making changes")]
+    CheckoutInterrupted(String),
+
     #[error("{0}")]
     Other(String),
 }
 
-const TREE_FILE_NAME: &str = "tree.db";
+impl From<std::io::Error> for TreeError {
+    fn from(err: std::io::Error) -> Self {
+        match io_problem(&err) {
+            Some(IoProblem::OutOfSpace) => TreeError::OutOfSpace(err),
+            Some(IoProblem::PermissionDenied) => TreeError::PermissionDenied(err),
+            None => TreeError::IoError(err),
+        }
+    }
+}
+
+impl From<sled::Error> for TreeError {
+    fn from(err: sled::Error) -> Self {
+        match err {
+            sled::Error::Io(err) if io_problem(&err).is_some() => err.into(),
+            err => TreeError::DatabaseError(err),
+        }
+    }
+}
+
+impl TreeError {
+    /// Wraps a failure of the blob store with a description of what failed, failures calling for
+    /// an action of the user are kept as they are.
+    pub fn from_blob(description: &str, err: BlobError) -> Self {
+        match err {
+            BlobError::OutOfSpace(err) => TreeError::OutOfSpace(err),
+            BlobError::PermissionDenied(err) => TreeError::PermissionDenied(err),
+            err => TreeError::Other(format!("{}: {:?}", description, err)),
+        }
+    }
+}
+
+impl Versioned for VxTree {
+    const VERSION: u8 = 4;
+
+    fn upgrade(version: u8, payload: &[u8]) -> Result<Self, bincode::Error> {
+        match version {
+            1 => {
+                let tree: TreeV1 = codec::decode_payload(payload)?;
+                Ok(VxTree {
+                    hash: tree.hash,
+                    folders: tree.folders,
+                    files: tree
+                        .files
+                        .into_iter()
+                        .map(|file| File {
+                            name: file.name,
+                            blob: file.blob.upgrade(),
+                            xattrs: Vec::new(),
+                            symlink: false,
+                        })
+                        .collect(),
+                    size: tree.size,
+                    file_count: tree.file_count,
+                    folder_count: tree.folder_count,
+                })
+            }
+            2 => {
+                let tree: TreeV2 = codec::decode_payload(payload)?;
+                Ok(VxTree {
+                    hash: tree.hash,
+                    folders: tree.folders,
+                    files: tree
+                        .files
+                        .into_iter()
+                        .map(|file| File {
+                            name: file.name,
+                            blob: file.blob,
+                            xattrs: Vec::new(),
+                            symlink: false,
+                        })
+                        .collect(),
+                    size: tree.size,
+                    file_count: tree.file_count,
+                    folder_count: tree.folder_count,
+                })
+            }
+            3 => {
+                let tree: TreeV3 = codec::decode_payload(payload)?;
+                Ok(VxTree {
+                    hash: tree.hash,
+                    folders: tree.folders,
+                    files: tree
+                        .files
+                        .into_iter()
+                        .map(|file| File {
+                            name: file.name,
+                            blob: file.blob,
+                            xattrs: file.xattrs,
+                            symlink: false,
+                        })
+                        .collect(),
+                    size: tree.size,
+                    file_count: tree.file_count,
+                    folder_count: tree.folder_count,
+                })
+            }
+            _ => Err(codec::unsupported(version)),
+        }
+    }
+}
+
+impl Versioned for IndexEntry {
+    const VERSION: u8 = 3;
+
+    fn upgrade(version: u8, payload: &[u8]) -> Result<Self, bincode::Error> {
+        match version {
+            1 => {
+                let entry: IndexEntryV1 = codec::decode_payload(payload)?;
+                Ok(IndexEntry {
+                    mtime: entry.mtime,
+                    size: entry.size,
+                    contenthash: entry.contenthash,
+                    kind: ContentKind::Unknown,
+                    normalized: false,
+                })
+            }
+            2 => {
+                let entry: IndexEntryV2 = codec::decode_payload(payload)?;
+                Ok(IndexEntry {
+                    mtime: entry.mtime,
+                    size: entry.size,
+                    contenthash: entry.contenthash,
+                    kind: entry.kind,
+                    normalized: false,
+                })
+            }
+            _ => Err(codec::unsupported(version)),
+        }
+    }
+}
+
+/// Tree layout of schema version 1, before blobs carried the kind of their content.
+#[derive(Deserialize)]
+struct TreeV1 {
+    hash: Digest,
+    folders: Vec<Folder>,
+    files: Vec<FileV1>,
+    size: u64,
+    file_count: u64,
+    folder_count: u64,
+}
+
+/// File layout of schema version 1 of trees.
+#[derive(Deserialize)]
+struct FileV1 {
+    name: String,
+    blob: BlobV1,
+}
+
+/// Tree layout of schema version 2, before files carried extended attributes.
+#[derive(Deserialize)]
+struct TreeV2 {
+    hash: Digest,
+    folders: Vec<Folder>,
+    files: Vec<FileV2>,
+    size: u64,
+    file_count: u64,
+    folder_count: u64,
+}
+
+/// File layout of schema version 2 of trees.
+#[derive(Deserialize)]
+struct FileV2 {
+    name: String,
+    blob: Blob,
+}
+
+/// Tree layout of schema version 3, before symbolic links were recorded.
+#[derive(Deserialize)]
+struct TreeV3 {
+    hash: Digest,
+    folders: Vec<Folder>,
+    files: Vec<FileV3>,
+    size: u64,
+    file_count: u64,
+    folder_count: u64,
+}
+
+/// File layout of schema version 3 of trees.
+#[derive(Deserialize)]
+struct FileV3 {
+    name: String,
+    blob: Blob,
+    xattrs: Vec<Xattr>,
+}
+
+/// Index entry layout of schema version 1, before the kind of content was recorded.
+#[derive(Deserialize)]
+struct IndexEntryV1 {
+    mtime: u128,
+    size: u64,
+    contenthash: Digest,
+}
+
+/// Index entry layout of schema version 2, before line ending normalization was recorded.
+#[derive(Deserialize)]
+struct IndexEntryV2 {
+    mtime: u128,
+    size: u64,
+    contenthash: Digest,
+    kind: ContentKind,
+}
+
+impl Versioned for PendingCheckout {
+    const VERSION: u8 = 1;
+}
+
+impl Versioned for FolderEntry {
+    const VERSION: u8 = 1;
+}
+
+pub(super) const TREE_FILE_NAME: &str = "tree.db";
+const INDEX_TREE: &str = "index";
+const FOLDER_INDEX_TREE: &str = "folders";
+const INTENTS_TREE: &str = "intents";
+const CHECKOUT_KEY: &[u8] = b"checkout";
+
+/// Index of files in the checkout, keyed by path relative to the checkout root.
+pub type Index = sled::Tree;
 
 /// Opens the database and returns a specific tree.
 pub fn open(context: &Context) -> Result<Db, TreeError> {
-    let db = sled::open(context.workspace_path.join(TREE_FILE_NAME))?;
+    let db = context.open_db(TREE_FILE_NAME)?;
     Ok(db)
 }
 
 /// Saves a tree to the database.
+/// Trees are keyed by the hash of their content, so a tree that is already stored is identical
+/// and is not written again, e.g. when identical subtrees are saved by parallel workers.
 pub fn save(db: &Db, tree: &VxTree) -> Result<(), TreeError> {
     let key = tree.hash.to_be_bytes();
-    let value = bincode::serialize(tree)?;
+    if db.contains_key(key)? {
+        return Ok(());
+    }
+    let value = codec::encode(tree)?;
 
     db.insert(key, value)?;
     // it is up to the caller to flush when needed
@@ -47,9 +293,124 @@ pub fn get(db: &Db, hash: Digest) -> Result<VxTree, TreeError> {
 
     match db.get(key)? {
         Some(ivec) => {
-            let tree: VxTree = bincode::deserialize(&ivec)?;
+            let tree: VxTree = codec::decode(&ivec)?;
             Ok(tree)
         }
         None => Err(TreeError::TreeNotFound),
     }
 }
+
+/// Deletes trees from the database, trees not stored are skipped.
+pub fn delete(context: &Context, db: &Db, hashes: &[Digest]) -> Result<(), TreeError> {
+    for hash in hashes {
+        db.remove(hash.to_be_bytes())?;
+    }
+    context.flush(db)?;
+    Ok(())
+}
+
+/// Counts trees stored in the database.
+pub fn count(db: &Db) -> Result<u64, TreeError> {
+    // the default sled tree only contains vx trees, the index is kept separately
+    Ok(db.len() as u64)
+}
+
+/// Lists hashes of all trees stored in the database.
+pub fn list_hashes(db: &Db) -> Result<Vec<Digest>, TreeError> {
+    let mut hashes = Vec::with_capacity(db.len());
+    for key in db.iter().keys() {
+        let key = key?;
+        let bytes: [u8; 16] = key
+            .as_ref()
+            .try_into()
+            .map_err(|_| TreeError::Other(format!("Invalid tree key: {:?}", key)))?;
+        hashes.push(Digest::from_be_bytes(bytes));
+    }
+    Ok(hashes)
+}
+
+/// Opens the index of the files in the checkout.
+pub fn open_index(db: &Db) -> Result<Index, TreeError> {
+    let index = db.open_tree(INDEX_TREE)?;
+    Ok(index)
+}
+
+/// Retrieves an index entry for a file by its path relative to the checkout root.
+pub fn get_index_entry(index: &Index, path: &Path) -> Result<Option<IndexEntry>, TreeError> {
+    match index.get(path.as_os_str().as_encoded_bytes())? {
+        Some(ivec) => Ok(Some(codec::decode(&ivec)?)),
+        None => Ok(None),
+    }
+}
+
+/// Saves an index entry for a file by its path relative to the checkout root.
+pub fn save_index_entry(index: &Index, path: &Path, entry: &IndexEntry) -> Result<(), TreeError> {
+    let value = codec::encode(entry)?;
+    index.insert(path.as_os_str().as_encoded_bytes(), value)?;
+    // it is up to the caller to flush when needed
+    Ok(())
+}
+
+/// Opens the index of the folders in the checkout, kept apart from the files so that a file
+/// replacing a folder of the same path never reads its entry.
+pub fn open_folder_index(db: &Db) -> Result<Index, TreeError> {
+    let index = db.open_tree(FOLDER_INDEX_TREE)?;
+    Ok(index)
+}
+
+/// Retrieves an index entry for a folder by its path relative to the checkout root.
+pub fn get_folder_entry(index: &Index, path: &Path) -> Result<Option<FolderEntry>, TreeError> {
+    match index.get(path.as_os_str().as_encoded_bytes())? {
+        Some(ivec) => Ok(Some(codec::decode(&ivec)?)),
+        None => Ok(None),
+    }
+}
+
+/// Saves an index entry for a folder by its path relative to the checkout root.
+pub fn save_folder_entry(index: &Index, path: &Path, entry: &FolderEntry) -> Result<(), TreeError> {
+    let value = codec::encode(entry)?;
+    index.insert(path.as_os_str().as_encoded_bytes(), value)?;
+    // it is up to the caller to flush when needed
+    Ok(())
+}
+
+/// Removes all entries of the index, e.g. once every file of the checkout has been rewritten.
+pub fn clear_index(index: &Index) -> Result<(), TreeError> {
+    index.clear()?;
+    Ok(())
+}
+
+/// Lists content hashes of all files in the index.
+pub fn list_index_hashes(index: &Index) -> Result<Vec<Digest>, TreeError> {
+    let mut hashes = Vec::with_capacity(index.len());
+    for value in index.iter().values() {
+        let entry: IndexEntry = codec::decode(&value?)?;
+        hashes.push(entry.contenthash);
+    }
+    Ok(hashes)
+}
+
+/// Records the intent to check out a commit, before the checkout touches any file.
+pub fn save_checkout_intent(db: &Db, pending: &PendingCheckout) -> Result<(), TreeError> {
+    let intents = db.open_tree(INTENTS_TREE)?;
+    intents.insert(CHECKOUT_KEY, codec::encode(pending)?)?;
+    intents.flush()?;
+    Ok(())
+}
+
+/// Gets the intent of a checkout that has not completed, if any.
+pub fn get_checkout_intent(db: &Db) -> Result<Option<PendingCheckout>, TreeError> {
+    let intents = db.open_tree(INTENTS_TREE)?;
+    match intents.get(CHECKOUT_KEY)? {
+        Some(ivec) => Ok(Some(codec::decode(&ivec)?)),
+        None => Ok(None),
+    }
+}
+
+/// Clears the intent once the checkout has completed.
+pub fn clear_checkout_intent(db: &Db) -> Result<(), TreeError> {
+    let intents = db.open_tree(INTENTS_TREE)?;
+    intents.remove(CHECKOUT_KEY)?;
+    intents.flush()?;
+    Ok(())
+}
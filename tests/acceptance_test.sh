@@ -22,6 +22,25 @@ check_success() {
     fi
 }
 
+# Function to create a repository of its own for a step in the test directory and enter it,
+# with the name of the repository, the description to check its creation with and the options
+# of `repo new`
+new_step_repo() {
+    local name="$1" description="$2"
+    shift 2
+    cd "$TEST_DIR"
+    "$VX_PATH" repo new "$name" "$@"
+    check_success "$description"
+    cd "$name"
+}
+
+# Function to destroy the repository of a step along with its checkout and return to the main one
+destroy_step_repo() {
+    cd "$TEST_DIR"
+    (cd "$1" && "$VX_PATH" repo destroy --yes --checkout --force)
+    cd "$REPO_DIR"
+}
+
 # Create a temp directory for the test
 TEST_DIR=$(mktemp -d)
 echo "Running test in: $TEST_DIR"
@@ -30,7 +49,7 @@ cd "$TEST_DIR"
 # Build the vx binary
 print_step "Building vx binary"
 cd -
-cargo build --release
+cargo build --release --features failpoints
 check_success "Build vx binary"
 
 # Get the path to the built binary
@@ -147,6 +166,2802 @@ else
     exit 1
 fi
 
+# Step 14: Test tag functionality
+print_step "14. Testing tag functionality"
+"$VX_PATH" tag add v1.0 main:"$COMMIT_SEQ"
+check_success "Create tag"
+
+"$VX_PATH" tree checkout v1.0
+if [ "$(cat file1.txt)" = "Hello, World!" ] && [ ! -f new-branch-file.txt ]; then
+    echo -e "${GREEN}SUCCESS: Checked out tagged commit${NC}"
+else
+    echo -e "${RED}FAILED: Tagged commit content verification failed${NC}"
+    exit 1
+fi
+
+"$VX_PATH" tag delete v1.0
+if [ -z "$("$VX_PATH" tag list)" ]; then
+    echo -e "${GREEN}SUCCESS: Tag was deleted${NC}"
+else
+    echo -e "${RED}FAILED: Tag deletion verification failed${NC}"
+    exit 1
+fi
+
+# Step 15: Test commit history graph
+print_step "15. Testing commit history graph"
+if "$VX_PATH" commit list feature-branch --graph | grep -q "feature-branch forked from main"; then
+    echo -e "${GREEN}SUCCESS: Commit graph shows the branch fork${NC}"
+else
+    echo -e "${RED}FAILED: Commit graph verification failed${NC}"
+    exit 1
+fi
+
+# Step 16: Verify the blob store
+print_step "16. Verifying the blob store"
+"$VX_PATH" blob verify-all
+check_success "Blob store verification"
+
+# Step 17: Test recovery of an interrupted commit
+print_step "17. Testing recovery of an interrupted commit"
+echo "Interrupted change" > interrupted-file.txt
+if VX_FAILPOINT=commit-before-advance "$VX_PATH" commit new "Interrupted commit"; then
+    echo -e "${RED}FAILED: Injected failure did not interrupt the commit${NC}"
+    exit 1
+fi
+
+# The next command completes the pending commit
+if "$VX_PATH" commit list | grep -q "Interrupted commit" && \
+   "$VX_PATH" tree status | grep -q "No files changed"; then
+    echo -e "${GREEN}SUCCESS: Interrupted commit was recovered${NC}"
+else
+    echo -e "${RED}FAILED: Interrupted commit recovery failed${NC}"
+    exit 1
+fi
+
+# Step 18: Test checkout keeping local changes
+print_step "18. Testing checkout with merge of local changes"
+"$VX_PATH" tree checkout main:"$COMMIT_SEQ"
+echo "Local change" > dir2/file4.txt
+"$VX_PATH" tree checkout feature-branch --merge
+check_success "Checkout with merge"
+
+if [ "$(cat dir2/file4.txt)" = "Local change" ] && grep -q "Modified content" file1.txt; then
+    echo -e "${GREEN}SUCCESS: Local changes were kept and target changes applied${NC}"
+else
+    echo -e "${RED}FAILED: Checkout with merge verification failed${NC}"
+    exit 1
+fi
+
+# Step 19: Test amending a commit in the middle of a branch
+print_step "19. Testing amend of a mid-branch commit"
+"$VX_PATH" tree checkout main
+echo "Mid content" > mid-file.txt
+MID_COMMIT_SEQ=$("$VX_PATH" commit new "Mid commit" | grep -o "Created new commit: [0-9]*" | cut -d' ' -f4)
+echo "Top content" > top-file.txt
+TOP_COMMIT_SEQ=$("$VX_PATH" commit new "Top commit" | grep -o "Created new commit: [0-9]*" | cut -d' ' -f4)
+check_success "Create commits to amend"
+
+# Amend the message only, commits above are kept as is
+"$VX_PATH" tree checkout main:"$MID_COMMIT_SEQ"
+"$VX_PATH" commit amend "Mid commit amended"
+check_success "Amend mid-branch commit message"
+
+COMMIT_LIST=$("$VX_PATH" commit list main)
+if echo "$COMMIT_LIST" | grep -q "Mid commit amended" && echo "$COMMIT_LIST" | grep -q "Top commit"; then
+    echo -e "${GREEN}SUCCESS: Mid-branch commit message was amended${NC}"
+else
+    echo -e "${RED}FAILED: Mid-branch commit message amend verification failed${NC}"
+    exit 1
+fi
+
+# Amend the content, commits above are rebuilt on top of it
+echo "Mid content amended" > mid-file.txt
+"$VX_PATH" commit amend
+check_success "Amend mid-branch commit content"
+
+"$VX_PATH" tree checkout main:"$TOP_COMMIT_SEQ"
+if [ "$(cat mid-file.txt)" = "Mid content amended" ] && [ "$(cat top-file.txt)" = "Top content" ]; then
+    echo -e "${GREEN}SUCCESS: Commits above the amended one were rebuilt${NC}"
+else
+    echo -e "${RED}FAILED: Mid-branch commit content amend verification failed${NC}"
+    exit 1
+fi
+
+# Step 20: Test that OS junk files are ignored by default
+print_step "20. Testing default ignore of OS junk files"
+echo "junk" > .DS_Store
+echo "junk" > dir1/Thumbs.db
+echo "backup" > file1.txt~
+STATUS_OUTPUT=$("$VX_PATH" tree status)
+check_success "Get status with junk files"
+
+if echo "$STATUS_OUTPUT" | grep -q -e "DS_Store" -e "Thumbs.db" -e "file1.txt~"; then
+    echo -e "${RED}FAILED: Junk files were reported in status${NC}"
+    exit 1
+else
+    echo -e "${GREEN}SUCCESS: Junk files were ignored${NC}"
+fi
+
+# Step 21: Test diff output modifiers between two commits
+print_step "21. Testing tree diff --name-only and --stat"
+NAME_ONLY_OUTPUT=$("$VX_PATH" tree diff main:"$MID_COMMIT_SEQ" main:"$TOP_COMMIT_SEQ" --name-only)
+check_success "Diff with --name-only"
+
+if [ "$NAME_ONLY_OUTPUT" = "top-file.txt" ]; then
+    echo -e "${GREEN}SUCCESS: Diff --name-only listed the changed file${NC}"
+else
+    echo -e "${RED}FAILED: Unexpected diff --name-only output: $NAME_ONLY_OUTPUT${NC}"
+    exit 1
+fi
+
+STAT_OUTPUT=$("$VX_PATH" tree diff main:"$MID_COMMIT_SEQ" main:"$TOP_COMMIT_SEQ" --stat)
+check_success "Diff with --stat"
+
+if echo "$STAT_OUTPUT" | grep -q "top-file.txt | +12 -0" && echo "$STAT_OUTPUT" | grep -q "1 file changed, 12 bytes added"; then
+    echo -e "${GREEN}SUCCESS: Diff --stat reported the file sizes${NC}"
+else
+    echo -e "${RED}FAILED: Unexpected diff --stat output: $STAT_OUTPUT${NC}"
+    exit 1
+fi
+
+# Step 22: Test commit messages with a subject and a body
+print_step "22. Testing multi-line commit messages"
+echo "Multi-line content" > multi-line.txt
+MULTI_LINE_MSG=$(printf "Multi-line subject\n\nFirst body line\nSecond body line")
+"$VX_PATH" commit new "$MULTI_LINE_MSG"
+check_success "Create commit with multi-line message"
+
+LIST_OUTPUT=$("$VX_PATH" commit list main)
+SHOW_OUTPUT=$("$VX_PATH" commit show)
+if echo "$LIST_OUTPUT" | grep -q "Multi-line subject" && ! echo "$LIST_OUTPUT" | grep -q "body line" && \
+   echo "$SHOW_OUTPUT" | grep -q "Message: Multi-line subject" && echo "$SHOW_OUTPUT" | grep -q "    Second body line"; then
+    echo -e "${GREEN}SUCCESS: Commit list shows the subject and commit show the full message${NC}"
+else
+    echo -e "${RED}FAILED: Multi-line commit message verification failed${NC}"
+    exit 1
+fi
+
+# Step 23: Test top-level status and log aliases
+print_step "23. Testing status and log aliases"
+echo "Alias content" > alias-file.txt
+if [ "$("$VX_PATH" status)" = "$("$VX_PATH" tree status)" ] && \
+   [ "$("$VX_PATH" log main)" = "$("$VX_PATH" commit list main)" ] && \
+   [ "$("$VX_PATH" log --graph)" = "$("$VX_PATH" commit list --graph)" ]; then
+    echo -e "${GREEN}SUCCESS: Aliases produce the same output as the full commands${NC}"
+else
+    echo -e "${RED}FAILED: Alias output differs from the full commands${NC}"
+    exit 1
+fi
+rm alias-file.txt
+
+# Step 24: Test JSON output of branch list
+print_step "24. Testing branch list --json"
+BRANCH_JSON=$("$VX_PATH" branch list --json)
+check_success "List branches as JSON"
+
+if echo "$BRANCH_JSON" | grep -q '"name":"main",.*"ahead":null,"behind":null' && \
+   echo "$BRANCH_JSON" | grep -q '"name":"feature-branch",.*"ahead":[0-9]*,"behind":[0-9]*'; then
+    echo -e "${GREEN}SUCCESS: Branch list JSON contains ahead/behind counts${NC}"
+else
+    echo -e "${RED}FAILED: Unexpected branch list JSON: $BRANCH_JSON${NC}"
+    exit 1
+fi
+
+# Step 25: Test inserting a commit in the middle of a branch
+print_step "25. Testing new commit in the middle of a branch"
+"$VX_PATH" tree checkout main:"$MID_COMMIT_SEQ"
+echo "Inserted content" > inserted-file.txt
+INSERT_OUTPUT=$("$VX_PATH" commit new "Inserted commit")
+check_success "Create commit in the middle of the branch"
+
+if echo "$INSERT_OUTPUT" | grep -q "Rebuilt [0-9]* commits above it"; then
+    echo -e "${GREEN}SUCCESS: Commits above the new one were rebuilt${NC}"
+else
+    echo -e "${RED}FAILED: Rebuild was not reported: $INSERT_OUTPUT${NC}"
+    exit 1
+fi
+
+"$VX_PATH" tree checkout main
+if [ "$(cat inserted-file.txt)" = "Inserted content" ] && [ "$(cat top-file.txt)" = "Top content" ]; then
+    echo -e "${GREEN}SUCCESS: Branch head contains the inserted commit's changes${NC}"
+else
+    echo -e "${RED}FAILED: Branch head does not contain the inserted commit's changes${NC}"
+    exit 1
+fi
+
+# Step 26: Test detached state reporting
+print_step "26. Testing repo status in detached state"
+"$VX_PATH" tree checkout main:"$MID_COMMIT_SEQ"
+HEAD_SEQ=$("$VX_PATH" branch show main | grep "Head Sequence" | awk '{print $3}')
+if "$VX_PATH" repo status | grep -q "Detached at main:$MID_COMMIT_SEQ (head is $HEAD_SEQ)"; then
+    echo -e "${GREEN}SUCCESS: Detached state was reported${NC}"
+else
+    echo -e "${RED}FAILED: Detached state was not reported${NC}"
+    exit 1
+fi
+
+"$VX_PATH" tree checkout main
+if "$VX_PATH" repo status | grep -q "On branch main at head $HEAD_SEQ"; then
+    echo -e "${GREEN}SUCCESS: Branch head state was reported${NC}"
+else
+    echo -e "${RED}FAILED: Branch head state was not reported${NC}"
+    exit 1
+fi
+
+# Step 27: Test checkout of a large folder written in parallel
+print_step "27. Testing checkout of a large folder"
+mkdir -p large-dir/nested
+for i in $(seq 1 200); do
+    echo "Large file $i" > large-dir/file$i.txt
+done
+echo "Nested large file" > large-dir/nested/file.txt
+LARGE_COMMIT_SEQ=$("$VX_PATH" commit new "Large folder" | grep -o "Created new commit: [0-9]*" | cut -d' ' -f4)
+check_success "Create commit with a large folder"
+
+"$VX_PATH" tree checkout main:$((LARGE_COMMIT_SEQ - 1))
+if [ -d large-dir ]; then
+    echo -e "${RED}FAILED: Large folder was not removed on checkout${NC}"
+    exit 1
+fi
+
+# Checkout from a subfolder to make sure files are written relative to the checkout root
+(cd dir1 && "$VX_PATH" tree checkout main:"$LARGE_COMMIT_SEQ")
+check_success "Checkout large folder from a subfolder"
+
+if [ "$(ls large-dir | grep -c '^file')" = "200" ] && [ "$(cat large-dir/file123.txt)" = "Large file 123" ] && \
+   [ "$(cat large-dir/nested/file.txt)" = "Nested large file" ]; then
+    echo -e "${GREEN}SUCCESS: Large folder was checked out${NC}"
+else
+    echo -e "${RED}FAILED: Large folder checkout verification failed${NC}"
+    exit 1
+fi
+
+# Step 28: Test content search across a commit
+print_step "28. Testing tree grep"
+mkdir -p grep-dir
+printf "first line\nneedle 42 here\nlast line\n" > grep-dir/text.txt
+printf "needle\0binary" > grep-dir/binary.bin
+"$VX_PATH" commit new "Grep files"
+check_success "Create commit with files to search"
+
+GREP_OUTPUT=$("$VX_PATH" tree grep "needle [0-9]+")
+check_success "Search the current commit"
+
+if [ "$GREP_OUTPUT" = "grep-dir/text.txt:2:needle 42 here" ]; then
+    echo -e "${GREEN}SUCCESS: Matching line was found and the binary file was skipped${NC}"
+else
+    echo -e "${RED}FAILED: Unexpected grep output: $GREP_OUTPUT${NC}"
+    exit 1
+fi
+
+if [ -n "$("$VX_PATH" tree grep "needle" main:"$LARGE_COMMIT_SEQ")" ]; then
+    echo -e "${RED}FAILED: Search of an older commit found content added later${NC}"
+    exit 1
+else
+    echo -e "${GREEN}SUCCESS: Search of an older commit found nothing${NC}"
+fi
+
+"$VX_PATH" tree grep "ne{2}dle [0-9]{1,3} h" >/dev/null
+check_success "Search with counted quantifiers"
+"$VX_PATH" tree grep "needle [0-9]{2,1}" >/dev/null 2>&1 && BOUNDS_CODE=0 || BOUNDS_CODE=$?
+if [ "$BOUNDS_CODE" = 4 ]; then
+    echo -e "${GREEN}SUCCESS: Quantifier with its minimum above its maximum was refused${NC}"
+else
+    echo -e "${RED}FAILED: Quantifier with its minimum above its maximum exited with $BOUNDS_CODE${NC}"
+    exit 1
+fi
+
+# Step 29: Test reporting objects unreachable from any branch
+print_step "29. Testing blob fsck --gc-dry-run"
+"$VX_PATH" tree checkout main
+echo "Superseded content of 31 bytes" > gc-file.txt
+"$VX_PATH" commit new "GC commit"
+echo "Amended content" > gc-file.txt
+"$VX_PATH" commit amend
+check_success "Create a commit and amend its content"
+
+GC_OUTPUT=$("$VX_PATH" blob fsck --gc-dry-run)
+check_success "Run the reachability report"
+
+if echo "$GC_OUTPUT" | grep -q "unreachable blob [0-9a-f]* 31$" && echo "$GC_OUTPUT" | grep -q "Garbage collection would remove"; then
+    echo -e "${GREEN}SUCCESS: Blob superseded by amend was reported unreachable${NC}"
+else
+    echo -e "${RED}FAILED: Unexpected reachability report: $GC_OUTPUT${NC}"
+    exit 1
+fi
+
+if [ "$("$VX_PATH" blob fsck --gc-dry-run)" = "$GC_OUTPUT" ] && [ "$(cat gc-file.txt)" = "Amended content" ]; then
+    echo -e "${GREEN}SUCCESS: Dry run did not delete anything${NC}"
+else
+    echo -e "${RED}FAILED: Dry run changed the repository${NC}"
+    exit 1
+fi
+
+# Step 30: Test destroying a repository
+print_step "30. Testing repo destroy"
+cd "$TEST_DIR"
+"$VX_PATH" repo new destroy-repo
+cd destroy-repo
+echo "Uncommitted" > uncommitted.txt
+
+if "$VX_PATH" repo destroy < /dev/null; then
+    echo -e "${RED}FAILED: Repository was destroyed without confirmation${NC}"
+    exit 1
+fi
+if "$VX_PATH" repo destroy --yes; then
+    echo -e "${RED}FAILED: Repository with uncommitted changes was destroyed${NC}"
+    exit 1
+fi
+echo -e "${GREEN}SUCCESS: Destroy was refused without confirmation and with uncommitted changes${NC}"
+
+"$VX_PATH" repo destroy --yes --force
+check_success "Destroy the workspace despite uncommitted changes"
+if [ ! -d .vx ] && [ -f uncommitted.txt ]; then
+    echo -e "${GREEN}SUCCESS: Workspace was removed and the checkout was kept${NC}"
+else
+    echo -e "${RED}FAILED: Workspace removal verification failed${NC}"
+    exit 1
+fi
+
+cd "$TEST_DIR"
+rm -rf destroy-repo
+"$VX_PATH" repo new destroy-repo
+cd destroy-repo
+"$VX_PATH" repo destroy --yes --checkout
+check_success "Destroy the repository with its checkout"
+cd "$TEST_DIR"
+if [ ! -e destroy-repo ]; then
+    echo -e "${GREEN}SUCCESS: Checkout was removed${NC}"
+else
+    echo -e "${RED}FAILED: Checkout was not removed${NC}"
+    exit 1
+fi
+cd "$REPO_DIR"
+
+# Step 31: Test storing identical content from parallel workers
+print_step "31. Testing concurrent insertion of identical blobs"
+BLOBS_BEFORE=$("$VX_PATH" repo size | grep "Blobs:" | awk '{print $2}')
+for i in $(seq 1 32); do
+    mkdir -p same-dir/folder-$i
+    echo "Identical content in every folder" > same-dir/folder-$i/same.txt
+done
+"$VX_PATH" commit new "Identical files"
+check_success "Commit folders with identical files"
+
+"$VX_PATH" blob verify-all
+check_success "Blob store has no orphan temporary files"
+
+BLOBS_AFTER=$("$VX_PATH" repo size | grep "Blobs:" | awk '{print $2}')
+if [ "$BLOBS_AFTER" -eq $((BLOBS_BEFORE + 1)) ]; then
+    echo -e "${GREEN}SUCCESS: Identical content was stored once${NC}"
+else
+    echo -e "${RED}FAILED: Expected one new blob, got $((BLOBS_AFTER - BLOBS_BEFORE))${NC}"
+    exit 1
+fi
+
+# Step 32: Test dumping stored commit records
+print_step "32. Testing commit show --raw"
+"$VX_PATH" commit amend "Identical files amended"
+RAW_OUTPUT=$("$VX_PATH" commit show --raw)
+check_success "Show raw commit records"
+
+if echo "$RAW_OUTPUT" | grep -q '"message": "Identical files amended"' && \
+   [ "$(echo "$RAW_OUTPUT" | grep -c '"visible": true')" -eq 1 ] && \
+   [ "$(echo "$RAW_OUTPUT" | grep -c '"visible": false')" -ge 1 ]; then
+    echo -e "${GREEN}SUCCESS: Raw output contains the visible and the superseded versions${NC}"
+else
+    echo -e "${RED}FAILED: Unexpected raw output: $RAW_OUTPUT${NC}"
+    exit 1
+fi
+
+# Step 33: Test branch protection
+print_step "33. Testing branch protection"
+"$VX_PATH" tree checkout main
+"$VX_PATH" branch protect main
+check_success "Protect main branch"
+"$VX_PATH" branch show main | grep -q "Protected:     true"
+check_success "Branch details show protection"
+
+echo "Protected content" > protected-file.txt
+if "$VX_PATH" commit new "Direct commit"; then
+    echo -e "${RED}FAILED: Direct commit to a protected branch was accepted${NC}"
+    exit 1
+fi
+"$VX_PATH" commit new "Forced commit" --force
+check_success "Forced commit to a protected branch"
+
+if "$VX_PATH" commit amend "Direct amend"; then
+    echo -e "${RED}FAILED: Amend in a protected branch was accepted${NC}"
+    exit 1
+fi
+"$VX_PATH" commit amend "Forced amend" --force
+check_success "Forced amend in a protected branch"
+
+"$VX_PATH" branch unprotect main
+echo "Unprotected content" > protected-file.txt
+"$VX_PATH" commit new "Commit after unprotect"
+check_success "Commit to an unprotected branch"
+
+# Step 34: Test commit timestamps in listings
+print_step "34. Testing commit timestamps"
+TODAY=$(date -u +%Y-%m-%d)
+LIST_OUTPUT=$("$VX_PATH" commit list main)
+SHOW_OUTPUT=$("$VX_PATH" commit show)
+RELATIVE_OUTPUT=$("$VX_PATH" commit show --relative)
+if echo "$LIST_OUTPUT" | head -1 | grep -q "${TODAY}T[0-9:]*Z" && \
+   echo "$SHOW_OUTPUT" | grep -q "^Date: ${TODAY}T" && \
+   echo "$RELATIVE_OUTPUT" | grep -Eq "^Date: .*\((just now|[0-9]+ minutes? ago)\)$"; then
+    echo -e "${GREEN}SUCCESS: Commits show absolute and relative creation times${NC}"
+else
+    echo -e "${RED}FAILED: Commit timestamp verification failed${NC}"
+    exit 1
+fi
+
+# Step 35: Test continuing an interrupted checkout
+print_step "35. Testing checkout --continue after a failure"
+PREV_SEQ=$("$VX_PATH" repo status | grep -o "at head [0-9]*" | cut -d' ' -f3)
+touch "$TEST_DIR/blob-marker"
+sleep 1
+echo "Content only in the interrupted commit" > interrupted-file.txt
+"$VX_PATH" commit new "Interrupted checkout target"
+check_success "Create commit to check out"
+
+# Hide the new blob so that checking out the commit fails halfway
+NEW_BLOB=$(find .vx/blobs -type f -newer "$TEST_DIR/blob-marker" | head -1)
+mv "$NEW_BLOB" "$TEST_DIR/hidden-blob"
+"$VX_PATH" tree checkout main:"$PREV_SEQ"
+UNKNOWN_ERROR=$("$VX_PATH" tree checkout main:999 2>&1 || true)
+if echo "$UNKNOWN_ERROR" | grep -q -- "--continue"; then
+    echo -e "${RED}FAILED: Checkout that never started suggested --continue${NC}"
+    echo "$UNKNOWN_ERROR"
+    exit 1
+fi
+if "$VX_PATH" tree checkout main 2> "$TEST_DIR/interrupted-error.txt"; then
+    echo -e "${RED}FAILED: Checkout with a missing blob succeeded${NC}"
+    exit 1
+fi
+if ! grep -q -- "--continue" "$TEST_DIR/interrupted-error.txt"; then
+    echo -e "${RED}FAILED: Interrupted checkout did not suggest --continue${NC}"
+    cat "$TEST_DIR/interrupted-error.txt"
+    exit 1
+fi
+rm "$TEST_DIR/interrupted-error.txt"
+if "$VX_PATH" status; then
+    echo -e "${RED}FAILED: Status of an interrupted checkout was reported${NC}"
+    exit 1
+fi
+echo -e "${GREEN}SUCCESS: Interrupted checkout was detected${NC}"
+
+mv "$TEST_DIR/hidden-blob" "$NEW_BLOB"
+"$VX_PATH" tree checkout --continue
+check_success "Continue interrupted checkout"
+
+if [ "$(cat interrupted-file.txt)" = "Content only in the interrupted commit" ] && \
+   "$VX_PATH" status | grep -q "No files changed"; then
+    echo -e "${GREEN}SUCCESS: Checkout was completed${NC}"
+else
+    echo -e "${RED}FAILED: Continued checkout verification failed${NC}"
+    exit 1
+fi
+
+# Step 36: Test commit diff restricted to paths
+print_step "36. Testing commit diff with a path filter"
+mkdir -p filter-dir/inside filter-other
+echo "Inside v1" > filter-dir/inside/file.txt
+echo "Other v1" > filter-other/file.txt
+FILTER_FROM=$("$VX_PATH" commit new "Filter base" | grep -o "Created new commit: [0-9]*" | cut -d' ' -f4)
+echo "Inside v2" > filter-dir/inside/file.txt
+echo "Other v2" > filter-other/file.txt
+FILTER_TO=$("$VX_PATH" commit new "Filter change" | grep -o "Created new commit: [0-9]*" | cut -d' ' -f4)
+check_success "Create commits to diff"
+
+FULL_DIFF=$("$VX_PATH" commit diff "$FILTER_FROM" "$FILTER_TO")
+FILTERED_DIFF=$(cd filter-dir && "$VX_PATH" commit diff main:"$FILTER_FROM" main:"$FILTER_TO" inside)
+if echo "$FULL_DIFF" | grep -q "filter-other/file.txt" && \
+   echo "$FILTERED_DIFF" | grep -q "modified file filter-dir/inside/file.txt" && \
+   ! echo "$FILTERED_DIFF" | grep -q "filter-other"; then
+    echo -e "${GREEN}SUCCESS: Diff was restricted to the path relative to the current directory${NC}"
+else
+    echo -e "${RED}FAILED: Unexpected filtered diff: $FILTERED_DIFF${NC}"
+    exit 1
+fi
+
+# Step 37: Test extracting a folder of an old commit outside of the checkout
+print_step "37. Testing tree extract"
+"$VX_PATH" tree extract main:"$FILTER_FROM" filter-dir "$TEST_DIR/extracted"
+check_success "Extract a folder of an old commit"
+
+if [ "$(cat "$TEST_DIR/extracted/inside/file.txt")" = "Inside v1" ] && \
+   [ "$(cat filter-dir/inside/file.txt)" = "Inside v2" ] && \
+   "$VX_PATH" status | grep -q "No files changed"; then
+    echo -e "${GREEN}SUCCESS: Old folder was extracted without touching the checkout${NC}"
+else
+    echo -e "${RED}FAILED: Extract verification failed${NC}"
+    exit 1
+fi
+
+if "$VX_PATH" tree extract main:"$FILTER_FROM" filter-dir extracted-inside; then
+    echo -e "${RED}FAILED: Extract into the checkout was accepted${NC}"
+    exit 1
+else
+    echo -e "${GREEN}SUCCESS: Extract into the checkout was refused${NC}"
+fi
+
+# Step 38: Test resolving conflicts of a rebuild paused by an amend
+print_step "38. Testing rebuild conflicts"
+echo "Conflict base" > conflict.txt
+"$VX_PATH" commit new "Conflict base"
+CONFLICT_BASE=$("$VX_PATH" commit show | grep "^Sequence:" | awk '{print $2}')
+echo "Conflict top" > conflict.txt
+"$VX_PATH" commit new "Conflict top"
+check_success "Create commits to conflict with"
+
+"$VX_PATH" tree checkout main:"$CONFLICT_BASE"
+echo "Conflict amended" > conflict.txt
+if AMEND_OUTPUT=$("$VX_PATH" commit amend 2>&1); then
+    echo -e "${RED}FAILED: Conflicting amend did not pause the rebuild${NC}"
+    exit 1
+fi
+echo "$AMEND_OUTPUT"
+REPO_STATUS=$("$VX_PATH" repo status)
+if echo "$AMEND_OUTPUT" | grep -q "Rebuild paused" && grep -q "^<<<<<<< local" conflict.txt && \
+   echo "$REPO_STATUS" | grep -q "in progress with 1 conflict"; then
+    echo -e "${GREEN}SUCCESS: Rebuild was paused with conflict markers in the checkout${NC}"
+else
+    echo -e "${RED}FAILED: Paused rebuild verification failed${NC}"
+    exit 1
+fi
+
+if "$VX_PATH" commit new "Commit during rebuild" || "$VX_PATH" commit rebuild --continue; then
+    echo -e "${RED}FAILED: Commit or continue with unresolved conflicts was accepted${NC}"
+    exit 1
+else
+    echo -e "${GREEN}SUCCESS: Commit and continue with unresolved conflicts were refused${NC}"
+fi
+
+echo "Conflict resolved" > conflict.txt
+"$VX_PATH" commit rebuild --continue
+check_success "Continue the rebuild"
+
+AMENDED_CONTENT=$(cat conflict.txt)
+"$VX_PATH" tree checkout main
+if [ "$AMENDED_CONTENT" = "Conflict amended" ] && [ "$(cat conflict.txt)" = "Conflict resolved" ] && \
+   ! "$VX_PATH" repo status | grep -q "in progress"; then
+    echo -e "${GREEN}SUCCESS: Resolved commit was rebuilt on top of the amended one${NC}"
+else
+    echo -e "${RED}FAILED: Rebuild continue verification failed${NC}"
+    exit 1
+fi
+
+"$VX_PATH" tree checkout main:"$CONFLICT_BASE"
+echo "Conflict abandoned" > conflict.txt
+"$VX_PATH" commit amend && exit 1
+"$VX_PATH" commit rebuild --abort
+check_success "Abort the rebuild"
+if [ "$(cat conflict.txt)" = "Conflict abandoned" ] && "$VX_PATH" status | grep -q "modified file conflict.txt"; then
+    echo -e "${GREEN}SUCCESS: Aborted rebuild kept the amended content as local changes${NC}"
+else
+    echo -e "${RED}FAILED: Rebuild abort verification failed${NC}"
+    exit 1
+fi
+
+# Step 39: Test verifying commit hashes
+print_step "39. Testing repo verify-chain"
+CHAIN_OUTPUT=$("$VX_PATH" repo verify-chain)
+check_success "Verify commit hashes"
+echo "$CHAIN_OUTPUT"
+if echo "$CHAIN_OUTPUT" | grep -q "no problems found"; then
+    echo -e "${GREEN}SUCCESS: Hashes of all commits match their content${NC}"
+else
+    echo -e "${RED}FAILED: Commit hash verification failed${NC}"
+    exit 1
+fi
+
+# Step 40: Test binary files being marked in diff stats
+print_step "40. Testing binary detection in tree diff --stat"
+echo "Conflict base" > conflict.txt
+"$VX_PATH" tree checkout main
+check_success "Checkout the branch head"
+BEFORE_BINARY_SEQ=$("$VX_PATH" commit show | grep "^Sequence:" | awk '{print $2}')
+printf "\x89PNG\0\0\x01" > image.png
+printf "plain text\n" > plain.txt
+AFTER_BINARY_SEQ=$("$VX_PATH" commit new "Binary change" | grep -o "Created new commit: [0-9]*" | cut -d' ' -f4)
+check_success "Create commit with a binary file"
+BINARY_STAT=$("$VX_PATH" tree diff main:"$BEFORE_BINARY_SEQ" main:"$AFTER_BINARY_SEQ" --stat)
+check_success "Diff with --stat"
+echo "$BINARY_STAT"
+if echo "$BINARY_STAT" | grep -q "image.png.*(binary)" && ! echo "$BINARY_STAT" | grep -q "plain.txt.*(binary)"; then
+    echo -e "${GREEN}SUCCESS: Binary file was marked in the diff stats${NC}"
+else
+    echo -e "${RED}FAILED: Unexpected diff --stat output: $BINARY_STAT${NC}"
+    exit 1
+fi
+
+# Step 41: Test starting a branch with no history
+print_step "41. Testing tree checkout --orphan"
+"$VX_PATH" tree checkout --orphan docs
+check_success "Checkout an orphan branch"
+ORPHAN_SEQ=$("$VX_PATH" commit new "Docs start" | grep -o "Created new commit: [0-9]*" | cut -d' ' -f4)
+check_success "Create the first commit of the orphan branch"
+ORPHAN_FILES=$("$VX_PATH" tree diff docs:0 docs:"$ORPHAN_SEQ" --name-only)
+if echo "$ORPHAN_FILES" | grep -q "^image.png$" && echo "$ORPHAN_FILES" | grep -q "^file1.txt$"; then
+    echo -e "${GREEN}SUCCESS: First commit of the orphan branch captured all present files${NC}"
+else
+    echo -e "${RED}FAILED: Unexpected content of the orphan branch: $ORPHAN_FILES${NC}"
+    exit 1
+fi
+
+if "$VX_PATH" tree checkout --orphan docs || "$VX_PATH" tree checkout --orphan "Bad Name"; then
+    echo -e "${RED}FAILED: Orphan branch with an existing or invalid name was created${NC}"
+    exit 1
+else
+    echo -e "${GREEN}SUCCESS: Existing and invalid orphan branch names were refused${NC}"
+fi
+"$VX_PATH" tree checkout main
+check_success "Checkout main back"
+
+# Step 42: Test status showing what ignore rules hide
+print_step "42. Testing tree status --include-ignored"
+echo "editor backup" > notes.txt~
+echo "visible" > visible.txt
+IGNORED_STATUS=$("$VX_PATH" tree status --include-ignored)
+check_success "Get status including ignored files"
+echo "$IGNORED_STATUS"
+if echo "$IGNORED_STATUS" | grep -q "added file notes.txt~ (ignored)" && \
+   echo "$IGNORED_STATUS" | grep -q "added file visible.txt$" && \
+   ! "$VX_PATH" tree status | grep -q "notes.txt~"; then
+    echo -e "${GREEN}SUCCESS: Ignored file was listed and marked only with --include-ignored${NC}"
+else
+    echo -e "${RED}FAILED: Unexpected status including ignored files${NC}"
+    exit 1
+fi
+rm notes.txt~ visible.txt
+
+# Step 43: Test reordering commits of a branch
+print_step "43. Testing commit move"
+echo "first" > move-a.txt
+MOVE_A=$("$VX_PATH" commit new "Move A" | grep -o "Created new commit: [0-9]*" | cut -d' ' -f4)
+echo "second" > move-b.txt
+"$VX_PATH" commit new "Move B"
+echo "third" > move-c.txt
+MOVE_C=$("$VX_PATH" commit new "Move C" | grep -o "Created new commit: [0-9]*" | cut -d' ' -f4)
+check_success "Create commits to reorder"
+
+"$VX_PATH" commit move "$MOVE_C" "$MOVE_A"
+check_success "Move the last commit below the others"
+MOVED_FILES=$("$VX_PATH" tree diff main:$((MOVE_A - 1)) main:"$MOVE_A" --name-only)
+if "$VX_PATH" commit show "$MOVE_A" | grep -q "^Message: Move C" && \
+   "$VX_PATH" commit show "$MOVE_C" | grep -q "^Message: Move B" && \
+   [ "$MOVED_FILES" = "move-c.txt" ] && [ -f move-c.txt ] && "$VX_PATH" status | grep -q "No files changed"; then
+    echo -e "${GREEN}SUCCESS: Commits were reordered with their changes reapplied${NC}"
+else
+    echo -e "${RED}FAILED: Commit move verification failed${NC}"
+    exit 1
+fi
+
+echo "changed" > move-a.txt
+MOVE_D=$("$VX_PATH" commit new "Change A" | grep -o "Created new commit: [0-9]*" | cut -d' ' -f4)
+if "$VX_PATH" commit move "$MOVE_D" "$MOVE_A" || "$VX_PATH" commit move 0 1; then
+    echo -e "${RED}FAILED: Conflicting move or move of the centinel was accepted${NC}"
+    exit 1
+elif "$VX_PATH" commit show "$MOVE_D" | grep -q "^Message: Change A"; then
+    echo -e "${GREEN}SUCCESS: Conflicting move and move of the centinel were refused${NC}"
+else
+    echo -e "${RED}FAILED: Refused move changed the branch${NC}"
+    exit 1
+fi
+
+# Step 44: Test reading a part of a blob
+print_step "44. Testing blob cat with a byte range"
+echo "0123456789 preview of a large asset" > preview.txt
+"$VX_PATH" commit new "Preview file"
+check_success "Create commit with a file to preview"
+PREVIEW_BLOB=$("$VX_PATH" blob import preview.txt | grep -o "[0-9a-f]\{32\}")
+RANGE_OUTPUT=$("$VX_PATH" blob cat "$PREVIEW_BLOB" --offset 3 --len 4)
+check_success "Read a range of the blob"
+if [ "$RANGE_OUTPUT" = "3456" ] && [ "$("$VX_PATH" blob cat "$PREVIEW_BLOB")" = "$(cat preview.txt)" ] && \
+   [ -z "$("$VX_PATH" blob cat "$PREVIEW_BLOB" --offset 1000)" ]; then
+    echo -e "${GREEN}SUCCESS: Blob ranges were read${NC}"
+else
+    echo -e "${RED}FAILED: Unexpected blob range: $RANGE_OUTPUT${NC}"
+    exit 1
+fi
+
+# Blobs are compressed in frames, a range spanning two of them is cut out of both
+seq 1 50000 > "$TEST_DIR/compressible.txt"
+COMPRESSIBLE_BLOB=$("$VX_PATH" blob import "$TEST_DIR/compressible.txt" | grep -o "[0-9a-f]\{32\}")
+COMPRESSED_FILE=".vx/blobs/${COMPRESSIBLE_BLOB:0:2}/${COMPRESSIBLE_BLOB:2}"
+if [ "$(wc -c < "$COMPRESSED_FILE")" -lt "$(wc -c < "$TEST_DIR/compressible.txt")" ] && \
+   [ "$("$VX_PATH" blob cat "$COMPRESSIBLE_BLOB" --offset 65530 --len 12)" = \
+     "$(tail -c +65531 "$TEST_DIR/compressible.txt" | head -c 12)" ] && \
+   "$VX_PATH" blob cat "$COMPRESSIBLE_BLOB" | cmp -s - "$TEST_DIR/compressible.txt"; then
+    echo -e "${GREEN}SUCCESS: Blob was stored compressed${NC}"
+else
+    echo -e "${RED}FAILED: Blob was not compressed or read back${NC}"
+    exit 1
+fi
+
+# Step 45: Test planning a commit without writing it
+print_step "45. Testing commit new --dry-run"
+HEAD_BEFORE_PLAN=$("$VX_PATH" repo status | grep -o "at head [0-9]*" | cut -d' ' -f3)
+echo "planned" > planned.txt
+PLAN_OUTPUT=$("$VX_PATH" commit new "Planned commit" --dry-run)
+check_success "Plan a commit"
+echo "$PLAN_OUTPUT"
+HEAD_AFTER_PLAN=$("$VX_PATH" repo status | grep -o "at head [0-9]*" | cut -d' ' -f3)
+if echo "$PLAN_OUTPUT" | grep -q "Would create new commit: $((HEAD_BEFORE_PLAN + 1)) - Planned commit" && \
+   echo "$PLAN_OUTPUT" | grep -q "added file planned.txt" && [ "$HEAD_BEFORE_PLAN" = "$HEAD_AFTER_PLAN" ] && \
+   "$VX_PATH" status | grep -q "added file planned.txt"; then
+    echo -e "${GREEN}SUCCESS: Dry run reported the changes without committing them${NC}"
+else
+    echo -e "${RED}FAILED: Commit dry run verification failed${NC}"
+    exit 1
+fi
+rm planned.txt
+if "$VX_PATH" commit new "Nothing to plan" --dry-run; then
+    echo -e "${RED}FAILED: Dry run without changes succeeded${NC}"
+    exit 1
+fi
+
+# Step 46: Test configuring the name of the foundational branch
+print_step "46. Testing the default branch name"
+cd "$TEST_DIR"
+"$VX_PATH" repo new trunk-repo --meta init.defaultBranch=trunk
+check_success "Create repository with a default branch from metadata"
+VX_DEFAULT_BRANCH=develop "$VX_PATH" repo new develop-repo
+check_success "Create repository with a default branch from the environment"
+if (cd trunk-repo && "$VX_PATH" branch list | grep -q "trunk" && ! "$VX_PATH" branch list | grep -q "main") && \
+   (cd develop-repo && "$VX_PATH" branch list | grep -q "develop"); then
+    echo -e "${GREEN}SUCCESS: Foundational branches got the configured names${NC}"
+else
+    echo -e "${RED}FAILED: Default branch name verification failed${NC}"
+    exit 1
+fi
+if "$VX_PATH" repo new invalid-branch-repo --meta "init.defaultBranch=Not Valid" || [ -e invalid-branch-repo ]; then
+    echo -e "${RED}FAILED: Repository with an invalid default branch name was created${NC}"
+    exit 1
+fi
+(cd trunk-repo && "$VX_PATH" repo destroy --yes --checkout)
+(cd develop-repo && "$VX_PATH" repo destroy --yes --checkout)
+cd "$REPO_DIR"
+
+# Step 47: Test untracked modes of status
+print_step "47. Testing tree status --untracked"
+mkdir -p untracked-dir/nested
+echo "inner" > untracked-dir/nested/inner.txt
+echo "loose" > untracked-file.txt
+NORMAL_STATUS=$("$VX_PATH" tree status)
+ALL_STATUS=$("$VX_PATH" tree status --untracked=all)
+NO_STATUS=$("$VX_PATH" tree status --untracked=no)
+check_success "Get status in every untracked mode"
+if echo "$NORMAL_STATUS" | grep -q "added folder untracked-dir$" && ! echo "$NORMAL_STATUS" | grep -q "inner.txt" && \
+   echo "$ALL_STATUS" | grep -q "added folder untracked-dir/nested$" && \
+   echo "$ALL_STATUS" | grep -q "added file untracked-dir/nested/inner.txt" && \
+   ! echo "$NO_STATUS" | grep -q "untracked"; then
+    echo -e "${GREEN}SUCCESS: Added files were reported according to the untracked mode${NC}"
+else
+    echo -e "${RED}FAILED: Unexpected status in untracked modes${NC}"
+    exit 1
+fi
+if "$VX_PATH" tree status --untracked=some; then
+    echo -e "${RED}FAILED: Invalid untracked mode was accepted${NC}"
+    exit 1
+fi
+rm -rf untracked-dir untracked-file.txt
+
+# Step 48: Test setting local changes aside with stashes
+print_step "48. Testing stash push, pop, list and drop"
+echo "Stash base" > stash.txt
+"$VX_PATH" commit new "Stash base"
+check_success "Commit a file to stash changes of"
+echo "Stashed change" > stash.txt
+echo "new" > stash-new.txt
+"$VX_PATH" stash push "Work in progress"
+check_success "Stash local changes"
+if [ "$(cat stash.txt)" = "Stash base" ] && [ ! -e stash-new.txt ] && \
+   ! "$VX_PATH" tree status | grep -q "stash" && \
+   "$VX_PATH" stash list | grep -q "Work in progress"; then
+    echo -e "${GREEN}SUCCESS: Local changes were set aside${NC}"
+else
+    echo -e "${RED}FAILED: Stash push verification failed${NC}"
+    exit 1
+fi
+if "$VX_PATH" stash push; then
+    echo -e "${RED}FAILED: Stash without local changes succeeded${NC}"
+    exit 1
+fi
+"$VX_PATH" stash pop
+check_success "Restore stashed changes"
+if [ "$(cat stash.txt)" = "Stashed change" ] && [ "$(cat stash-new.txt)" = "new" ] && \
+   [ -z "$("$VX_PATH" stash list)" ]; then
+    echo -e "${GREEN}SUCCESS: Stashed changes were restored and the stash dropped${NC}"
+else
+    echo -e "${RED}FAILED: Stash pop verification failed${NC}"
+    exit 1
+fi
+"$VX_PATH" stash push
+check_success "Stash changes again"
+echo "Conflicting change" > stash.txt
+POP_OUTPUT=$("$VX_PATH" stash pop 2>&1) && POP_EXIT=0 || POP_EXIT=$?
+if [ "$POP_EXIT" -eq 1 ] && echo "$POP_OUTPUT" | grep -q "both modified stash.txt" && grep -q "<<<<<<< local" stash.txt && \
+   "$VX_PATH" stash list | grep -q "WIP on main:[0-9]*: "; then
+    echo -e "${GREEN}SUCCESS: Conflicts were flagged and the stash kept${NC}"
+else
+    echo -e "${RED}FAILED: Stash conflict verification failed${NC}"
+    echo "exit code: $POP_EXIT"
+    echo "$POP_OUTPUT"
+    exit 1
+fi
+STASH_ID=$("$VX_PATH" stash list | cut -f1)
+"$VX_PATH" stash drop "$STASH_ID"
+check_success "Drop the stash"
+if [ -n "$("$VX_PATH" stash list)" ]; then
+    echo -e "${RED}FAILED: Stash was not dropped${NC}"
+    exit 1
+fi
+echo "Stash base" > stash.txt
+rm stash-new.txt
+
+# Step 49: Test ignoring whitespace changes in status
+print_step "49. Testing tree status --ignore-whitespace"
+printf "first line\nsecond line\n" > whitespace.txt
+"$VX_PATH" commit new "Whitespace base"
+check_success "Commit a text file"
+printf "first line  \r\nsecond line\r\n" > whitespace.txt
+if "$VX_PATH" tree status | grep -q "whitespace.txt" && \
+   ! "$VX_PATH" tree status --ignore-whitespace | grep -q "whitespace.txt"; then
+    echo -e "${GREEN}SUCCESS: Whitespace only changes were not reported${NC}"
+else
+    echo -e "${RED}FAILED: Whitespace only changes verification failed${NC}"
+    exit 1
+fi
+printf "first line\r\nchanged line\r\n" > whitespace.txt
+if ! "$VX_PATH" tree status --ignore-whitespace | grep -q "modified file whitespace.txt"; then
+    echo -e "${RED}FAILED: Content change was hidden by --ignore-whitespace${NC}"
+    exit 1
+fi
+printf "first line\nsecond line\n" > whitespace.txt
+
+# Step 50: Test line ending policies
+print_step "50. Testing line ending normalization"
+new_step_repo lf-repo "Create repository with the LF policy" --meta text.eol=lf
+printf "first\r\nsecond\r\n" > crlf.txt
+printf "bin\0ary\r\n" > binary.dat
+cp binary.dat "$TEST_DIR/binary.orig"
+"$VX_PATH" commit new "CRLF files"
+check_success "Commit files with CRLF line endings"
+if "$VX_PATH" tree status | grep -q "crlf.txt"; then
+    echo -e "${RED}FAILED: Committed CRLF file is reported as changed${NC}"
+    exit 1
+fi
+rm crlf.txt binary.dat
+"$VX_PATH" tree checkout main
+check_success "Checkout files under the LF policy"
+if [ "$(cat crlf.txt)" = "$(printf "first\nsecond")" ] && ! grep -q $'\r' crlf.txt && \
+   cmp -s binary.dat "$TEST_DIR/binary.orig"; then
+    echo -e "${GREEN}SUCCESS: Text was normalized to LF and binary content kept as is${NC}"
+else
+    echo -e "${RED}FAILED: LF policy round trip verification failed${NC}"
+    exit 1
+fi
+new_step_repo crlf-repo "Create repository with the CRLF policy" --meta text.eol=crlf
+printf "first\nsecond\n" > lf.txt
+"$VX_PATH" commit new "LF file"
+rm lf.txt
+"$VX_PATH" tree checkout main
+check_success "Checkout files under the CRLF policy"
+if grep -q $'second\r' lf.txt && ! "$VX_PATH" tree status | grep -q "lf.txt"; then
+    echo -e "${GREEN}SUCCESS: Text was written with CRLF line endings${NC}"
+else
+    echo -e "${RED}FAILED: CRLF policy verification failed${NC}"
+    exit 1
+fi
+cd "$TEST_DIR"
+if "$VX_PATH" repo new bad-eol-repo --meta text.eol=sometimes || [ -e bad-eol-repo ]; then
+    echo -e "${RED}FAILED: Repository with an invalid line ending policy was created${NC}"
+    exit 1
+fi
+(cd lf-repo && "$VX_PATH" repo destroy --yes --checkout)
+(cd crlf-repo && "$VX_PATH" repo destroy --yes --checkout)
+rm "$TEST_DIR/binary.orig"
+cd "$REPO_DIR"
+
+# Step 51: Test compacting the databases
+print_step "51. Testing repo pack"
+LOG_BEFORE=$("$VX_PATH" log)
+PACK_OUTPUT=$("$VX_PATH" repo pack)
+check_success "Pack the repository"
+echo "$PACK_OUTPUT"
+if echo "$PACK_OUTPUT" | grep -q "commits.db: [0-9]* -> [0-9]*" && echo "$PACK_OUTPUT" | grep -q "^Total: " && \
+   [ "$("$VX_PATH" log)" = "$LOG_BEFORE" ]; then
+    echo -e "${GREEN}SUCCESS: Databases were compacted with their content intact${NC}"
+else
+    echo -e "${RED}FAILED: Repo pack verification failed${NC}"
+    exit 1
+fi
+
+# Step 52: Test format templates of commits
+print_step "52. Testing commit --format"
+FORMATTED_LOG=$("$VX_PATH" log --format "%b:%s %m")
+check_success "List commits with a template"
+CURRENT_SEQ=$("$VX_PATH" commit show --format "%s")
+CURRENT_SUBJECT=$("$VX_PATH" commit show --format "%m")
+HASHES=$("$VX_PATH" commit show --format "%H%n%h%%")
+if echo "$FORMATTED_LOG" | grep -q "^main:$CURRENT_SEQ $CURRENT_SUBJECT$" && \
+   [ "$(echo "$HASHES" | sed -n 1p | cut -c1-7)%" = "$(echo "$HASHES" | sed -n 2p)" ] && \
+   [ "$(echo "$HASHES" | sed -n 1p | wc -c)" -eq 33 ]; then
+    echo -e "${GREEN}SUCCESS: Commits were rendered with templates${NC}"
+else
+    echo -e "${RED}FAILED: Format template verification failed${NC}"
+    exit 1
+fi
+if "$VX_PATH" log --format "%q"; then
+    echo -e "${RED}FAILED: Unknown placeholder was accepted${NC}"
+    exit 1
+fi
+
+# Step 53: Test per-path attributes
+print_step "53. Testing .vxattributes"
+new_step_repo attr-repo "Create repository for attributes"
+mkdir assets
+printf "*.txt eol=lf\n" > .vxattributes
+printf "# data files are never text\n*.dat binary\n" > assets/.vxattributes
+printf "first\r\nsecond\r\n" > unix.txt
+printf "first\r\nsecond\r\n" > keep.md
+printf "needle\n" > assets/table.dat
+printf "needle\n" > assets/notes.md
+"$VX_PATH" commit new "Files with attributes"
+check_success "Commit files with attributes"
+rm unix.txt keep.md
+"$VX_PATH" tree checkout main
+check_success "Checkout files with attributes"
+GREP_OUTPUT=$("$VX_PATH" tree grep needle)
+if ! grep -q $'\r' unix.txt && grep -q $'second\r' keep.md && \
+   echo "$GREP_OUTPUT" | grep -q "notes.md" && ! echo "$GREP_OUTPUT" | grep -q "table.dat" && \
+   "$VX_PATH" tree status | grep -q "No files changed"; then
+    echo -e "${GREEN}SUCCESS: Attributes set line endings and kinds per path${NC}"
+else
+    echo -e "${RED}FAILED: Attributes verification failed${NC}"
+    exit 1
+fi
+destroy_step_repo attr-repo
+
+# Step 54: Test the self-check
+print_step "54. Testing doctor"
+DOCTOR_OUTPUT=$("$VX_PATH" doctor)
+check_success "Run the self-check"
+echo "$DOCTOR_OUTPUT"
+if echo "$DOCTOR_OUTPUT" | grep -q "^Repository: $REPO_DIR$" && echo "$DOCTOR_OUTPUT" | grep -q "^On branch " && \
+   echo "$DOCTOR_OUTPUT" | grep -q "^Commits: [0-9]* checked, 0 mismatched$" && \
+   echo "$DOCTOR_OUTPUT" | grep -q "^No problems found$"; then
+    echo -e "${GREEN}SUCCESS: Self-check reported a healthy repository${NC}"
+else
+    echo -e "${RED}FAILED: Doctor verification failed${NC}"
+    exit 1
+fi
+if (cd / && "$VX_PATH" doctor); then
+    echo -e "${RED}FAILED: Self-check passed outside of a repository${NC}"
+    exit 1
+fi
+
+# Step 55: Test telling whether a directory belongs to a repository
+print_step "55. Testing repository discovery"
+REFUSED_OUTPUT=$("$VX_PATH" repo new nested-repo 2>&1 || true)
+if echo "$REFUSED_OUTPUT" | grep -q "^Refusing to create a repository inside the checkout of $REPO_DIR" && \
+   [ ! -e nested-repo ]; then
+    echo -e "${GREEN}SUCCESS: Nested repository was refused${NC}"
+else
+    echo -e "${RED}FAILED: Nested repository refusal verification failed${NC}"
+    exit 1
+fi
+NESTED_OUTPUT=$("$VX_PATH" repo new nested-repo --allow-nested 2>&1)
+check_success "Create a repository inside the checkout"
+OUTSIDE_OUTPUT=$(cd / && "$VX_PATH" doctor 2>&1 || true)
+if echo "$NESTED_OUTPUT" | grep -q "^Warning: creating a repository inside the checkout of $REPO_DIR" && \
+   echo "$OUTSIDE_OUTPUT" | grep -q "^Repository: not found"; then
+    echo -e "${GREEN}SUCCESS: Repositories were discovered without errors${NC}"
+else
+    echo -e "${RED}FAILED: Repository discovery verification failed${NC}"
+    exit 1
+fi
+(cd nested-repo && "$VX_PATH" repo destroy --yes --checkout)
+
+# Step 56: Test commit messages stored as blobs
+print_step "56. Testing commit messages stored as blobs"
+new_step_repo message-repo "Create repository storing long messages as blobs" --meta commit.messageBlobSize=32
+echo "Long message" > long.txt
+"$VX_PATH" commit new "$(printf "Subject of a long message\n\nA body that takes the message over the limit")"
+check_success "Commit with a long message"
+SHOW_OUTPUT=$("$VX_PATH" commit show)
+RAW_OUTPUT=$("$VX_PATH" commit show --raw)
+GC_OUTPUT=$("$VX_PATH" blob fsck --gc-dry-run)
+if echo "$SHOW_OUTPUT" | grep -q "^Message: Subject of a long message$" && \
+   echo "$SHOW_OUTPUT" | grep -q "^    A body that takes the message over the limit$" && \
+   echo "$RAW_OUTPUT" | grep -q '"message": "Subject of a long message"' && \
+   ! echo "$RAW_OUTPUT" | grep -q '"message_hash": "00000000000000000000000000000000"' && \
+   ! echo "$GC_OUTPUT" | grep -q "unreachable blob" && "$VX_PATH" repo verify-chain; then
+    echo -e "${GREEN}SUCCESS: Long message was stored as a blob and read back${NC}"
+else
+    echo -e "${RED}FAILED: Message blob verification failed${NC}"
+    exit 1
+fi
+cd "$TEST_DIR"
+if "$VX_PATH" repo new bad-message-repo --meta commit.messageBlobSize=large || [ -e bad-message-repo ]; then
+    echo -e "${RED}FAILED: Repository with an invalid message size was created${NC}"
+    exit 1
+fi
+(cd message-repo && "$VX_PATH" repo destroy --yes --checkout)
+cd "$REPO_DIR"
+
+# Step 57: Test exporting the history as a git fast-import stream
+print_step "57. Testing export to a git fast-import stream"
+new_step_repo export-repo "Create repository to export"
+echo "First version" > export.txt
+"$VX_PATH" commit new "Export first"
+check_success "Commit first version to export"
+"$VX_PATH" branch new export-feature
+check_success "Create branch to export"
+mkdir -p empty-folder nested
+echo "Second version" > export.txt
+echo "Nested file" > nested/inner.txt
+"$VX_PATH" commit new "Export second"
+check_success "Commit second version to export"
+"$VX_PATH" repo export-git > "$TEST_DIR/export.stream"
+check_success "Export history as a git fast-import stream"
+if ! grep -q "^commit refs/heads/export-feature$" "$TEST_DIR/export.stream" || \
+   ! grep -q "^M 100644 :[0-9]* nested/inner.txt$" "$TEST_DIR/export.stream" || \
+   grep -q "empty-folder" "$TEST_DIR/export.stream"; then
+    echo -e "${RED}FAILED: Git fast-import stream verification failed${NC}"
+    exit 1
+fi
+if command -v git >/dev/null 2>&1; then
+    git init -q "$TEST_DIR/export-git"
+    (cd "$TEST_DIR/export-git" && git fast-import --quiet < "$TEST_DIR/export.stream")
+    check_success "Import stream with git fast-import"
+    VX_COMMITS=$(grep -c "^commit " "$TEST_DIR/export.stream")
+    GIT_COMMITS=$(git -C "$TEST_DIR/export-git" rev-list --count export-feature)
+    GIT_CONTENT=$(git -C "$TEST_DIR/export-git" show export-feature:export.txt)
+    GIT_SUBJECT=$(git -C "$TEST_DIR/export-git" log -1 --format=%s export-feature)
+    if [ "$VX_COMMITS" != "$GIT_COMMITS" ] || [ "$GIT_CONTENT" != "Second version" ] || \
+       [ "$GIT_SUBJECT" != "Export second" ]; then
+        echo -e "${RED}FAILED: Imported git history does not match${NC}"
+        exit 1
+    fi
+    rm -rf "$TEST_DIR/export-git"
+fi
+echo -e "${GREEN}SUCCESS: History was exported as a git fast-import stream${NC}"
+rm -f "$TEST_DIR/export.stream"
+destroy_step_repo export-repo
+
+# Step 58: Test reusing the message of an existing commit
+print_step "58. Testing reused commit messages"
+new_step_repo reuse-repo "Create repository to reuse messages in"
+echo "First" > reuse.txt
+"$VX_PATH" commit new "$(printf "Reused subject\n\nReused body")"
+check_success "Commit with a message to reuse"
+echo "Second" > reuse.txt
+"$VX_PATH" commit new --reuse-message 1
+check_success "Commit reusing a message"
+echo "Third" > reuse.txt
+VX_EDITOR="sed -i s/Reused/Edited/" "$VX_PATH" commit new --reedit-message 2
+check_success "Commit reediting a message"
+echo "Fourth" > reuse.txt
+if VX_EDITOR="sed -i d" "$VX_PATH" commit new --reedit-message 1 || \
+   "$VX_PATH" commit new "Message" --reuse-message 1; then
+    echo -e "${RED}FAILED: Commit with an empty or conflicting message was created${NC}"
+    exit 1
+fi
+SHOW_REUSED=$("$VX_PATH" commit show 2)
+SHOW_EDITED=$("$VX_PATH" commit show 3)
+if echo "$SHOW_REUSED" | grep -q "^Message: Reused subject$" && \
+   echo "$SHOW_REUSED" | grep -q "^    Reused body$" && \
+   echo "$SHOW_EDITED" | grep -q "^Message: Edited subject$" && \
+   echo "$SHOW_EDITED" | grep -q "^    Edited body$" && \
+   ! echo "$SHOW_EDITED" | grep -q "#" && ! "$VX_PATH" commit show 4 >/dev/null 2>&1; then
+    echo -e "${GREEN}SUCCESS: Messages were reused from existing commits${NC}"
+else
+    echo -e "${RED}FAILED: Reused message verification failed${NC}"
+    exit 1
+fi
+destroy_step_repo reuse-repo
+
+# Step 59: Test trusting the index for large files
+print_step "59. Testing trusted index size"
+cd "$TEST_DIR"
+TRUST_OUTPUT=$("$VX_PATH" repo new trust-repo --meta index.trustSize=1000 2>&1)
+check_success "Create repository trusting the index for large files"
+cd trust-repo
+head -c 2000 /dev/zero | tr '\0' 'a' > large.txt
+echo "small" > small.txt
+"$VX_PATH" commit new "Add large file"
+check_success "Commit large file"
+# change the content of both files, keeping their sizes and mtimes
+touch -r large.txt "$TEST_DIR/large.mtime"
+touch -r small.txt "$TEST_DIR/small.mtime"
+head -c 2000 /dev/zero | tr '\0' 'b' > large.txt
+echo "SMALL" > small.txt
+touch -r "$TEST_DIR/large.mtime" large.txt
+touch -r "$TEST_DIR/small.mtime" small.txt
+TRUST_STATUS=$("$VX_PATH" tree status)
+if echo "$TRUST_OUTPUT" | grep -q "^Warning: files from 1000 bytes are taken as unchanged" && \
+   echo "$TRUST_STATUS" | grep -q "small.txt" && ! echo "$TRUST_STATUS" | grep -q "large.txt"; then
+    echo -e "${GREEN}SUCCESS: Large files were taken from the index${NC}"
+else
+    echo -e "${RED}FAILED: Trusted index verification failed${NC}"
+    exit 1
+fi
+rm -f "$TEST_DIR/large.mtime" "$TEST_DIR/small.mtime"
+cd "$TEST_DIR"
+if "$VX_PATH" repo new bad-trust-repo --meta index.trustSize=huge || [ -e bad-trust-repo ]; then
+    echo -e "${RED}FAILED: Repository with an invalid trusted size was created${NC}"
+    exit 1
+fi
+(cd trust-repo && "$VX_PATH" repo destroy --yes --checkout --force)
+cd "$REPO_DIR"
+
+# Step 60: Test listing recent commits of a branch
+print_step "60. Testing branch show with recent commits"
+new_step_repo branch-show-repo "Create repository to show branch commits in"
+for i in 1 2 3; do
+    echo "Version $i" > show.txt
+    "$VX_PATH" commit new "Show commit $i"
+    check_success "Commit version $i"
+done
+HEAD_HASH=$("$VX_PATH" commit show --format "%h")
+SHOW_OUTPUT=$("$VX_PATH" branch show --commits 2)
+if echo "$SHOW_OUTPUT" | grep -q "^Recent Commits:$" && \
+   echo "$SHOW_OUTPUT" | grep -q "^  3	$HEAD_HASH	Show commit 3$" && \
+   echo "$SHOW_OUTPUT" | grep -q "^  2	[0-9a-f]*	Show commit 2$" && \
+   ! echo "$SHOW_OUTPUT" | grep -q "Show commit 1" && \
+   ! "$VX_PATH" branch show | grep -q "Recent Commits"; then
+    echo -e "${GREEN}SUCCESS: Recent commits of the branch were listed${NC}"
+else
+    echo -e "${RED}FAILED: Branch commits verification failed${NC}"
+    exit 1
+fi
+destroy_step_repo branch-show-repo
+
+# Step 61: Test checking out into a clean checkout
+print_step "61. Testing tree checkout --force-clean"
+new_step_repo clean-repo "Create repository to clean"
+mkdir -p docs/nested
+echo "Tracked" > tracked.txt
+echo "Nested" > docs/nested/file.txt
+"$VX_PATH" commit new "Files to restore"
+check_success "Commit files to restore"
+echo "Damaged" > tracked.txt
+rm -rf docs
+mkdir untracked-dir
+echo "Untracked" > untracked-dir/file.txt
+echo "Ignored" > .DS_Store
+"$VX_PATH" tree checkout main --force-clean
+check_success "Checkout into a clean checkout"
+if [ "$(cat tracked.txt)" = "Tracked" ] && [ "$(cat docs/nested/file.txt)" = "Nested" ] && \
+   [ ! -e untracked-dir ] && [ ! -e .DS_Store ] && [ -d .vx ] && \
+   "$VX_PATH" tree status | grep -q "No files changed"; then
+    echo -e "${GREEN}SUCCESS: Checkout was written from scratch${NC}"
+else
+    echo -e "${RED}FAILED: Clean checkout verification failed${NC}"
+    exit 1
+fi
+destroy_step_repo clean-repo
+
+# Step 62: Test listing the commits changing a path
+print_step "62. Testing commit list with paths"
+new_step_repo path-log-repo "Create repository for path history"
+echo "One" > tracked.txt
+"$VX_PATH" commit new "Add tracked file"
+mkdir -p docs
+echo "Guide" > docs/guide.txt
+"$VX_PATH" commit new "Add guide"
+echo "Two" > tracked.txt
+"$VX_PATH" commit new "Change tracked file"
+echo "Other" > other.txt
+"$VX_PATH" commit new "Add other file"
+check_success "Commit files for path history"
+TRACKED_LOG=$(cd docs && "$VX_PATH" commit list -- ../tracked.txt)
+DOCS_LOG=$("$VX_PATH" commit list --format "%m" -- docs)
+if echo "$TRACKED_LOG" | grep -q "Change tracked file" && \
+   echo "$TRACKED_LOG" | grep -q "Add tracked file" && \
+   [ "$(echo "$TRACKED_LOG" | wc -l)" -eq 2 ] && [ "$DOCS_LOG" = "Add guide" ] && \
+   [ -z "$("$VX_PATH" commit list -- missing.txt)" ]; then
+    echo -e "${GREEN}SUCCESS: Only commits changing the paths were listed${NC}"
+else
+    echo -e "${RED}FAILED: Path history verification failed${NC}"
+    exit 1
+fi
+destroy_step_repo path-log-repo
+
+# Step 63: Test importing a file into the blob store
+print_step "63. Testing blob import"
+new_step_repo import-repo "Create repository for blob import"
+printf "Imported content\r\n" > "$TEST_DIR/imported.txt"
+IMPORTED_HASH=$("$VX_PATH" blob import "$TEST_DIR/imported.txt")
+check_success "Import file into the blob store"
+BLOBS_BEFORE=$("$VX_PATH" repo size | grep "Blobs:")
+REIMPORTED_HASH=$("$VX_PATH" blob import "$TEST_DIR/imported.txt")
+if [ -n "$IMPORTED_HASH" ] && [ "$IMPORTED_HASH" = "$REIMPORTED_HASH" ] && \
+   [ "$("$VX_PATH" repo size | grep "Blobs:")" = "$BLOBS_BEFORE" ] && \
+   "$VX_PATH" blob cat "$IMPORTED_HASH" | cmp -s - "$TEST_DIR/imported.txt"; then
+    echo -e "${GREEN}SUCCESS: File was stored once with its content as is${NC}"
+else
+    echo -e "${RED}FAILED: Blob import verification failed${NC}"
+    exit 1
+fi
+rm "$TEST_DIR/imported.txt"
+destroy_step_repo import-repo
+
+# Step 64: Test tuning the databases with repository metadata
+print_step "64. Testing database tuning"
+new_step_repo tuned-repo "Create repository with database tuning" --meta db.cacheCapacity=1048576 --meta db.flushEveryMs=0
+echo "Tuned" > tuned.txt
+"$VX_PATH" commit new "Commit with tuned databases"
+check_success "Commit with tuned databases"
+if "$VX_PATH" commit list | grep -q "Commit with tuned databases" && \
+   ! (cd "$TEST_DIR" && "$VX_PATH" repo new bad-tuned-repo --meta db.cacheCapacity=lots) && \
+   [ ! -e "$TEST_DIR/bad-tuned-repo" ]; then
+    echo -e "${GREEN}SUCCESS: Databases were opened with the tuning and invalid values refused${NC}"
+else
+    echo -e "${RED}FAILED: Database tuning verification failed${NC}"
+    exit 1
+fi
+destroy_step_repo tuned-repo
+
+# Step 65: Test the summary of the status
+print_step "65. Testing tree status --summary"
+new_step_repo summary-repo "Create repository for status summary"
+echo "Kept" > kept.txt
+echo "Removed" > removed.txt
+"$VX_PATH" commit new "Files to summarize"
+check_success "Commit files to summarize"
+if "$VX_PATH" tree status --summary | grep -q "No files changed"; then
+    echo -e "${GREEN}SUCCESS: Clean checkout was reported without an error${NC}"
+else
+    echo -e "${RED}FAILED: Clean status summary verification failed${NC}"
+    exit 1
+fi
+echo "Changed" > kept.txt
+rm removed.txt
+mkdir added
+echo "Added" > added/file.txt
+echo "Added" > added.txt
+SUMMARY_STATUS=0
+SUMMARY=$("$VX_PATH" tree status --summary) || SUMMARY_STATUS=$?
+if [ "$SUMMARY_STATUS" -eq 5 ] && [ "$(echo "$SUMMARY" | head -1)" = "2 added, 1 modified, 1 deleted" ] && \
+   echo "$SUMMARY" | grep -q "files: 1 added, 1 modified, 1 deleted" && \
+   echo "$SUMMARY" | grep -q "folders: 1 added, 0 modified, 0 deleted" && \
+   ! echo "$SUMMARY" | grep -q "kept.txt"; then
+    echo -e "${GREEN}SUCCESS: Changes were counted and reported with exit code 5${NC}"
+else
+    echo -e "${RED}FAILED: Status summary verification failed${NC}"
+    exit 1
+fi
+destroy_step_repo summary-repo
+
+# Step 66: Test recording extended attributes of files
+print_step "66. Testing extended attributes"
+new_step_repo xattr-repo "Create repository recording extended attributes" --meta xattr.capture=user.origin
+echo "Downloaded" > downloaded.txt
+"$VX_PATH" commit new "Without attributes"
+check_success "Commit file without attributes"
+PLAIN_TREE=$("$VX_PATH" commit show --format "%T")
+# the attributes are set with python, the attr tools are rarely installed
+if command -v python3 >/dev/null 2>&1 && \
+   python3 -c "import os; os.setxattr('downloaded.txt', 'user.origin', b'web')" 2>/dev/null; then
+    python3 -c "import os; os.setxattr('downloaded.txt', 'user.ignored', b'x')"
+    "$VX_PATH" commit new "With attributes"
+    check_success "Commit file with attributes"
+    python3 -c "import os; os.removexattr('downloaded.txt', 'user.origin')"
+    "$VX_PATH" tree checkout main --force-clean
+    check_success "Checkout file with attributes"
+    ORIGIN=$(python3 -c "import os; print(os.getxattr('downloaded.txt', 'user.origin').decode())")
+    if [ "$ORIGIN" = "web" ] && [ "$("$VX_PATH" commit show --format "%T")" != "$PLAIN_TREE" ] && \
+       ! python3 -c "import os; os.getxattr('downloaded.txt', 'user.ignored')" 2>/dev/null; then
+        echo -e "${GREEN}SUCCESS: Listed attributes were recorded and restored${NC}"
+    else
+        echo -e "${RED}FAILED: Extended attributes verification failed${NC}"
+        exit 1
+    fi
+else
+    echo "Skipping: extended attributes can not be set here"
+fi
+destroy_step_repo xattr-repo
+
+# Step 67: Test checkout collecting failures of files that can not be written
+print_step "67. Testing tree checkout --keep-going"
+new_step_repo keep-going-repo "Create repository for keep-going checkout"
+mkdir locked
+echo "First" > a.txt
+echo "First" > locked/file.txt
+echo "First" > z.txt
+"$VX_PATH" commit new "First"
+check_success "Commit first version"
+echo "Second" > a.txt
+echo "Second" > locked/file.txt
+echo "Second" > z.txt
+"$VX_PATH" commit new "Second"
+check_success "Commit second version"
+# root writes read-only files anyway, so the file is made immutable then
+if [ "$(id -u)" = "0" ]; then
+    LOCK_STATUS=0
+    chattr +i locked/file.txt 2>/dev/null || LOCK_STATUS=$?
+else
+    chmod a-w locked
+    LOCK_STATUS=0
+fi
+if [ "$LOCK_STATUS" = "0" ]; then
+    KEEP_GOING_STATUS=0
+    KEEP_GOING=$("$VX_PATH" tree checkout main:1 --keep-going 2>&1) || KEEP_GOING_STATUS=$?
+    if [ "$(id -u)" = "0" ]; then chattr -i locked/file.txt; else chmod u+w locked; fi
+    if [ "$KEEP_GOING_STATUS" != "0" ] && \
+       echo "$KEEP_GOING" | grep -q "Failed to write 1 path(s):" && \
+       echo "$KEEP_GOING" | grep -q "  locked/file.txt: " && \
+       [ "$(cat a.txt)" = "First" ] && [ "$(cat z.txt)" = "First" ] && \
+       [ "$(cat locked/file.txt)" = "Second" ] && \
+       [ "$("$VX_PATH" commit show --format "%m")" = "Second" ]; then
+        echo -e "${GREEN}SUCCESS: Other files were written and the failure was reported${NC}"
+    else
+        echo -e "${RED}FAILED: Keep-going checkout verification failed${NC}"
+        echo "$KEEP_GOING"
+        exit 1
+    fi
+    "$VX_PATH" tree checkout --continue
+    check_success "Continue the incomplete checkout"
+    if [ "$(cat locked/file.txt)" = "First" ] && \
+       [ "$("$VX_PATH" commit show --format "%m")" = "First" ]; then
+        echo -e "${GREEN}SUCCESS: Checkout was completed once the file was writable${NC}"
+    else
+        echo -e "${RED}FAILED: Continued checkout verification failed${NC}"
+        exit 1
+    fi
+else
+    echo "Skipping: files can not be made unwritable here"
+fi
+destroy_step_repo keep-going-repo
+
+# Step 68: Test commit counts and sizes of branches
+print_step "68. Testing branch list --with-size"
+new_step_repo size-repo "Create repository for branch sizes"
+printf "12345" > five.txt
+"$VX_PATH" commit new "Five bytes"
+check_success "Commit five bytes"
+"$VX_PATH" branch new small
+check_success "Create branch at five bytes"
+"$VX_PATH" tree checkout main
+printf "1234567890" > ten.txt
+"$VX_PATH" commit new "Ten more bytes"
+check_success "Commit ten more bytes"
+SIZES=$("$VX_PATH" branch list --with-size)
+check_success "List branches with sizes"
+SIZES_JSON=$("$VX_PATH" branch list --with-size --json)
+check_success "List branches with sizes as JSON"
+if echo "$SIZES" | grep -q "Name: main, .*, Commits: 2, Size: 15$" && \
+   echo "$SIZES" | grep -q "Name: small, .*, Commits: 0, Size: 5$" && \
+   echo "$SIZES_JSON" | grep -q '"name":"main",.*"commits":2,"size":15' && \
+   ! "$VX_PATH" branch list | grep -q "Size:"; then
+    echo -e "${GREEN}SUCCESS: Commit counts and head tree sizes were listed${NC}"
+else
+    echo -e "${RED}FAILED: Branch sizes verification failed${NC}"
+    echo "$SIZES"
+    echo "$SIZES_JSON"
+    exit 1
+fi
+destroy_step_repo size-repo
+
+# Step 69: Test undoing the last commit
+print_step "69. Testing commit undo"
+new_step_repo undo-repo "Create repository for commit undo"
+echo "Kept" > kept.txt
+"$VX_PATH" commit new "Kept commit"
+check_success "Commit kept file"
+echo "Oops" > oops.txt
+"$VX_PATH" commit new "Oops commit"
+check_success "Commit file to undo"
+"$VX_PATH" commit undo
+check_success "Undo last commit keeping its changes"
+UNDO_STATUS=$("$VX_PATH" tree status)
+if [ "$("$VX_PATH" commit show --format "%s %m")" = "1 Kept commit" ] && \
+   [ "$(cat oops.txt)" = "Oops" ] && echo "$UNDO_STATUS" | grep -q "oops.txt"; then
+    echo -e "${GREEN}SUCCESS: Commit was undone and its changes kept as local changes${NC}"
+else
+    echo -e "${RED}FAILED: Soft undo verification failed${NC}"
+    echo "$UNDO_STATUS"
+    exit 1
+fi
+"$VX_PATH" commit new "Oops again"
+check_success "Commit file to undo again"
+"$VX_PATH" commit undo --hard
+check_success "Undo last commit discarding its changes"
+if [ "$("$VX_PATH" commit show --format "%s %m")" = "1 Kept commit" ] && [ ! -e oops.txt ] && \
+   [ -f kept.txt ] && "$VX_PATH" tree status | grep -q "No files changed"; then
+    echo -e "${GREEN}SUCCESS: Commit was undone and the previous commit checked out${NC}"
+else
+    echo -e "${RED}FAILED: Hard undo verification failed${NC}"
+    exit 1
+fi
+"$VX_PATH" commit undo
+check_success "Undo first commit"
+if ! "$VX_PATH" commit undo 2>/dev/null; then
+    echo -e "${GREEN}SUCCESS: Centinel commit can not be undone${NC}"
+else
+    echo -e "${RED}FAILED: Centinel commit was undone${NC}"
+    exit 1
+fi
+destroy_step_repo undo-repo
+
+# Step 70: Test the limit on how deep folders may be nested
+print_step "70. Testing tree.maxDepth"
+new_step_repo depth-repo "Create repository limiting folder depth" --meta tree.maxDepth=3
+mkdir -p one/two/three
+echo "Deep" > one/two/three/deep.txt
+"$VX_PATH" commit new "Three levels"
+check_success "Commit folders at the depth limit"
+mkdir -p one/two/three/four
+echo "Too deep" > one/two/three/four/deeper.txt
+DEPTH_STATUS=0
+DEPTH_OUTPUT=$("$VX_PATH" commit new "Four levels" 2>&1) || DEPTH_STATUS=$?
+rm -rf one
+"$VX_PATH" tree checkout main --force-clean
+check_success "Check out folders at the depth limit"
+if [ "$DEPTH_STATUS" != "0" ] && echo "$DEPTH_OUTPUT" | grep -q "one/two/three/four" && \
+   [ "$(cat one/two/three/deep.txt)" = "Deep" ] && \
+   ! "$VX_PATH" repo new invalid-depth-repo --meta tree.maxDepth=0 2>/dev/null; then
+    echo -e "${GREEN}SUCCESS: Folders beyond the depth limit were refused${NC}"
+else
+    echo -e "${RED}FAILED: Depth limit verification failed${NC}"
+    echo "$DEPTH_OUTPUT"
+    exit 1
+fi
+destroy_step_repo depth-repo
+
+# Step 71: Test setting the date of new commits
+print_step "71. Testing commit new --date"
+new_step_repo date-repo "Create repository for commit dates"
+echo "Imported" > imported.txt
+"$VX_PATH" commit new "Imported" --date "2024-03-01T14:30:00+02:00"
+check_success "Commit with an ISO 8601 date"
+ISO_DATE=$("$VX_PATH" commit show --format "%at %ad")
+echo "Epoch" > epoch.txt
+"$VX_PATH" commit new "Epoch" --date 1700000000
+check_success "Commit with a Unix epoch date"
+EPOCH_DATE=$("$VX_PATH" commit show --format "%at")
+echo "Reproducible" > reproducible.txt
+SOURCE_DATE_EPOCH=1600000000 "$VX_PATH" commit new "Reproducible"
+check_success "Commit dated by SOURCE_DATE_EPOCH"
+ENV_DATE=$("$VX_PATH" commit show --format "%at")
+echo "Invalid" > invalid.txt
+if [ "$ISO_DATE" = "1709296200 2024-03-01T12:30:00Z" ] && [ "$EPOCH_DATE" = "1700000000" ] && \
+   [ "$ENV_DATE" = "1600000000" ] && \
+   ! "$VX_PATH" commit new "Invalid" --date "2024-02-30" 2>/dev/null; then
+    echo -e "${GREEN}SUCCESS: Commits were dated as requested${NC}"
+else
+    echo -e "${RED}FAILED: Commit date verification failed${NC}"
+    echo "$ISO_DATE / $EPOCH_DATE / $ENV_DATE"
+    exit 1
+fi
+destroy_step_repo date-repo
+
+# Step 72: Test the summary of a checkout
+print_step "72. Testing checkout report"
+new_step_repo report-repo "Create repository for checkout report"
+mkdir removed
+echo "First" > changed.txt
+echo "Same" > same.txt
+echo "Inside" > removed/inside.txt
+"$VX_PATH" commit new "First"
+check_success "Commit first version"
+echo "Second" > changed.txt
+rm -rf removed
+echo "New" > added.txt
+"$VX_PATH" commit new "Second"
+check_success "Commit second version"
+REPORT=$("$VX_PATH" tree checkout main:1)
+check_success "Checkout first version"
+if echo "$REPORT" | grep -q "Updated 3 path(s): 1 created, 1 overwritten, 1 deleted, 1 unchanged" && \
+   [ -f removed/inside.txt ] && [ ! -e added.txt ]; then
+    echo -e "${GREEN}SUCCESS: Checkout reported the files it wrote and removed${NC}"
+else
+    echo -e "${RED}FAILED: Checkout report verification failed${NC}"
+    echo "$REPORT"
+    exit 1
+fi
+destroy_step_repo report-repo
+
+# Step 73: Test interactive resolution of conflicts
+print_step "73. Testing interactive conflict resolution"
+new_step_repo resolve-repo "Create repository for interactive resolution"
+echo "Base" > conflict.txt
+"$VX_PATH" commit new "Base"
+echo "Top" > conflict.txt
+"$VX_PATH" commit new "Top"
+check_success "Create commits to conflict with"
+"$VX_PATH" tree checkout main:1
+echo "Mine" > conflict.txt
+AMEND_STATUS=0
+AMEND_OUTPUT=$(echo "t" | "$VX_PATH" commit amend --interactive 2>&1) || AMEND_STATUS=$?
+if [ "$AMEND_STATUS" -ne 0 ] && echo "$AMEND_OUTPUT" | grep -q "not a terminal" && \
+   grep -q "^<<<<<<< local" conflict.txt; then
+    echo -e "${GREEN}SUCCESS: Conflict markers were left without a terminal${NC}"
+else
+    echo -e "${RED}FAILED: Non-interactive fallback verification failed${NC}"
+    echo "$AMEND_OUTPUT"
+    exit 1
+fi
+
+# script runs the command in a pseudo terminal, feeding it the answers
+if script --version 2>/dev/null | grep -q util-linux; then
+    RESOLVE_OUTPUT=$(printf 'x\nt\n' | \
+        script -qec "$VX_PATH commit rebuild --continue --interactive" /dev/null)
+    check_success "Resolve the rebuild conflict by taking theirs"
+    AMENDED_CONTENT=$(cat conflict.txt)
+    "$VX_PATH" tree checkout main
+    if echo "$RESOLVE_OUTPUT" | grep -q "Resolved conflict.txt" && \
+       echo "$RESOLVE_OUTPUT" | grep -q "Rebuild completed" && \
+       [ "$AMENDED_CONTENT" = "Mine" ] && [ "$(cat conflict.txt)" = "Top" ]; then
+        echo -e "${GREEN}SUCCESS: Rebuild conflict was resolved from the prompt${NC}"
+    else
+        echo -e "${RED}FAILED: Interactive rebuild resolution verification failed${NC}"
+        echo "$RESOLVE_OUTPUT"
+        exit 1
+    fi
+
+    printf '#!/bin/sh\necho "Edited" > "$1"\n' > "$TEST_DIR/resolve-editor.sh"
+    chmod +x "$TEST_DIR/resolve-editor.sh"
+    "$VX_PATH" tree checkout main:1
+    echo "Local" > conflict.txt
+    MERGE_OUTPUT=$(printf 'e\n' | VX_EDITOR="$TEST_DIR/resolve-editor.sh" \
+        script -qec "$VX_PATH tree checkout main --merge --interactive" /dev/null)
+    check_success "Resolve the merge conflict in the editor"
+    if echo "$MERGE_OUTPUT" | grep -q "Resolved conflict.txt" && \
+       [ "$(cat conflict.txt)" = "Edited" ]; then
+        echo -e "${GREEN}SUCCESS: Merge conflict was resolved in the editor${NC}"
+    else
+        echo -e "${RED}FAILED: Interactive merge resolution verification failed${NC}"
+        echo "$MERGE_OUTPUT"
+        exit 1
+    fi
+else
+    echo "Skipping prompts, util-linux script is not available"
+fi
+destroy_step_repo resolve-repo
+
+# Step 74: Test the description of a repository
+print_step "74. Testing repository info"
+new_step_repo info-repo "Create repository to describe" --meta init.defaultBranch=trunk --meta text.eol=lf
+INFO_TEXT=$("$VX_PATH" repo info)
+check_success "Describe repository"
+INFO_JSON=$("$VX_PATH" repo info --json)
+check_success "Describe repository as JSON"
+if echo "$INFO_TEXT" | grep -q "^Default branch:  trunk" && \
+   echo "$INFO_TEXT" | grep -q "tree.maxDepth = 256" && \
+   echo "$INFO_JSON" | grep -q '"name": "info-repo"' && \
+   echo "$INFO_JSON" | grep -q '"hashAlgorithm": "xxh3-128"' && \
+   echo "$INFO_JSON" | grep -q '"blobShardDigits": 2' && \
+   echo "$INFO_JSON" | grep -q '"index.trustSize": null' && \
+   echo "$INFO_JSON" | grep -q '"text.eol": "lf"' && \
+   echo "$INFO_JSON" | grep -q '"init.defaultBranch": "trunk"'; then
+    echo -e "${GREEN}SUCCESS: Repository was described with its layout and settings${NC}"
+else
+    echo -e "${RED}FAILED: Repository info verification failed${NC}"
+    echo "$INFO_TEXT"
+    echo "$INFO_JSON"
+    exit 1
+fi
+destroy_step_repo info-repo
+
+# Step 75: Test walking folders sorted in batches spilled to disk
+print_step "75. Testing batched folder listings"
+new_step_repo batch-repo "Create repository with a small listing batch" --meta tree.dirBatchSize=3
+mkdir big
+for i in $(seq 1 20); do
+    echo "$i" > "big/file$i.txt"
+    mkdir "big/dir$i"
+    echo "inside" > "big/dir$i/inside.txt"
+done
+"$VX_PATH" commit new "Big folder"
+check_success "Commit a folder larger than a batch"
+echo "changed" > big/file7.txt
+rm big/file13.txt
+rm -r big/dir5
+echo "new" > big/file21.txt
+BATCH_STATUS=$("$VX_PATH" status)
+if echo "$BATCH_STATUS" | grep -q "modified file big/file7.txt" && \
+   echo "$BATCH_STATUS" | grep -q "deleted file big/file13.txt" && \
+   echo "$BATCH_STATUS" | grep -q "deleted folder big/dir5" && \
+   echo "$BATCH_STATUS" | grep -q "added file big/file21.txt" && \
+   [ "$(echo "$BATCH_STATUS" | grep -c "big/")" -eq 4 ]; then
+    echo -e "${GREEN}SUCCESS: Status merged the sorted batches of the folder${NC}"
+else
+    echo -e "${RED}FAILED: Batched status verification failed${NC}"
+    echo "$BATCH_STATUS"
+    exit 1
+fi
+"$VX_PATH" commit new "Changed big folder"
+"$VX_PATH" tree checkout main:1
+check_success "Checkout the folder walked in batches"
+if [ "$(cat big/file7.txt)" = "7" ] && [ -f big/file13.txt ] && [ -f big/dir5/inside.txt ] && \
+   [ ! -e big/file21.txt ] && "$VX_PATH" status | grep -q "No files changed" && \
+   [ -z "$(ls -A .vx/.vxtemp 2>/dev/null)" ]; then
+    echo -e "${GREEN}SUCCESS: Checkout restored the folder and removed the run files${NC}"
+else
+    echo -e "${RED}FAILED: Batched checkout verification failed${NC}"
+    exit 1
+fi
+cd "$TEST_DIR"
+(cd batch-repo && "$VX_PATH" repo destroy --yes --checkout)
+BATCH_ERROR=$("$VX_PATH" repo new invalid-batch-repo --meta tree.dirBatchSize=0 2>&1) && exit 1
+if echo "$BATCH_ERROR" | grep -q "tree.dirBatchSize" && [ ! -e invalid-batch-repo ]; then
+    echo -e "${GREEN}SUCCESS: A zero listing batch was refused${NC}"
+else
+    echo -e "${RED}FAILED: Invalid listing batch verification failed${NC}"
+    echo "$BATCH_ERROR"
+    exit 1
+fi
+cd "$REPO_DIR"
+
+# Step 76: Test quiet and JSON checkouts for scripts
+print_step "76. Testing tree checkout --quiet and --json"
+new_step_repo quiet-repo "Create repository for scripted checkouts"
+echo "one" > a.txt
+echo "one" > b.txt
+"$VX_PATH" commit new "First"
+echo "two" > a.txt
+rm b.txt
+echo "new" > c.txt
+"$VX_PATH" commit new "Second"
+QUIET_OUTPUT=$("$VX_PATH" tree checkout main:1 --quiet)
+CHECKOUT_JSON=$("$VX_PATH" tree checkout main:2 --json)
+if [ -z "$QUIET_OUTPUT" ] && \
+   echo "$CHECKOUT_JSON" | grep -q '"commit": *"main:2"' && \
+   echo "$CHECKOUT_JSON" | grep -q '"created": *1' && \
+   echo "$CHECKOUT_JSON" | grep -q '"overwritten": *1' && \
+   echo "$CHECKOUT_JSON" | grep -q '"deleted": *1' && \
+   echo "$CHECKOUT_JSON" | grep -q '"failed": *0' && \
+   echo "$CHECKOUT_JSON" | grep -q '"detached": *false' && \
+   [ "$(cat a.txt)" = "two" ] && [ ! -e b.txt ]; then
+    echo -e "${GREEN}SUCCESS: Checkouts printed nothing and the counts as JSON${NC}"
+else
+    echo -e "${RED}FAILED: Scripted checkout verification failed${NC}"
+    echo "$QUIET_OUTPUT"
+    echo "$CHECKOUT_JSON"
+    exit 1
+fi
+QUIET_STATUS=0
+QUIET_ERROR=$("$VX_PATH" tree checkout main:9 --quiet 2>&1) || QUIET_STATUS=$?
+if [ "$QUIET_STATUS" -ne 0 ] && [ -n "$QUIET_ERROR" ]; then
+    echo -e "${GREEN}SUCCESS: A failing quiet checkout still printed its error and failed${NC}"
+else
+    echo -e "${RED}FAILED: Failing quiet checkout verification failed${NC}"
+    exit 1
+fi
+destroy_step_repo quiet-repo
+
+# Step 77: Test bisecting the commits of a branch
+print_step "77. Testing bisect"
+new_step_repo bisect-repo "Create repository for bisect"
+for i in $(seq 1 10); do
+    echo "$i" > number.txt
+    "$VX_PATH" commit new "Number $i" > /dev/null
+done
+BISECT_RUN=$("$VX_PATH" bisect start 1 10 -- sh -c '[ "$(cat number.txt)" -lt 7 ]')
+if echo "$BISECT_RUN" | grep -q "First bad commit: main:7 Number 7" && \
+   [ "$(cat number.txt)" = "7" ] && \
+   "$VX_PATH" bisect status | grep -q "started at main:10"; then
+    echo -e "${GREEN}SUCCESS: Bisect run found the first bad commit${NC}"
+else
+    echo -e "${RED}FAILED: Bisect run verification failed${NC}"
+    echo "$BISECT_RUN"
+    exit 1
+fi
+"$VX_PATH" bisect reset
+check_success "Reset bisect"
+if [ "$(cat number.txt)" = "10" ] && ! "$VX_PATH" bisect status 2>/dev/null; then
+    echo -e "${GREEN}SUCCESS: Bisect reset checked out the original commit${NC}"
+else
+    echo -e "${RED}FAILED: Bisect reset verification failed${NC}"
+    exit 1
+fi
+"$VX_PATH" bisect start 2 6 | grep -q "Testing commit main:4"
+check_success "Start a manual bisect"
+"$VX_PATH" bisect good | grep -q "Testing commit main:5"
+check_success "Mark a commit good"
+BISECT_BAD=$("$VX_PATH" bisect bad)
+if echo "$BISECT_BAD" | grep -q "First bad commit: main:5" && [ "$(cat number.txt)" = "5" ]; then
+    echo -e "${GREEN}SUCCESS: Manual bisect found the first bad commit${NC}"
+else
+    echo -e "${RED}FAILED: Manual bisect verification failed${NC}"
+    echo "$BISECT_BAD"
+    exit 1
+fi
+"$VX_PATH" bisect reset > /dev/null
+echo "local" > number.txt
+BISECT_ERROR=$("$VX_PATH" bisect start 1 10 2>&1) && exit 1
+if echo "$BISECT_ERROR" | grep -q "uncommitted change" && \
+   ! "$VX_PATH" bisect status 2>/dev/null; then
+    echo -e "${GREEN}SUCCESS: Bisect with local changes was refused${NC}"
+else
+    echo -e "${RED}FAILED: Bisect with local changes verification failed${NC}"
+    echo "$BISECT_ERROR"
+    exit 1
+fi
+destroy_step_repo bisect-repo
+
+# Step 78: Test finding renamed files in diffs
+print_step "78. Testing tree diff --find-renames"
+new_step_repo rename-repo "Create repository for rename detection"
+seq 1 100 > numbers.txt
+seq 1 50 > copy.txt
+echo "gone" > gone.txt
+"$VX_PATH" commit new "Before renames"
+mv numbers.txt counted.txt
+echo "101" >> counted.txt
+mkdir moved
+mv copy.txt moved/copy.txt
+rm gone.txt
+echo "new" > new.txt
+"$VX_PATH" commit new "After renames"
+RENAMES=$("$VX_PATH" tree diff 1 2 --find-renames)
+EXACT_RENAMES=$("$VX_PATH" tree diff 1 2 --find-renames=100)
+if echo "$RENAMES" | grep -q "renamed file numbers.txt -> counted.txt (9[0-9]%)" && \
+   echo "$RENAMES" | grep -q "renamed file copy.txt -> moved/copy.txt (100%)" && \
+   echo "$RENAMES" | grep -q "deleted file gone.txt" && \
+   echo "$RENAMES" | grep -q "added file new.txt" && \
+   ! echo "$RENAMES" | grep -q "deleted file numbers.txt" && \
+   echo "$EXACT_RENAMES" | grep -q "renamed file copy.txt -> moved/copy.txt (100%)" && \
+   echo "$EXACT_RENAMES" | grep -q "deleted file numbers.txt" && \
+   echo "$EXACT_RENAMES" | grep -q "added file counted.txt"; then
+    echo -e "${GREEN}SUCCESS: Diff paired moved and edited files as renames${NC}"
+else
+    echo -e "${RED}FAILED: Rename detection verification failed${NC}"
+    echo "$RENAMES"
+    echo "$EXACT_RENAMES"
+    exit 1
+fi
+if ! "$VX_PATH" tree diff 1 2 --find-renames=0 2>/dev/null; then
+    echo -e "${GREEN}SUCCESS: A zero rename threshold was refused${NC}"
+else
+    echo -e "${RED}FAILED: Invalid rename threshold verification failed${NC}"
+    exit 1
+fi
+destroy_step_repo rename-repo
+
+# Step 79: Test the per-branch lock serializing commits
+print_step "79. Testing branch head locks"
+new_step_repo lock-repo "Create repository for branch locks"
+echo "one" > file.txt
+"$VX_PATH" commit new "First"
+LOCK_FILES=$(ls .vx/locks)
+if echo "$LOCK_FILES" | grep -q "^branch-[0-9]*\.lock$"; then
+    echo -e "${GREEN}SUCCESS: Commit took a lock file named by the branch id${NC}"
+else
+    echo -e "${RED}FAILED: Branch lock file verification failed${NC}"
+    echo "$LOCK_FILES"
+    exit 1
+fi
+if command -v flock > /dev/null; then
+    LOCK_FILE=".vx/locks/$LOCK_FILES"
+    flock "$LOCK_FILE" sleep 2 &
+    LOCK_HOLDER=$!
+    sleep 0.5
+    echo "two" > file.txt
+    LOCK_START=$(date +%s%N)
+    "$VX_PATH" commit new "Second"
+    LOCK_ELAPSED=$(( ($(date +%s%N) - LOCK_START) / 1000000 ))
+    wait "$LOCK_HOLDER"
+    if [ "$LOCK_ELAPSED" -ge 1000 ] && "$VX_PATH" log | grep -q "Second"; then
+        echo -e "${GREEN}SUCCESS: Commit waited for the branch lock held by another process${NC}"
+    else
+        echo -e "${RED}FAILED: Branch lock wait verification failed${NC}"
+        echo "Commit took ${LOCK_ELAPSED}ms"
+        exit 1
+    fi
+else
+    echo "flock is not available, skipping the lock wait check"
+fi
+destroy_step_repo lock-repo
+
+# Step 80: Test writing content to a file or standard output
+print_step "80. Testing --output and - for standard output"
+new_step_repo output-repo "Create repository for output destinations"
+mkdir docs
+echo "first line" > docs/readme.txt
+"$VX_PATH" commit new "Add readme"
+"$VX_PATH" repo export-git --output "$TEST_DIR/export.fi" 2>/dev/null
+check_success "Export git stream to a file"
+EXPORT_STDOUT=$("$VX_PATH" repo export-git -o - 2>/dev/null)
+BLOB_HASH=$("$VX_PATH" blob import docs/readme.txt)
+"$VX_PATH" blob cat "$BLOB_HASH" --output "$TEST_DIR/blob.txt"
+check_success "Write blob content to a file"
+EXTRACTED=$("$VX_PATH" tree extract 1 docs/readme.txt -)
+if [ "$EXPORT_STDOUT" = "$(cat "$TEST_DIR/export.fi")" ] && \
+   echo "$EXPORT_STDOUT" | grep -q "^commit refs/heads/main" && \
+   [ "$(cat "$TEST_DIR/blob.txt")" = "first line" ] && \
+   [ "$EXTRACTED" = "first line" ] && \
+   ! "$VX_PATH" tree extract 1 docs - > /dev/null 2>&1; then
+    echo -e "${GREEN}SUCCESS: Commands wrote the same content to files and standard output${NC}"
+else
+    echo -e "${RED}FAILED: Output destination verification failed${NC}"
+    echo "$EXTRACTED"
+    exit 1
+fi
+rm "$TEST_DIR/export.fi" "$TEST_DIR/blob.txt"
+destroy_step_repo output-repo
+
+# Step 81: Test signing commits and verifying their signatures
+print_step "81. Testing commit signing with ed25519"
+new_step_repo signing-repo "Create repository for commit signing"
+head -c 32 /dev/urandom | od -An -tx1 | tr -d ' \n' > "$TEST_DIR/signing.key"
+echo "not a key" > "$TEST_DIR/bad.key"
+SET_KEY=$("$VX_PATH" repo set-key "$TEST_DIR/signing.key")
+check_success "Register signing key"
+PUBLIC_KEY=${SET_KEY##* }
+echo "signed" > signed.txt
+"$VX_PATH" commit new --sign "Signed commit"
+check_success "Create signed commit"
+echo "unsigned" > unsigned.txt
+"$VX_PATH" commit new "Unsigned commit"
+VERIFIED=$("$VX_PATH" commit verify 1)
+check_success "Verify signed commit"
+if [ "${#PUBLIC_KEY}" = 64 ] && \
+   [ "$VERIFIED" = "Good signature of commit main:1 by key $PUBLIC_KEY" ] && \
+   "$VX_PATH" commit show 1 --raw | grep -q "\"public_key\": \"$PUBLIC_KEY\"" && \
+   ! "$VX_PATH" commit verify 2 > /dev/null 2>&1 && \
+   "$VX_PATH" repo info | grep -q "commit.signingKey" && \
+   ! "$VX_PATH" repo set-key "$TEST_DIR/bad.key" > /dev/null 2>&1; then
+    echo -e "${GREEN}SUCCESS: Signed commit verified, unsigned commit and invalid key refused${NC}"
+else
+    echo -e "${RED}FAILED: Commit signing verification failed${NC}"
+    echo "$SET_KEY"
+    echo "$VERIFIED"
+    exit 1
+fi
+rm "$TEST_DIR/signing.key" "$TEST_DIR/bad.key"
+destroy_step_repo signing-repo
+
+# Step 82: Test coloring of status output
+print_step "82. Testing tree status --color"
+new_step_repo color-repo "Create repository for colored status"
+echo "kept" > kept.txt
+echo "gone" > gone.txt
+"$VX_PATH" commit new "Add files"
+echo "changed" > kept.txt
+rm gone.txt
+echo "new" > new.txt
+ESC=$(printf '\033')
+COLORED=$("$VX_PATH" tree status --color=always)
+FORCED=$("$VX_PATH" tree status --color)
+PIPED=$("$VX_PATH" tree status)
+if echo "$COLORED" | grep -q "^  ${ESC}\[32madded file new.txt${ESC}\[0m$" && \
+   echo "$COLORED" | grep -q "^  ${ESC}\[31mdeleted file gone.txt${ESC}\[0m$" && \
+   echo "$COLORED" | grep -q "^  ${ESC}\[33mmodified file kept.txt${ESC}\[0m$" && \
+   [ "$FORCED" = "$COLORED" ] && \
+   ! echo "$PIPED" | grep -q "$ESC" && \
+   [ "$("$VX_PATH" tree status --color=never)" = "$PIPED" ] && \
+   ! "$VX_PATH" tree status --color=sometimes > /dev/null 2>&1; then
+    echo -e "${GREEN}SUCCESS: Status colored when forced and plain when piped${NC}"
+else
+    echo -e "${RED}FAILED: Colored status verification failed${NC}"
+    echo "$COLORED" | cat -v
+    exit 1
+fi
+destroy_step_repo color-repo
+
+# Step 83: Test the author of new commits taken from metadata or the environment
+print_step "83. Testing commit author from configuration"
+new_step_repo author-repo "Create repository with an author" --meta "user.author=Repo Author <repo@example.com>"
+echo "first" > first.txt
+VX_AUTHOR="Env Author <env@example.com>" "$VX_PATH" commit new "Commit by the repository author"
+check_success "Commit with the repository author"
+"$VX_PATH" repo new env-repo --allow-nested
+cd env-repo
+echo "second" > second.txt
+VX_AUTHOR="Env Author <env@example.com>" "$VX_PATH" commit new "Commit by the environment author"
+check_success "Commit with the environment author"
+ENV_SHOW=$("$VX_PATH" commit show)
+ENV_LIST=$("$VX_PATH" commit list --author)
+cd ..
+REPO_LIST=$("$VX_PATH" commit list --author)
+if echo "$REPO_LIST" | head -1 | grep -q "	Repo Author <repo@example.com>	Commit by the repository author$" && \
+   echo "$ENV_LIST" | head -1 | grep -q "	Env Author <env@example.com>	Commit by the environment author$" && \
+   echo "$ENV_SHOW" | grep -q "^Author: Env Author <env@example.com>$" && \
+   "$VX_PATH" repo info | grep -q "user.author = Repo Author <repo@example.com>" && \
+   ! "$VX_PATH" commit list --author --graph > /dev/null 2>&1; then
+    echo -e "${GREEN}SUCCESS: Commits made by the configured author${NC}"
+else
+    echo -e "${RED}FAILED: Commit author verification failed${NC}"
+    echo "$REPO_LIST"
+    echo "$ENV_LIST"
+    exit 1
+fi
+rm -rf env-repo
+destroy_step_repo author-repo
+
+# Step 84: Test listing commits created within a period
+print_step "84. Testing commit list --since and --until"
+new_step_repo period-repo "Create repository for listing by time"
+echo "old" > old.txt
+"$VX_PATH" commit new --date 2024-01-10 "Old commit"
+echo "skewed" > skewed.txt
+"$VX_PATH" commit new --date 2023-06-01 "Skewed commit"
+echo "new" > new.txt
+"$VX_PATH" commit new --date 2024-03-01T12:00:00Z "New commit"
+SINCE=$("$VX_PATH" commit list --since 2024-01-01)
+UNTIL=$("$VX_PATH" commit list --until 2024-01-31 --format "%m")
+BETWEEN=$("$VX_PATH" commit list --since 2023-01-01 --until 2024-02-01 --format "%m")
+# the centinel commit is dated with the creation of the repository, i.e. now
+if [ "$(echo "$SINCE" | wc -l)" = 3 ] && echo "$SINCE" | grep -q "New commit" && \
+   echo "$SINCE" | grep -q "Old commit" && ! echo "$SINCE" | grep -q "Skewed commit" && \
+   [ "$(echo "$UNTIL" | head -2)" = "$(printf 'Skewed commit\nOld commit')" ] && \
+   [ "$BETWEEN" = "$(printf 'Skewed commit\nOld commit')" ] && \
+   ! "$VX_PATH" commit list --since yesterday > /dev/null 2>&1; then
+    echo -e "${GREEN}SUCCESS: Commits listed within the period, skewed commit included${NC}"
+else
+    echo -e "${RED}FAILED: Listing by time verification failed${NC}"
+    echo "$SINCE"
+    echo "$UNTIL"
+    exit 1
+fi
+destroy_step_repo period-repo
+
+# Step 85: Test the diff alias comparing the stored trees of two commits
+print_step "85. Testing vx diff between two commits"
+new_step_repo diff-alias-repo "Create repository for the diff alias"
+mkdir src
+echo "kept" > src/kept.txt
+echo "gone" > gone.txt
+"$VX_PATH" commit new "First"
+echo "changed" > src/kept.txt
+rm gone.txt
+echo "added" > added.txt
+"$VX_PATH" commit new "Second"
+# local changes must not show up, only the stored trees are compared
+echo "local" > local.txt
+DIFF=$("$VX_PATH" diff 1 2)
+NAMES=$("$VX_PATH" diff 1 2 --name-only)
+if [ "$DIFF" = "$("$VX_PATH" tree diff 1 2)" ] && \
+   echo "$DIFF" | grep -q "added file added.txt" && \
+   echo "$DIFF" | grep -q "deleted file gone.txt" && \
+   echo "$DIFF" | grep -q "modified file src/kept.txt" && \
+   ! echo "$DIFF" | grep -q "local.txt" && \
+   ! echo "$DIFF" | grep -q "folder *$" && \
+   [ "$NAMES" = "$("$VX_PATH" tree diff 1 2 --name-only)" ] && \
+   echo "$NAMES" | grep -q "^src/kept.txt$" && \
+   [ "$("$VX_PATH" diff 2 --name-only)" = "" ]; then
+    echo -e "${GREEN}SUCCESS: vx diff compared the stored trees like tree diff${NC}"
+else
+    echo -e "${RED}FAILED: Diff alias verification failed${NC}"
+    echo "$DIFF"
+    echo "$NAMES"
+    exit 1
+fi
+destroy_step_repo diff-alias-repo
+
+# Step 86: Test .vxignore files excluding paths from tracking
+print_step "86. Testing .vxignore patterns"
+new_step_repo vxignore-repo "Create repository for ignore files"
+echo "tracked log" > tracked.log
+"$VX_PATH" commit new "Track a log file"
+printf '# logs and build output\n*.log\n!keep.log\nbuild/\ndocs/**/tmp\n/root-only.txt\n' > .vxignore
+mkdir -p build src/build-notes docs/a/b/tmp node/pkg
+echo "output" > build/out.bin
+echo "notes" > src/build-notes/n.txt
+echo "scratch" > docs/a/b/tmp/scratch.txt
+echo "new log" > new.log
+echo "kept log" > keep.log
+echo "root" > root-only.txt
+echo "nested" > src/root-only.txt
+printf 'pkg/\n' > node/.vxignore
+echo "module" > node/pkg/index.js
+echo "main" > node/main.js
+STATUS=$("$VX_PATH" tree status --untracked=all)
+IGNORED=$("$VX_PATH" tree status --untracked=all --include-ignored)
+echo "tracked log changed" > tracked.log
+MODIFIED=$("$VX_PATH" tree status)
+"$VX_PATH" commit new "Add ignore files"
+check_success "Commit with ignore files"
+if echo "$STATUS" | grep -q "added file keep.log" && \
+   echo "$STATUS" | grep -q "added file src/build-notes/n.txt" && \
+   echo "$STATUS" | grep -q "added file src/root-only.txt" && \
+   echo "$STATUS" | grep -q "added file node/main.js" && \
+   ! echo "$STATUS" | grep -q "new.log\|build/out.bin\|docs/a/b/tmp\| root-only.txt\|node/pkg" && \
+   echo "$IGNORED" | grep -q "added folder build (ignored)" && \
+   echo "$IGNORED" | grep -q "added file new.log (ignored)" && \
+   echo "$IGNORED" | grep -q "added folder node/pkg (ignored)" && \
+   echo "$MODIFIED" | grep -q "modified file tracked.log"; then
+    echo -e "${GREEN}SUCCESS: Ignore files excluded paths, tracked files kept${NC}"
+else
+    echo -e "${RED}FAILED: Ignore file verification failed${NC}"
+    echo "$STATUS"
+    echo "$IGNORED"
+    exit 1
+fi
+"$VX_PATH" tree checkout 1
+check_success "Check out the commit before the ignore files"
+if [ "$(cat tracked.log)" = "tracked log" ] && [ ! -e .vxignore ] && [ -f new.log ] && \
+   [ -f build/out.bin ]; then
+    echo -e "${GREEN}SUCCESS: Checkout updated the tracked log and left ignored files${NC}"
+else
+    echo -e "${RED}FAILED: Checkout of ignored files verification failed${NC}"
+    exit 1
+fi
+destroy_step_repo vxignore-repo
+
+# Step 87: Test symbolic links stored with their target and recreated by checkouts
+print_step "87. Testing symbolic links"
+new_step_repo symlink-repo "Create repository for symbolic links"
+mkdir -p docs
+echo "target content" > target.txt
+echo "inner" > docs/inner.txt
+ln -s target.txt link.txt
+ln -s docs docs-link
+ln -s missing.txt dangling.txt
+"$VX_PATH" commit new "Add links"
+check_success "Commit symbolic links"
+CLEAN=$("$VX_PATH" tree status)
+ln -sfn docs/inner.txt link.txt
+CHANGED=$("$VX_PATH" tree status)
+"$VX_PATH" commit new "Retarget link"
+check_success "Commit retargeted link"
+if echo "$CLEAN" | grep -q "No files changed" && echo "$CHANGED" | grep -q "modified file link.txt" && \
+   ! echo "$CHANGED" | grep -q "docs-link\|dangling.txt\|target.txt"; then
+    echo -e "${GREEN}SUCCESS: Links were committed and a new target reported as a change${NC}"
+else
+    echo -e "${RED}FAILED: Symbolic link status verification failed${NC}"
+    echo "$CLEAN"
+    echo "$CHANGED"
+    exit 1
+fi
+"$VX_PATH" tree checkout 1
+check_success "Check out the commit with the original link"
+if [ -L link.txt ] && [ "$(readlink link.txt)" = "target.txt" ] && \
+   [ -L docs-link ] && [ "$(readlink docs-link)" = "docs" ] && \
+   [ -L dangling.txt ] && [ "$(readlink dangling.txt)" = "missing.txt" ] && \
+   [ "$(cat target.txt)" = "target content" ] && \
+   "$VX_PATH" tree status | grep -q "No files changed"; then
+    echo -e "${GREEN}SUCCESS: Checkout recreated the links with their targets${NC}"
+else
+    echo -e "${RED}FAILED: Checkout of symbolic links verification failed${NC}"
+    ls -l
+    exit 1
+fi
+rm link.txt
+echo "target.txt" > link.txt
+REPLACED=$("$VX_PATH" tree status)
+if echo "$REPLACED" | grep -q "modified file link.txt"; then
+    echo -e "${GREEN}SUCCESS: A file replacing a link with its target was reported${NC}"
+else
+    echo -e "${RED}FAILED: Link replaced by a file was not reported${NC}"
+    echo "$REPLACED"
+    exit 1
+fi
+"$VX_PATH" tree checkout 2
+check_success "Check out the retargeted link over a file"
+"$VX_PATH" repo export-git > "$TEST_DIR/symlink.stream"
+check_success "Export links as a git fast-import stream"
+if [ -L link.txt ] && [ "$(readlink link.txt)" = "docs/inner.txt" ] && \
+   grep -q "^M 120000 :[0-9]* link.txt$" "$TEST_DIR/symlink.stream" && \
+   grep -q "^M 100644 :[0-9]* target.txt$" "$TEST_DIR/symlink.stream"; then
+    echo -e "${GREEN}SUCCESS: Link was restored over a file and exported as a link${NC}"
+else
+    echo -e "${RED}FAILED: Symbolic link checkout or export verification failed${NC}"
+    ls -l
+    exit 1
+fi
+destroy_step_repo symlink-repo
+
+# Step 88: Test renaming branches
+print_step "88. Testing branch rename"
+new_step_repo rename-repo "Create repository for branch rename"
+echo "main file" > main.txt
+"$VX_PATH" commit new "Main commit"
+"$VX_PATH" branch new feature
+check_success "Create branch to rename"
+echo "feature file" > feature.txt
+"$VX_PATH" commit new "Feature commit"
+"$VX_PATH" tag add feature-tag feature:1
+"$VX_PATH" branch protect feature
+echo "stashed change" > feature.txt
+"$VX_PATH" stash push "Feature work"
+"$VX_PATH" branch rename feature topic
+check_success "Rename the current branch"
+TOPIC_ID=$("$VX_PATH" branch show topic | grep "  ID:" | awk '{print $2}')
+if "$VX_PATH" branch show | grep -q "Name:          topic" && \
+   "$VX_PATH" branch show topic | grep -q "Protected:     true" && \
+   ! "$VX_PATH" branch show feature >/dev/null 2>&1 && \
+   "$VX_PATH" commit list | grep -q "Feature commit" && \
+   "$VX_PATH" tag list | grep -q "^feature-tag	$TOPIC_ID:1$" && \
+   "$VX_PATH" stash list | grep -q "	$TOPIC_ID:1	Feature work"; then
+    echo -e "${GREEN}SUCCESS: Renamed branch kept its commits, tag, stash and protection${NC}"
+else
+    echo -e "${RED}FAILED: Branch rename verification failed${NC}"
+    "$VX_PATH" branch list
+    "$VX_PATH" tag list
+    "$VX_PATH" stash list
+    exit 1
+fi
+"$VX_PATH" stash pop
+check_success "Pop the stash onto the renamed branch"
+"$VX_PATH" commit new "Topic commit" --force
+check_success "Commit to the renamed branch"
+"$VX_PATH" branch rename main trunk
+check_success "Rename the foundational branch"
+TRUNK_ID=$("$VX_PATH" branch show trunk | grep "  ID:" | awk '{print $2}')
+RENAME_EXISTING=$("$VX_PATH" branch rename topic trunk 2>&1 || true)
+RENAME_INVALID=$("$VX_PATH" branch rename topic Bad_Name 2>&1 || true)
+if [ "$(cat feature.txt)" = "stashed change" ] && \
+   "$VX_PATH" commit list | grep -q "Topic commit" && \
+   "$VX_PATH" branch show topic | grep -q "Parent:        $TRUNK_ID$" && \
+   "$VX_PATH" commit list trunk | grep -q "Main commit" && \
+   echo "$RENAME_EXISTING" | grep -q "BranchExists" && \
+   echo "$RENAME_INVALID" | grep -q "InvalidName" && \
+   "$VX_PATH" branch show topic >/dev/null; then
+    echo -e "${GREEN}SUCCESS: Parent rename followed by children, taken and invalid names refused${NC}"
+else
+    echo -e "${RED}FAILED: Branch rename of the parent verification failed${NC}"
+    "$VX_PATH" branch list
+    echo "$RENAME_EXISTING"
+    echo "$RENAME_INVALID"
+    exit 1
+fi
+destroy_step_repo rename-repo
+
+# Step 89: Test merging branches
+print_step "89. Testing branch merge"
+new_step_repo merge-repo "Create repository for branch merge"
+printf "first\nsecond\nthird\n" > lines.txt
+echo "shared" > shared.txt
+echo "gone" > gone.txt
+"$VX_PATH" commit new "Base commit"
+"$VX_PATH" branch new feature
+printf "feature first\nsecond\nthird\n" > lines.txt
+echo "feature file" > feature.txt
+rm gone.txt
+"$VX_PATH" commit new "Feature commit"
+"$VX_PATH" tree checkout main:1
+printf "first\nsecond\nmain third\n" > lines.txt
+"$VX_PATH" commit new "Main commit"
+MERGE_OUTPUT=$("$VX_PATH" branch merge feature)
+check_success "Merge a branch without conflicts"
+UP_TO_DATE=$("$VX_PATH" branch merge feature)
+if echo "$MERGE_OUTPUT" | grep -q "Merged branch feature in commit 3 - Merge branch 'feature'" && \
+   [ "$(cat lines.txt)" = "$(printf "feature first\nsecond\nmain third")" ] && \
+   [ "$(cat feature.txt)" = "feature file" ] && [ ! -e gone.txt ] && \
+   "$VX_PATH" tree status | grep -q "No files changed" && \
+   echo "$UP_TO_DATE" | grep -q "Already up to date"; then
+    echo -e "${GREEN}SUCCESS: Merge applied the changes of both branches and committed them${NC}"
+else
+    echo -e "${RED}FAILED: Branch merge verification failed${NC}"
+    echo "$MERGE_OUTPUT"
+    cat lines.txt
+    exit 1
+fi
+"$VX_PATH" branch new other
+echo "other shared" > shared.txt
+"$VX_PATH" commit new "Other commit"
+"$VX_PATH" tree checkout main:3
+echo "main shared" > shared.txt
+"$VX_PATH" commit new "Main shared commit"
+echo "local" > local.txt
+MERGE_DIRTY=$("$VX_PATH" branch merge other 2>&1 || true)
+rm local.txt
+if CONFLICT_OUTPUT=$("$VX_PATH" branch merge other 2>&1); then
+    echo -e "${RED}FAILED: Merge with conflicts succeeded${NC}"
+    exit 1
+fi
+if echo "$MERGE_DIRTY" | grep -q "local changes" && \
+   echo "$CONFLICT_OUTPUT" | grep -q "both modified shared.txt" && \
+   grep -q "<<<<<<<" shared.txt && grep -q "other shared" shared.txt && \
+   "$VX_PATH" commit list | head -1 | grep -q "Main shared commit"; then
+    echo -e "${GREEN}SUCCESS: Conflicting merge left markers to resolve, local changes refused${NC}"
+else
+    echo -e "${RED}FAILED: Branch merge with conflicts verification failed${NC}"
+    echo "$MERGE_DIRTY"
+    echo "$CONFLICT_OUTPUT"
+    cat shared.txt
+    exit 1
+fi
+echo "resolved shared" > shared.txt
+"$VX_PATH" commit new "Resolve merge"
+check_success "Commit the resolved merge"
+"$VX_PATH" tree checkout feature >/dev/null
+printf "feature first again\nsecond\nthird\n" > lines.txt
+"$VX_PATH" commit new "Feature commit again" >/dev/null
+check_success "Change a line merged before"
+"$VX_PATH" tree checkout main >/dev/null
+if "$VX_PATH" branch merge feature | grep -q "Merged branch feature" && \
+   [ "$(cat lines.txt)" = "$(printf "feature first again\nsecond\nmain third")" ]; then
+    echo -e "${GREEN}SUCCESS: Second merge took the head merged before as the ancestor${NC}"
+else
+    echo -e "${RED}FAILED: Second merge conflicted with the changes merged before${NC}"
+    cat lines.txt
+    exit 1
+fi
+destroy_step_repo merge-repo
+
+# Step 90: Test status taking unchanged files from the index
+print_step "90. Testing status index"
+new_step_repo index-repo "Create repository for the status index"
+echo "indexed" > indexed.txt
+echo "resized" > resized.txt
+touch -d "1 minute ago" indexed.txt resized.txt
+"$VX_PATH" commit new "Settled files"
+check_success "Commit settled files"
+# change the content keeping the size and mtime, and the size of the other file
+touch -r indexed.txt "$TEST_DIR/indexed.mtime"
+echo "INDEXED" > indexed.txt
+touch -r "$TEST_DIR/indexed.mtime" indexed.txt
+echo "resized longer" > resized.txt
+INDEX_STATUS=$("$VX_PATH" tree status)
+if ! echo "$INDEX_STATUS" | grep -q "indexed.txt" && echo "$INDEX_STATUS" | grep -q "resized.txt"; then
+    echo -e "${GREEN}SUCCESS: Status took a file of unchanged mtime and size from the index${NC}"
+else
+    echo -e "${RED}FAILED: Status index verification failed${NC}"
+    echo "$INDEX_STATUS"
+    exit 1
+fi
+echo "indexed" > indexed.txt
+echo "second version" > resized.txt
+"$VX_PATH" commit new "Second version"
+"$VX_PATH" tree checkout 1
+check_success "Check out the first version"
+# a file written by the checkout is indexed by the first status once it settled
+touch -d "1 minute ago" resized.txt
+"$VX_PATH" tree status | grep -q "No files changed"
+touch -r resized.txt "$TEST_DIR/indexed.mtime"
+echo "RESIZED" > resized.txt
+touch -r "$TEST_DIR/indexed.mtime" resized.txt
+if "$VX_PATH" tree status | grep -q "No files changed"; then
+    echo -e "${GREEN}SUCCESS: Status indexed a settled file written by a checkout${NC}"
+else
+    echo -e "${RED}FAILED: Status index refresh verification failed${NC}"
+    "$VX_PATH" tree status
+    exit 1
+fi
+rm -f "$TEST_DIR/indexed.mtime"
+destroy_step_repo index-repo
+
+# Step 91: Test exit codes telling error categories apart
+print_step "91. Testing exit codes"
+new_step_repo exit-repo "Create repository for exit codes"
+echo "content" > file.txt
+"$VX_PATH" commit new "Exit commit"
+exit_code() {
+    "$VX_PATH" "$@" >/dev/null 2>&1 && echo 0 || echo $?
+}
+NO_CHANGES_CODE=$(exit_code commit new "Nothing")
+NO_STASH_CODE=$(exit_code stash push)
+MISSING_BRANCH_CODE=$(exit_code branch show missing)
+MISSING_COMMIT_CODE=$(exit_code commit show 99)
+MISSING_TAG_CODE=$(exit_code tag delete missing)
+INVALID_NAME_CODE=$(exit_code branch new Bad_Name)
+INVALID_ARGS_CODE=$(exit_code commit bogus)
+FAILURE_CODE=$(exit_code commit rebuild --abort)
+if [ "$NO_CHANGES_CODE" = 3 ] && [ "$NO_STASH_CODE" = 3 ] && \
+   [ "$MISSING_BRANCH_CODE" = 2 ] && [ "$MISSING_COMMIT_CODE" = 2 ] && \
+   [ "$MISSING_TAG_CODE" = 2 ] && [ "$INVALID_NAME_CODE" = 4 ] && \
+   [ "$INVALID_ARGS_CODE" = 4 ] && [ "$FAILURE_CODE" = 1 ] && \
+   [ "$(exit_code --version)" = 0 ]; then
+    echo -e "${GREEN}SUCCESS: Exit codes told nothing to do, not found and invalid arguments apart${NC}"
+else
+    echo -e "${RED}FAILED: Exit code verification failed${NC}"
+    echo "no changes $NO_CHANGES_CODE, no stash $NO_STASH_CODE"
+    echo "missing $MISSING_BRANCH_CODE $MISSING_COMMIT_CODE $MISSING_TAG_CODE"
+    echo "invalid $INVALID_NAME_CODE $INVALID_ARGS_CODE, failure $FAILURE_CODE"
+    exit 1
+fi
+destroy_step_repo exit-repo
+
+# Step 92: Test JSON output of listing commands and errors
+print_step "92. Testing JSON output"
+new_step_repo json-repo "Create repository for JSON output"
+echo "content" > file.txt
+"$VX_PATH" commit new "JSON \"quoted\" commit"
+echo "more" > added.txt
+echo "changed" > file.txt
+STATUS_JSON=$("$VX_PATH" --json tree status)
+STATUS_ALIAS_JSON=$("$VX_PATH" status --json)
+LOG_JSON=$("$VX_PATH" commit list --json)
+BRANCHES_JSON=$("$VX_PATH" branch list --json)
+NOT_FOUND_JSON=$("$VX_PATH" --json commit show 99 2>&1 >/dev/null || true)
+"$VX_PATH" --json commit list --graph >/dev/null 2>&1 && GRAPH_CODE=0 || GRAPH_CODE=$?
+"$VX_PATH" commit new "JSON diff" >/dev/null
+DIFF_JSON=$("$VX_PATH" --json diff 1 2)
+SHOW_JSON=$("$VX_PATH" --json commit show 2)
+UNSUPPORTED_JSON=$("$VX_PATH" --json tag add json-tag 2>&1 >/dev/null || true)
+"$VX_PATH" --json stash push >/dev/null 2>&1 && STASH_CODE=0 || STASH_CODE=$?
+USAGE_JSON=$("$VX_PATH" --json commit bogus 2>&1 >/dev/null) && USAGE_CODE=0 || USAGE_CODE=$?
+"$VX_PATH" tag add json-tag 1 >/dev/null
+echo "stashed" > file.txt
+"$VX_PATH" stash push "JSON stash" >/dev/null
+TAGS_JSON=$("$VX_PATH" --json tag list)
+STASHES_JSON=$("$VX_PATH" --json stash list)
+if echo "$STATUS_JSON" | grep -q '{"action":"added","path":"added.txt","type":"file",' && \
+   echo "$STATUS_JSON" | grep -q '{"action":"modified","path":"file.txt","type":"file",' && \
+   [ "$DIFF_JSON" = "$STATUS_JSON" ] && \
+   echo "$SHOW_JSON" | grep -q '^{"id":{"branch":"[0-9]*","seq":2},' && \
+   echo "$UNSUPPORTED_JSON" | grep -q '^{"code":4,"message":"' && [ "$STASH_CODE" = 4 ] && \
+   echo "$USAGE_JSON" | grep -q '^{"code":4,"message":"unrecognized subcommand' && [ "$USAGE_CODE" = 4 ] && \
+   echo "$TAGS_JSON" | grep -q '^\[{"name":"json-tag","commit":{"branch":"[0-9]*","seq":1}}\]$' && \
+   echo "$STASHES_JSON" | grep -q '^\[{"id":1,"message":"JSON stash","base":{"branch":"[0-9]*","seq":2},' && \
+   [ "$STATUS_JSON" = "$STATUS_ALIAS_JSON" ] && \
+   echo "$LOG_JSON" | grep -q '^\[{"id":{"branch":' && \
+   echo "$LOG_JSON" | grep -q '"message":"JSON \\"quoted\\" commit"' && \
+   echo "$BRANCHES_JSON" | grep -q '"name":"main"' && \
+   echo "$NOT_FOUND_JSON" | grep -q '^{"code":2,"message":"' && [ "$GRAPH_CODE" = 4 ]; then
+    echo -e "${GREEN}SUCCESS: Status, commits, branches and errors were printed as JSON${NC}"
+else
+    echo -e "${RED}FAILED: JSON output verification failed${NC}"
+    echo "status $STATUS_JSON"
+    echo "log $LOG_JSON"
+    echo "error $NOT_FOUND_JSON, graph $GRAPH_CODE"
+    echo "diff $DIFF_JSON"
+    echo "show $SHOW_JSON"
+    echo "unsupported $UNSUPPORTED_JSON, stash $STASH_CODE, usage $USAGE_JSON $USAGE_CODE"
+    echo "tags $TAGS_JSON"
+    echo "stashes $STASHES_JSON"
+    exit 1
+fi
+destroy_step_repo json-repo
+
+# Step 93: Test garbage collection of unreachable trees and blobs
+print_step "93. Testing gc"
+new_step_repo gc-repo "Create repository for gc"
+echo "Superseded content of 31 bytes" > gc-file.txt
+"$VX_PATH" commit new "GC commit"
+echo "Amended content" > gc-file.txt
+"$VX_PATH" commit amend
+check_success "Create a commit and amend its content"
+DRY_RUN_OUTPUT=$("$VX_PATH" gc --dry-run)
+check_success "Run gc --dry-run"
+GC_OUTPUT=$("$VX_PATH" gc)
+check_success "Run gc"
+AFTER_OUTPUT=$("$VX_PATH" gc --dry-run)
+rm gc-file.txt
+"$VX_PATH" tree checkout main:1 --force-clean
+if echo "$DRY_RUN_OUTPUT" | grep -q "unreachable blob [0-9a-f]* 31$" && \
+   echo "$DRY_RUN_OUTPUT" | grep -q "would remove 1 trees and 1 blobs of 31 bytes" && \
+   echo "$GC_OUTPUT" | grep -q "^Removed 1 trees and 1 blobs, freeing 31 bytes" && \
+   echo "$AFTER_OUTPUT" | grep -q "would remove 0 trees and 0 blobs of 0 bytes" && \
+   "$VX_PATH" blob fsck >/dev/null && [ "$(cat gc-file.txt)" = "Amended content" ]; then
+    echo -e "${GREEN}SUCCESS: gc removed the superseded tree and blob and kept the reachable ones${NC}"
+else
+    echo -e "${RED}FAILED: gc verification failed${NC}"
+    echo "dry run: $DRY_RUN_OUTPUT"
+    echo "gc: $GC_OUTPUT"
+    echo "after: $AFTER_OUTPUT"
+    exit 1
+fi
+destroy_step_repo gc-repo
+
+# Step 94: Test checking the integrity of the stores
+print_step "94. Testing fsck"
+new_step_repo fsck-repo "Create repository for fsck"
+echo "first content" > first.txt
+mkdir -p nested
+echo "second content" > nested/second.txt
+"$VX_PATH" commit new "Fsck commit"
+"$VX_PATH" tag add fsck-tag 1
+CLEAN_OUTPUT=$("$VX_PATH" fsck)
+check_success "Check a consistent repository"
+FIRST_HASH=$("$VX_PATH" blob import first.txt)
+SECOND_HASH=$("$VX_PATH" blob import nested/second.txt)
+FIRST_FILE=$(find .vx -path "*/blobs/${FIRST_HASH:0:2}/${FIRST_HASH:2}")
+SECOND_FILE=$(find .vx -path "*/blobs/${SECOND_HASH:0:2}/${SECOND_HASH:2}")
+chmod u+w "$FIRST_FILE"
+echo "tampered" > "$FIRST_FILE"
+rm -f "$SECOND_FILE"
+"$VX_PATH" fsck > fsck-output.txt 2>&1 && FSCK_CODE=0 || FSCK_CODE=$?
+if echo "$CLEAN_OUTPUT" | grep -q "^Checked 2 commits, 1 tags, 3 trees and 2 blobs, no problems found$" && \
+   [ "$FSCK_CODE" = 1 ] && grep -q "^  mismatched blob $FIRST_HASH$" fsck-output.txt && \
+   grep -q "^  missing blob file $SECOND_HASH$" fsck-output.txt && \
+   grep -q "found 2 problem(s)$" fsck-output.txt; then
+    echo -e "${GREEN}SUCCESS: fsck passed a consistent repository and reported damaged blobs${NC}"
+else
+    echo -e "${RED}FAILED: fsck verification failed${NC}"
+    echo "clean: $CLEAN_OUTPUT"
+    echo "damaged ($FSCK_CODE): $(cat fsck-output.txt)"
+    exit 1
+fi
+destroy_step_repo fsck-repo
+
+# Step 95: Test following the history of a branch into its parent branches
+print_step "95. Testing log --follow"
+new_step_repo follow-repo "Create repository for log --follow"
+echo "first" > first.txt
+"$VX_PATH" commit new "First on main"
+echo "second" > second.txt
+"$VX_PATH" commit new "Second on main"
+"$VX_PATH" branch new follow-feature
+"$VX_PATH" tree checkout follow-feature
+echo "feature" > feature.txt
+"$VX_PATH" commit new "Feature commit"
+check_success "Create a commit on a forked branch"
+FOLLOW_LOG=$("$VX_PATH" log --follow | cut -f1,5)
+PLAIN_LOG=$("$VX_PATH" log | cut -f4)
+PATH_LOG=$("$VX_PATH" log --follow -- first.txt | cut -f1)
+FORMAT_LOG=$("$VX_PATH" log --follow --format "%b:%s")
+FEATURE_HASH=$("$VX_PATH" commit show --format "%h")
+"$VX_PATH" log --follow --graph >/dev/null 2>&1 && GRAPH_CODE=0 || GRAPH_CODE=$?
+EXPECTED_FOLLOW=$(printf "follow-feature:1\tFeature commit\nmain:2\tSecond on main\nmain:1\tFirst on main\nmain:0\tInitial commit")
+if [ "$FOLLOW_LOG" = "$EXPECTED_FOLLOW" ] && \
+   [ "$PLAIN_LOG" = "$(printf "Feature commit\nSecond on main")" ] && \
+   [ "$PATH_LOG" = "main:1" ] && \
+   [ "$FORMAT_LOG" = "$(printf "follow-feature:1\nmain:2\nmain:1\nmain:0")" ] && \
+   [ "$("$VX_PATH" log --follow | sed -n 1p | cut -f2)" = "$FEATURE_HASH" ] && \
+   [ "$GRAPH_CODE" = 4 ]; then
+    echo -e "${GREEN}SUCCESS: log --follow continued into the parent branch at the fork point${NC}"
+else
+    echo -e "${RED}FAILED: log --follow verification failed${NC}"
+    echo "follow: $FOLLOW_LOG"
+    echo "plain: $PLAIN_LOG"
+    echo "paths: $PATH_LOG, format: $FORMAT_LOG, graph: $GRAPH_CODE"
+    exit 1
+fi
+destroy_step_repo follow-repo
+
+# Step 96: Test moving the head of a branch with reset
+print_step "96. Testing reset"
+new_step_repo reset-repo "Create repository for reset"
+echo "one" > one.txt
+"$VX_PATH" commit new "Reset one"
+echo "two" > two.txt
+"$VX_PATH" commit new "Reset two"
+echo "three" > three.txt
+"$VX_PATH" commit new "Reset three"
+"$VX_PATH" branch new reset-other
+"$VX_PATH" tree checkout main
+SOFT_OUTPUT=$("$VX_PATH" reset 2)
+check_success "Reset the head one commit back"
+SOFT_STATUS=$("$VX_PATH" status)
+"$VX_PATH" reset --hard 1
+check_success "Reset the head to the first commit discarding files"
+HARD_STATUS=$("$VX_PATH" status)
+HARD_FILES=$(ls | tr '\n' ' ')
+ADVANCE_OUTPUT=$("$VX_PATH" reset --hard 3)
+check_success "Reset the head forward to a dropped commit"
+ADVANCED_FILES=$(ls | tr '\n' ' ')
+"$VX_PATH" reset --hard 1 >/dev/null
+"$VX_PATH" reset reset-other:0 >/dev/null 2>&1 && OTHER_CODE=0 || OTHER_CODE=$?
+echo "new two" > new-two.txt
+"$VX_PATH" commit new "Reset new two"
+"$VX_PATH" reset 3 >/dev/null 2>&1 && ABOVE_CODE=0 || ABOVE_CODE=$?
+RESET_LOG=$("$VX_PATH" log | cut -f4)
+if echo "$SOFT_OUTPUT" | grep -q "^Moved branch head to commit: 2 - Reset two" && \
+   echo "$SOFT_OUTPUT" | grep -q "^Dropped 1 commit above it" && \
+   echo "$SOFT_STATUS" | grep -q "added file three.txt" && \
+   [ "$HARD_STATUS" = "No files changed since current commit" ] && \
+   [ "$HARD_FILES" = "one.txt " ] && [ "$ADVANCED_FILES" = "one.txt three.txt two.txt " ] && \
+   echo "$ADVANCE_OUTPUT" | grep -q "^Restored 2 dropped commits up to it" && \
+   [ "$ABOVE_CODE" = 1 ] && [ "$OTHER_CODE" = 1 ] && \
+   [ "$RESET_LOG" = "$(printf "Reset new two\nReset one\nInitial commit")" ]; then
+    echo -e "${GREEN}SUCCESS: reset moved the head back and forward, keeping or discarding the files${NC}"
+else
+    echo -e "${RED}FAILED: reset verification failed${NC}"
+    echo "soft: $SOFT_OUTPUT"
+    echo "soft status: $SOFT_STATUS"
+    echo "hard status: $HARD_STATUS, files: $HARD_FILES"
+    echo "advance: $ADVANCE_OUTPUT, files: $ADVANCED_FILES"
+    echo "above $ABOVE_CODE, other $OTHER_CODE, log: $RESET_LOG"
+    exit 1
+fi
+destroy_step_repo reset-repo
+
+# Step 97: Test reverting the changes of a commit with a new commit
+print_step "97. Testing revert"
+new_step_repo revert-repo "Create repository for revert"
+printf "line one\nline two\nline three\n" > lines.txt
+"$VX_PATH" commit new "Revert base"
+printf "line one\nline two changed\nline three\n" > lines.txt
+echo "added" > added.txt
+"$VX_PATH" commit new "Revert target"
+echo "later" > later.txt
+"$VX_PATH" commit new "Revert later"
+REVERT_OUTPUT=$("$VX_PATH" revert 2)
+check_success "Revert the changes of a commit"
+REVERT_STATUS=$("$VX_PATH" status)
+"$VX_PATH" revert 2 >/dev/null 2>&1 && AGAIN_CODE=0 || AGAIN_CODE=$?
+printf "line one\nline two\nline three again\n" > lines.txt
+"$VX_PATH" commit new "Revert change three"
+printf "line one\nline two\nline three once more\n" > lines.txt
+"$VX_PATH" commit new "Revert change three again"
+"$VX_PATH" revert 5 > revert-conflict.txt 2>&1 && CONFLICT_CODE=0 || CONFLICT_CODE=$?
+rm revert-conflict.txt
+CONFLICT_STATUS=$("$VX_PATH" status)
+echo "local" > local.txt
+"$VX_PATH" revert 6 >/dev/null 2>&1 && LOCAL_CODE=0 || LOCAL_CODE=$?
+rm local.txt
+REVERT_LOG=$("$VX_PATH" log | cut -f4 | head -3)
+if echo "$REVERT_OUTPUT" | grep -q "^Created new commit: 4 - Revert: Revert target$" && \
+   [ ! -e added.txt ] && [ "$(cat later.txt)" = "later" ] && [ "$AGAIN_CODE" = 3 ] && \
+   [ "$REVERT_STATUS" = "No files changed since current commit" ] && \
+   [ "$CONFLICT_CODE" = 1 ] && [ "$LOCAL_CODE" = 1 ] && \
+   [ "$CONFLICT_STATUS" = "No files changed since current commit" ] && \
+   [ "$(sed -n 3p lines.txt)" = "line three once more" ] && \
+   [ "$REVERT_LOG" = "$(printf "Revert change three again\nRevert change three\nRevert: Revert target")" ]; then
+    echo -e "${GREEN}SUCCESS: revert undid a commit and refused conflicts and local changes${NC}"
+else
+    echo -e "${RED}FAILED: revert verification failed${NC}"
+    echo "revert: $REVERT_OUTPUT"
+    echo "again $AGAIN_CODE, conflict $CONFLICT_CODE, local $LOCAL_CODE"
+    echo "status: $CONFLICT_STATUS"
+    echo "log: $REVERT_LOG"
+    exit 1
+fi
+destroy_step_repo revert-repo
+
+# Step 98: Test restoring a file or folder of a commit into the checkout
+print_step "98. Testing tree restore"
+new_step_repo restore-repo "Create repository for restore"
+mkdir -p docs
+echo "keep me" > keep.txt
+echo "broken later" > broken.txt
+echo "guide" > docs/guide.txt
+echo "notes" > docs/notes.txt
+"$VX_PATH" commit new "Restore base"
+rm keep.txt
+echo "oops" > broken.txt
+echo "untouched" > other.txt
+"$VX_PATH" tree restore 1 keep.txt
+check_success "Restore a deleted file"
+(cd docs && rm guide.txt notes.txt && "$VX_PATH" tree restore 1 .)
+check_success "Restore a folder"
+"$VX_PATH" tree restore 1 broken.txt >/dev/null
+RESTORE_STATUS=$("$VX_PATH" status)
+"$VX_PATH" tree restore 1 missing.txt >/dev/null 2>&1 && MISSING_CODE=0 || MISSING_CODE=$?
+if [ "$(cat keep.txt)" = "keep me" ] && [ "$(cat broken.txt)" = "broken later" ] && \
+   [ "$(cat docs/guide.txt)" = "guide" ] && [ "$(cat docs/notes.txt)" = "notes" ] && \
+   [ "$(cat other.txt)" = "untouched" ] && [ "$MISSING_CODE" = 2 ] && \
+   echo "$RESTORE_STATUS" | grep -q "other.txt" && \
+   ! echo "$RESTORE_STATUS" | grep -q "keep.txt\|broken.txt\|docs"; then
+    echo -e "${GREEN}SUCCESS: tree restore brought back files and a folder of a commit${NC}"
+else
+    echo -e "${RED}FAILED: tree restore verification failed${NC}"
+    echo "status: $RESTORE_STATUS"
+    echo "missing $MISSING_CODE"
+    exit 1
+fi
+destroy_step_repo restore-repo
+
+# Step 99: Test that garbage collection keeps the commits of tags dropped from their branch
+print_step "99. Testing gc keeping tagged commits"
+new_step_repo gc-tag-repo "Create repository for gc of tagged commits"
+echo "Base" > base.txt
+"$VX_PATH" commit new "Tag base"
+echo "Released" > release.txt
+"$VX_PATH" commit new "Tagged release"
+"$VX_PATH" tag add rel
+"$VX_PATH" commit undo --hard
+check_success "Tag a commit and drop it from the branch"
+"$VX_PATH" gc >/dev/null
+check_success "Run gc with a tagged commit dropped from the branch"
+"$VX_PATH" tree checkout rel >/dev/null
+check_success "Check out the tagged commit after gc"
+RELEASED_CONTENT=$(cat release.txt)
+"$VX_PATH" tree checkout main >/dev/null
+if [ "$RELEASED_CONTENT" = "Released" ] && "$VX_PATH" fsck >/dev/null; then
+    echo -e "${GREEN}SUCCESS: gc kept the tree of a tagged commit dropped from its branch${NC}"
+else
+    echo -e "${RED}FAILED: gc of tagged commits verification failed${NC}"
+    exit 1
+fi
+destroy_step_repo gc-tag-repo
+
+# Step 100: Test that a spec naming no branch or tag is reported as not found
+print_step "100. Testing exit codes of unknown specs"
+new_step_repo spec-repo "Create repository for unknown specs"
+echo "Spec" > spec.txt
+"$VX_PATH" commit new "Spec commit"
+"$VX_PATH" commit show nosuch >/dev/null 2>&1 && SHOW_CODE=0 || SHOW_CODE=$?
+"$VX_PATH" tree checkout nosuch >/dev/null 2>&1 && CHECKOUT_CODE=0 || CHECKOUT_CODE=$?
+"$VX_PATH" commit show nosuch:1 >/dev/null 2>&1 && BRANCH_SEQ_CODE=0 || BRANCH_SEQ_CODE=$?
+SHOW_ERROR=$("$VX_PATH" commit show nosuch 2>&1 || true)
+if [ "$SHOW_CODE" = 2 ] && [ "$CHECKOUT_CODE" = 2 ] && [ "$BRANCH_SEQ_CODE" = 2 ] && \
+   echo "$SHOW_ERROR" | grep -q "UnknownSpec(\"nosuch\")"; then
+    echo -e "${GREEN}SUCCESS: Unknown specs exited with the not found code${NC}"
+else
+    echo -e "${RED}FAILED: Unknown spec exit code verification failed${NC}"
+    echo "show $SHOW_CODE, checkout $CHECKOUT_CODE, branch and seq $BRANCH_SEQ_CODE"
+    echo "$SHOW_ERROR"
+    exit 1
+fi
+destroy_step_repo spec-repo
+
+# Step 101: Test that amending a mid-branch commit reapplies the changes of the commits above
+print_step "101. Testing amend rebuilding the commits above"
+new_step_repo amend-rebuild-repo "Create repository for amend rebuilds"
+printf "one\ntwo\nthree\nfour\nfive\n" > lines.txt
+"$VX_PATH" commit new "Rebuild base"
+printf "one mid\ntwo\nthree\nfour\nfive\n" > lines.txt
+"$VX_PATH" commit new "Rebuild mid"
+printf "one mid\ntwo\nthree\nfour\nfive top\n" > lines.txt
+echo "top" > top.txt
+"$VX_PATH" commit new "Rebuild top"
+"$VX_PATH" tree checkout 2 >/dev/null
+printf "one mid\ntwo\nthree amended\nfour\nfive\n" > lines.txt
+"$VX_PATH" commit amend >/dev/null
+check_success "Amend the content of a mid-branch commit"
+"$VX_PATH" tree checkout main >/dev/null
+REBUILT_LINES=$(cat lines.txt)
+REBUILT_TOP=$(cat top.txt)
+"$VX_PATH" tree checkout 2 >/dev/null
+printf "one mid\ntwo\nthree amended\nfour\nfive mid\n" > lines.txt
+"$VX_PATH" commit amend > amend-conflict.txt 2>&1 && CONFLICT_CODE=0 || CONFLICT_CODE=$?
+rm amend-conflict.txt
+PAUSED_STATUS=$("$VX_PATH" repo status)
+"$VX_PATH" commit rebuild --abort >/dev/null
+check_success "Abort the paused rebuild"
+if [ "$REBUILT_LINES" = "$(printf "one mid\ntwo\nthree amended\nfour\nfive top")" ] && \
+   [ "$REBUILT_TOP" = "top" ] && [ "$CONFLICT_CODE" != 0 ] && \
+   echo "$PAUSED_STATUS" | grep -q "Rebuild of commit 3 is in progress"; then
+    echo -e "${GREEN}SUCCESS: amend reapplied the changes above and paused on a conflict${NC}"
+else
+    echo -e "${RED}FAILED: amend rebuild verification failed${NC}"
+    echo "rebuilt: $REBUILT_LINES"
+    echo "conflict $CONFLICT_CODE, status: $PAUSED_STATUS"
+    exit 1
+fi
+destroy_step_repo amend-rebuild-repo
+
+# Step 102: Test that tag names a spec would not resolve to the tag are refused
+print_step "102. Testing refused tag names"
+new_step_repo tag-name-repo "Create repository for tag names"
+echo "Tagged" > tagged.txt
+"$VX_PATH" commit new "Tag name commit"
+"$VX_PATH" branch new feature >/dev/null
+"$VX_PATH" tree checkout main >/dev/null
+"$VX_PATH" tag add 5 >/dev/null 2>&1 && DIGITS_CODE=0 || DIGITS_CODE=$?
+"$VX_PATH" tag add feature >/dev/null 2>&1 && BRANCH_CODE=0 || BRANCH_CODE=$?
+"$VX_PATH" tag add v5 >/dev/null
+check_success "Tag a commit with a name mixing letters and digits"
+if [ "$DIGITS_CODE" = 4 ] && [ "$BRANCH_CODE" = 4 ] && [ "$("$VX_PATH" tag list | wc -l)" = 1 ] && \
+   "$VX_PATH" commit show v5 | grep -q "Tag name commit"; then
+    echo -e "${GREEN}SUCCESS: Tag names of digits only and of branches were refused${NC}"
+else
+    echo -e "${RED}FAILED: Tag name verification failed${NC}"
+    echo "digits $DIGITS_CODE, branch $BRANCH_CODE"
+    "$VX_PATH" tag list
+    exit 1
+fi
+destroy_step_repo tag-name-repo
+
+# Step 103: Test commits taking the entries of settled folders from the folder index
+print_step "103. Testing the folder index"
+new_step_repo folder-index-repo "Create repository for the folder index"
+mkdir -p settled/inner
+echo "one" > settled/file.txt
+echo "inner" > settled/inner/file.txt
+echo "ignored" > settled/skipped.log
+echo "*.log" > .vxignore
+touch -d "1 hour ago" . settled settled/inner settled/file.txt settled/inner/file.txt settled/skipped.log .vxignore
+"$VX_PATH" commit new "Index folders"
+check_success "Commit settled folders"
+echo "two" > settled/file.txt
+"$VX_PATH" commit new "Edit in place" >/dev/null
+check_success "Commit a file edited in place"
+: > .vxignore
+touch -d "1 hour ago" settled/file.txt
+"$VX_PATH" commit new "Track logs" >/dev/null
+check_success "Commit a file no longer ignored"
+echo "new" > settled/inner/new.txt
+"$VX_PATH" commit new "Add a file" >/dev/null
+check_success "Commit a file added to a settled folder"
+FOLDER_FILES=$("$VX_PATH" tree diff 1 4 --name-only)
+if [ "$FOLDER_FILES" = "$(printf ".vxignore\nsettled/file.txt\nsettled/inner/new.txt\nsettled/skipped.log")" ] && \
+   "$VX_PATH" status | grep -q "No files changed"; then
+    echo -e "${GREEN}SUCCESS: Commits of settled folders kept every change${NC}"
+else
+    echo -e "${RED}FAILED: Folder index verification failed${NC}"
+    echo "$FOLDER_FILES"
+    exit 1
+fi
+destroy_step_repo folder-index-repo
+
+# Step 104: Test checkouts replacing a link with a folder and a folder with a link
+print_step "104. Testing links replaced by folders"
+new_step_repo link-folder-repo "Create repository for links replaced by folders"
+mkdir -p "$TEST_DIR/link-target" swapped
+echo "inside" > swapped/file.txt
+"$VX_PATH" commit new "Folder" >/dev/null
+check_success "Commit a folder"
+rm -rf swapped
+ln -s "$TEST_DIR/link-target" swapped
+"$VX_PATH" commit new "Link" >/dev/null
+check_success "Commit a link in place of the folder"
+"$VX_PATH" tree checkout main:1 >/dev/null
+check_success "Check out the folder in place of the link"
+FOLDER_STATUS=$("$VX_PATH" status)
+if [ -z "$(ls -A "$TEST_DIR/link-target")" ] && [ -d swapped ] && [ ! -L swapped ] && \
+   [ "$(cat swapped/file.txt)" = "inside" ] && echo "$FOLDER_STATUS" | grep -q "No files changed"; then
+    echo -e "${GREEN}SUCCESS: Folder replaced the link without writing through it${NC}"
+else
+    echo -e "${RED}FAILED: Folder was written through the link${NC}"
+    ls -la "$TEST_DIR/link-target" swapped
+    echo "$FOLDER_STATUS"
+    exit 1
+fi
+"$VX_PATH" tree checkout main:2 >/dev/null
+check_success "Check out the link in place of the folder"
+"$VX_PATH" tree checkout main:1 >/dev/null
+"$VX_PATH" tree checkout --merge main:2 >/dev/null
+check_success "Merge the link in place of the folder into the checkout"
+if [ -L swapped ] && [ "$(readlink swapped)" = "$TEST_DIR/link-target" ] && \
+   [ -z "$(ls -A "$TEST_DIR/link-target")" ] && "$VX_PATH" status | grep -q "No files changed"; then
+    echo -e "${GREEN}SUCCESS: Link replaced the folder${NC}"
+else
+    echo -e "${RED}FAILED: Link did not replace the folder${NC}"
+    ls -la swapped
+    exit 1
+fi
+destroy_step_repo link-folder-repo
+
+# Step 105: Test gc keeping the commits a reset can restore
+print_step "105. Testing gc of dropped commits"
+new_step_repo dropped-gc-repo "Create repository for dropped commits"
+echo "kept" > dropped.txt
+"$VX_PATH" commit new "Kept commit" >/dev/null
+check_success "Create the commit to keep"
+echo "dropped" > dropped.txt
+"$VX_PATH" commit new "Dropped commit" >/dev/null
+check_success "Create the commit to drop"
+"$VX_PATH" commit undo --hard >/dev/null
+check_success "Drop the commit"
+"$VX_PATH" gc >/dev/null
+check_success "Collect garbage with a dropped commit"
+"$VX_PATH" reset main:2 --hard >/dev/null
+check_success "Restore the dropped commit"
+if "$VX_PATH" fsck >/dev/null && [ "$(cat dropped.txt)" = "dropped" ] && \
+   "$VX_PATH" status | grep -q "No files changed"; then
+    echo -e "${GREEN}SUCCESS: Dropped commit was restored after gc${NC}"
+else
+    echo -e "${RED}FAILED: gc removed the tree of a restorable commit${NC}"
+    "$VX_PATH" fsck
+    exit 1
+fi
+destroy_step_repo dropped-gc-repo
+
+# Step 106: Test the default branch recorded by the repository
+print_step "106. Testing the recorded default branch"
+new_step_repo default-branch-repo "Create repository with a recorded default branch"
+echo "main" > main.txt
+"$VX_PATH" commit new "Main commit" >/dev/null
+check_success "Commit to the default branch"
+for orphan in web docs site a z; do
+    "$VX_PATH" tree checkout --orphan "$orphan" >/dev/null
+    check_success "Create orphan branch $orphan"
+done
+"$VX_PATH" tree checkout main >/dev/null
+check_success "Check out the default branch"
+"$VX_PATH" branch rename main trunk >/dev/null
+check_success "Rename the default branch"
+if "$VX_PATH" repo info | grep -q "^Default branch:  trunk" && \
+   "$VX_PATH" repo info --json | grep -q '"defaultBranch": "trunk"'; then
+    echo -e "${GREEN}SUCCESS: Repository info shows the recorded default branch${NC}"
+else
+    echo -e "${RED}FAILED: Repository info shows another default branch${NC}"
+    "$VX_PATH" repo info
+    exit 1
+fi
+destroy_step_repo default-branch-repo
+
+# Step 107: Test the status alias taking the arguments of tree status
+print_step "107. Testing status alias arguments"
+new_step_repo status-alias-repo "Create repository for the status alias"
+echo "tracked" > tracked.txt
+"$VX_PATH" commit new "Tracked commit" >/dev/null
+check_success "Commit a file"
+mkdir added
+echo "added" > added/file.txt
+if "$VX_PATH" status --untracked=no | grep -q "No files changed" && \
+   "$VX_PATH" status --untracked=all | grep -q "added/file.txt" && \
+   [ "$("$VX_PATH" status --untracked=all)" = "$("$VX_PATH" tree status --untracked=all)" ]; then
+    echo -e "${GREEN}SUCCESS: Status alias took the arguments of tree status${NC}"
+else
+    echo -e "${RED}FAILED: Status alias ignored its arguments${NC}"
+    "$VX_PATH" status --untracked=no
+    exit 1
+fi
+destroy_step_repo status-alias-repo
+
 # Clean up
 print_step "Cleaning up"
 cd -